@@ -191,6 +191,60 @@ impl Particle {
     pub fn from_str(s: &str) -> Self {
         Self::try_from(s).unwrap_or(Self::Unknown)
     }
+
+    /// Particle symbol used on MCNP input cards, e.g. `n` for `IMP:n`
+    ///
+    /// Returns `None` for [Particle::Unknown], which has no symbol.
+    ///
+    /// ```rust
+    /// # use ntools_mctal::Particle;
+    /// assert_eq!(Particle::Neutron.designator(), Some('n'));
+    /// assert_eq!(Particle::NegativeMuon.designator(), Some('|'));
+    /// assert_eq!(Particle::Unknown.designator(), None);
+    /// ```
+    pub fn designator(&self) -> Option<char> {
+        let symbol = match self {
+            Self::Unknown => return None,
+            Self::Neutron => 'n',
+            Self::Photon => 'p',
+            Self::Electron => 'e',
+            Self::NegativeMuon => '|',
+            Self::AntiNeutron => 'q',
+            Self::ElectronNeutrino => 'u',
+            Self::MuonNeutrino => 'v',
+            Self::Positron => 'f',
+            Self::Proton => 'h',
+            Self::LambdaBaryon => 'l',
+            Self::PosSigmaBaryon => '+',
+            Self::NegSigmaBaryon => '-',
+            Self::XiBaryon => 'x',
+            Self::NegXiBaryon => 'y',
+            Self::OmegaBaryon => 'o',
+            Self::PosMuon => '!',
+            Self::AntiElectronNeutrino => '<',
+            Self::AntiMuonNeutrino => '>',
+            Self::AntiProton => 'g',
+            Self::PosPion => '/',
+            Self::NeuPion => 'z',
+            Self::PosKaon => 'k',
+            Self::ShortKaon => '%',
+            Self::LongKaon => '^',
+            Self::AntiLambdaBaryon => 'b',
+            Self::AntiPosSigmaBaryon => '_',
+            Self::AntiNegSigmaBaryon => '~',
+            Self::AntiNeuXiBaryon => 'c',
+            Self::PosXiBaryon => 'w',
+            Self::AntiOmega => '@',
+            Self::Deuteron => 'd',
+            Self::Triton => 't',
+            Self::Helion => 's',
+            Self::Alpha => 'a',
+            Self::NegPion => '*',
+            Self::NegKaon => '?',
+            Self::HeavyIon => '#',
+        };
+        Some(symbol)
+    }
 }
 
 /// Convert from any valid numerical designator
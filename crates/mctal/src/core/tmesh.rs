@@ -1,5 +1,9 @@
+use crate::error::{Error, Result};
 use crate::{Particle, TallyResult};
 
+use ntools_mesh::{Format, Mesh, Voxel};
+use ntools_utils::f;
+
 /// TMESH tally type data
 ///
 /// ### Overview
@@ -84,6 +88,233 @@ impl Tmesh {
         // 0=unbounded but should be considered 1x bin
         values.iter().filter(|v| **v > 0).product()
     }
+
+    /// Convert to a [Mesh](ntools_mesh::Mesh) with single energy/time bins
+    ///
+    /// The MCTAL file only records the number of energy and time bins, not
+    /// their bounds. This is fine for the common case of a single energy and
+    /// time bin, which become the [Group::Total](ntools_mesh::Group) of the
+    /// mesh. Anything else needs explicit bounds, see
+    /// [to_mesh_with_bins()](Tmesh::to_mesh_with_bins).
+    ///
+    /// Once converted, every mesh tool is available for TMESH results, such as
+    /// VTK conversion and weight window generation.
+    ///
+    /// ```rust
+    /// # use ntools_mctal::{Particle, TallyResult, Tmesh};
+    /// let tmesh = Tmesh {
+    ///     id: 11,
+    ///     particles: vec![Particle::Neutron],
+    ///     n_cora: 2, n_corb: 1, n_corc: 1,
+    ///     cora: vec![0.0, 5.0, 10.0],
+    ///     corb: vec![0.0, 10.0],
+    ///     corc: vec![0.0, 10.0],
+    ///     n_voxels: 2,
+    ///     results: vec![
+    ///         TallyResult { value: 2.0, error: 0.1 },
+    ///         TallyResult { value: 1.0, error: 0.2 },
+    ///     ],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let mesh = tmesh.to_mesh().unwrap();
+    ///
+    /// assert_eq!(mesh.id, 11);
+    /// assert_eq!(mesh.imesh, vec![0.0, 5.0, 10.0]);
+    /// assert_eq!(mesh.voxels[1].result, 1.0);
+    /// ```
+    pub fn to_mesh(&self) -> Result<Mesh> {
+        self.to_mesh_with_bins(&[], &[])
+    }
+
+    /// Convert to a [Mesh](ntools_mesh::Mesh) using known energy/time bins
+    ///
+    /// Bounds follow the `emesh`/`tmesh` convention of the mesh, i.e. the
+    /// lower bound of the first bin followed by the upper bound of every bin.
+    /// An empty slice is a single unbounded bin.
+    ///
+    /// The MCTAL results may or may not include a 'Total' bin. If there is no
+    /// total for multiple bins, one is calculated by summing the results and
+    /// combining the absolute errors in quadrature.
+    ///
+    /// Notes on the geometry:
+    ///
+    /// - Voxels are assumed to follow the MCNP cell index order, with the
+    ///   CORA index varying fastest
+    /// - Cylindrical CORC angles are converted from degrees to revolutions if
+    ///   the final bound is greater than 1. MCTAL does not record the unit, so
+    ///   a mesh in degrees ending at or below 1 degree is taken as revolutions,
+    ///   i.e. 1 degree would be a full revolution. Convert such bounds to
+    ///   revolutions before calling.
+    /// - The ORIGIN, AXS, and VEC are not written to MCTAL, so take the MCNP
+    ///   defaults and may be set on the mesh afterwards
+    /// - Spherical meshes are not supported
+    ///
+    /// ```rust
+    /// # use ntools_mctal::{Particle, TallyResult, Tmesh};
+    /// # use ntools_mesh::Group;
+    /// let tmesh = Tmesh {
+    ///     particles: vec![Particle::Photon],
+    ///     n_cora: 1, n_corb: 1, n_corc: 1,
+    ///     cora: vec![0.0, 10.0],
+    ///     corb: vec![0.0, 10.0],
+    ///     corc: vec![0.0, 10.0],
+    ///     n_voxels: 1,
+    ///     n_energy_bins: 2,
+    ///     results: vec![
+    ///         TallyResult { value: 3.0, error: 0.1 },
+    ///         TallyResult { value: 1.0, error: 0.1 },
+    ///     ],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// // Two energy bins without a total
+    /// let mesh = tmesh.to_mesh_with_bins(&[0.0, 1.0, 20.0], &[]).unwrap();
+    ///
+    /// assert_eq!(mesh.energy_groups(), vec![Group::Value(1.0), Group::Value(20.0), Group::Total]);
+    /// assert_eq!(mesh.voxels[2].result, 4.0);
+    /// ```
+    pub fn to_mesh_with_bins(&self, energies: &[f64], times: &[f64]) -> Result<Mesh> {
+        self.check_conversion()?;
+
+        let geometry = match self.geometry {
+            Geometry::Rectangular => ntools_mesh::Geometry::Rectangular,
+            Geometry::Cylindrical => ntools_mesh::Geometry::Cylindrical,
+            Geometry::Spherical => return Err(invalid("spherical meshes are not supported")),
+        };
+
+        let (emesh, eints, e_total) =
+            group_bins(self.n_energy_bins, energies, &[0.0, 1e36], "energy")?;
+        let (tmesh, tints, t_total) = group_bins(self.n_time_bins, times, &[], "time")?;
+
+        let kmesh = match self.geometry {
+            Geometry::Cylindrical if self.corc.last().is_some_and(|t| *t > 1.0) => {
+                self.corc.iter().map(|t| t / 360.0).collect()
+            }
+            _ => self.corc.clone(),
+        };
+
+        let particle = match self.particles.as_slice() {
+            [] => ntools_mesh::Particle::Unknown,
+            [p] => ntools_mesh::Particle::from_id(p.id()),
+            _ => {
+                return Err(invalid(
+                    "results for multiple particles can not be separated",
+                ))
+            }
+        };
+
+        let mut mesh = Mesh {
+            id: self.id,
+            geometry,
+            particle,
+            imesh: self.cora.clone(),
+            iints: self.n_cora,
+            jmesh: self.corb.clone(),
+            jints: self.n_corb,
+            kmesh,
+            kints: self.n_corc,
+            emesh,
+            eints,
+            tmesh,
+            tints,
+            format: Format::NONE,
+            ..Default::default()
+        };
+
+        // results as read, adding totals where missing
+        let n_e = self.n_energy_bins.max(1);
+        let n_t = self.n_time_bins.max(1);
+        let result = |cell: usize, e: usize, t: usize| -> (f64, f64) {
+            let r = &self.results[(cell * n_e + e) * n_t + t];
+            (r.value, r.error)
+        };
+
+        let mut voxels = vec![Voxel::default(); mesh.n_voxels_expected()];
+        for cell in 0..self.n_voxels {
+            let i = cell % self.n_cora;
+            let j = (cell / self.n_cora) % self.n_corb;
+            let k = cell / (self.n_cora * self.n_corb);
+
+            for e in 0..mesh.n_ebins() {
+                for t in 0..mesh.n_tbins() {
+                    let es = match e == n_e && !e_total {
+                        true => (0..n_e).collect(),
+                        false => vec![e],
+                    };
+                    let ts = match t == n_t && !t_total {
+                        true => (0..n_t).collect(),
+                        false => vec![t],
+                    };
+
+                    let (value, error) = sum_results(
+                        es.iter()
+                            .flat_map(|e| ts.iter().map(|t| result(cell, *e, *t)))
+                            .collect(),
+                    );
+
+                    let index = mesh.voxel_index_from_etijk(e, t, i, j, k);
+                    voxels[index] = Voxel {
+                        index,
+                        result: value,
+                        error,
+                    };
+                }
+            }
+        }
+
+        mesh.voxels = voxels;
+        Ok(mesh)
+    }
+}
+
+// Private implementations
+impl Tmesh {
+    /// Make sure the results can be mapped onto mesh voxels
+    fn check_conversion(&self) -> Result<()> {
+        let other_bins = [
+            ("flagged", self.n_flagged_bins),
+            ("user", self.n_user_bins),
+            ("segment", self.n_segment_bins),
+            ("multiplier", self.n_multiplier_bins),
+            ("cosine", self.n_cosine_bins),
+        ];
+
+        if let Some((name, n)) = other_bins.iter().find(|(_, n)| *n > 1) {
+            return Err(invalid(&f!("{n} {name} bins can not be mapped to a mesh")));
+        }
+
+        if self.n_voxels != self.n_cora * self.n_corb * self.n_corc {
+            return Err(invalid(&f!(
+                "{} voxels do not match {}x{}x{} bins",
+                self.n_voxels,
+                self.n_cora,
+                self.n_corb,
+                self.n_corc
+            )));
+        }
+
+        let bounds = [
+            (&self.cora, self.n_cora),
+            (&self.corb, self.n_corb),
+            (&self.corc, self.n_corc),
+        ];
+
+        if bounds.iter().any(|(b, n)| *n == 0 || b.len() != n + 1) {
+            return Err(invalid(
+                "CORA/CORB/CORC bounds do not match the number of bins",
+            ));
+        }
+
+        if self.results.len() != self.n_expected_results() {
+            return Err(Error::UnexpectedLength {
+                expected: self.n_expected_results(),
+                found: self.results.len(),
+            });
+        }
+
+        Ok(())
+    }
 }
 
 /// Mesh geometry types
@@ -137,3 +368,54 @@ impl std::fmt::Display for Geometry {
         write!(f, "{}", self.geometry_name())
     }
 }
+
+/// Mesh bounds, number of bins, and whether the results include a total
+fn group_bins(
+    n_bins: usize,
+    bounds: &[f64],
+    default: &[f64],
+    name: &str,
+) -> Result<(Vec<f64>, usize, bool)> {
+    let n_bins = n_bins.max(1);
+
+    // single unbounded bin
+    if bounds.is_empty() {
+        return match n_bins {
+            1 => Ok((default.to_vec(), default.len().saturating_sub(1), true)),
+            _ => Err(invalid(&f!(
+                "{n_bins} {name} bins found, bounds must be provided"
+            ))),
+        };
+    }
+
+    if bounds.len() < 2 || bounds.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(invalid(&f!("{name} bounds must be increasing")));
+    }
+
+    let n_bounded = bounds.len() - 1;
+    match (n_bounded, n_bins) {
+        (1, 1) => Ok((bounds.to_vec(), 1, true)),
+        (a, b) if a > 1 && b == a + 1 => Ok((bounds.to_vec(), a, true)),
+        (a, b) if a > 1 && b == a => Ok((bounds.to_vec(), a, false)),
+        _ => Err(invalid(&f!(
+            "{n_bounded} {name} bins from bounds do not match {n_bins} bins in results"
+        ))),
+    }
+}
+
+/// Sum of results, with absolute errors combined in quadrature
+fn sum_results(results: Vec<(f64, f64)>) -> (f64, f64) {
+    let total = results.iter().map(|(v, _)| v).sum::<f64>();
+    let variance = results.iter().map(|(v, e)| (v * e).powi(2)).sum::<f64>();
+
+    match total > 0.0 {
+        true => (total, variance.sqrt() / total),
+        false => (total, 0.0),
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::TmeshConversion {
+        reason: reason.to_string(),
+    }
+}
@@ -41,6 +41,9 @@ pub enum Error {
 
     /// Unable to infew particle type from a string
     FailedToInferParticle { tag: String },
+
+    /// Unable to convert a TMESH tally into a mesh
+    TmeshConversion { reason: String },
 }
 
 // Boilerplate for the library. Anyone using the library is a developer and
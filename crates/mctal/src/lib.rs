@@ -19,6 +19,10 @@
 //! mesh tallies are not. Tools for reading `FMESH` data are available in other
 //! ntools crates (See [mesh](https://repositony.github.io/ntools/ntools_mesh/index.html)).
 //!
+//! A [Tmesh] is converted to a common [Mesh](ntools_mesh::Mesh) with
+//! [to_mesh()](Tmesh::to_mesh), so that the same mesh tools, VTK conversion,
+//! and weight window generation are available for `TMESH` results.
+//!
 //! \* *Note: numerical items do not need to be in the columns implied by
 //! fortran formats, only blank-delimited and in  the right order*
 //!
@@ -0,0 +1,179 @@
+//! Integration tests for converting TMESH tallies to meshes
+
+use ntools_mctal::{Geometry, Particle, TallyResult, Tmesh};
+use ntools_mesh::Group;
+use rstest::rstest;
+
+/// Single voxel with a result for every energy and time bin
+fn tmesh(n_energy_bins: usize, n_time_bins: usize, results: &[(f64, f64)]) -> Tmesh {
+    Tmesh {
+        id: 14,
+        particles: vec![Particle::Neutron],
+        n_cora: 1,
+        n_corb: 1,
+        n_corc: 1,
+        cora: vec![0.0, 10.0],
+        corb: vec![0.0, 10.0],
+        corc: vec![0.0, 1.0],
+        n_voxels: 1,
+        n_energy_bins,
+        n_time_bins,
+        results: results
+            .iter()
+            .map(|(value, error)| TallyResult {
+                value: *value,
+                error: *error,
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Cylindrical mesh with three theta bins
+fn cylinder(corc: Vec<f64>) -> Tmesh {
+    Tmesh {
+        geometry: Geometry::Cylindrical,
+        n_corc: 3,
+        corc,
+        n_voxels: 3,
+        ..tmesh(1, 1, &[(1.0, 0.1); 3])
+    }
+}
+
+/// Sum of values with the absolute errors combined in quadrature
+fn quadrature(results: &[(f64, f64)]) -> (f64, f64) {
+    let total: f64 = results.iter().map(|(v, _)| v).sum();
+    let variance: f64 = results.iter().map(|(v, e)| (v * e).powi(2)).sum();
+    (total, variance.sqrt() / total)
+}
+
+fn assert_close(a: &[f64], b: &[f64]) {
+    assert_eq!(a.len(), b.len());
+    for (a, b) in a.iter().zip(b) {
+        assert!((a - b).abs() < 1e-12, "{a} != {b}");
+    }
+}
+
+#[rstest]
+#[case::degrees(vec![0.0, 120.0, 240.0, 360.0], vec![0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0])]
+#[case::partial_degrees(vec![0.0, 30.0, 60.0, 90.0], vec![0.0, 1.0 / 12.0, 1.0 / 6.0, 0.25])]
+#[case::revolutions(vec![0.0, 0.25, 0.5, 1.0], vec![0.0, 0.25, 0.5, 1.0])]
+#[case::ambiguous(vec![0.0, 0.2, 0.6, 1.0], vec![0.0, 0.2, 0.6, 1.0])]
+fn cylindrical_theta(#[case] corc: Vec<f64>, #[case] expected: Vec<f64>) {
+    let mesh = cylinder(corc).to_mesh().unwrap();
+    assert_eq!(mesh.geometry, ntools_mesh::Geometry::Cylindrical);
+    assert_close(&mesh.kmesh, &expected);
+}
+
+#[test]
+fn rectangular_bounds_unchanged() {
+    let tmesh = Tmesh {
+        geometry: Geometry::Rectangular,
+        ..cylinder(vec![0.0, 120.0, 240.0, 360.0])
+    };
+    let mesh = tmesh.to_mesh().unwrap();
+    assert_eq!(mesh.kmesh, vec![0.0, 120.0, 240.0, 360.0]);
+}
+
+#[test]
+fn synthesised_totals() {
+    // (e, t) ordered with time varying fastest, no totals for either
+    let results = [(1.0, 0.1), (2.0, 0.2), (3.0, 0.05), (4.0, 0.5)];
+    let mesh = tmesh(2, 2, &results)
+        .to_mesh_with_bins(&[0.0, 1.0, 20.0], &[0.0, 1e5, 1e10])
+        .unwrap();
+
+    assert_eq!(mesh.n_ebins(), 3);
+    assert_eq!(mesh.n_tbins(), 3);
+    assert_eq!(mesh.energy_groups().last(), Some(&Group::Total));
+    assert_eq!(mesh.time_groups().last(), Some(&Group::Total));
+
+    let voxel = |e, t| &mesh.voxels[mesh.voxel_index_from_etijk(e, t, 0, 0, 0)];
+    let assert_voxel = |e, t, (value, error): (f64, f64)| {
+        let v = voxel(e, t);
+        assert!((v.result - value).abs() < 1e-12, "{} != {value}", v.result);
+        assert!((v.error - error).abs() < 1e-12, "{} != {error}", v.error);
+    };
+
+    // results are taken as read
+    for e in 0..2 {
+        for t in 0..2 {
+            assert_voxel(e, t, results[e * 2 + t]);
+        }
+    }
+
+    // totals over energy, time, and both
+    assert_voxel(2, 0, quadrature(&[results[0], results[2]]));
+    assert_voxel(2, 1, quadrature(&[results[1], results[3]]));
+    assert_voxel(0, 2, quadrature(&[results[0], results[1]]));
+    assert_voxel(1, 2, quadrature(&[results[2], results[3]]));
+    assert_voxel(2, 2, quadrature(&results));
+}
+
+#[test]
+fn existing_total() {
+    // the third energy bin is the total from MCNP, and is not recalculated
+    let results = [(1.0, 0.1), (3.0, 0.05), (5.0, 0.01)];
+    let mesh = tmesh(3, 1, &results)
+        .to_mesh_with_bins(&[0.0, 1.0, 20.0], &[])
+        .unwrap();
+
+    assert_eq!(mesh.n_ebins(), 3);
+    assert_eq!(mesh.voxels[2].result, 5.0);
+    assert_eq!(mesh.voxels[2].error, 0.01);
+}
+
+#[test]
+fn zero_total() {
+    let mesh = tmesh(2, 1, &[(0.0, 0.0), (0.0, 0.0)])
+        .to_mesh_with_bins(&[0.0, 1.0, 20.0], &[])
+        .unwrap();
+
+    assert_eq!(mesh.voxels[2].result, 0.0);
+    assert_eq!(mesh.voxels[2].error, 0.0);
+}
+
+#[rstest]
+#[case::multiple_particles(
+    Tmesh { particles: vec![Particle::Neutron, Particle::Photon], ..tmesh(1, 1, &[(1.0, 0.1)]) },
+    vec![],
+    "multiple particles"
+)]
+#[case::spherical(
+    Tmesh { geometry: Geometry::Spherical, ..tmesh(1, 1, &[(1.0, 0.1)]) },
+    vec![],
+    "spherical"
+)]
+#[case::missing_bounds(tmesh(2, 1, &[(1.0, 0.1); 2]), vec![], "bounds must be provided")]
+#[case::mismatched_bounds(tmesh(2, 1, &[(1.0, 0.1); 2]), vec![0.0, 1.0, 2.0, 3.0, 4.0], "do not match")]
+fn rejected(#[case] tmesh: Tmesh, #[case] energies: Vec<f64>, #[case] reason: &str) {
+    let error = tmesh
+        .to_mesh_with_bins(&energies, &[])
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains(reason), "{error}");
+}
+
+#[test]
+fn unexpected_results() {
+    assert!(tmesh(1, 1, &[(1.0, 0.1); 2]).to_mesh().is_err());
+}
+
+#[test]
+fn particle_kept() {
+    let mesh = Tmesh {
+        particles: vec![Particle::Photon],
+        ..tmesh(1, 1, &[(1.0, 0.1)])
+    }
+    .to_mesh()
+    .unwrap();
+    assert_eq!(mesh.particle, ntools_mesh::Particle::Photon);
+
+    let mesh = Tmesh {
+        particles: Vec::new(),
+        ..tmesh(1, 1, &[(1.0, 0.1)])
+    }
+    .to_mesh()
+    .unwrap();
+    assert_eq!(mesh.particle, ntools_mesh::Particle::Unknown);
+}
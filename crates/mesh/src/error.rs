@@ -58,6 +58,9 @@ pub enum Error {
     /// Clearer parser errors with better context
     FailedParse { reason: String, context: String },
 
+    /// VTK data that can not be converted back into a [Mesh](crate::mesh::Mesh)
+    InvalidVtk { reason: String },
+
     /// Raw nom crate errors
     Nom(String),
 }
@@ -1,5 +1,8 @@
 use ntools_utils::ValueExt;
 
+// external crates
+use serde::{Deserialize, Serialize};
+
 /// Energy/Time groups are either `Total` or an upper bin edge
 ///
 /// For energies, the meshtal outputs always define `emesh` bounds so there is
@@ -22,7 +25,7 @@ use ntools_utils::ValueExt;
 /// | 0.0 1e16      | Total                             |
 /// | 0.0 1e16 1e36 | Value(1e16), Value(1e36), Total   |
 ///
-#[derive(Debug, PartialEq, Clone, Copy, PartialOrd)]
+#[derive(Debug, PartialEq, Clone, Copy, PartialOrd, Serialize, Deserialize)]
 pub enum Group {
     /// The 'Total' bin group
     Total,
@@ -3,8 +3,11 @@ use crate::error::{Error, Result};
 use crate::Group;
 use ntools_utils::{f, ValueExt};
 
+// external crates
+use serde::{Deserialize, Serialize};
+
 /// Variants for the type of [Point] coordinates
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub enum PointKind {
     /// Point (i, j, k) interpreted as indicies
     Index = 0,
@@ -77,7 +80,7 @@ impl std::fmt::Display for BoundaryTreatment {
 /// A [Point] represents a location somewhere in the mesh data. It must specify
 /// the time and energy groups, the (i,j,k) coordinates, and how these values
 /// should be interpreted.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     /// Energy [Group](crate::group::Group)
     pub e: Group,
@@ -1,5 +1,5 @@
 // internal modules
-use crate::vtk::MeshToVtk;
+use crate::vtk::{MeshToVtk, VtkOutput};
 
 // extrenal crates
 use log::warn;
@@ -55,6 +55,16 @@ pub struct MeshToVtkBuilder {
     compressor: Compressor,
     /// Cylindrical mesh resolution
     resolution: u8,
+    /// Cylindrical mesh resolution in the radial direction
+    radial_resolution: u8,
+    /// Cylindrical mesh resolution in the axial direction
+    axial_resolution: u8,
+    /// Maximum number of voxels in (i,j,k) for each block of partitioned output
+    block_size: Option<[usize; 3]>,
+    /// Name of the file the mesh was read from, recorded in the metadata
+    source_file: Option<String>,
+    /// Layout of the output data as cells, nodes, or voxel centres
+    output: VtkOutput,
 }
 
 impl MeshToVtkBuilder {
@@ -69,9 +79,14 @@ impl MeshToVtkBuilder {
             byte_order: self.byte_order,
             compressor: self.compressor,
             resolution: self.resolution,
+            radial_resolution: self.radial_resolution,
+            axial_resolution: self.axial_resolution,
             energy_groups: self.energy_groups,
             time_groups: self.time_groups,
             include_errors: self.include_errors,
+            block_size: self.block_size,
+            source_file: self.source_file,
+            output: self.output,
         }
     }
 
@@ -125,6 +140,39 @@ impl MeshToVtkBuilder {
         self
     }
 
+    /// Cylindrical mesh resolution in the radial direction
+    ///
+    /// Integer value for subdividing every radial bin of cylindrical meshes.
+    /// Warping or clipping large voxels in ParaView can show faceting and poor
+    /// interpolation, which is improved by splitting them into smaller cells.
+    ///
+    /// This is independent of the theta [resolution](Self::resolution), and
+    /// also multiplies the number of cells and verticies generated.
+    pub fn radial_resolution(mut self, resolution: u8) -> Self {
+        if resolution > 1 {
+            warn!(
+                "Warning: Increasing cylindrical mesh resolution may increase memory usage significantly"
+            );
+        }
+        self.radial_resolution = resolution;
+        self
+    }
+
+    /// Cylindrical mesh resolution in the axial direction
+    ///
+    /// Integer value for subdividing every axial bin of cylindrical meshes,
+    /// independent of the theta and radial resolutions. See
+    /// [radial_resolution()](Self::radial_resolution) for details.
+    pub fn axial_resolution(mut self, resolution: u8) -> Self {
+        if resolution > 1 {
+            warn!(
+                "Warning: Increasing cylindrical mesh resolution may increase memory usage significantly"
+            );
+        }
+        self.axial_resolution = resolution;
+        self
+    }
+
     /// Set the byte ordering
     ///
     /// Note that Visit being Visit only reads big endian, even though most
@@ -146,6 +194,50 @@ impl MeshToVtkBuilder {
         self.compressor = xml_compressor;
         self
     }
+
+    /// Maximum block size for partitioned output
+    ///
+    /// Only used by [write_partitioned()](MeshToVtk::write_partitioned), which
+    /// splits the mesh into spatial blocks of at most `[i, j, k]` voxels. Each
+    /// block is written to its own file, so peak memory scales with the block
+    /// size rather than the mesh size.
+    ///
+    /// By default the whole mesh is written as a single piece.
+    pub fn block_size(mut self, size: [usize; 3]) -> Self {
+        self.block_size = Some(size);
+        self
+    }
+
+    /// Name of the source file to record in the output metadata
+    ///
+    /// The [Mesh](crate::Mesh) does not know where it was read from, so this
+    /// is only included in the field data of the output when provided.
+    pub fn source_file(mut self, name: &str) -> Self {
+        self.source_file = Some(name.to_string());
+        self
+    }
+
+    /// Layout of the output data
+    ///
+    /// By default results are written as cell data. Point data is needed by
+    /// many ParaView filters, so the alternatives are:
+    ///
+    /// - [VtkOutput::Nodes] keeps the cell data and adds point data at every
+    ///   grid node, averaged from the adjacent voxels
+    /// - [VtkOutput::Centroids] writes the voxel centres as polydata vertices
+    ///   carrying every result/error array as point data
+    ///
+    /// ```rust
+    /// # use ntools_mesh::vtk::{MeshToVtk, VtkOutput};
+    /// // Write the voxel centres as a point cloud
+    /// let converter = MeshToVtk::builder()
+    ///     .output(VtkOutput::Centroids)
+    ///     .build();
+    /// ```
+    pub fn output(mut self, output: VtkOutput) -> Self {
+        self.output = output;
+        self
+    }
 }
 
 impl Default for MeshToVtkBuilder {
@@ -154,9 +246,14 @@ impl Default for MeshToVtkBuilder {
             byte_order: ByteOrder::BigEndian,
             compressor: Compressor::LZMA,
             resolution: 1,
+            radial_resolution: 1,
+            axial_resolution: 1,
             energy_groups: Vec::new(),
             time_groups: Vec::new(),
             include_errors: false,
+            block_size: None,
+            source_file: None,
+            output: VtkOutput::Cells,
         }
     }
 }
@@ -1,17 +1,18 @@
 // standard library
-use std::ops::RangeInclusive;
+use std::ops::{Range, RangeInclusive};
 
 // ntools modules
 use crate::{Geometry, Group, Mesh};
 use ntools_utils::f;
 
 // internal modules
-use crate::vtk::MeshToVtkBuilder;
-use crate::vtk::Vertex;
+use crate::vtk::cylinder;
+use crate::vtk::field::{field_attribute, number_array, text_array};
+use crate::vtk::partition::Block;
+use crate::vtk::{MeshToVtkBuilder, VtkOutput};
 
 // extrenal crates
 use log::warn;
-use nalgebra::{Rotation, Vector3};
 use vtkio::model::{
     Attribute, Attributes, ByteOrder, CellType, Cells, Coordinates, DataArray, DataSet,
     ElementType, Extent, IOBuffer, RangeExtent, RectilinearGridPiece, UnstructuredGridPiece,
@@ -135,6 +136,19 @@ use vtkio::xml::Compressor;
 /// Setting the `resolution` to 3 will subbdivide the theta bins into 3, thereby
 /// tripling the number of edges plotted from 8 to 24 for a more rounded look.
 ///
+/// Large radial or axial voxels can also look faceted when the mesh is warped
+/// or clipped, so the r and z bins may be subdivided independently.
+///
+/// ```rust
+/// # use ntools_mesh::vtk::{MeshToVtk};
+/// // Split every voxel into 2 in r, 4 in z, and 3 in theta
+/// let converter = MeshToVtk::builder()
+///     .radial_resolution(2)
+///     .axial_resolution(4)
+///     .resolution(3)
+///     .build();
+/// ```
+///
 /// Note that this can increase memory usage and file size significantly but is
 /// a nice feature for generating more accurate cylinders.  
 ///
@@ -152,6 +166,16 @@ pub struct MeshToVtk {
     pub compressor: Compressor,
     /// Cylindrical mesh resolution
     pub resolution: u8,
+    /// Cylindrical mesh resolution in the radial direction
+    pub radial_resolution: u8,
+    /// Cylindrical mesh resolution in the axial direction
+    pub axial_resolution: u8,
+    /// Maximum number of voxels in (i,j,k) for each block of partitioned output
+    pub block_size: Option<[usize; 3]>,
+    /// Name of the file the mesh was read from, recorded in the metadata
+    pub source_file: Option<String>,
+    /// Layout of the output data as cells, nodes, or voxel centres
+    pub output: VtkOutput,
 }
 
 // Public API
@@ -172,10 +196,7 @@ impl MeshToVtk {
     /// fields directly, convert any [Mesh] into a Vtk ready for writing or
     /// futher processing.
     pub fn convert(&self, mesh: &Mesh) -> Vtk {
-        match mesh.geometry {
-            Geometry::Rectangular => self.rectangular_vtk(mesh),
-            Geometry::Cylindrical => self.cylindrical_vtk(mesh),
-        }
+        self.convert_block(mesh, &Block::whole(mesh))
     }
 }
 
@@ -187,8 +208,25 @@ impl Default for MeshToVtk {
 
 /// Common use implementations
 impl MeshToVtk {
+    /// Convert a single block of the mesh to a vtkio::Vtk piece
+    pub(crate) fn convert_block(&self, mesh: &Mesh, block: &Block) -> Vtk {
+        match (self.output, mesh.geometry) {
+            (VtkOutput::Centroids, _) => self.centroid_piece(mesh, block),
+            (_, Geometry::Rectangular) => self.rectangular_piece(mesh, block),
+            (_, Geometry::Cylindrical) => self.cylindrical_piece(mesh, block),
+        }
+    }
+
+    /// Suffix of the error array names, which differs between geometries
+    pub(crate) fn error_suffix(mesh: &Mesh) -> &'static str {
+        match mesh.geometry {
+            Geometry::Rectangular => ", error",
+            Geometry::Cylindrical => "_error",
+        }
+    }
+
     /// Collect energy groups, and if none are given fallback to using all groups
-    fn collect_energy_group_idx(&self, mesh: &Mesh) -> Vec<usize> {
+    pub(crate) fn collect_energy_group_idx(&self, mesh: &Mesh) -> Vec<usize> {
         // none defined? convert everything
         if self.energy_groups.is_empty() {
             return (0..mesh.n_ebins()).collect::<Vec<usize>>();
@@ -215,7 +253,7 @@ impl MeshToVtk {
     }
 
     /// Collect time groups, and if none are given fallback to using all groups
-    fn collect_time_group_idx(&self, mesh: &Mesh) -> Vec<usize> {
+    pub(crate) fn collect_time_group_idx(&self, mesh: &Mesh) -> Vec<usize> {
         // none defined? convert everything
         if self.time_groups.is_empty() {
             return (0..mesh.n_tbins()).collect::<Vec<usize>>();
@@ -270,7 +308,7 @@ impl MeshToVtk {
     ///
     /// The rules are no whitespace, no brackets, basically nothing nice for
     /// formatting.
-    fn group_name_visit(&self, mesh: &Mesh, e_idx: usize, t_idx: usize) -> String {
+    pub(crate) fn group_name_visit(&self, mesh: &Mesh, e_idx: usize, t_idx: usize) -> String {
         let energy_prefix = f!("Energy-{e_idx}");
 
         let time_prefix = if mesh.n_tbins() > 1 {
@@ -281,46 +319,96 @@ impl MeshToVtk {
 
         energy_prefix + &time_prefix
     }
+
+    /// Collect mesh metadata into field data
+    ///
+    /// Data arrays are named `Energy-<e>` and `Time-<t>` by group index, so the
+    /// bounds and indicies are included to recover the groups of each array.
+    pub(crate) fn metadata(&self, mesh: &Mesh) -> Attribute {
+        let mut arrays = vec![
+            number_array("tally_id", 1, vec![mesh.id as f64]),
+            text_array("particle", &f!("{:?}", mesh.particle)),
+            text_array("geometry", &mesh.geometry.to_string()),
+            number_array("origin", 3, mesh.origin.to_vec()),
+            number_array("axs", 3, mesh.axs.to_vec()),
+            number_array("vec", 3, mesh.vec.to_vec()),
+            number_array("imesh", 1, mesh.imesh.clone()),
+            number_array("jmesh", 1, mesh.jmesh.clone()),
+            number_array("kmesh", 1, mesh.kmesh.clone()),
+            number_array("energy_bounds", 1, mesh.emesh.clone()),
+            number_array("time_bounds", 1, mesh.tmesh.clone()),
+            number_array(
+                "energy_groups",
+                1,
+                Self::as_f64(self.collect_energy_group_idx(mesh)),
+            ),
+            number_array(
+                "time_groups",
+                1,
+                Self::as_f64(self.collect_time_group_idx(mesh)),
+            ),
+            text_array("ntools_version", env!("CARGO_PKG_VERSION")),
+        ];
+
+        if let Some(source) = &self.source_file {
+            arrays.push(text_array("source_file", source));
+        }
+
+        field_attribute(arrays)
+    }
+
+    /// Indicies as floats for field data
+    fn as_f64(indicies: Vec<usize>) -> Vec<f64> {
+        indicies.into_iter().map(|i| i as f64).collect()
+    }
 }
 
 /// Implementations for proecessing Rectangular mesh types
 impl MeshToVtk {
-    /// Convert mesh voxel data to vtkio types for writing
-    fn rectangular_vtk(&self, mesh: &Mesh) -> Vtk {
+    /// Convert voxel data for a block of the mesh to vtkio types for writing
+    pub(crate) fn rectangular_piece(&self, mesh: &Mesh, block: &Block) -> Vtk {
         Vtk {
             version: Version::Auto,
             title: f!("Fmesh{} results", mesh.id),
             byte_order: self.byte_order,
             file_path: None,
             data: DataSet::inline(RectilinearGridPiece {
-                extent: Self::extent(mesh),
-                coords: Self::coordinates(mesh),
-                data: self.collect_attributes(mesh),
+                extent: Self::extent(block),
+                coords: Self::coordinates(mesh, block),
+                data: self.collect_attributes(mesh, block),
             }),
         }
     }
 
     /// Defines number of mesh voxels in each extent for the rectilinear grid
-    fn extent(mesh: &Mesh) -> Extent {
+    pub(crate) fn extent(block: &Block) -> Extent {
         let range_ext: RangeExtent = [
-            RangeInclusive::new(0, mesh.iints as i32),
-            RangeInclusive::new(0, mesh.jints as i32),
-            RangeInclusive::new(0, mesh.kints as i32),
+            RangeInclusive::new(block.i.start as i32, block.i.end as i32),
+            RangeInclusive::new(block.j.start as i32, block.j.end as i32),
+            RangeInclusive::new(block.k.start as i32, block.k.end as i32),
         ];
         Extent::Ranges(range_ext)
     }
 
     /// Defines coordiantes for rectilinear grid from mesh bounds
-    fn coordinates(mesh: &Mesh) -> Coordinates {
+    fn coordinates(mesh: &Mesh, block: &Block) -> Coordinates {
         Coordinates {
-            x: IOBuffer::F64(mesh.imesh.clone()),
-            y: IOBuffer::F64(mesh.jmesh.clone()),
-            z: IOBuffer::F64(mesh.kmesh.clone()),
+            x: IOBuffer::F64(Self::bounds(&mesh.imesh, &block.i)),
+            y: IOBuffer::F64(Self::bounds(&mesh.jmesh, &block.j)),
+            z: IOBuffer::F64(Self::bounds(&mesh.kmesh, &block.k)),
         }
     }
 
+    /// Mesh bounds enclosing a range of voxels, empty for an empty mesh
+    fn bounds(mesh_bounds: &[f64], range: &Range<usize>) -> Vec<f64> {
+        mesh_bounds
+            .get(range.start..=range.end)
+            .unwrap_or_default()
+            .to_vec()
+    }
+
     /// Collect rectilinear cell results into appropriate order/format
-    fn collect_attributes(&self, mesh: &Mesh) -> Attributes {
+    fn collect_attributes(&self, mesh: &Mesh, block: &Block) -> Attributes {
         let mut attributes: Attributes = Attributes::new();
 
         let energy_groups = self.collect_energy_group_idx(mesh);
@@ -328,13 +416,13 @@ impl MeshToVtk {
 
         for e_idx in &energy_groups {
             for t_idx in &time_groups {
-                let voxels = mesh.voxels_by_group_index(*e_idx, *t_idx).unwrap();
-
-                let (results, errors): (Vec<f64>, Vec<f64>) = voxels
-                    .iter()
-                    .map(|v| (v.result, v.error))
-                    .collect::<Vec<(f64, f64)>>()
-                    .into_iter()
+                // rectilinear grids are in cell index order, i.e. i varies fastest
+                let (results, errors): (Vec<f64>, Vec<f64>) = block
+                    .cell_order()
+                    .map(|(i, j, k)| {
+                        let v = mesh.voxels[mesh.voxel_index_from_etijk(*e_idx, *t_idx, i, j, k)];
+                        (v.result, v.error)
+                    })
                     .unzip();
 
                 let cell_data = DataArray {
@@ -343,7 +431,7 @@ impl MeshToVtk {
                         num_comp: 1,
                         lookup_table: None,
                     },
-                    data: IOBuffer::F64(Self::sort_by_cell_index(mesh, results)),
+                    data: IOBuffer::F64(results),
                 };
                 attributes.cell.push(Attribute::DataArray(cell_data));
 
@@ -355,35 +443,28 @@ impl MeshToVtk {
                             num_comp: 1,
                             lookup_table: None,
                         },
-                        data: IOBuffer::F64(Self::sort_by_cell_index(mesh, errors)),
+                        data: IOBuffer::F64(errors),
                     };
                     attributes.cell.push(Attribute::DataArray(cell_data));
                 }
             }
         }
 
-        attributes
-    }
-
-    /// Sort a list of results for the rectilinear grid cell ordering
-    fn sort_by_cell_index(mesh: &Mesh, values: Vec<f64>) -> Vec<f64> {
-        let idx = (0..values.len())
-            .map(|i| mesh.cell_index_from_voxel_index(i))
-            .collect::<Vec<usize>>();
-
-        let mut result = idx.iter().zip(values.iter()).collect::<Vec<_>>();
+        if self.output == VtkOutput::Nodes {
+            attributes.point = self.rectangular_node_data(mesh, block);
+        }
 
-        result.sort_by(|a, b| a.0.cmp(b.0));
-        result.into_iter().map(|r| *r.1).collect()
+        attributes.cell.push(self.metadata(mesh));
+        attributes
     }
 }
 
 /// Implementations for proecessing Cylindrical mesh types
 impl MeshToVtk {
-    /// Convert mesh voxel data to vtkio types for writing
-    fn cylindrical_vtk(&self, mesh: &Mesh) -> Vtk {
+    /// Convert voxel data for a block of the mesh to vtkio types for writing
+    pub(crate) fn cylindrical_piece(&self, mesh: &Mesh, block: &Block) -> Vtk {
         // generate cell verticies from mesh bounds
-        let (points, offset, cell_types) = self.cell_verticies(mesh);
+        let (points, offset, cell_types) = self.cell_verticies(mesh, block);
         let connect = (0..*offset.last().unwrap()).collect::<Vec<u64>>();
 
         Vtk {
@@ -400,198 +481,47 @@ impl MeshToVtk {
                     },
                     types: cell_types,
                 },
-                data: self.collect_cyl_attributes(mesh),
+                data: self.collect_cyl_attributes(mesh, block),
             }),
         }
     }
 
     /// Cylinders need to be built explicitly from vertex points
-    fn cell_verticies(&self, mesh: &Mesh) -> (Vec<f64>, Vec<u64>, Vec<CellType>) {
-        let mut points: Vec<f64> = Vec::new();
-        let mut offsets: Vec<u64> = Vec::new();
-        let mut cell_types: Vec<CellType> = Vec::new();
-        let rotation_axs = Self::init_rotation(&mesh.axs);
-        let rotation_vec = mesh.vec[1].atan2(mesh.vec[0]);
-
-        // first inner segments always CellType::Wedge
-        for layer in 0..mesh.jints {
-            self.wedge_segments(
-                mesh,
-                layer,
-                &mut points,
-                &mut offsets,
-                &mut cell_types,
-                &rotation_axs,
-                rotation_vec,
-            );
-        }
-
-        // any additional ring segments use CellType::Voxel
-        if mesh.iints > 1 {
-            // start from 1, the first ring is already made from CellType::Wedge
-            for ring in 1..mesh.iints {
-                for layer in 0..mesh.jints {
-                    self.voxel_segments(
-                        mesh,
-                        ring,
-                        layer,
-                        &mut points,
-                        &mut offsets,
-                        &mut cell_types,
-                        &rotation_axs,
-                        rotation_vec,
-                    );
-                }
-            }
-        }
-
-        (points, offsets, cell_types)
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    /// For the central voxels where r=0
-    fn wedge_segments(
-        &self,
-        mesh: &Mesh,
-        layer: usize,
-        points: &mut Vec<f64>,
-        offsets: &mut Vec<u64>,
-        cell_types: &mut Vec<CellType>,
-        rotation_axs: &Option<Rotation<f64, 3>>,
-        rotation_vec: f64,
-    ) {
-        let mut step = 2.0 * std::f64::consts::PI / (mesh.kints as f64);
-        step /= self.get_resolution(&mesh.kints) as f64;
-        let r = mesh.imesh[1];
-
-        // wedge type has 6 verticies
-        // only need to find three and then repeat for the lower layer
-        for i in 0..(mesh.kints * self.get_resolution(&mesh.kints) as usize) {
-            let t0 = step * (i as f64) + rotation_vec;
-            let t1 = step * (i as f64 + 1.0) + rotation_vec;
-
-            let x0 = r * t0.cos();
-            let y0 = r * t0.sin();
-
-            let x1 = r * t1.cos();
-            let y1 = r * t1.sin();
-
-            for idx in layer..=(layer + 1) {
-                let z = mesh.jmesh[idx];
-                points.extend(
-                    Vertex { x: 0.0, y: 0.0, z }
-                        .rotate(rotation_axs)
-                        .translate(&mesh.origin)
-                        .as_array(),
-                );
-                points.extend(
-                    Vertex { x: x0, y: y0, z }
-                        .rotate(rotation_axs)
-                        .translate(&mesh.origin)
-                        .as_array(),
-                );
-                points.extend(
-                    Vertex { x: x1, y: y1, z }
-                        .rotate(rotation_axs)
-                        .translate(&mesh.origin)
-                        .as_array(),
-                );
-            }
-
-            Self::update_offsets(offsets, 6);
-            cell_types.push(CellType::Wedge);
-        }
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    /// For anything beyond the first inside ring
-    fn voxel_segments(
-        &self,
-        mesh: &Mesh,
-        ring: usize,
-        layer: usize,
-        points: &mut Vec<f64>,
-        offsets: &mut Vec<u64>,
-        cell_types: &mut Vec<CellType>,
-        rotation_axs: &Option<Rotation<f64, 3>>,
-        rotation_vec: f64,
-    ) {
-        let mut step = 2.0 * std::f64::consts::PI / (mesh.kints as f64);
-        step /= self.get_resolution(&mesh.kints) as f64;
-
-        let r0 = mesh.imesh[ring - 1];
-        let r1 = mesh.imesh[ring];
-
-        // voxel type has 8 verticies
-        // only need to find 4 and then repeat at lower layer
-        for i in 0..(mesh.kints * self.get_resolution(&mesh.kints) as usize) {
-            let t0 = step * (i as f64) + rotation_vec;
-            let t1 = step * (i as f64 + 1.0) + rotation_vec;
-
-            let x00: f64 = r0 * t0.cos();
-            let y00: f64 = r0 * t0.sin();
-
-            let x01: f64 = r0 * t1.cos();
-            let y01: f64 = r0 * t1.sin();
-
-            let x10: f64 = r1 * t0.cos();
-            let y10: f64 = r1 * t0.sin();
-
-            let x11: f64 = r1 * t1.cos();
-            let y11: f64 = r1 * t1.sin();
-
-            for idx in layer..=(layer + 1) {
-                let z = mesh.jmesh[idx];
-
-                points.extend(
-                    Vertex { x: x00, y: y00, z }
-                        .rotate(rotation_axs)
-                        .translate(&mesh.origin)
-                        .as_array(),
-                );
-                points.extend(
-                    Vertex { x: x01, y: y01, z }
-                        .rotate(rotation_axs)
-                        .translate(&mesh.origin)
-                        .as_array(),
-                );
-                points.extend(
-                    Vertex { x: x10, y: y10, z }
-                        .rotate(rotation_axs)
-                        .translate(&mesh.origin)
-                        .as_array(),
-                );
-                points.extend(
-                    Vertex { x: x11, y: y11, z }
-                        .rotate(rotation_axs)
-                        .translate(&mesh.origin)
-                        .as_array(),
-                );
-            }
-
-            Self::update_offsets(offsets, 8);
-            cell_types.push(CellType::Voxel);
-        }
+    fn cell_verticies(&self, mesh: &Mesh, block: &Block) -> (Vec<f64>, Vec<u64>, Vec<CellType>) {
+        let transform = cylinder::Transform::new(mesh.origin, mesh.axs, mesh.vec);
+        let step = 2.0 * std::f64::consts::PI / (mesh.kints as f64);
+
+        cylinder::verticies(block.voxel_order(), self.subdivisions(mesh), |[r, z, t]| {
+            // the inner vertices of wedges are always on the axis
+            let radius = match r == 0.0 {
+                true => 0.0,
+                false => cylinder::lerp(r, |n| mesh.imesh[n]),
+            };
+            transform.point(radius, cylinder::lerp(z, |n| mesh.jmesh[n]), step * t)
+        })
     }
 
     /// Bring all of the cell data together
-    fn collect_cyl_attributes(&self, mesh: &Mesh) -> Attributes {
+    fn collect_cyl_attributes(&self, mesh: &Mesh, block: &Block) -> Attributes {
         let mut attributes: Attributes = Attributes::new();
         let energy_groups = self.collect_energy_group_idx(mesh);
         let time_groups = self.collect_time_group_idx(mesh);
 
+        // number of unstructured cells generated for each voxel
+        let n_cells = self.subdivisions(mesh).iter().product();
+
         for e_idx in &energy_groups {
             for t_idx in &time_groups {
-                let voxels = mesh.voxels_by_group_index(*e_idx, *t_idx).unwrap();
-
-                let (mut results, mut errors): (Vec<f64>, Vec<f64>) = voxels
-                    .iter()
-                    .map(|v| (v.result, v.error))
-                    .collect::<Vec<(f64, f64)>>()
-                    .into_iter()
+                // unstructured cells are built in voxel index order, i.e. k varies fastest
+                let (mut results, mut errors): (Vec<f64>, Vec<f64>) = block
+                    .voxel_order()
+                    .map(|(i, j, k)| {
+                        let v = mesh.voxels[mesh.voxel_index_from_etijk(*e_idx, *t_idx, i, j, k)];
+                        (v.result, v.error)
+                    })
                     .unzip();
 
-                results = Self::repeat_values(results, self.get_resolution(&mesh.kints));
+                results = Self::repeat_values(results, n_cells);
 
                 let cell_data = DataArray {
                     name: self.group_name_visit(mesh, *e_idx, *t_idx),
@@ -605,7 +535,7 @@ impl MeshToVtk {
 
                 // do the same for the errors if they are to be included
                 if self.include_errors {
-                    errors = Self::repeat_values(errors, self.get_resolution(&mesh.kints));
+                    errors = Self::repeat_values(errors, n_cells);
 
                     let cell_data = DataArray {
                         name: self.group_name_visit(mesh, *e_idx, *t_idx) + "_error",
@@ -620,54 +550,31 @@ impl MeshToVtk {
             }
         }
 
+        if self.output == VtkOutput::Nodes {
+            attributes.point = self.cylindrical_node_data(mesh, block);
+        }
+
+        attributes.cell.push(self.metadata(mesh));
         attributes
     }
 
     /// Repeat whatever set of values is in a vector
-    fn repeat_values(values: Vec<f64>, repeat: u8) -> Vec<f64> {
+    fn repeat_values(values: Vec<f64>, repeat: usize) -> Vec<f64> {
         values
             .into_iter()
-            .flat_map(|n| std::iter::repeat(n).take(repeat.into()))
+            .flat_map(|n| std::iter::repeat(n).take(repeat))
             .collect()
     }
 
-    /// Used in calculation of verticies
-    fn update_offsets(offsets: &mut Vec<u64>, size: usize) {
-        let offset = match offsets.is_empty() {
-            true => size.try_into().unwrap(),
-            false => (size + (*offsets.last().unwrap() as usize))
-                .try_into()
-                .unwrap(),
-        };
-        offsets.push(offset);
-    }
-
-    /// Fix the resolution issue in the background for 1-2 theta bins
-    ///
-    /// For performance the converter would have to be mutable, or the user
-    /// would have to know to set the resolution for a couple of special cases.
-    /// This is just easier for everyone.
-    fn get_resolution(&self, n_bins: &usize) -> u8 {
-        match n_bins {
-            // only one theta bin, minimim verticies needed will be 3
-            1 => self.resolution.max(3),
-            // only two theta bins, minimim verticies needed will be 4
-            2 => self.resolution.max(2),
-            // anything else is fine
-            _ => self.resolution,
-        }
-    }
-
-    /// Initialise the rotation matrix from AXS if required
-    fn init_rotation(axis: &[f64]) -> Option<Rotation<f64, 3>> {
-        let axs_default = [0.0, 0.0, 1.0];
-
-        if axs_default == *axis {
-            None
-        } else {
-            let axs_default = Vector3::from(axs_default);
-            let axs_user = Vector3::from([axis[0], axis[1], axis[2]]);
-            Some(Rotation::face_towards(&axs_user, &axs_default))
-        }
+    /// Number of subdivisions of every cylindrical voxel in `[r, z, theta]`
+    pub(crate) fn subdivisions(&self, mesh: &Mesh) -> [usize; 3] {
+        cylinder::subdivisions(
+            [
+                self.radial_resolution,
+                self.axial_resolution,
+                self.resolution,
+            ],
+            mesh.kints,
+        )
     }
 }
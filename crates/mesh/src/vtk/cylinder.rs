@@ -0,0 +1,181 @@
+//! Unstructured cells for cylindrical geometries
+//!
+//! There is no VTK representation of a cylindrical mesh, so every voxel is
+//! built explicitly from wedge and voxel cells. These helpers are shared by
+//! every ntools crate that converts cylindrical meshes to VTK, and work on
+//! fractional voxel indicies so that each crate may map them onto its own
+//! bounds.
+
+// internal modules
+use crate::vtk::Vertex;
+
+// extrenal crates
+use nalgebra::{Rotation, Vector3};
+use vtkio::model::CellType;
+
+/// Cartesian points from local `(r, z, theta)` cylindrical coordinates
+///
+/// The local coordinates are relative to the origin, with z along AXS and
+/// theta measured in radians from VEC.
+pub struct Transform {
+    rotation: Option<Rotation<f64, 3>>,
+    azimuth: f64,
+    origin: [f64; 3],
+}
+
+impl Transform {
+    /// Set up the transform from the ORIGIN, AXS, and VEC of a mesh
+    pub fn new(origin: [f64; 3], axs: [f64; 3], vec: [f64; 3]) -> Self {
+        Self {
+            rotation: init_rotation(&axs),
+            azimuth: vec[1].atan2(vec[0]),
+            origin,
+        }
+    }
+
+    /// Cartesian point for a radius, axial position, and angle in radians
+    pub fn point(&self, radius: f64, z: f64, theta: f64) -> [f64; 3] {
+        let theta = theta + self.azimuth;
+        Vertex {
+            x: radius * theta.cos(),
+            y: radius * theta.sin(),
+            z,
+        }
+        .rotate(&self.rotation)
+        .translate(&self.origin)
+        .as_array()
+    }
+}
+
+/// Visit every unstructured cell of a set of voxels with its vertex positions
+///
+/// Each voxel is split into `[r, z, theta]` subdivisions, and every cell is
+/// visited with the positions of its verticies as fractional voxel indicies.
+/// Voxels are visited in the order given with all of their cells contiguous,
+/// so cell data is just each voxel value repeated.
+///
+/// Cells touching the axis are `CellType::Wedge` (6 verticies), and any
+/// others are `CellType::Voxel` (8 verticies).
+pub fn cells<F>(
+    voxels: impl IntoIterator<Item = (usize, usize, usize)>,
+    subdivisions: [usize; 3],
+    mut visit: F,
+) where
+    F: FnMut(CellType, &[[f64; 3]]),
+{
+    let [nr, nz, nt] = subdivisions;
+    let position = |idx: usize, sub: usize, n: usize| idx as f64 + sub as f64 / n as f64;
+
+    for (ring, layer, theta) in voxels {
+        for sr in 0..nr {
+            let (r0, r1) = (position(ring, sr, nr), position(ring, sr + 1, nr));
+            for sz in 0..nz {
+                let (z0, z1) = (position(layer, sz, nz), position(layer, sz + 1, nz));
+                for st in 0..nt {
+                    let (t0, t1) = (position(theta, st, nt), position(theta, st + 1, nt));
+
+                    if r0 == 0.0 {
+                        visit(
+                            CellType::Wedge,
+                            &[
+                                [0.0, z0, t0],
+                                [r1, z0, t0],
+                                [r1, z0, t1],
+                                [0.0, z1, t0],
+                                [r1, z1, t0],
+                                [r1, z1, t1],
+                            ],
+                        );
+                    } else {
+                        visit(
+                            CellType::Voxel,
+                            &[
+                                [r0, z0, t0],
+                                [r0, z0, t1],
+                                [r1, z0, t0],
+                                [r1, z0, t1],
+                                [r0, z1, t0],
+                                [r0, z1, t1],
+                                [r1, z1, t0],
+                                [r1, z1, t1],
+                            ],
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Points, offsets, and cell types of the unstructured cells for a set of voxels
+///
+/// The `point` closure maps the fractional `[r, z, theta]` voxel index of
+/// every vertex to its cartesian coordinates. See [cells()] for the ordering.
+pub fn verticies(
+    voxels: impl IntoIterator<Item = (usize, usize, usize)>,
+    subdivisions: [usize; 3],
+    mut point: impl FnMut([f64; 3]) -> [f64; 3],
+) -> (Vec<f64>, Vec<u64>, Vec<CellType>) {
+    let mut points: Vec<f64> = Vec::new();
+    let mut offsets: Vec<u64> = Vec::new();
+    let mut cell_types: Vec<CellType> = Vec::new();
+
+    cells(voxels, subdivisions, |cell_type, nodes| {
+        for node in nodes {
+            points.extend(point(*node));
+        }
+
+        let last = offsets.last().copied().unwrap_or(0);
+        offsets.push(last + nodes.len() as u64);
+        cell_types.push(cell_type);
+    });
+
+    (points, offsets, cell_types)
+}
+
+/// Number of subdivisions of every voxel in `[r, z, theta]`
+///
+/// Resolutions are given as `[radial, axial, theta]`. Meshes with only 1 or 2
+/// theta bins are subdivided further in the background, because a cylinder
+/// needs at least 3 or 4 edges to look like one.
+pub fn subdivisions(resolution: [u8; 3], n_theta: usize) -> [usize; 3] {
+    let [radial, axial, theta] = resolution;
+    let theta = match n_theta {
+        // only one theta bin, minimim verticies needed will be 3
+        1 => theta.max(3),
+        // only two theta bins, minimim verticies needed will be 4
+        2 => theta.max(2),
+        // anything else is fine
+        _ => theta,
+    };
+
+    [
+        radial.max(1) as usize,
+        axial.max(1) as usize,
+        theta.max(1) as usize,
+    ]
+}
+
+/// Linear interpolation between integer nodes for a fractional position
+pub fn lerp(position: f64, node: impl Fn(usize) -> f64) -> f64 {
+    let lower = position.floor() as usize;
+    let fraction = position - lower as f64;
+
+    match fraction == 0.0 {
+        true => node(lower),
+        false => (1.0 - fraction) * node(lower) + fraction * node(lower + 1),
+    }
+}
+
+/// Initialise the rotation matrix from AXS if required
+pub fn init_rotation(axis: &[f64]) -> Option<Rotation<f64, 3>> {
+    let axs_default = [0.0, 0.0, 1.0];
+
+    if axs_default == *axis {
+        None
+    } else {
+        let axs_default = Vector3::from(axs_default);
+        let axs_user = Vector3::from([axis[0], axis[1], axis[2]]);
+        Some(Rotation::face_towards(&axs_user, &axs_default))
+    }
+}
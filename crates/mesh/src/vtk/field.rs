@@ -0,0 +1,703 @@
+//! Dataset level field data for ntools VTK files
+//!
+//! vtkio has no support for dataset level field data, so metadata is attached
+//! as a field attribute during conversion and inserted into the file by a
+//! [FieldWriter] as it is written. These helpers are shared by every ntools
+//! crate that writes or reads VTK files with metadata.
+
+// standard library
+use std::io::{self, Write};
+use std::path::Path;
+
+// ntools modules
+use ntools_utils::f;
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::vtk::VtkFormat;
+
+// extrenal crates
+use vtkio::model::{Attribute, Attributes, ByteOrder, DataSet, FieldArray, IOBuffer, Piece, Vtk};
+
+/// Name of the field data attribute used for metadata
+pub const FIELD_NAME: &str = "FieldData";
+
+/// Name of the field array recording the byte order of legacy binary files
+///
+/// Legacy files have no byte order in the header, and VTK assumes big endian.
+/// This is written alongside the metadata so that little endian files written
+/// by ntools can still be read back.
+pub const BYTE_ORDER: &str = "byte_order";
+
+/// Field array for a text value
+///
+/// Text is stored as bytes, written as a `String` array for XML formats and an
+/// `unsigned_char` array for legacy formats.
+pub fn text_array(name: &str, value: &str) -> FieldArray {
+    FieldArray {
+        name: name.to_string(),
+        elem: value.len() as u32,
+        data: IOBuffer::U8(value.as_bytes().to_vec()),
+    }
+}
+
+/// Field array for a set of numbers with `num_comp` components per tuple
+pub fn number_array(name: &str, num_comp: u32, values: Vec<f64>) -> FieldArray {
+    FieldArray {
+        name: name.to_string(),
+        elem: num_comp,
+        data: IOBuffer::F64(values),
+    }
+}
+
+/// Bundle field arrays into a field data attribute, skipping empty arrays
+pub fn field_attribute(arrays: Vec<FieldArray>) -> Attribute {
+    Attribute::Field {
+        name: FIELD_NAME.to_string(),
+        data_array: arrays
+            .into_iter()
+            .filter(|a| a.elem > 0 && !a.data.is_empty())
+            .collect(),
+    }
+}
+
+/// Remove all field data attributes from a vtk
+///
+/// vtkio ignores field data for XML formats, and legacy formats would attach
+/// it to the cell data. These are therefore taken out and written separately
+/// as dataset level field data by a [FieldWriter].
+pub fn take_field_data(vtk: &mut Vtk) -> Vec<FieldArray> {
+    let mut fields = Vec::new();
+    for attributes in attributes_mut(vtk) {
+        take_from(&mut attributes.cell, &mut fields);
+        take_from(&mut attributes.point, &mut fields);
+    }
+    fields
+}
+
+/// Encode whitespace in array names for legacy formats
+///
+/// Legacy names are whitespace delimited, so VTK expects spaces as `%20` and
+/// decodes them when reading.
+pub fn encode_legacy_names(vtk: &mut Vtk) {
+    for attributes in attributes_mut(vtk) {
+        for attribute in attributes
+            .cell
+            .iter_mut()
+            .chain(attributes.point.iter_mut())
+        {
+            if let Attribute::DataArray(array) = attribute {
+                array.name = array.name.replace(' ', "%20");
+            }
+        }
+    }
+}
+
+/// Attributes of every inline piece
+fn attributes_mut(vtk: &mut Vtk) -> Vec<&mut Attributes> {
+    fn inline<P>(
+        pieces: &mut [Piece<P>],
+        data: fn(&mut P) -> &mut Attributes,
+    ) -> Vec<&mut Attributes> {
+        pieces
+            .iter_mut()
+            .filter_map(|piece| match piece {
+                Piece::Inline(p) => Some(data(p)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    match &mut vtk.data {
+        DataSet::RectilinearGrid { pieces, .. } => inline(pieces, |p| &mut p.data),
+        DataSet::UnstructuredGrid { pieces, .. } => inline(pieces, |p| &mut p.data),
+        DataSet::PolyData { pieces, .. } => inline(pieces, |p| &mut p.data),
+        _ => Vec::new(),
+    }
+}
+
+/// Split field attributes out of a list of attributes
+fn take_from(attributes: &mut Vec<Attribute>, fields: &mut Vec<FieldArray>) {
+    let (field_data, other): (Vec<Attribute>, Vec<Attribute>) = std::mem::take(attributes)
+        .into_iter()
+        .partition(|a| matches!(a, Attribute::Field { .. }));
+
+    *attributes = other;
+    for attribute in field_data {
+        if let Attribute::Field { data_array, .. } = attribute {
+            fields.extend(data_array);
+        }
+    }
+}
+
+/// Writer adding dataset level field data to a vtk file as it is written
+///
+/// Output is only held back until the end of the file header, where the field
+/// data is inserted. Everything after this is passed straight through, so the
+/// bulk of the file is never searched or kept in memory.
+///
+/// For XML formats the field data is the first child of the dataset element,
+/// and for legacy formats it directly follows the `DATASET` line, before any
+/// coordinates or binary data.
+pub struct FieldWriter<W: Write> {
+    inner: W,
+    format: VtkFormat,
+    block: Option<Vec<u8>>,
+    header: Vec<u8>,
+}
+
+impl<W: Write> FieldWriter<W> {
+    /// Wrap a writer, ready to insert the fields for the given format
+    pub fn new(inner: W, fields: &[FieldArray], format: VtkFormat, byte_order: ByteOrder) -> Self {
+        let block = match format {
+            VtkFormat::Xml => xml_field_data(fields).into_bytes(),
+            VtkFormat::LegacyBinary => {
+                let mut fields = fields.to_vec();
+                fields.push(text_array(BYTE_ORDER, byte_order_name(byte_order)));
+                legacy_field_data(&fields, Some(byte_order))
+            }
+            VtkFormat::LegacyAscii => legacy_field_data(fields, None),
+        };
+
+        Self {
+            inner,
+            format,
+            block: (!fields.is_empty()).then_some(block),
+            header: Vec::new(),
+        }
+    }
+
+    /// Write anything still held back and flush the inner writer
+    ///
+    /// If the end of the header was never found the field data is appended.
+    pub fn finish(mut self) -> Result<W> {
+        if let Some(block) = self.block.take() {
+            self.inner.write_all(&self.header)?;
+            self.inner.write_all(&block)?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Position in the header where the field data is inserted, once known
+    fn header_end(&self) -> Option<usize> {
+        match self.format {
+            VtkFormat::Xml => find(&self.header, b"<Piece"),
+            _ => legacy_header_end(&self.header),
+        }
+    }
+}
+
+impl<W: Write> Write for FieldWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(block) = &self.block else {
+            return self.inner.write(buf);
+        };
+
+        self.header.extend_from_slice(buf);
+        if let Some(idx) = self.header_end() {
+            self.inner.write_all(&self.header[..idx])?;
+            self.inner.write_all(block)?;
+            self.inner.write_all(&self.header[idx..])?;
+            self.header = Vec::new();
+            self.block = None;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Position after the `DATASET` line of a legacy header
+///
+/// The version and title lines are skipped, in case the title itself starts
+/// with "DATASET".
+fn legacy_header_end(header: &[u8]) -> Option<usize> {
+    let (title_end, _) = header
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| **c == b'\n')
+        .nth(1)?;
+    let dataset = title_end + find(&header[title_end..], b"\nDATASET ")? + 1;
+    let end = dataset + header[dataset..].iter().position(|c| *c == b'\n')?;
+    Some(end + 1)
+}
+
+/// Text used for the byte order field array
+fn byte_order_name(byte_order: ByteOrder) -> &'static str {
+    match byte_order {
+        ByteOrder::BigEndian => "BigEndian",
+        ByteOrder::LittleEndian => "LittleEndian",
+    }
+}
+
+/// Position of the first occurrence of `marker`
+fn find(content: &[u8], marker: &[u8]) -> Option<usize> {
+    content
+        .windows(marker.len())
+        .position(|window| window == marker)
+}
+
+/// Generate the `<FieldData>` element for XML formats
+fn xml_field_data(fields: &[FieldArray]) -> String {
+    let mut s = String::from("<FieldData>");
+    for field in fields {
+        match &field.data {
+            IOBuffer::U8(text) => {
+                // ascii string arrays are character codes, null terminated
+                let codes = text
+                    .iter()
+                    .map(|c| c.to_string())
+                    .chain(std::iter::once("0".to_string()))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                s += &f!(
+                    "<DataArray type=\"String\" Name=\"{}\" NumberOfTuples=\"1\" format=\"ascii\">{codes}</DataArray>",
+                    field.name
+                );
+            }
+            IOBuffer::F64(values) => {
+                let numbers = ascii_values(values);
+                s += &f!(
+                    "<DataArray type=\"Float64\" Name=\"{}\" NumberOfComponents=\"{}\" NumberOfTuples=\"{}\" format=\"ascii\">{numbers}</DataArray>",
+                    field.name,
+                    field.elem,
+                    values.len() / field.elem as usize
+                );
+            }
+            _ => (),
+        }
+    }
+    s + "</FieldData>"
+}
+
+/// Generate the `FIELD` section for legacy formats
+///
+/// Binary data is written with the requested byte order to match the rest of
+/// the file, and ascii otherwise.
+fn legacy_field_data(fields: &[FieldArray], binary: Option<ByteOrder>) -> Vec<u8> {
+    let fields = fields
+        .iter()
+        .filter(|field| matches!(field.data, IOBuffer::U8(_) | IOBuffer::F64(_)))
+        .collect::<Vec<&FieldArray>>();
+
+    let mut block = f!("FIELD {FIELD_NAME} {}\n", fields.len()).into_bytes();
+    for field in fields {
+        let (type_name, n) = match &field.data {
+            IOBuffer::U8(v) => ("unsigned_char", v.len()),
+            IOBuffer::F64(v) => ("double", v.len()),
+            _ => unreachable!(),
+        };
+
+        block.extend(
+            f!(
+                "{} {} {} {type_name}\n",
+                field.name,
+                field.elem,
+                n / field.elem as usize
+            )
+            .bytes(),
+        );
+
+        match (&field.data, binary) {
+            (IOBuffer::U8(v), Some(_)) => block.extend(v),
+            (IOBuffer::F64(v), Some(ByteOrder::BigEndian)) => {
+                v.iter().for_each(|x| block.extend(x.to_be_bytes()))
+            }
+            (IOBuffer::F64(v), Some(ByteOrder::LittleEndian)) => {
+                v.iter().for_each(|x| block.extend(x.to_le_bytes()))
+            }
+            (IOBuffer::U8(v), None) => block.extend(ascii_values(v).bytes()),
+            (IOBuffer::F64(v), None) => block.extend(ascii_values(v).bytes()),
+            _ => (),
+        }
+        block.push(b'\n');
+    }
+    block
+}
+
+/// Space separated values for ascii output
+///
+/// Debug formatting keeps very large and small floats in scientific notation.
+fn ascii_values<T: std::fmt::Debug>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|v| f!("{v:?}"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Field data arrays with lookup by name
+pub struct FieldData(Vec<FieldArray>);
+
+impl FieldData {
+    /// Collect every field data attribute in a vtk
+    pub fn from_vtk(vtk: &Vtk) -> Self {
+        let attributes = match &vtk.data {
+            DataSet::RectilinearGrid { pieces, .. } => Self::inline(pieces, |p| &p.data),
+            DataSet::UnstructuredGrid { pieces, .. } => Self::inline(pieces, |p| &p.data),
+            DataSet::PolyData { pieces, .. } => Self::inline(pieces, |p| &p.data),
+            _ => Vec::new(),
+        };
+
+        Self(
+            attributes
+                .into_iter()
+                .flat_map(|a| a.cell.iter().chain(a.point.iter()))
+                .filter_map(|a| match a {
+                    Attribute::Field { data_array, .. } => Some(data_array.clone()),
+                    _ => None,
+                })
+                .flatten()
+                .collect(),
+        )
+    }
+
+    /// Attributes of every inline piece
+    fn inline<P>(pieces: &[Piece<P>], data: fn(&P) -> &Attributes) -> Vec<&Attributes> {
+        pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Inline(p) => Some(data(p)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// True if there are no field data arrays
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Numerical values of a field array
+    pub fn number(&self, name: &str) -> Option<Vec<f64>> {
+        self.find(name)?.data.cast_into::<f64>()
+    }
+
+    /// Text value of a field array
+    pub fn text(&self, name: &str) -> Option<String> {
+        match &self.find(name)?.data {
+            IOBuffer::U8(bytes) => Some(String::from_utf8_lossy(bytes).to_string()),
+            _ => None,
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&FieldArray> {
+        self.0.iter().find(|array| array.name == name)
+    }
+}
+
+/// Import a vtk file, including any dataset level field data
+///
+/// The field data is attached to the cell data of the first piece, exactly as
+/// for the converted vtk before writing.
+///
+/// Legacy binary files written by ntools record their byte order in the field
+/// data, so both big and little endian files are supported. Anything else is
+/// assumed to be big endian as per the VTK standard.
+pub fn import(path: &Path) -> Result<Vtk> {
+    let mut content = std::fs::read(path)?;
+
+    let (mut vtk, fields) = if content.starts_with(b"# vtk DataFile") {
+        let (fields, byte_order) = take_legacy_field_data(&mut content)?;
+        let vtk = match byte_order {
+            ByteOrder::BigEndian => Vtk::parse_legacy_be(content.as_slice())?,
+            ByteOrder::LittleEndian => Vtk::parse_legacy_le(content.as_slice())?,
+        };
+        (vtk, fields)
+    } else {
+        let fields = xml_field_arrays(&content);
+        let mut vtk = Vtk::import(path)?;
+        vtk.load_all_pieces().map_err(vtkio::Error::from)?;
+        (vtk, fields)
+    };
+
+    if !fields.is_empty() {
+        attach_field_data(&mut vtk, fields);
+    }
+    Ok(vtk)
+}
+
+/// Attach field arrays to the cell data of the first inline piece
+fn attach_field_data(vtk: &mut Vtk, fields: Vec<FieldArray>) {
+    if let Some(attributes) = attributes_mut(vtk).into_iter().next() {
+        attributes.cell.push(field_attribute(fields));
+    }
+}
+
+/// Parse the ascii `<FieldData>` element of an XML file
+///
+/// Only ascii arrays are supported, which is what a [FieldWriter] writes.
+/// Anything else is ignored.
+fn xml_field_arrays(content: &[u8]) -> Vec<FieldArray> {
+    let content = String::from_utf8_lossy(content);
+    let Some(start) = content.find("<FieldData>") else {
+        return Vec::new();
+    };
+    let Some(end) = content[start..].find("</FieldData>") else {
+        return Vec::new();
+    };
+
+    content[start..start + end]
+        .split("<DataArray")
+        .skip(1)
+        .filter_map(|element| {
+            let (header, rest) = element.split_once('>')?;
+            let values = rest.split("</DataArray>").next()?;
+
+            if xml_attribute(header, "format")? != "ascii" {
+                return None;
+            }
+
+            let name = xml_attribute(header, "Name")?;
+            match xml_attribute(header, "type")? {
+                "String" => {
+                    let text = values
+                        .split_whitespace()
+                        .filter_map(|c| c.parse::<u8>().ok())
+                        .take_while(|c| *c != 0)
+                        .collect::<Vec<u8>>();
+                    Some(text_array(name, &String::from_utf8_lossy(&text)))
+                }
+                _ => {
+                    let num_comp = xml_attribute(header, "NumberOfComponents")
+                        .and_then(|n| n.parse::<u32>().ok())
+                        .unwrap_or(1);
+                    let numbers = values
+                        .split_whitespace()
+                        .map(|v| v.parse::<f64>())
+                        .collect::<std::result::Result<Vec<f64>, _>>()
+                        .ok()?;
+                    Some(number_array(name, num_comp, numbers))
+                }
+            }
+        })
+        .collect()
+}
+
+/// Value of a `key="value"` attribute in an XML element header
+fn xml_attribute<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&f!(" {key}=\""))? + key.len() + 3;
+    let end = header[start..].find('"')?;
+    Some(&header[start..start + end])
+}
+
+/// Remove and parse the dataset level `FIELD` section of a legacy file
+///
+/// vtkio only understands field data within point or cell data, so anything
+/// before these sections is taken out before parsing the rest of the file.
+///
+/// Binary values are only decoded once the whole section has been read, using
+/// the byte order recorded in the [BYTE_ORDER] array if there is one. This
+/// array is not returned with the other fields.
+fn take_legacy_field_data(content: &mut Vec<u8>) -> Result<(Vec<FieldArray>, ByteOrder)> {
+    let attributes = [
+        find(content, b"\nPOINT_DATA"),
+        find(content, b"\nCELL_DATA"),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+    .unwrap_or(content.len());
+
+    let start = match find(content, b"\nFIELD ") {
+        Some(i) if i < attributes => i + 1,
+        _ => return Ok((Vec::new(), ByteOrder::BigEndian)),
+    };
+
+    let binary = find(&content[..start], b"\nBINARY").is_some();
+    let mut cursor = Cursor {
+        content: content.as_slice(),
+        pos: start,
+    };
+
+    // FIELD <name> <number of arrays>
+    cursor.token();
+    cursor.token();
+    let n = cursor.parse::<usize>()?;
+
+    let mut arrays = Vec::with_capacity(n);
+    for _ in 0..n {
+        // <name> <components> <tuples> <type>
+        let name = cursor.token().unwrap_or_default();
+        let num_comp = cursor.parse::<u32>()?;
+        let n_values = num_comp as usize * cursor.parse::<usize>()?;
+        let type_name = cursor.token().unwrap_or_default();
+
+        let data = match binary {
+            true => {
+                cursor.skip_line();
+                LegacyArray::Binary(
+                    type_name.clone(),
+                    cursor.binary_bytes(&type_name, n_values)?,
+                )
+            }
+            false => LegacyArray::Ascii(cursor.ascii_values(&type_name, n_values)?),
+        };
+        arrays.push((name, num_comp, data));
+    }
+
+    // binary blocks already include their trailing newline
+    if !binary {
+        cursor.skip_line();
+    }
+
+    // text is a single byte per character so reads the same either way
+    let byte_order = arrays
+        .iter()
+        .find_map(|(name, _, data)| match (name.as_str(), data) {
+            (BYTE_ORDER, LegacyArray::Binary(_, bytes)) => Some(bytes.as_slice()),
+            _ => None,
+        })
+        .map(|bytes| match bytes {
+            b"LittleEndian" => ByteOrder::LittleEndian,
+            _ => ByteOrder::BigEndian,
+        })
+        .unwrap_or(ByteOrder::BigEndian);
+
+    let fields = arrays
+        .into_iter()
+        .filter(|(name, _, _)| name != BYTE_ORDER)
+        .map(|(name, num_comp, data)| FieldArray {
+            name,
+            elem: num_comp,
+            data: match data {
+                LegacyArray::Ascii(buffer) => buffer,
+                LegacyArray::Binary(type_name, bytes) => {
+                    decode_binary(&type_name, &bytes, byte_order)
+                }
+            },
+        })
+        .collect();
+
+    let end = cursor.pos;
+    content.drain(start..end);
+    Ok((fields, byte_order))
+}
+
+/// Values of a legacy field array before binary data is decoded
+enum LegacyArray {
+    Ascii(IOBuffer),
+    Binary(String, Vec<u8>),
+}
+
+/// Decode binary values of a legacy data type with the given byte order
+fn decode_binary(type_name: &str, bytes: &[u8], byte_order: ByteOrder) -> IOBuffer {
+    fn values<const N: usize>(
+        bytes: &[u8],
+        byte_order: ByteOrder,
+        value: fn([u8; N], ByteOrder) -> f64,
+    ) -> IOBuffer {
+        IOBuffer::F64(
+            bytes
+                .chunks_exact(N)
+                .map(|b| value(b.try_into().unwrap(), byte_order))
+                .collect(),
+        )
+    }
+
+    match type_name {
+        "unsigned_char" | "char" => IOBuffer::U8(bytes.to_vec()),
+        "float" => values(bytes, byte_order, |b, o| match o {
+            ByteOrder::BigEndian => f32::from_be_bytes(b) as f64,
+            ByteOrder::LittleEndian => f32::from_le_bytes(b) as f64,
+        }),
+        "int" => values(bytes, byte_order, |b, o| match o {
+            ByteOrder::BigEndian => i32::from_be_bytes(b) as f64,
+            ByteOrder::LittleEndian => i32::from_le_bytes(b) as f64,
+        }),
+        "unsigned_int" => values(bytes, byte_order, |b, o| match o {
+            ByteOrder::BigEndian => u32::from_be_bytes(b) as f64,
+            ByteOrder::LittleEndian => u32::from_le_bytes(b) as f64,
+        }),
+        "long" => values(bytes, byte_order, |b, o| match o {
+            ByteOrder::BigEndian => i64::from_be_bytes(b) as f64,
+            ByteOrder::LittleEndian => i64::from_le_bytes(b) as f64,
+        }),
+        "unsigned_long" => values(bytes, byte_order, |b, o| match o {
+            ByteOrder::BigEndian => u64::from_be_bytes(b) as f64,
+            ByteOrder::LittleEndian => u64::from_le_bytes(b) as f64,
+        }),
+        _ => values(bytes, byte_order, |b, o| match o {
+            ByteOrder::BigEndian => f64::from_be_bytes(b),
+            ByteOrder::LittleEndian => f64::from_le_bytes(b),
+        }),
+    }
+}
+
+/// Minimal reader for the legacy field data section
+struct Cursor<'a> {
+    content: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    /// Next whitespace delimited token
+    fn token(&mut self) -> Option<String> {
+        while self.pos < self.content.len() && self.content[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        let start = self.pos;
+        while self.pos < self.content.len() && !self.content[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        (start < self.pos)
+            .then(|| String::from_utf8_lossy(&self.content[start..self.pos]).to_string())
+    }
+
+    /// Next token parsed to the target type
+    fn parse<T: std::str::FromStr>(&mut self) -> Result<T> {
+        let token = self.token().unwrap_or_default();
+        token.parse::<T>().map_err(|_| Error::InvalidVtk {
+            reason: f!("unable to parse \"{token}\" in legacy field data"),
+        })
+    }
+
+    /// Move to the start of the next line
+    fn skip_line(&mut self) {
+        while self.pos < self.content.len() && self.content[self.pos] != b'\n' {
+            self.pos += 1;
+        }
+        self.pos += 1;
+    }
+
+    /// Read `n` ascii values of a legacy data type
+    fn ascii_values(&mut self, type_name: &str, n: usize) -> Result<IOBuffer> {
+        match type_name {
+            "unsigned_char" | "char" => Ok(IOBuffer::U8(
+                (0..n).map(|_| self.parse::<u8>()).collect::<Result<_>>()?,
+            )),
+            _ => Ok(IOBuffer::F64(
+                (0..n).map(|_| self.parse::<f64>()).collect::<Result<_>>()?,
+            )),
+        }
+    }
+
+    /// Read the raw bytes of `n` binary values of a legacy data type
+    fn binary_bytes(&mut self, type_name: &str, n: usize) -> Result<Vec<u8>> {
+        let size = match type_name {
+            "unsigned_char" | "char" => 1,
+            "float" | "int" | "unsigned_int" => 4,
+            "double" | "long" | "unsigned_long" => 8,
+            _ => {
+                return Err(Error::InvalidVtk {
+                    reason: f!("unsupported legacy field data type \"{type_name}\""),
+                })
+            }
+        };
+
+        let end = self.pos + n * size;
+        let bytes = self.content.get(self.pos..end).ok_or(Error::InvalidVtk {
+            reason: "legacy field data ended unexpectedly".to_string(),
+        })?;
+        self.pos = end;
+
+        // trailing newline after the binary block
+        if self.content.get(self.pos) == Some(&b'\n') {
+            self.pos += 1;
+        }
+        Ok(bytes.to_vec())
+    }
+}
@@ -0,0 +1,378 @@
+// standard library
+use std::collections::HashMap;
+use std::path::Path;
+
+// ntools modules
+use crate::error::{Error, Result};
+use crate::{Format, Geometry, Mesh, Particle, Voxel};
+use ntools_utils::f;
+
+// internal modules
+use crate::vtk::field::{self, FieldData};
+use crate::vtk::MeshToVtk;
+
+// extrenal crates
+use vtkio::model::{Attribute, Attributes, DataSet, Piece, Vtk};
+
+/// Read a VTK file written by [write_vtk()](crate::vtk::write_vtk) back into a [Mesh]
+///
+/// Any of the formats written by this crate are supported, i.e. `.vtr`/`.vtu`
+/// XML files and `.vtk` legacy files of either byte order.
+///
+/// The [Mesh] is rebuilt from the metadata embedded in the file as field data,
+/// so files without it can not be read this way. For generic VTK files from
+/// other codes, see [read_vtk_arrays()].
+///
+/// ```rust, no_run
+/// # use ntools_mesh::vtk::read_vtk;
+/// let mesh = read_vtk("path/to/output.vtr").unwrap();
+/// ```
+///
+/// Note that energy and time groups excluded from the original conversion are
+/// filled with zero results and errors.
+pub fn read_vtk(path: impl AsRef<Path>) -> Result<Mesh> {
+    vtk_to_mesh(&field::import(path.as_ref())?)
+}
+
+/// Convert a vtkio::Vtk with ntools metadata back into a [Mesh]
+///
+/// The inverse of [mesh_to_vtk()](crate::vtk::mesh_to_vtk), for a VTK that is
+/// already in memory.
+///
+/// ```rust
+/// # use ntools_mesh::vtk::{MeshToVtk, vtk_to_mesh};
+/// # use ntools_mesh::read_target;
+/// let mesh = read_target("./data/meshes/fmesh_114.msht", 114).unwrap();
+///
+/// // Convert to a VTK and back again, errors are only kept if included
+/// let vtk = MeshToVtk::builder()
+///     .include_errors(true)
+///     .build()
+///     .convert(&mesh);
+///
+/// let copy = vtk_to_mesh(&vtk).unwrap();
+///
+/// assert_eq!(mesh.voxels, copy.voxels);
+/// assert_eq!(mesh.imesh, copy.imesh);
+/// ```
+pub fn vtk_to_mesh(vtk: &Vtk) -> Result<Mesh> {
+    let metadata = FieldData::from_vtk(vtk);
+    if metadata.is_empty() {
+        return Err(invalid(
+            "no ntools metadata found, use read_vtk_arrays() for generic VTK files",
+        ));
+    }
+
+    let mut mesh = mesh_from_metadata(&metadata)?;
+    let arrays = cell_arrays(vtk)?;
+
+    // only the converted groups were written, anything else stays zero
+    let converter = MeshToVtk::new();
+    let (error_suffix, resolution) = match mesh.geometry {
+        Geometry::Rectangular => {
+            let (x, y, z) = rectilinear_coordinates(vtk)?;
+            mesh.iints = x.len().saturating_sub(1);
+            mesh.jints = y.len().saturating_sub(1);
+            mesh.kints = z.len().saturating_sub(1);
+            mesh.imesh = x;
+            mesh.jmesh = y;
+            mesh.kmesh = z;
+            (", error", None)
+        }
+        Geometry::Cylindrical => {
+            let n_cells = unstructured_cells(vtk)?;
+            let n_voxels = mesh.iints * mesh.jints * mesh.kints;
+            if n_voxels == 0 || n_cells % n_voxels != 0 {
+                return Err(invalid(&f!(
+                    "{n_cells} cells do not match the {n_voxels} voxels of the metadata"
+                )));
+            }
+            ("_error", Some(n_cells / n_voxels))
+        }
+    };
+
+    mesh.voxels = zero_voxels(&mesh);
+
+    for e_idx in 0..mesh.n_ebins() {
+        for t_idx in 0..mesh.n_tbins() {
+            let name = converter.group_name_visit(&mesh, e_idx, t_idx);
+            let Some(results) = arrays.get(&name) else {
+                continue;
+            };
+            let errors = arrays.get(&(name.clone() + error_suffix));
+
+            match resolution {
+                None => set_from_cell_order(&mut mesh, e_idx, t_idx, results, errors)?,
+                Some(r) => set_from_voxel_order(&mut mesh, e_idx, t_idx, results, errors, r)?,
+            }
+        }
+    }
+
+    Ok(mesh)
+}
+
+/// Read named cell data arrays from any VTK rectilinear grid into a [Mesh]
+///
+/// Generic rectilinear grids from other codes, such as the VTK export of an
+/// OpenMC mesh tally, have no ntools metadata. Instead, the cell data array to
+/// use for results is given explicitly, with an optional array of relative
+/// errors.
+///
+/// ```rust, no_run
+/// # use ntools_mesh::vtk::read_vtk_arrays;
+/// let mesh = read_vtk_arrays("path/to/tally.vtr", "flux", Some("flux_rel_err")).unwrap();
+/// ```
+///
+/// The result is a single group [Mesh] of Unknown particle type. Errors are
+/// taken as relative errors, and default to zero if not provided.
+pub fn read_vtk_arrays(path: impl AsRef<Path>, result: &str, error: Option<&str>) -> Result<Mesh> {
+    vtk_arrays_to_mesh(&field::import(path.as_ref())?, result, error)
+}
+
+/// Convert named cell data arrays of a vtkio::Vtk rectilinear grid into a [Mesh]
+///
+/// See [read_vtk_arrays()] for details.
+///
+/// ```rust
+/// # use ntools_mesh::vtk::{mesh_to_vtk, vtk_arrays_to_mesh};
+/// # use ntools_mesh::read_target;
+/// let mesh = read_target("./data/meshes/fmesh_104.msht", 104).unwrap();
+/// let vtk = mesh_to_vtk(&mesh);
+///
+/// // Ignore the metadata and just take the results array
+/// let copy = vtk_arrays_to_mesh(&vtk, "Energy-0", None).unwrap();
+/// assert_eq!(copy.voxels[3].result, mesh.voxels[3].result);
+/// assert_eq!(copy.voxels[3].error, 0.0);
+/// ```
+pub fn vtk_arrays_to_mesh(vtk: &Vtk, result: &str, error: Option<&str>) -> Result<Mesh> {
+    let arrays = cell_arrays(vtk)?;
+    let (x, y, z) = rectilinear_coordinates(vtk)?;
+
+    let mut mesh = Mesh {
+        iints: x.len().saturating_sub(1),
+        jints: y.len().saturating_sub(1),
+        kints: z.len().saturating_sub(1),
+        origin: [x[0], y[0], z[0]],
+        imesh: x,
+        jmesh: y,
+        kmesh: z,
+        emesh: vec![0.0, 1e36],
+        eints: 1,
+        ..Default::default()
+    };
+    mesh.voxels = zero_voxels(&mesh);
+
+    let results = arrays
+        .get(result)
+        .ok_or_else(|| invalid(&f!("no cell data array named \"{result}\"")))?;
+
+    let errors = match error {
+        Some(name) => Some(
+            arrays
+                .get(name)
+                .ok_or_else(|| invalid(&f!("no cell data array named \"{name}\"")))?,
+        ),
+        None => None,
+    };
+
+    set_from_cell_order(&mut mesh, 0, 0, results, errors)?;
+    Ok(mesh)
+}
+
+/// Initialise everything but the voxels from ntools metadata
+fn mesh_from_metadata(metadata: &FieldData) -> Result<Mesh> {
+    let number = |name: &str| {
+        metadata
+            .number(name)
+            .ok_or_else(|| invalid(&f!("missing \"{name}\" in metadata")))
+    };
+    let vector = |name: &str| -> Result<[f64; 3]> {
+        number(name)?
+            .try_into()
+            .map_err(|_| invalid(&f!("expected 3 values for \"{name}\"")))
+    };
+
+    let geometry = match metadata.text("geometry").as_deref() {
+        Some("XYZ") => Geometry::Rectangular,
+        Some("RZT") => Geometry::Cylindrical,
+        other => return Err(invalid(&f!("unknown geometry {other:?}"))),
+    };
+
+    // written as the variant name, so match on that
+    let particle = metadata
+        .text("particle")
+        .and_then(|name| {
+            (0..=37)
+                .map(Particle::from_id)
+                .find(|p| f!("{p:?}") == name)
+        })
+        .unwrap_or(Particle::Unknown);
+
+    let imesh = number("imesh")?;
+    let jmesh = number("jmesh")?;
+    let kmesh = number("kmesh")?;
+    let emesh = number("energy_bounds")?;
+    let tmesh = metadata.number("time_bounds").unwrap_or_default();
+
+    Ok(Mesh {
+        id: number("tally_id")?.first().copied().unwrap_or_default() as u32,
+        geometry,
+        particle,
+        iints: imesh.len().saturating_sub(1),
+        jints: jmesh.len().saturating_sub(1),
+        kints: kmesh.len().saturating_sub(1),
+        eints: emesh.len().saturating_sub(1),
+        tints: tmesh.len().saturating_sub(1),
+        imesh,
+        jmesh,
+        kmesh,
+        emesh,
+        tmesh,
+        origin: vector("origin")?,
+        axs: vector("axs")?,
+        vec: vector("vec")?,
+        format: Format::NONE,
+        voxels: Vec::new(),
+    })
+}
+
+/// Every voxel of the mesh with zero result and error
+fn zero_voxels(mesh: &Mesh) -> Vec<Voxel> {
+    let n = mesh.n_ebins() * mesh.n_tbins() * mesh.iints * mesh.jints * mesh.kints;
+    (0..n)
+        .map(|index| Voxel {
+            index,
+            result: 0.0,
+            error: 0.0,
+        })
+        .collect()
+}
+
+/// Set a group from values in cell index order, i.e. i varies fastest
+fn set_from_cell_order(
+    mesh: &mut Mesh,
+    e_idx: usize,
+    t_idx: usize,
+    results: &[f64],
+    errors: Option<&Vec<f64>>,
+) -> Result<()> {
+    let (ni, nj, nk) = (mesh.iints, mesh.jints, mesh.kints);
+    check_length(ni * nj * nk, results, errors)?;
+
+    for (cell, result) in results.iter().enumerate() {
+        let (i, j, k) = (cell % ni, (cell / ni) % nj, cell / (ni * nj));
+        let idx = mesh.voxel_index_from_etijk(e_idx, t_idx, i, j, k);
+        mesh.voxels[idx].result = *result;
+        mesh.voxels[idx].error = errors.map(|e| e[cell]).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Set a group from values in voxel index order, repeated `resolution` times
+fn set_from_voxel_order(
+    mesh: &mut Mesh,
+    e_idx: usize,
+    t_idx: usize,
+    results: &[f64],
+    errors: Option<&Vec<f64>>,
+    resolution: usize,
+) -> Result<()> {
+    let n_voxels = mesh.iints * mesh.jints * mesh.kints;
+    check_length(n_voxels * resolution, results, errors)?;
+
+    let offset = mesh.voxel_index_from_etijk(e_idx, t_idx, 0, 0, 0);
+    for v in 0..n_voxels {
+        mesh.voxels[offset + v].result = results[v * resolution];
+        mesh.voxels[offset + v].error = errors.map(|e| e[v * resolution]).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Make sure the results and errors are the expected length
+fn check_length(expected: usize, results: &[f64], errors: Option<&Vec<f64>>) -> Result<()> {
+    for found in std::iter::once(results.len()).chain(errors.map(|e| e.len())) {
+        if found != expected {
+            return Err(Error::UnexpectedLength { expected, found });
+        }
+    }
+    Ok(())
+}
+
+/// Collect every cell data array by name
+fn cell_arrays(vtk: &Vtk) -> Result<HashMap<String, Vec<f64>>> {
+    let attributes = match &vtk.data {
+        DataSet::RectilinearGrid { pieces, .. } => single_piece(pieces).map(|p| &p.data),
+        DataSet::UnstructuredGrid { pieces, .. } => single_piece(pieces).map(|p| &p.data),
+        _ => Err(invalid(
+            "only rectilinear and unstructured grids are supported",
+        )),
+    }?;
+
+    Ok(data_arrays(attributes))
+}
+
+/// Numerical data arrays of the cell data, as f64
+///
+/// Names are decoded in case of spaces encoded for legacy formats.
+fn data_arrays(attributes: &Attributes) -> HashMap<String, Vec<f64>> {
+    attributes
+        .cell
+        .iter()
+        .filter_map(|attribute| match attribute {
+            Attribute::DataArray(array) => array
+                .data
+                .cast_into::<f64>()
+                .map(|values| (array.name.replace("%20", " "), values)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Coordinates of a rectilinear grid, as f64
+fn rectilinear_coordinates(vtk: &Vtk) -> Result<(Vec<f64>, Vec<f64>, Vec<f64>)> {
+    let piece = match &vtk.data {
+        DataSet::RectilinearGrid { pieces, .. } => single_piece(pieces)?,
+        _ => return Err(invalid("expected a rectilinear grid")),
+    };
+
+    let cast = |buffer: &vtkio::model::IOBuffer| {
+        buffer
+            .cast_into::<f64>()
+            .filter(|c| !c.is_empty())
+            .ok_or_else(|| invalid("unsupported coordinate data"))
+    };
+
+    Ok((
+        cast(&piece.coords.x)?,
+        cast(&piece.coords.y)?,
+        cast(&piece.coords.z)?,
+    ))
+}
+
+/// Number of cells in an unstructured grid
+fn unstructured_cells(vtk: &Vtk) -> Result<usize> {
+    match &vtk.data {
+        DataSet::UnstructuredGrid { pieces, .. } => Ok(single_piece(pieces)?.cells.types.len()),
+        _ => Err(invalid(
+            "expected an unstructured grid for cylindrical meshes",
+        )),
+    }
+}
+
+/// The only piece of a dataset, which must already be loaded
+fn single_piece<P>(pieces: &[Piece<P>]) -> Result<&P> {
+    match pieces {
+        [Piece::Inline(p)] => Ok(p),
+        [_] => Err(invalid("piece data not loaded")),
+        _ => Err(invalid(&f!(
+            "expected a single piece, found {}",
+            pieces.len()
+        ))),
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidVtk {
+        reason: reason.to_string(),
+    }
+}
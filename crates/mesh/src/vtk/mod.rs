@@ -75,9 +75,70 @@
 //!
 //! In the background, a call to [MeshToVtk::new()] simply returns a default
 //! configuration generated by the builder anyway.
+//!
+//! # Metadata
+//!
+//! Every converted VTK carries the mesh metadata (tally id, particle, geometry,
+//! origin/axs/vec, energy and time bounds, and the ntools version) as dataset
+//! field data. The source file name is included when set on the builder with
+//! `source_file()`.
+//!
+//! vtkio does not write field data itself, so files should be written with
+//! [write_vtk()] for the metadata to be kept.
+//!
+//! # Reading VTK files
+//!
+//! The metadata also means files written by [write_vtk()] can be read back
+//! into a [Mesh] with [read_vtk()]. Generic rectilinear grids from other codes
+//! are read from named cell data arrays with [read_vtk_arrays()].
+//!
+//! # Point data
+//!
+//! Results are written as cell data by default. For filters that need point
+//! data the converter can also interpolate values to the grid nodes, or write
+//! the voxel centres as a point cloud, using the `output()` builder option with
+//! a [VtkOutput] variant.
+//!
+//! ```rust
+//! # use ntools_mesh::vtk::{MeshToVtk, VtkOutput};
+//! // Cell data plus node-averaged point data
+//! let converter = MeshToVtk::builder()
+//!     .output(VtkOutput::Nodes)
+//!     .build();
+//!
+//! // Voxel centres as polydata vertices, written as ".vtp" for XML
+//! let converter = MeshToVtk::builder()
+//!     .output(VtkOutput::Centroids)
+//!     .build();
+//! ```
+//!
+//! # Partitioned output
+//!
+//! Very large meshes may instead be written as a set of spatial blocks with
+//! [MeshToVtk::write_partitioned()]. Each block is converted and written to
+//! its own file in parallel, with a `.pvtr`/`.pvtu` master file that ParaView
+//! opens as a single dataset.
+//!
+//! ```rust, no_run
+//! # use ntools_mesh::vtk::MeshToVtk;
+//! # use ntools_mesh::read_target;
+//! let mesh = read_target("path/to/file.msht", 104).unwrap();
+//!
+//! // Write blocks of at most 50x50x50 voxels
+//! MeshToVtk::builder()
+//!     .block_size([50, 50, 50])
+//!     .build()
+//!     .write_partitioned(&mesh, "output.pvtr")
+//!     .unwrap();
+//! ```
 
 mod builder;
 mod convert;
+pub mod cylinder;
+pub mod field;
+mod import;
+mod partition;
+mod points;
 
 #[doc(inline)]
 pub use builder::MeshToVtkBuilder;
@@ -85,9 +146,15 @@ pub use builder::MeshToVtkBuilder;
 #[doc(inline)]
 pub use convert::MeshToVtk;
 
+#[doc(inline)]
+pub use import::{read_vtk, read_vtk_arrays, vtk_arrays_to_mesh, vtk_to_mesh};
+
 use nalgebra::{Rotation, Vector3};
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::Path;
-use vtkio::model::ByteOrder;
+use vtkio::model::{ByteOrder, DataSet};
+use vtkio::writer::{BinaryWriter, WriteVtk};
 use vtkio::Vtk;
 
 use crate::error::Result;
@@ -140,16 +207,64 @@ pub fn mesh_to_vtk(mesh: &Mesh) -> Vtk {
 /// vtkio will get thow and error.
 ///
 /// For XML, the file must end in `.vtr` for rectangular meshes (rectilinear
-/// grid), and `.vtu` for cylindrical meshes (unstructured). For both legacy
-/// formats `.vtk` will work fine.
-pub fn write_vtk(vtk: Vtk, path: impl AsRef<Path>, format: VtkFormat) -> Result<()> {
+/// grid), `.vtu` for cylindrical meshes (unstructured), and `.vtp` for
+/// [VtkOutput::Centroids] (polydata). For both legacy formats `.vtk` will work
+/// fine.
+pub fn write_vtk(mut vtk: Vtk, path: impl AsRef<Path>, format: VtkFormat) -> Result<()> {
+    let path = path.as_ref();
+    let byte_order = vtk.byte_order;
+    let fields = field::take_field_data(&mut vtk);
+
+    match format {
+        VtkFormat::Xml => check_xml_extension(&vtk, path)?,
+        _ => field::encode_legacy_names(&mut vtk),
+    }
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut writer = field::FieldWriter::new(file, &fields, format, byte_order);
+
     match format {
-        VtkFormat::Xml => Ok(vtk.export(path)?),
-        VtkFormat::LegacyBinary => match vtk.byte_order {
-            ByteOrder::BigEndian => Ok(vtk.export_be(path)?),
-            ByteOrder::LittleEndian => Ok(vtk.export_le(path)?),
-        },
-        VtkFormat::LegacyAscii => Ok(vtk.export_ascii(path)?),
+        VtkFormat::Xml => vtk.write_xml(&mut writer)?,
+        VtkFormat::LegacyBinary => {
+            let mut binary = BinaryWriter(&mut writer);
+            match byte_order {
+                ByteOrder::BigEndian => binary.write_vtk_be(vtk).map_err(vtkio::Error::from)?,
+                ByteOrder::LittleEndian => binary.write_vtk_le(vtk).map_err(vtkio::Error::from)?,
+            };
+        }
+        VtkFormat::LegacyAscii => {
+            // ascii is built in memory first, as vtkio does for export
+            let mut ascii = String::new();
+            vtk.write_legacy_ascii(&mut ascii)?;
+            writer.write_all(ascii.as_bytes())?;
+        }
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+/// XML file types must match the extension, as checked by vtkio on export
+fn check_xml_extension(vtk: &Vtk, path: &Path) -> Result<()> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let expected = match &vtk.data {
+        DataSet::ImageData { .. } => Some("vti"),
+        DataSet::StructuredGrid { .. } => Some("vts"),
+        DataSet::RectilinearGrid { .. } => Some("vtr"),
+        DataSet::UnstructuredGrid { .. } => Some("vtu"),
+        DataSet::PolyData { .. } => Some("vtp"),
+        DataSet::Field { .. } => None,
+    };
+
+    match ext {
+        _ if Some(ext) == expected => Ok(()),
+        "vti" | "vts" | "vtr" | "vtu" | "vtp" => {
+            Err(vtkio::Error::XML(vtkio::xml::Error::TypeExtensionMismatch).into())
+        }
+        _ => Err(vtkio::Error::UnknownFileExtension(Some(ext.to_string())).into()),
     }
 }
 
@@ -167,6 +282,22 @@ pub enum VtkFormat {
     LegacyBinary,
 }
 
+/// Enum of VTK output data layouts
+///
+/// Mesh results are naturally cell data, which is what [VtkOutput::Cells]
+/// writes. Many ParaView filters (contours, glyphs, etc.) need point data
+/// instead, so the other variants avoid a manual `CellDatatoPointData` step.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VtkOutput {
+    /// Cell data on the rectilinear/unstructured grid (default)
+    #[default]
+    Cells,
+    /// Cell data plus values interpolated to the grid nodes as point data
+    Nodes,
+    /// Voxel centres written as polydata vertices with point data
+    Centroids,
+}
+
 /// Vertex for use in cylindrical and unstructured mesh types
 pub struct Vertex {
     pub x: f64,
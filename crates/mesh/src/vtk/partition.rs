@@ -0,0 +1,272 @@
+// standard library
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+// ntools modules
+use crate::error::Result;
+use crate::{Geometry, Mesh};
+use ntools_utils::f;
+
+// internal modules
+use crate::vtk::{write_vtk, MeshToVtk, VtkFormat, VtkOutput};
+
+// extrenal crates
+use rayon::prelude::*;
+use vtkio::model::ByteOrder;
+
+/// Range of (i,j,k) voxel indicies covered by a spatial block of a mesh
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Block {
+    /// Range of i indicies
+    pub i: Range<usize>,
+    /// Range of j indicies
+    pub j: Range<usize>,
+    /// Range of k indicies
+    pub k: Range<usize>,
+}
+
+impl Block {
+    /// A single block covering every voxel in the mesh geometry
+    pub fn whole(mesh: &Mesh) -> Self {
+        Self {
+            i: 0..mesh.iints,
+            j: 0..mesh.jints,
+            k: 0..mesh.kints,
+        }
+    }
+
+    /// Split the mesh geometry into blocks of at most `size` voxels in (i,j,k)
+    ///
+    /// Blocks are ordered with i varying fastest. Any zero in `size` is treated
+    /// as no limit for that dimension.
+    pub fn partition(mesh: &Mesh, size: [usize; 3]) -> Vec<Self> {
+        let i_ranges = Self::split(mesh.iints, size[0]);
+        let j_ranges = Self::split(mesh.jints, size[1]);
+        let k_ranges = Self::split(mesh.kints, size[2]);
+
+        let mut blocks = Vec::with_capacity(i_ranges.len() * j_ranges.len() * k_ranges.len());
+        for k in &k_ranges {
+            for j in &j_ranges {
+                for i in &i_ranges {
+                    blocks.push(Self {
+                        i: i.clone(),
+                        j: j.clone(),
+                        k: k.clone(),
+                    });
+                }
+            }
+        }
+        blocks
+    }
+
+    /// Iterate (i,j,k) in cell index order, i.e. i varies fastest
+    pub fn cell_order(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        self.k.clone().flat_map(move |k| {
+            self.j
+                .clone()
+                .flat_map(move |j| self.i.clone().map(move |i| (i, j, k)))
+        })
+    }
+
+    /// Iterate (i,j,k) in voxel index order, i.e. k varies fastest
+    pub fn voxel_order(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        self.i.clone().flat_map(move |i| {
+            self.j
+                .clone()
+                .flat_map(move |j| self.k.clone().map(move |k| (i, j, k)))
+        })
+    }
+
+    /// Split `0..n` into consecutive ranges no longer than `size`
+    fn split(n: usize, size: usize) -> Vec<Range<usize>> {
+        let size = if size == 0 { n.max(1) } else { size };
+        (0..n)
+            .step_by(size)
+            .map(|start| start..(start + size).min(n))
+            .collect()
+    }
+}
+
+/// Implementations for partitioned output of large meshes
+impl MeshToVtk {
+    /// Write a [Mesh] as spatial blocks with a parallel VTK master file
+    ///
+    /// Very large meshes can be too much for ParaView to load comfortably as a
+    /// single file, and building the full VTK in memory can be a problem in
+    /// itself.
+    ///
+    /// The mesh is split into blocks of at most
+    /// [block_size](crate::vtk::MeshToVtkBuilder::block_size) voxels, and each
+    /// block converted and written to its own piece file in parallel. Peak
+    /// memory therefore scales with the block size rather than the mesh size.
+    ///
+    /// | Geometry    | Master file | Pieces |
+    /// | ----------- | ----------- | ------ |
+    /// | Rectangular | `.pvtr`     | `.vtr` |
+    /// | Cylindrical | `.pvtu`     | `.vtu` |
+    /// | Centroids   | `.pvtp`     | `.vtp` |
+    ///
+    /// The extension of `path` is replaced by the appropriate master file
+    /// extension, and pieces are written alongside it as `<stem>_<n>.vtr`,
+    /// `<stem>_<n>.vtu`, or `<stem>_<n>.vtp` for
+    /// [VtkOutput::Centroids](crate::vtk::VtkOutput::Centroids). Open the master file in ParaView to load every piece.
+    ///
+    /// ```rust, no_run
+    /// # use ntools_mesh::vtk::MeshToVtk;
+    /// # use ntools_mesh::read_target;
+    /// let mesh = read_target("path/to/file.msht", 104).unwrap();
+    ///
+    /// // Split into blocks of at most 100x100x100 voxels
+    /// let converter = MeshToVtk::builder()
+    ///     .block_size([100, 100, 100])
+    ///     .build();
+    ///
+    /// // Writes "output.pvtr" and the "output_<n>.vtr" pieces
+    /// converter.write_partitioned(&mesh, "output.pvtr").unwrap();
+    /// ```
+    pub fn write_partitioned(&self, mesh: &Mesh, path: impl AsRef<Path>) -> Result<()> {
+        let blocks = match self.block_size {
+            Some(size) => Block::partition(mesh, size),
+            None => vec![Block::whole(mesh)],
+        };
+
+        let (master_ext, piece_ext) = match (self.output, mesh.geometry) {
+            (VtkOutput::Centroids, _) => ("pvtp", "vtp"),
+            (_, Geometry::Rectangular) => ("pvtr", "vtr"),
+            (_, Geometry::Cylindrical) => ("pvtu", "vtu"),
+        };
+
+        // pieces live alongside the master file
+        let master = path.as_ref().with_extension(master_ext);
+        let stem = master
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let pieces = (0..blocks.len())
+            .map(|n| master.with_file_name(f!("{stem}_{n}.{piece_ext}")))
+            .collect::<Vec<PathBuf>>();
+
+        // only one block per thread is ever held in memory
+        blocks
+            .par_iter()
+            .zip(pieces.par_iter())
+            .try_for_each(|(block, piece)| -> Result<()> {
+                write_vtk(self.convert_block(mesh, block), piece, VtkFormat::Xml)
+            })?;
+
+        let sources = pieces
+            .iter()
+            .map(|p| {
+                p.file_name()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<String>>();
+
+        std::fs::write(master, self.master_file(mesh, &blocks, &sources))?;
+        Ok(())
+    }
+
+    /// Generate the xml content of the parallel master file
+    fn master_file(&self, mesh: &Mesh, blocks: &[Block], sources: &[String]) -> String {
+        let byte_order = match self.byte_order {
+            ByteOrder::BigEndian => "BigEndian",
+            ByteOrder::LittleEndian => "LittleEndian",
+        };
+
+        let data_set = match (self.output, mesh.geometry) {
+            (VtkOutput::Centroids, _) => "PPolyData",
+            (_, Geometry::Rectangular) => "PRectilinearGrid",
+            (_, Geometry::Cylindrical) => "PUnstructuredGrid",
+        };
+        let rectilinear = data_set == "PRectilinearGrid";
+
+        let mut s = String::from("<?xml version=\"1.0\"?>\n");
+        s += &f!("<VTKFile type=\"{data_set}\" version=\"1.0\" byte_order=\"{byte_order}\" header_type=\"UInt64\">\n");
+
+        match rectilinear {
+            true => {
+                s += &f!(
+                    "  <{data_set} WholeExtent=\"0 {} 0 {} 0 {}\" GhostLevel=\"0\">\n",
+                    mesh.iints,
+                    mesh.jints,
+                    mesh.kints
+                )
+            }
+            false => s += &f!("  <{data_set} GhostLevel=\"0\">\n"),
+        }
+
+        // point data for interpolated nodes and centroids, cell data otherwise
+        let names = self.array_names(mesh);
+        if self.output != VtkOutput::Cells {
+            s += "    <PPointData>\n";
+            for name in &names {
+                s += &f!("      <PDataArray type=\"Float64\" Name=\"{name}\"/>\n");
+            }
+            s += "    </PPointData>\n";
+        }
+
+        if self.output != VtkOutput::Centroids {
+            s += "    <PCellData>\n";
+            for name in &names {
+                s += &f!("      <PDataArray type=\"Float64\" Name=\"{name}\"/>\n");
+            }
+            s += "    </PCellData>\n";
+        }
+
+        match rectilinear {
+            true => {
+                s += "    <PCoordinates>\n";
+                for axis in ["x", "y", "z"] {
+                    s += &f!("      <PDataArray type=\"Float64\" Name=\"{axis}\"/>\n");
+                }
+                s += "    </PCoordinates>\n";
+            }
+            false => {
+                s += "    <PPoints>\n";
+                s += "      <PDataArray type=\"Float64\" NumberOfComponents=\"3\"/>\n";
+                s += "    </PPoints>\n";
+            }
+        }
+
+        for (block, source) in blocks.iter().zip(sources) {
+            match rectilinear {
+                true => {
+                    s += &f!(
+                        "    <Piece Extent=\"{} {} {} {} {} {}\" Source=\"{source}\"/>\n",
+                        block.i.start,
+                        block.i.end,
+                        block.j.start,
+                        block.j.end,
+                        block.k.start,
+                        block.k.end
+                    )
+                }
+                false => s += &f!("    <Piece Source=\"{source}\"/>\n"),
+            }
+        }
+
+        s += &f!("  </{data_set}>\n");
+        s += "</VTKFile>\n";
+        s
+    }
+
+    /// Names of every data array written to the pieces
+    fn array_names(&self, mesh: &Mesh) -> Vec<String> {
+        let error_suffix = Self::error_suffix(mesh);
+
+        let mut names = Vec::new();
+        for e_idx in &self.collect_energy_group_idx(mesh) {
+            for t_idx in &self.collect_time_group_idx(mesh) {
+                let name = self.group_name_visit(mesh, *e_idx, *t_idx);
+                if self.include_errors {
+                    names.push(name.clone());
+                    names.push(name + error_suffix);
+                } else {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+}
@@ -0,0 +1,230 @@
+// standard library
+use std::ops::Range;
+
+// ntools modules
+use crate::{Geometry, Mesh};
+use ntools_utils::f;
+
+// internal modules
+use crate::vtk::partition::Block;
+use crate::vtk::{cylinder, MeshToVtk};
+
+// extrenal crates
+use vtkio::model::{
+    Attribute, Attributes, DataArray, DataSet, ElementType, IOBuffer, PolyDataPiece, Version,
+    VertexNumbers, Vtk,
+};
+
+/// Implementations for point data and point cloud output
+impl MeshToVtk {
+    /// Convert the voxel centres of a block to polydata vertices
+    ///
+    /// Every selected result/error array is written as point data, with points
+    /// in voxel index order for both geometries.
+    pub(crate) fn centroid_piece(&self, mesh: &Mesh, block: &Block) -> Vtk {
+        let points = match mesh.geometry {
+            Geometry::Rectangular => block
+                .voxel_order()
+                .flat_map(|(i, j, k)| {
+                    [
+                        Self::centre(&mesh.imesh, i),
+                        Self::centre(&mesh.jmesh, j),
+                        Self::centre(&mesh.kmesh, k),
+                    ]
+                })
+                .collect::<Vec<f64>>(),
+            Geometry::Cylindrical => Self::cylindrical_centroids(mesh, block),
+        };
+
+        // one single-point vertex cell per voxel
+        let n_points = points.len() / 3;
+        let verts = VertexNumbers::Legacy {
+            num_cells: n_points as u32,
+            vertices: (0..n_points as u32).flat_map(|p| [1, p]).collect(),
+        };
+
+        let mut data = Attributes::new();
+        data.point = self.point_arrays(mesh, |e_idx, t_idx, error| {
+            block
+                .voxel_order()
+                .map(|(i, j, k)| Self::value(mesh, e_idx, t_idx, [i, j, k], error))
+                .collect()
+        });
+        data.cell.push(self.metadata(mesh));
+
+        Vtk {
+            version: Version::Auto,
+            title: f!("Fmesh{} results", mesh.id),
+            byte_order: self.byte_order,
+            file_path: None,
+            data: DataSet::inline(PolyDataPiece {
+                points: points.into(),
+                verts: Some(verts),
+                lines: None,
+                polys: None,
+                strips: None,
+                data,
+            }),
+        }
+    }
+
+    /// Cartesian voxel centres of a cylindrical mesh block
+    fn cylindrical_centroids(mesh: &Mesh, block: &Block) -> Vec<f64> {
+        let transform = cylinder::Transform::new(mesh.origin, mesh.axs, mesh.vec);
+        let step = 2.0 * std::f64::consts::PI / (mesh.kints as f64);
+
+        block
+            .voxel_order()
+            .flat_map(|(i, j, k)| {
+                transform.point(
+                    Self::centre(&mesh.imesh, i),
+                    Self::centre(&mesh.jmesh, j),
+                    step * (k as f64 + 0.5),
+                )
+            })
+            .collect()
+    }
+
+    /// Rectilinear node values for a block, in point order with x fastest
+    ///
+    /// Each node takes the average of the voxels sharing it, which is the same
+    /// approach as the ParaView `CellDatatoPointData` filter.
+    pub(crate) fn rectangular_node_data(&self, mesh: &Mesh, block: &Block) -> Vec<Attribute> {
+        self.point_arrays(mesh, |e_idx, t_idx, error| {
+            let mut values = Vec::new();
+            for c in block.k.start..=block.k.end {
+                for b in block.j.start..=block.j.end {
+                    for a in block.i.start..=block.i.end {
+                        let cells = Self::adjacent(a, mesh.iints).flat_map(|i| {
+                            Self::adjacent(b, mesh.jints).flat_map(move |j| {
+                                Self::adjacent(c, mesh.kints).map(move |k| [i, j, k])
+                            })
+                        });
+                        values.push(Self::average(mesh, e_idx, t_idx, cells, error));
+                    }
+                }
+            }
+            values
+        })
+    }
+
+    /// Cylindrical node values for every explicit vertex of a block
+    ///
+    /// Unstructured cells do not share vertices, so values are generated in
+    /// exactly the same order as the vertices of each wedge/voxel cell. Nodes
+    /// on the axis average every theta bin, and the extra vertices from
+    /// subdividing voxels are interpolated linearly between nodes.
+    pub(crate) fn cylindrical_node_data(&self, mesh: &Mesh, block: &Block) -> Vec<Attribute> {
+        let subdivisions = self.subdivisions(mesh);
+
+        self.point_arrays(mesh, |e_idx, t_idx, error| {
+            let mut values = Vec::new();
+            cylinder::cells(block.voxel_order(), subdivisions, |_, nodes| {
+                values.extend(nodes.iter().map(|[r, z, t]| {
+                    cylinder::lerp(*r, |a| {
+                        cylinder::lerp(*z, |b| {
+                            cylinder::lerp(*t, |c| {
+                                Self::cylindrical_node(mesh, e_idx, t_idx, [a, b, c], error)
+                            })
+                        })
+                    })
+                }));
+            });
+            values
+        })
+    }
+
+    /// Value at radial node `a`, axial node `b`, and theta node `c`
+    fn cylindrical_node(
+        mesh: &Mesh,
+        e_idx: usize,
+        t_idx: usize,
+        [a, b, c]: [usize; 3],
+        error: bool,
+    ) -> f64 {
+        // theta is periodic, so the last node is shared with the first
+        let thetas = match (a, mesh.kints) {
+            // every theta bin meets on the axis
+            (0, _) => (0..mesh.kints).collect(),
+            (_, 1) => vec![0],
+            (_, n) => vec![(c + n - 1) % n, c % n],
+        };
+
+        let cells = Self::adjacent(a, mesh.iints).flat_map(|i| {
+            let thetas = thetas.clone();
+            Self::adjacent(b, mesh.jints)
+                .flat_map(move |j| thetas.clone().into_iter().map(move |k| [i, j, k]))
+        });
+
+        Self::average(mesh, e_idx, t_idx, cells, error)
+    }
+
+    /// Build named point data arrays for every selected group
+    ///
+    /// The closure provides the values for an (energy, time, error) triplet.
+    fn point_arrays<F>(&self, mesh: &Mesh, values: F) -> Vec<Attribute>
+    where
+        F: Fn(usize, usize, bool) -> Vec<f64>,
+    {
+        let mut arrays = Vec::new();
+
+        for e_idx in &self.collect_energy_group_idx(mesh) {
+            for t_idx in &self.collect_time_group_idx(mesh) {
+                let name = self.group_name_visit(mesh, *e_idx, *t_idx);
+                arrays.push(Self::scalars(name.clone(), values(*e_idx, *t_idx, false)));
+
+                if self.include_errors {
+                    let name = name + Self::error_suffix(mesh);
+                    arrays.push(Self::scalars(name, values(*e_idx, *t_idx, true)));
+                }
+            }
+        }
+
+        arrays
+    }
+
+    /// Single component data array
+    fn scalars(name: String, values: Vec<f64>) -> Attribute {
+        Attribute::DataArray(DataArray {
+            name,
+            elem: ElementType::Scalars {
+                num_comp: 1,
+                lookup_table: None,
+            },
+            data: IOBuffer::F64(values),
+        })
+    }
+
+    /// Average result or error of a set of (i,j,k) voxels
+    fn average(
+        mesh: &Mesh,
+        e_idx: usize,
+        t_idx: usize,
+        cells: impl Iterator<Item = [usize; 3]>,
+        error: bool,
+    ) -> f64 {
+        let (sum, count) = cells.fold((0.0, 0), |(sum, count), ijk| {
+            (sum + Self::value(mesh, e_idx, t_idx, ijk, error), count + 1)
+        });
+        sum / count.max(1) as f64
+    }
+
+    /// Result or error of a single voxel
+    fn value(mesh: &Mesh, e_idx: usize, t_idx: usize, [i, j, k]: [usize; 3], error: bool) -> f64 {
+        let voxel = mesh.voxels[mesh.voxel_index_from_etijk(e_idx, t_idx, i, j, k)];
+        match error {
+            true => voxel.error,
+            false => voxel.result,
+        }
+    }
+
+    /// Voxel indicies either side of a node, limited to the mesh
+    fn adjacent(node: usize, n_voxels: usize) -> Range<usize> {
+        node.saturating_sub(1)..(node + 1).min(n_voxels)
+    }
+
+    /// Mid-point of a voxel from the mesh bounds
+    fn centre(bounds: &[f64], idx: usize) -> f64 {
+        0.5 * (bounds[idx] + bounds[idx + 1])
+    }
+}
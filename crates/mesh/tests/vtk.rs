@@ -0,0 +1,549 @@
+//! Integration tests for VTK conversion
+
+use std::path::{Path, PathBuf};
+
+use ntools_mesh::vtk::{read_vtk, write_vtk, MeshToVtk, VtkFormat, VtkOutput};
+use ntools_mesh::{read_target, Mesh, Voxel};
+use rstest::rstest;
+use vtkio::model::{
+    Attribute, Attributes, ByteOrder, CellType, DataSet, Extent, Piece, UnstructuredGridPiece,
+};
+use vtkio::Vtk;
+
+fn read_mesh(id: u32) -> Mesh {
+    read_target(format!("./data/meshes/fmesh_{id}.msht"), id).unwrap()
+}
+
+/// The only piece of an unstructured grid
+fn unstructured_piece(vtk: &Vtk) -> &UnstructuredGridPiece {
+    match &vtk.data {
+        DataSet::UnstructuredGrid { pieces, .. } => match pieces.as_slice() {
+            [Piece::Inline(piece)] => piece,
+            _ => panic!("expected a single inline piece"),
+        },
+        _ => panic!("expected an unstructured grid"),
+    }
+}
+
+/// Distance of every point from the cylinder axis
+fn radii(mesh: &Mesh, piece: &UnstructuredGridPiece) -> Vec<f64> {
+    let axs = mesh.axs;
+    piece
+        .points
+        .cast_into::<f64>()
+        .unwrap()
+        .chunks_exact(3)
+        .map(|p| {
+            let d = [
+                p[0] - mesh.origin[0],
+                p[1] - mesh.origin[1],
+                p[2] - mesh.origin[2],
+            ];
+            let along = d[0] * axs[0] + d[1] * axs[1] + d[2] * axs[2];
+            let total = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+            (total - along * along).max(0.0).sqrt()
+        })
+        .collect()
+}
+
+#[rstest]
+#[case(134, 1)]
+#[case(134, 3)]
+#[case(234, 1)]
+#[case(334, 2)]
+fn cylindrical_ring_radii(#[case] id: u32, #[case] radial_resolution: u8) {
+    let mesh = read_mesh(id);
+    let vtk = MeshToVtk::builder()
+        .radial_resolution(radial_resolution)
+        .build()
+        .convert(&mesh);
+    let piece = unstructured_piece(&vtk);
+    let radii = radii(&mesh, piece);
+
+    // outer ring vertices sit on the last radial bound, not the one before
+    let max = radii.iter().copied().fold(0.0, f64::max);
+    assert!((max - mesh.imesh[mesh.iints]).abs() < 1e-9);
+
+    // the first wedge spans the axis to the first subdivision of ring 0
+    assert_eq!(piece.cells.types[0], CellType::Wedge);
+    let expected = mesh.imesh[1] / radial_resolution as f64;
+    assert!(radii[0].abs() < 1e-9);
+    assert!((radii[1] - expected).abs() < 1e-9);
+
+    // every bound is used, and nothing lies outside the mesh
+    for bound in &mesh.imesh {
+        assert!(radii.iter().any(|r| (r - bound).abs() < 1e-9));
+    }
+    assert!(radii.iter().all(|r| *r <= max + 1e-9));
+}
+
+/// Fresh directory for the output of a single test
+fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ntools_mesh_{name}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Values of every `key="value"` attribute in the master file, in order
+fn xml_values(content: &str, key: &str) -> Vec<String> {
+    let marker = format!(" {key}=\"");
+    content
+        .split(&marker)
+        .skip(1)
+        .map(|rest| rest.split('"').next().unwrap().to_string())
+        .collect()
+}
+
+/// Names of the arrays declared in a section of the master file
+fn declared(content: &str, section: &str) -> Option<Vec<String>> {
+    let start = content.find(&format!("<{section}>"))?;
+    let end = content[start..].find(&format!("</{section}>"))? + start;
+    Some(xml_values(&content[start..end], "Name"))
+}
+
+/// Names of the data arrays in a set of attributes
+fn names(attributes: &[Attribute]) -> Vec<String> {
+    attributes
+        .iter()
+        .filter_map(|a| match a {
+            Attribute::DataArray(array) => Some(array.name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Attributes and rectilinear extent, if any, of a single piece file
+fn piece_data(path: &Path) -> (Attributes, Option<[usize; 6]>) {
+    let mut vtk = Vtk::import(path).unwrap();
+    vtk.load_all_pieces().unwrap();
+
+    match vtk.data {
+        DataSet::RectilinearGrid { mut pieces, .. } => match pieces.remove(0) {
+            Piece::Inline(piece) => {
+                let extent = match piece.extent {
+                    Extent::Ranges([x, y, z]) => [x, y, z]
+                        .into_iter()
+                        .flat_map(|r| [*r.start() as usize, *r.end() as usize])
+                        .collect::<Vec<usize>>()
+                        .try_into()
+                        .unwrap(),
+                    _ => panic!("expected extent ranges"),
+                };
+                (piece.data, Some(extent))
+            }
+            _ => panic!("expected an inline piece"),
+        },
+        DataSet::UnstructuredGrid { mut pieces, .. } => match pieces.remove(0) {
+            Piece::Inline(piece) => (piece.data, None),
+            _ => panic!("expected an inline piece"),
+        },
+        DataSet::PolyData { mut pieces, .. } => match pieces.remove(0) {
+            Piece::Inline(piece) => (piece.data, None),
+            _ => panic!("expected an inline piece"),
+        },
+        _ => panic!("unexpected dataset"),
+    }
+}
+
+#[rstest]
+#[case([0, 0, 0], 1)]
+#[case([2, 2, 2], 4)]
+#[case([3, 0, 2], 4)]
+#[case([1, 1, 1], 24)]
+fn partitioned_extents(#[case] block_size: [usize; 3], #[case] n_blocks: usize) {
+    let mesh = read_mesh(114);
+    let dir = output_dir(&format!("extents_{n_blocks}_{}", block_size[0]));
+
+    MeshToVtk::builder()
+        .block_size(block_size)
+        .build()
+        .write_partitioned(&mesh, dir.join("mesh.vtk"))
+        .unwrap();
+
+    // extension replaced by the master file extension
+    let master = std::fs::read_to_string(dir.join("mesh.pvtr")).unwrap();
+    assert!(!dir.join("mesh.vtk").exists());
+    assert!(master.contains("<PRectilinearGrid WholeExtent=\"0 4 0 2 0 3\""));
+
+    // one piece file for every block, and nothing else
+    let sources = xml_values(&master, "Source");
+    assert_eq!(sources.len(), n_blocks);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), n_blocks + 1);
+
+    // every voxel is covered exactly once, and pieces agree with the master
+    let mut covered = vec![0; mesh.n_voxels_per_group()];
+    for (n, (extent, source)) in xml_values(&master, "Extent")
+        .iter()
+        .zip(&sources)
+        .enumerate()
+    {
+        assert_eq!(source, &format!("mesh_{n}.vtr"));
+
+        let extent = extent
+            .split_whitespace()
+            .map(|v| v.parse::<usize>().unwrap())
+            .collect::<Vec<usize>>();
+        let (_, piece_extent) = piece_data(&dir.join(source));
+        assert_eq!(piece_extent.unwrap().to_vec(), extent);
+
+        for i in extent[0]..extent[1] {
+            for j in extent[2]..extent[3] {
+                for k in extent[4]..extent[5] {
+                    covered[mesh.voxel_index_from_etijk(0, 0, i, j, k)] += 1;
+                }
+            }
+        }
+    }
+    assert!(covered.iter().all(|c| *c == 1));
+}
+
+#[rstest]
+#[case([0, 0, 0], 1)]
+#[case([1, 2, 3], 4)]
+#[case([2, 1, 1], 12)]
+fn partitioned_cylinder(#[case] block_size: [usize; 3], #[case] n_blocks: usize) {
+    let mesh = read_mesh(134);
+    let dir = output_dir(&format!("cylinder_{n_blocks}"));
+
+    let converter = MeshToVtk::builder().block_size(block_size).build();
+    converter
+        .write_partitioned(&mesh, dir.join("mesh.vtu"))
+        .unwrap();
+
+    let master = std::fs::read_to_string(dir.join("mesh.pvtu")).unwrap();
+    assert!(master.contains("<PUnstructuredGrid GhostLevel=\"0\">"));
+    assert!(!master.contains("Extent="));
+
+    let sources = xml_values(&master, "Source");
+    assert_eq!(sources.len(), n_blocks);
+
+    // pieces add up to the same cells as a single conversion
+    let n_cells = sources
+        .iter()
+        .map(|source| {
+            let (data, _) = piece_data(&dir.join(source));
+            match &data.cell[0] {
+                Attribute::DataArray(array) => array.data.len(),
+                _ => panic!("expected a data array"),
+            }
+        })
+        .sum::<usize>();
+
+    let vtk = converter.convert(&mesh);
+    assert_eq!(n_cells, unstructured_piece(&vtk).cells.types.len());
+}
+
+#[rstest]
+#[case(114, VtkOutput::Cells, "pvtr")]
+#[case(114, VtkOutput::Nodes, "pvtr")]
+#[case(114, VtkOutput::Centroids, "pvtp")]
+#[case(134, VtkOutput::Cells, "pvtu")]
+#[case(134, VtkOutput::Nodes, "pvtu")]
+#[case(134, VtkOutput::Centroids, "pvtp")]
+fn partitioned_master_arrays(#[case] id: u32, #[case] output: VtkOutput, #[case] ext: &str) {
+    let mesh = read_mesh(id);
+    let dir = output_dir(&format!("arrays_{id}_{output:?}"));
+
+    MeshToVtk::builder()
+        .block_size([2, 1, 2])
+        .include_errors(true)
+        .output(output)
+        .build()
+        .write_partitioned(&mesh, dir.join("mesh"))
+        .unwrap();
+
+    let master = std::fs::read_to_string(dir.join("mesh").with_extension(ext)).unwrap();
+    let point_data = declared(&master, "PPointData");
+    let cell_data = declared(&master, "PCellData");
+
+    // nodes have both, centroids only point data, and cells only cell data
+    match output {
+        VtkOutput::Cells => assert!(point_data.is_none() && cell_data.is_some()),
+        VtkOutput::Nodes => assert!(point_data.is_some() && cell_data.is_some()),
+        VtkOutput::Centroids => assert!(point_data.is_some() && cell_data.is_none()),
+    }
+
+    // declarations match exactly what every piece contains
+    for source in xml_values(&master, "Source") {
+        let (data, _) = piece_data(&dir.join(source));
+        assert_eq!(names(&data.point), point_data.clone().unwrap_or_default());
+        assert_eq!(names(&data.cell), cell_data.clone().unwrap_or_default());
+    }
+
+    // energy and time groups, each with errors
+    let expected = mesh.n_ebins() * mesh.n_tbins() * 2;
+    let declared = point_data.or(cell_data).unwrap();
+    assert_eq!(declared.len(), expected);
+}
+
+/// Position of the first occurrence of `marker` in a file
+fn find(content: &[u8], marker: &[u8]) -> Option<usize> {
+    content
+        .windows(marker.len())
+        .position(|window| window == marker)
+}
+
+/// Number of occurrences of `marker` in a file
+fn count(content: &[u8], marker: &[u8]) -> usize {
+    content
+        .windows(marker.len())
+        .filter(|window| *window == marker)
+        .count()
+}
+
+/// Metadata and results that should survive a round trip
+fn assert_same_mesh(mesh: &Mesh, copy: &Mesh) {
+    assert_eq!(copy.id, mesh.id);
+    assert_eq!(copy.particle, mesh.particle);
+    assert_eq!(copy.geometry, mesh.geometry);
+    assert_eq!(copy.origin, mesh.origin);
+    assert_eq!(copy.emesh, mesh.emesh);
+    assert_eq!(copy.tmesh, mesh.tmesh);
+    assert_eq!(copy.voxels, mesh.voxels);
+}
+
+#[rstest]
+#[case(114, VtkOutput::Cells)]
+#[case(114, VtkOutput::Nodes)]
+#[case(134, VtkOutput::Cells)]
+#[case(134, VtkOutput::Nodes)]
+fn xml_field_data(#[case] id: u32, #[case] output: VtkOutput) {
+    let mesh = read_mesh(id);
+    let dir = output_dir(&format!("xml_field_{id}_{output:?}"));
+    let path = dir.join(match id {
+        114 => "mesh.vtr",
+        _ => "mesh.vtu",
+    });
+
+    let vtk = MeshToVtk::builder()
+        .include_errors(true)
+        .output(output)
+        .build()
+        .convert(&mesh);
+    write_vtk(vtk, &path, VtkFormat::Xml).unwrap();
+
+    // a single field data element, first child of the dataset element
+    let content = std::fs::read(&path).unwrap();
+    let field = find(&content, b"<FieldData>").unwrap();
+    assert_eq!(count(&content, b"<FieldData>"), 1);
+    assert!(find(&content, b"Grid").unwrap() < field);
+    assert!(field < find(&content, b"<Piece").unwrap());
+
+    // nothing is attached to the cell or point data
+    assert!(find(&content[field..], b"</FieldData>").unwrap() < find(&content, b"<Piece").unwrap());
+    assert_same_mesh(&mesh, &read_vtk(&path).unwrap());
+}
+
+#[rstest]
+#[case(114, VtkOutput::Cells, VtkFormat::LegacyAscii)]
+#[case(114, VtkOutput::Nodes, VtkFormat::LegacyAscii)]
+#[case(114, VtkOutput::Cells, VtkFormat::LegacyBinary)]
+#[case(114, VtkOutput::Nodes, VtkFormat::LegacyBinary)]
+#[case(134, VtkOutput::Cells, VtkFormat::LegacyAscii)]
+#[case(134, VtkOutput::Nodes, VtkFormat::LegacyAscii)]
+#[case(134, VtkOutput::Cells, VtkFormat::LegacyBinary)]
+#[case(134, VtkOutput::Nodes, VtkFormat::LegacyBinary)]
+fn legacy_field_data(#[case] id: u32, #[case] output: VtkOutput, #[case] format: VtkFormat) {
+    let mesh = read_mesh(id);
+    let dir = output_dir(&format!("legacy_field_{id}_{output:?}_{format:?}"));
+    let path = dir.join("mesh.vtk");
+
+    let vtk = MeshToVtk::builder()
+        .include_errors(true)
+        .output(output)
+        .build()
+        .convert(&mesh);
+    write_vtk(vtk, &path, format).unwrap();
+
+    // inserted on its own line straight after the dataset line
+    let content = std::fs::read(&path).unwrap();
+    let field = find(&content, b"\nFIELD FieldData").unwrap();
+    assert_eq!(count(&content, b"\nFIELD FieldData"), 1);
+    let previous_line = content[..field].iter().rposition(|c| *c == b'\n').unwrap();
+    assert!(content[previous_line..].starts_with(b"\nDATASET "));
+
+    // so comes before any coordinates
+    let coordinates = match mesh.geometry {
+        ntools_mesh::Geometry::Rectangular => find(&content, b"\nDIMENSIONS").unwrap(),
+        _ => find(&content, b"\nPOINTS").unwrap(),
+    };
+    assert!(field < coordinates);
+
+    // vtkio always writes a point data section, even if empty
+    assert!(field < find(&content, b"\nCELL_DATA").unwrap());
+    assert!(field < find(&content, b"\nPOINT_DATA").unwrap());
+
+    // whitespace in array names is encoded for legacy formats
+    if mesh.geometry == ntools_mesh::Geometry::Rectangular {
+        assert!(find(&content, b"Energy-0_Time-0,%20error").is_some());
+    }
+
+    assert_same_mesh(&mesh, &read_vtk(&path).unwrap());
+}
+
+#[rstest]
+fn file_round_trip(
+    #[values(114, 134)] id: u32,
+    #[values(VtkFormat::Xml, VtkFormat::LegacyAscii, VtkFormat::LegacyBinary)] format: VtkFormat,
+    #[values(ByteOrder::BigEndian, ByteOrder::LittleEndian)] byte_order: ByteOrder,
+) {
+    let mesh = read_mesh(id);
+    let dir = output_dir(&format!("round_trip_{id}_{format:?}_{byte_order:?}"));
+    let path = dir.join(match (format, mesh.geometry) {
+        (VtkFormat::Xml, ntools_mesh::Geometry::Rectangular) => "mesh.vtr",
+        (VtkFormat::Xml, _) => "mesh.vtu",
+        _ => "mesh.vtk",
+    });
+
+    let vtk = MeshToVtk::builder()
+        .include_errors(true)
+        .byte_order(byte_order)
+        .build()
+        .convert(&mesh);
+    write_vtk(vtk, &path, format).unwrap();
+
+    let copy = read_vtk(&path).unwrap();
+    assert_same_mesh(&mesh, &copy);
+    assert_eq!(copy.imesh, mesh.imesh);
+    assert_eq!(copy.jmesh, mesh.jmesh);
+    assert_eq!(copy.kmesh, mesh.kmesh);
+}
+
+/// Small 2x2x1 rectangular mesh with results 1-4 in voxel order
+fn small_mesh() -> Mesh {
+    Mesh {
+        imesh: vec![0.0, 1.0, 3.0],
+        iints: 2,
+        jmesh: vec![0.0, 2.0, 6.0],
+        jints: 2,
+        kmesh: vec![0.0, 4.0],
+        kints: 1,
+        emesh: vec![0.0, 100.0],
+        eints: 1,
+        voxels: (0..4)
+            .map(|index| Voxel {
+                index,
+                result: (index + 1) as f64,
+                error: 0.1 * (index + 1) as f64,
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Values of the first point data array
+fn first_point_array(attributes: &Attributes) -> Vec<f64> {
+    match &attributes.point[0] {
+        Attribute::DataArray(array) => array.data.cast_into::<f64>().unwrap(),
+        _ => panic!("expected a data array"),
+    }
+}
+
+#[test]
+fn rectangular_node_averages() {
+    let mesh = small_mesh();
+    let vtk = MeshToVtk::builder()
+        .output(VtkOutput::Nodes)
+        .include_errors(true)
+        .build()
+        .convert(&mesh);
+
+    let data = match vtk.data {
+        DataSet::RectilinearGrid { mut pieces, .. } => match pieces.remove(0) {
+            Piece::Inline(piece) => piece.data,
+            _ => panic!("expected an inline piece"),
+        },
+        _ => panic!("expected a rectilinear grid"),
+    };
+
+    // cell data is still written alongside the point data
+    assert_eq!(names(&data.cell), names(&data.point));
+
+    // nodes with x fastest, averaging every voxel that shares the node
+    let layer = [1.0, 2.0, 3.0, 1.5, 2.5, 3.5, 2.0, 3.0, 4.0];
+    let expected = layer.iter().chain(&layer).copied().collect::<Vec<f64>>();
+    assert_eq!(first_point_array(&data), expected);
+
+    // errors are averaged the same way
+    match &data.point[1] {
+        Attribute::DataArray(array) => {
+            let errors = array.data.cast_into::<f64>().unwrap();
+            for (error, result) in errors.iter().zip(&expected) {
+                assert!((error - 0.1 * result).abs() < 1e-12);
+            }
+        }
+        _ => panic!("expected a data array"),
+    }
+}
+
+#[test]
+fn rectangular_centroids() {
+    let mesh = small_mesh();
+    let vtk = MeshToVtk::builder()
+        .output(VtkOutput::Centroids)
+        .build()
+        .convert(&mesh);
+
+    let piece = match vtk.data {
+        DataSet::PolyData { mut pieces, .. } => match pieces.remove(0) {
+            Piece::Inline(piece) => piece,
+            _ => panic!("expected an inline piece"),
+        },
+        _ => panic!("expected polydata"),
+    };
+
+    // voxel centres in voxel index order, i.e. k fastest
+    let points = piece.points.cast_into::<f64>().unwrap();
+    assert_eq!(
+        points,
+        vec![
+            0.5, 1.0, 2.0, //
+            0.5, 4.0, 2.0, //
+            2.0, 1.0, 2.0, //
+            2.0, 4.0, 2.0, //
+        ]
+    );
+
+    // one vertex cell per point, with the voxel results as point data
+    assert_eq!(piece.verts.unwrap().num_cells(), 4);
+    assert!(piece
+        .data
+        .cell
+        .iter()
+        .all(|a| !matches!(a, Attribute::DataArray(_))));
+    assert_eq!(first_point_array(&piece.data), vec![1.0, 2.0, 3.0, 4.0]);
+}
+
+#[rstest]
+#[case([1, 1, 1])]
+#[case([2, 1, 1])]
+#[case([1, 3, 1])]
+#[case([1, 1, 2])]
+#[case([2, 2, 3])]
+fn cylindrical_cell_counts(#[case] resolution: [u8; 3]) {
+    let [radial, axial, theta] = resolution;
+    let mesh = read_mesh(134);
+    let vtk = MeshToVtk::builder()
+        .radial_resolution(radial)
+        .axial_resolution(axial)
+        .resolution(theta)
+        .build()
+        .convert(&mesh);
+    let piece = unstructured_piece(&vtk);
+
+    // every voxel is split into r*z*theta cells
+    let per_voxel = (radial * axial * theta) as usize;
+    let n_cells = mesh.n_voxels_per_group() * per_voxel;
+    assert_eq!(piece.cells.types.len(), n_cells);
+
+    // only the innermost radial subdivision touches the axis
+    let n_wedges = mesh.jints * mesh.kints * (axial * theta) as usize;
+    let wedges = piece.cells.types.iter().filter(|t| **t == CellType::Wedge);
+    assert_eq!(wedges.count(), n_wedges);
+
+    // cells do not share verticies
+    let n_points = 6 * n_wedges + 8 * (n_cells - n_wedges);
+    assert_eq!(piece.points.len(), 3 * n_points);
+    assert_eq!(piece.cells.cell_verts.num_cells(), n_cells);
+}
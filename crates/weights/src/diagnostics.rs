@@ -0,0 +1,353 @@
+// standard library
+use std::fmt;
+
+// ntools modules
+use ntools_utils::{f, ValueExt};
+
+// internal modules
+use crate::weight_window::WeightWindow;
+
+/// Validation and diagnostics report for a [WeightWindow]
+///
+/// Generated by [WeightWindow::diagnostics()] as a quick sanity check before
+/// submitting jobs. Consistency issues are anything that would result in an
+/// invalid file, and the group diagnostics are common causes of poor
+/// performance, such as large jumps in weight between adjacent voxels leading
+/// to long histories.
+///
+/// The report is printable as text for a quick look.
+///
+/// ```rust
+/// # use ntools_weights::WeightWindow;
+/// let ww = WeightWindow {
+///     nfx: 3, nfy: 1, nfz: 1,
+///     ncx: 1, ncy: 1, ncz: 1,
+///     qps_x: vec![[1.0, 3.0, 3.0]],
+///     qps_y: vec![[1.0, 1.0, 1.0]],
+///     qps_z: vec![[1.0, 1.0, 1.0]],
+///     e: vec![100.0],
+///     weights: vec![0.5, 0.005, 0.0],
+///     particle: 1,
+///     ..Default::default()
+/// };
+///
+/// let report = ww.diagnostics();
+/// assert!(report.is_consistent());
+/// assert_eq!(report.groups[0].max_adjacent_ratio, 100.0);
+///
+/// // Print a text summary
+/// println!("{report}");
+/// ```
+///
+/// The fields are public so that checks are easy to automate, for example to
+/// fail a CI check on bad windows.
+///
+/// ```rust, no_run
+/// # use ntools_weights::read_wwinp;
+/// for ww in read_wwinp("path/to/wwout").unwrap() {
+///     let report = ww.diagnostics();
+///     if !report.is_consistent() || report.max_adjacent_ratio() > 1e3 {
+///         panic!("Bad weight windows\n{report}");
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics {
+    /// Particle type of the weight window set
+    pub particle: u8,
+    /// Number of weights in the set
+    pub n_weights: usize,
+    /// Number of weights expected from the mesh, energy, and time bins
+    pub n_expected: usize,
+    /// Any inconsistencies that would result in an invalid file
+    pub issues: Vec<String>,
+    /// Diagnostics for each energy/time group, empty if the weights are
+    /// inconsistent with the mesh
+    pub groups: Vec<GroupDiagnostics>,
+}
+
+/// Diagnostics for a single energy/time group of weights
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupDiagnostics {
+    /// Energy group index
+    pub energy_idx: usize,
+    /// Time group index
+    pub time_idx: usize,
+    /// Upper energy bound of the group
+    pub energy: f64,
+    /// Upper time bound of the group, if time dependent
+    pub time: Option<f64>,
+    /// Smallest non-zero weight
+    pub min: f64,
+    /// Largest weight
+    pub max: f64,
+    /// Ratio of the largest to smallest non-zero weight
+    pub dynamic_range: f64,
+    /// Largest ratio between non-zero weights of adjacent voxels
+    pub max_adjacent_ratio: f64,
+    /// The (i,j,k) fine mesh indices of the voxels with the largest ratio
+    pub max_adjacent_voxels: Option<[[usize; 3]; 2]>,
+    /// Number of non-zero voxels surrounded entirely by zero voxels
+    pub isolated_non_zero: usize,
+    /// Number of zero voxels surrounded entirely by non-zero voxels
+    pub isolated_zero: usize,
+    /// Percentage of non-zero weights in the group
+    pub non_analogue_percentage: f64,
+}
+
+impl Diagnostics {
+    /// True if no issues were found that would result in an invalid file
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Largest ratio between adjacent voxels of any group
+    pub fn max_adjacent_ratio(&self) -> f64 {
+        self.groups
+            .iter()
+            .map(|g| g.max_adjacent_ratio)
+            .fold(0.0, f64::max)
+    }
+
+    /// Largest dynamic range of any group
+    pub fn max_dynamic_range(&self) -> f64 {
+        self.groups
+            .iter()
+            .map(|g| g.dynamic_range)
+            .fold(0.0, f64::max)
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Weight window diagnostics for particle {}",
+            self.particle
+        )?;
+        writeln!(
+            f,
+            "  weights: {} of {} expected",
+            self.n_weights, self.n_expected
+        )?;
+
+        match self.is_consistent() {
+            true => writeln!(f, "  consistency: ok")?,
+            false => {
+                writeln!(f, "  consistency: {} issue(s)", self.issues.len())?;
+                for issue in &self.issues {
+                    writeln!(f, "    - {issue}")?;
+                }
+            }
+        }
+
+        if self.groups.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(
+            f,
+            "\n  {:>5} {:>5} {:>11} {:>11} {:>11} {:>11} {:>11} {:>11} {:>8} {:>8} {:>9}",
+            "e",
+            "t",
+            "energy",
+            "time",
+            "min",
+            "max",
+            "range",
+            "max ratio",
+            "iso +",
+            "iso 0",
+            "non-zero"
+        )?;
+
+        for g in &self.groups {
+            writeln!(
+                f,
+                "  {:>5} {:>5} {:>11} {:>11} {:>11} {:>11} {:>11} {:>11} {:>8} {:>8} {:>8.2}%",
+                g.energy_idx,
+                g.time_idx,
+                g.energy.sci(4, 2),
+                g.time.map(|t| t.sci(4, 2)).unwrap_or("-".to_string()),
+                g.min.sci(4, 2),
+                g.max.sci(4, 2),
+                g.dynamic_range.sci(4, 2),
+                g.max_adjacent_ratio.sci(4, 2),
+                g.isolated_non_zero,
+                g.isolated_zero,
+                g.non_analogue_percentage
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Implementations for weight window diagnostics
+impl WeightWindow {
+    /// Generate a validation and diagnostics report
+    ///
+    /// See [Diagnostics] for details of the checks.
+    pub fn diagnostics(&self) -> Diagnostics {
+        let issues = self.consistency_issues();
+        let n_expected = self.n_weights_expected();
+
+        // only safe to look at the groups if the weights match the mesh
+        let groups = match self.weights.len() == n_expected && n_expected > 0 {
+            true => self.group_diagnostics(),
+            false => Vec::new(),
+        };
+
+        Diagnostics {
+            particle: self.particle,
+            n_weights: self.weights.len(),
+            n_expected,
+            issues,
+            groups,
+        }
+    }
+
+    /// Collect any inconsistencies between the header, mesh, and weights
+    fn consistency_issues(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+
+        if self.weights.len() != self.n_weights_expected() {
+            issues.push(f!(
+                "{} weights does not match nfx*nfy*nfz*ne*nt = {}",
+                self.weights.len(),
+                self.n_weights_expected()
+            ));
+        }
+
+        for (axis, qps, nc, nf) in [
+            ("x", &self.qps_x, self.ncx, self.nfx),
+            ("y", &self.qps_y, self.ncy, self.nfy),
+            ("z", &self.qps_z, self.ncz, self.nfz),
+        ] {
+            if qps.len() != nc {
+                issues.push(f!(
+                    "{} coarse meshes in qps_{axis} but nc{axis} = {nc}",
+                    qps.len()
+                ));
+            }
+
+            let n_fine = qps.iter().map(|q| q[2]).sum::<f64>();
+            if n_fine != nf as f64 {
+                issues.push(f!("{n_fine} fine meshes in qps_{axis} but nf{axis} = {nf}"));
+            }
+
+            if qps.windows(2).any(|w| w[1][1] <= w[0][1]) {
+                issues.push(f!("coarse mesh bounds in qps_{axis} are not increasing"));
+            }
+        }
+
+        if self.e.len() != self.ne {
+            issues.push(f!("{} energy bounds but ne = {}", self.e.len(), self.ne));
+        }
+
+        if self.e.windows(2).any(|w| w[1] <= w[0]) {
+            issues.push("energy bounds are not increasing".to_string());
+        }
+
+        match self.iv {
+            1 if self.nt != 1 => issues.push(f!("nt = {} without time bins (iv=1)", self.nt)),
+            2 if self.nt > 1 && self.t.len() != self.nt => {
+                issues.push(f!("{} time bounds but nt = {}", self.t.len(), self.nt))
+            }
+            1 | 2 => (),
+            iv => issues.push(f!("unknown time dependence flag iv = {iv}")),
+        }
+
+        match (self.nwg, self.nr) {
+            (1, 10) | (2, 16) | (3, 16) => (),
+            (nwg, nr) => issues.push(f!("mesh type nwg = {nwg} inconsistent with nr = {nr}")),
+        }
+
+        if self.weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            issues.push("negative or non-finite weights".to_string());
+        }
+
+        issues
+    }
+
+    /// Diagnostics for every energy/time group
+    fn group_diagnostics(&self) -> Vec<GroupDiagnostics> {
+        let n_voxels = self.nfx * self.nfy * self.nfz;
+
+        self.weights
+            .chunks(n_voxels)
+            .enumerate()
+            .map(|(n, group)| {
+                // energy groups outer, time groups inner
+                let (energy_idx, time_idx) = (n / self.nt, n % self.nt);
+                self.single_group(group, energy_idx, time_idx)
+            })
+            .collect()
+    }
+
+    /// Diagnostics for the weights of a single group
+    fn single_group(
+        &self,
+        weights: &[f64],
+        energy_idx: usize,
+        time_idx: usize,
+    ) -> GroupDiagnostics {
+        let non_zero = weights.iter().filter(|w| **w > 0.0);
+        let min = non_zero.clone().copied().fold(f64::INFINITY, f64::min);
+        let max = weights.iter().copied().fold(0.0, f64::max);
+
+        let mut max_adjacent_ratio = 0.0;
+        let mut max_adjacent_voxels = None;
+        let mut isolated_non_zero = 0;
+        let mut isolated_zero = 0;
+
+        for k in 0..self.nfz {
+            for j in 0..self.nfy {
+                for i in 0..self.nfx {
+                    let w = weights[self.fine_index(i, j, k)];
+                    let neighbours = self.neighbours(i, j, k);
+
+                    for [a, b, c] in &neighbours {
+                        let n = weights[self.fine_index(*a, *b, *c)];
+                        if w > 0.0 && n > 0.0 {
+                            let ratio = w.max(n) / w.min(n);
+                            if ratio > max_adjacent_ratio {
+                                max_adjacent_ratio = ratio;
+                                max_adjacent_voxels = Some([[i, j, k], [*a, *b, *c]]);
+                            }
+                        }
+                    }
+
+                    if neighbours.is_empty() {
+                        continue;
+                    }
+
+                    let values = neighbours
+                        .iter()
+                        .map(|[a, b, c]| weights[self.fine_index(*a, *b, *c)]);
+
+                    if w > 0.0 && values.clone().all(|n| n == 0.0) {
+                        isolated_non_zero += 1;
+                    } else if w == 0.0 && values.clone().all(|n| n > 0.0) {
+                        isolated_zero += 1;
+                    }
+                }
+            }
+        }
+
+        GroupDiagnostics {
+            energy_idx,
+            time_idx,
+            energy: self.e.get(energy_idx).copied().unwrap_or_default(),
+            time: self.t.get(time_idx).copied(),
+            min: if min.is_finite() { min } else { 0.0 },
+            max,
+            dynamic_range: if min.is_finite() { max / min } else { 0.0 },
+            max_adjacent_ratio,
+            max_adjacent_voxels,
+            isolated_non_zero,
+            isolated_zero,
+            non_analogue_percentage: 100.0 * non_zero.count() as f64 / weights.len() as f64,
+        }
+    }
+}
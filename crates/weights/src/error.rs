@@ -11,4 +11,59 @@ pub enum Error {
 
     #[error("vtkio error")]
     VtkioError(#[from] vtkio::Error),
+
+    #[error("invalid vtk: {reason}")]
+    InvalidVtk { reason: String },
+
+    #[error("invalid weight window file: {reason}")]
+    InvalidWwinp { reason: String },
+
+    #[error("expected {expected} weights for particle {particle}, found {found}")]
+    WeightCountMismatch {
+        particle: u8,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("geometry of particle {particle} does not match particle {target}")]
+    InconsistentGeometry { particle: u8, target: u8 },
+
+    #[error("duplicate weight window set for particle {particle}")]
+    DuplicateParticle { particle: u8 },
+
+    #[error("unknown particle type {particle}")]
+    UnknownParticle { particle: u8 },
+
+    #[error("no valid weight window sets to write")]
+    NoWeightWindows,
+
+    #[error("invalid mesh: {reason}")]
+    InvalidMesh { reason: String },
+
+    #[error("unable to remap weights: {reason}")]
+    InvalidRemap { reason: String },
+
+    #[error("unable to smooth weights: {reason}")]
+    InvalidSmoothing { reason: String },
+
+    #[error("unable to convert between weights and mesh: {reason}")]
+    MeshConversion { reason: String },
+
+    #[error("unable to combine weight windows: {reason}")]
+    IncompatibleWeights { reason: String },
+
+    #[error("mesh error: {0}")]
+    MeshError(ntools_mesh::Error),
+}
+
+/// Keep the io, vtkio, and invalid vtk errors from shared mesh crate routines
+impl From<ntools_mesh::Error> for Error {
+    fn from(error: ntools_mesh::Error) -> Self {
+        match error {
+            ntools_mesh::Error::Io(e) => Self::IOError(e),
+            ntools_mesh::Error::Vtkio(e) => Self::VtkioError(e),
+            ntools_mesh::Error::InvalidVtk { reason } => Self::InvalidVtk { reason },
+            other => Self::MeshError(other),
+        }
+    }
 }
@@ -0,0 +1,127 @@
+// internal modules
+use crate::weight_window::WeightWindow;
+
+/// Implementations for filling analogue holes in the weights
+impl WeightWindow {
+    /// Fill zero (analogue) voxels by extrapolating from non-zero neighbours
+    ///
+    /// Generation methods commonly set poorly converged voxels to zero, which
+    /// can leave holes deep inside shields where variance reduction is needed
+    /// most. This fills zero voxels up to `max_distance` face-adjacent steps
+    /// away from any non-zero weight, working outwards one layer at a time.
+    ///
+    /// For each zero voxel, every known neighbour gives an estimate in log
+    /// space. Where the next voxel along the same direction is also known, the
+    /// gradient between them is extrapolated linearly, otherwise the
+    /// neighbour weight is used directly. The filled weight is the average of
+    /// all estimates, i.e. the geometric mean in linear space.
+    ///
+    /// Every energy/time group is filled independently, and theta is periodic
+    /// for cylindrical meshes. Zero voxels further than `max_distance` from
+    /// any non-zero weight are left as analogue.
+    ///
+    /// Returns the number of weights that were filled.
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let mut ww = WeightWindow {
+    ///     nfx: 6, nfy: 1, nfz: 1,
+    ///     weights: vec![1.0, 0.1, 0.0, 0.0, 0.0, 0.0],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// // Fill up to two voxels away, continuing the trend of the weights
+    /// let filled = ww.fill_holes(2);
+    ///
+    /// assert_eq!(filled, 2);
+    /// assert!((ww.weights[2] - 0.01).abs() < 1e-12);
+    /// assert!((ww.weights[3] - 0.001).abs() < 1e-12);
+    /// assert_eq!(ww.weights[4], 0.0);
+    /// ```
+    pub fn fill_holes(&mut self, max_distance: usize) -> usize {
+        if max_distance == 0 || !self.has_valid_groups() {
+            return 0;
+        }
+
+        let n_voxels = self.nfx * self.nfy * self.nfz;
+        let mut weights = std::mem::take(&mut self.weights);
+        let mut filled = 0;
+
+        for group in weights.chunks_mut(n_voxels) {
+            // log of every known weight, None for the holes
+            let mut known = group
+                .iter()
+                .map(|w| (*w > 0.0).then(|| w.ln()))
+                .collect::<Vec<Option<f64>>>();
+
+            for _ in 0..max_distance {
+                // only extrapolate from previous layers so the result does not
+                // depend on the voxel order
+                let layer = self
+                    .fine_voxels()
+                    .filter(|[i, j, k]| known[self.fine_index(*i, *j, *k)].is_none())
+                    .filter_map(|ijk| Some((ijk, self.extrapolate(&known, ijk)?)))
+                    .collect::<Vec<([usize; 3], f64)>>();
+
+                if layer.is_empty() {
+                    break;
+                }
+
+                for ([i, j, k], value) in layer {
+                    let idx = self.fine_index(i, j, k);
+                    known[idx] = Some(value);
+                    group[idx] = value.exp().clamp(1.0e-99, 9.999e99);
+                    filled += 1;
+                }
+            }
+        }
+
+        self.weights = weights;
+        filled
+    }
+
+    /// Average log space estimate from the known neighbours of a voxel
+    fn extrapolate(&self, known: &[Option<f64>], ijk: [usize; 3]) -> Option<f64> {
+        let value = |[i, j, k]: [usize; 3]| known[self.fine_index(i, j, k)];
+
+        let estimates = (0..3)
+            .flat_map(|axis| [(axis, true), (axis, false)])
+            .filter_map(|(axis, forward)| {
+                let next = self.step(ijk, axis, forward)?;
+                let near = value(next)?;
+
+                // continue the gradient if the following voxel is also known
+                let far = self
+                    .step(next, axis, forward)
+                    .filter(|beyond| *beyond != ijk)
+                    .and_then(value);
+
+                Some(match far {
+                    Some(far) => 2.0 * near - far,
+                    None => near,
+                })
+            })
+            .collect::<Vec<f64>>();
+
+        match estimates.is_empty() {
+            true => None,
+            false => Some(estimates.iter().sum::<f64>() / estimates.len() as f64),
+        }
+    }
+
+    /// Adjacent voxel along an axis, wrapping theta for cylindrical meshes
+    fn step(&self, mut ijk: [usize; 3], axis: usize, forward: bool) -> Option<[usize; 3]> {
+        let n = [self.nfx, self.nfy, self.nfz][axis];
+        let periodic = axis == 2 && self.nwg == 2 && n > 2;
+
+        ijk[axis] = match (forward, periodic) {
+            (true, _) if ijk[axis] + 1 < n => ijk[axis] + 1,
+            (true, true) => 0,
+            (false, _) if ijk[axis] > 0 => ijk[axis] - 1,
+            (false, true) => n - 1,
+            _ => return None,
+        };
+
+        Some(ijk)
+    }
+}
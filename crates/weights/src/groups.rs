@@ -0,0 +1,220 @@
+// ntools modules
+use ntools_utils::f;
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::weight_window::WeightWindow;
+
+/// How weights are combined when several groups map onto one
+///
+/// Zero (analogue) weights are ignored when combining groups, so a new group
+/// is only analogue if every contributing group was analogue.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum GroupCollapse {
+    /// Lowest non-zero weight of the contributing groups (default)
+    #[default]
+    Minimum,
+    /// Flux weighted average of the contributing groups
+    ///
+    /// Fluxes are given for every weight, in exactly the same order, i.e. with
+    /// i varying fastest and no 'Total' groups. Mesh voxels are stored in a
+    /// different order, so take the fluxes of the mesh used to generate the
+    /// weights with [mesh_results()](WeightWindow::mesh_results).
+    FluxWeighted(Vec<f64>),
+}
+
+/// Implementations for remapping energy and time groups
+impl WeightWindow {
+    /// Map the weights onto new sets of energy and time group upper bounds
+    ///
+    /// Every new group takes the weights of the original groups it overlaps.
+    /// Collapsing several groups into one combines the weights using the
+    /// chosen [GroupCollapse] method, while splitting a group simply copies
+    /// the weights to every new group within it. New groups above the last
+    /// original bound take the weights of the last original group, as MCNP
+    /// does for particles above the top bound.
+    ///
+    /// Energy groups start at 0, and time groups at -infinity. An empty `t`, or
+    /// a single time bound, collapses all time groups into one and removes any
+    /// time dependence.
+    ///
+    /// ```rust
+    /// # use ntools_weights::{WeightWindow, GroupCollapse};
+    /// let mut ww = WeightWindow {
+    ///     nfx: 2, nfy: 1, nfz: 1,
+    ///     ne: 3,
+    ///     e: vec![1.0, 10.0, 100.0],
+    ///     weights: vec![
+    ///         0.1, 0.2, // e <= 1.0
+    ///         0.4, 0.0, // e <= 10.0
+    ///         0.3, 0.6, // e <= 100.0
+    ///     ],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// // Collapse the upper two groups, keeping the lowest non-zero weight
+    /// ww.remap_groups(&[1.0, 100.0], &[], GroupCollapse::Minimum).unwrap();
+    ///
+    /// assert_eq!(ww.ne, 2);
+    /// assert_eq!(ww.weights, vec![0.1, 0.2, 0.3, 0.6]);
+    ///
+    /// // Split the first group by copying
+    /// ww.remap_groups(&[0.5, 1.0, 100.0], &[], GroupCollapse::Minimum).unwrap();
+    ///
+    /// assert_eq!(ww.weights, vec![0.1, 0.2, 0.1, 0.2, 0.3, 0.6]);
+    /// ```
+    ///
+    /// For flux weighted combinations, provide the flux for every weight. Use
+    /// [mesh_results()](WeightWindow::mesh_results) to take these from a mesh.
+    ///
+    /// ```rust
+    /// # use ntools_weights::{WeightWindow, GroupCollapse};
+    /// let mut ww = WeightWindow {
+    ///     nfx: 1, nfy: 1, nfz: 1,
+    ///     ne: 2,
+    ///     e: vec![1.0, 10.0],
+    ///     weights: vec![0.1, 0.4],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let flux = vec![3.0, 1.0];
+    /// ww.remap_groups(&[10.0], &[], GroupCollapse::FluxWeighted(flux)).unwrap();
+    ///
+    /// assert!((ww.weights[0] - 0.175).abs() < 1e-12);
+    /// ```
+    pub fn remap_groups(&mut self, e: &[f64], t: &[f64], method: GroupCollapse) -> Result<()> {
+        self.check_weights()?;
+        check_bounds(e, "energy")?;
+        check_bounds(&self.e, "energy")?;
+        if t.len() > 1 {
+            check_bounds(t, "time")?;
+        }
+
+        if self.e.len() != self.ne || (self.nt > 1 && self.t.len() != self.nt) {
+            return Err(invalid("group bounds do not match ne/nt"));
+        }
+
+        if let GroupCollapse::FluxWeighted(flux) = &method {
+            if flux.len() != self.weights.len() {
+                return Err(invalid(&f!(
+                    "{} fluxes provided for {} weights",
+                    flux.len(),
+                    self.weights.len()
+                )));
+            }
+        }
+
+        // a single time group covers all time, so has no explicit bounds
+        let old_t = match self.nt {
+            1 => vec![f64::INFINITY],
+            _ => self.t.clone(),
+        };
+        let new_t = match t.len() {
+            0 | 1 => vec![f64::INFINITY],
+            _ => t.to_vec(),
+        };
+
+        let energy_map = overlapping(&self.e, e, 0.0);
+        let time_map = overlapping(&old_t, &new_t, f64::NEG_INFINITY);
+
+        let n_voxels = self.nfx * self.nfy * self.nfz;
+        let mut weights = Vec::with_capacity(e.len() * new_t.len() * n_voxels);
+
+        for old_e in &energy_map {
+            for old_t in &time_map {
+                // every original group contributing to this one
+                let offsets = old_e
+                    .iter()
+                    .flat_map(|e_idx| old_t.iter().map(move |t_idx| (e_idx, t_idx)))
+                    .map(|(e_idx, t_idx)| (e_idx * self.nt + t_idx) * n_voxels)
+                    .collect::<Vec<usize>>();
+
+                for voxel in 0..n_voxels {
+                    let indices = offsets.iter().map(|offset| offset + voxel);
+                    weights.push(self.combine(indices, &method));
+                }
+            }
+        }
+
+        self.ne = e.len();
+        self.e = e.to_vec();
+        self.nt = new_t.len();
+        self.t = if self.nt > 1 { new_t } else { Vec::new() };
+        self.iv = if self.nt > 1 { 2 } else { 1 };
+        self.weights = weights;
+
+        Ok(())
+    }
+
+    /// Combine the non-zero weights at the given indices
+    fn combine(&self, indices: impl Iterator<Item = usize>, method: &GroupCollapse) -> f64 {
+        let indices = indices.filter(|idx| self.weights[*idx] > 0.0);
+
+        match method {
+            GroupCollapse::Minimum => indices
+                .map(|idx| self.weights[idx])
+                .reduce(f64::min)
+                .unwrap_or(0.0),
+            GroupCollapse::FluxWeighted(flux) => {
+                let (sum, total) = indices.fold((0.0, 0.0), |(sum, total), idx| {
+                    (sum + flux[idx] * self.weights[idx], total + flux[idx])
+                });
+
+                match total > 0.0 {
+                    true => sum / total,
+                    false => 0.0,
+                }
+            }
+        }
+    }
+}
+
+/// Indices of the original groups overlapping each new group
+///
+/// Groups are given by their upper bounds, with the lower bound of the first
+/// group given separately.
+fn overlapping(old: &[f64], new: &[f64], lower: f64) -> Vec<Vec<usize>> {
+    let lower_bounds = |bounds: &[f64]| {
+        std::iter::once(lower)
+            .chain(bounds.iter().copied())
+            .collect::<Vec<f64>>()
+    };
+    let (old_lower, new_lower) = (lower_bounds(old), lower_bounds(new));
+
+    new.iter()
+        .zip(new_lower)
+        .map(|(upper, lower)| {
+            let groups = old
+                .iter()
+                .zip(&old_lower)
+                .enumerate()
+                .filter(|(_, (old_upper, old_lower))| *old_lower < upper && **old_upper > lower)
+                .map(|(idx, _)| idx)
+                .collect::<Vec<usize>>();
+
+            // anything above the last bound is treated as the last group
+            match groups.is_empty() {
+                true => vec![old.len() - 1],
+                false => groups,
+            }
+        })
+        .collect()
+}
+
+/// Bounds must exist and be strictly increasing
+fn check_bounds(bounds: &[f64], kind: &str) -> Result<()> {
+    if bounds.is_empty() {
+        return Err(invalid(&f!("no {kind} bounds")));
+    }
+
+    match bounds.windows(2).all(|w| w[1] > w[0]) {
+        true => Ok(()),
+        false => Err(invalid(&f!("{kind} bounds are not increasing"))),
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidRemap {
+        reason: reason.to_string(),
+    }
+}
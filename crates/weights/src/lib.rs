@@ -8,8 +8,8 @@
 //!
 //! ## MCNP formats
 //!
-//! Writers are implemented to generate the standardised UTF-8 file format for
-//! WWINP/WWOUT/WWONE files for direct use in MCNP simulations.
+//! Writers and a reader are implemented for the standardised UTF-8 file format
+//! of WWINP/WWOUT/WWONE files for direct use in MCNP simulations.
 //!
 //! Details may be found in the Appendicies of the user manuals:
 //!
@@ -47,6 +47,81 @@
 //! let weight_window = write_multi_particle(&ww_sets, "wwout_NP", false);
 //! ```
 //!
+//! Coarse meshes may be split into any number of fine meshes, set up from the
+//! coarse mesh bounds with [set_coarse_mesh()](WeightWindow::set_coarse_mesh).
+//! Weights are always given per fine mesh voxel.
+//!
+//! The writers panic on failure for convenience, but every writer has a `try_`
+//! variant that returns an [Error] instead, e.g.
+//! [try_write()](WeightWindow::try_write) and [try_write_multi_particle()].
+//! Sets that can not be combined are checked up front with
+//! [check_multi_particle()].
+//!
+//! Existing files, including those written by MCNP, are read back with
+//! [read_wwinp()], which returns a [WeightWindow] for every particle type.
+//!
+//! ```rust, no_run
+//! # use ntools_weights::read_wwinp;
+//! // Read every weight window set in the file
+//! let weight_windows = read_wwinp("/path/to/wwout").unwrap();
+//! ```
+//!
+//! Sets can be sanity-checked before use with
+//! [diagnostics()](WeightWindow::diagnostics), which reports any inconsistent
+//! mesh parameters alongside the dynamic range, adjacent voxel ratios, and
+//! isolated voxels of each group.
+//!
+//! ```rust, no_run
+//! # use ntools_weights::read_wwinp;
+//! for ww in read_wwinp("/path/to/wwout").unwrap() {
+//!     println!("{}", ww.diagnostics());
+//! }
+//! ```
+//!
+//! Large jumps between adjacent voxels are then reduced with
+//! [limit_adjacent_ratio()](WeightWindow::limit_adjacent_ratio), or the weights
+//! smoothed in log space with [smooth()](WeightWindow::smooth). Analogue holes
+//! left by poorly converged voxels are filled from their neighbours with
+//! [fill_holes()](WeightWindow::fill_holes).
+//!
+//! Energy and time groups are collapsed or split onto new bounds with
+//! [remap_groups()](WeightWindow::remap_groups), for example to use fewer
+//! energy groups than the mesh used to generate the weights.
+//!
+//! Weights are projected onto a different mesh geometry with
+//! [remap()](WeightWindow::remap), for reuse after small changes to a model.
+//!
+//! Weights from successive iterations are combined with
+//! [merge()](WeightWindow::merge), keeping the previous weights wherever the
+//! new set is analogue. Where both sets have weights, the [WeightMerge] method
+//! keeps the new weight, the better converged weight, or averages the two in
+//! log space.
+//!
+//! ## Mesh conversion
+//!
+//! Converting to a [Mesh](ntools_mesh::Mesh) with
+//! [to_mesh()](WeightWindow::to_mesh) makes every mesh analysis tool available
+//! for weights, such as point queries and statistics. This is also useful for
+//! comparing weights read from file to the flux mesh that generated them.
+//!
+//! A mesh only has the fine mesh bounds, so converting back with
+//! [with_mesh_weights()](WeightWindow::with_mesh_weights) keeps the coarse
+//! mesh structure and header values of the original. A new weight window with
+//! one coarse mesh per voxel is made with [from_mesh()](WeightWindow::from_mesh).
+//!
+//! ```rust, no_run
+//! # use ntools_weights::{read_wwinp, WeightWindow};
+//! let ww = read_wwinp("/path/to/wwout").unwrap().remove(0);
+//!
+//! // Weights as mesh voxel results, and back again
+//! let mesh = ww.to_mesh().unwrap();
+//! let copy = ww.with_mesh_weights(&mesh).unwrap();
+//! assert_eq!(copy, ww);
+//!
+//! // Or a new weight window from the mesh alone
+//! let fine = WeightWindow::from_mesh(&mesh).unwrap();
+//! ```
+//!
 //! ## Visualisation
 //!
 //! The weights may also be written out to a Visual Toolkit files using the [vtk]
@@ -64,8 +139,16 @@
 //!
 //! For more details and advanced use see the vtk module documentation.
 
+mod diagnostics;
 mod error;
+mod fill;
+mod groups;
+mod merge;
+mod mesh;
 mod operations;
+mod reader;
+mod remap;
+mod smoothing;
 pub mod vtk;
 mod weight_window;
 
@@ -76,4 +159,25 @@ pub use crate::weight_window::WeightWindow;
 pub use crate::error::Error;
 
 #[doc(inline)]
-pub use crate::operations::{write_multi_particle, write_single_particle};
+pub use crate::diagnostics::{Diagnostics, GroupDiagnostics};
+
+#[doc(inline)]
+pub use crate::groups::GroupCollapse;
+
+#[doc(inline)]
+pub use crate::merge::WeightMerge;
+
+#[doc(inline)]
+pub use crate::remap::{Grid, SpatialRemap};
+
+#[doc(inline)]
+pub use crate::smoothing::limit_ratio;
+
+#[doc(inline)]
+pub use crate::operations::{
+    check_multi_particle, try_write_multi_particle, try_write_single_particle,
+    write_multi_particle, write_single_particle,
+};
+
+#[doc(inline)]
+pub use crate::reader::read_wwinp;
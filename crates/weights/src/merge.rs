@@ -0,0 +1,261 @@
+// ntools modules
+use ntools_utils::f;
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::operations::is_geometry_match;
+use crate::weight_window::WeightWindow;
+
+/// How a new set of weights is combined with a previous set
+///
+/// Analogue (zero) weights in the new set are always filled from the previous
+/// set, and a weight is only analogue after merging if both sets were
+/// analogue. The variants only differ where both sets have a weight.
+///
+/// Relative errors are given for every weight, in exactly the same order, i.e.
+/// with i varying fastest and no 'Total' groups. Mesh voxels are stored in a
+/// different order, so take the errors of the flux mesh used to generate each
+/// set with [mesh_errors()](WeightWindow::mesh_errors).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum WeightMerge {
+    /// Keep the new weight wherever it exists (default)
+    #[default]
+    Fill,
+    /// Keep whichever weight has the lower relative error
+    BestError {
+        /// Relative errors of the new weights
+        errors: Vec<f64>,
+        /// Relative errors of the previous weights
+        previous_errors: Vec<f64>,
+    },
+    /// Geometric mean of the two weights, i.e. the average in log space
+    LogAverage,
+    /// Average in log space, weighted by the inverse variance of each weight
+    ErrorWeighted {
+        /// Relative errors of the new weights
+        errors: Vec<f64>,
+        /// Relative errors of the previous weights
+        previous_errors: Vec<f64>,
+    },
+}
+
+/// Implementations for combining weights between iterations
+impl WeightWindow {
+    /// Check that weights from another set can be combined with this one
+    ///
+    /// Sets are compatible if they are for the same particle type, on the same
+    /// mesh geometry, with the same energy and time bins, and the same number
+    /// of weights.
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let a = WeightWindow::default();
+    /// let mut b = WeightWindow::default();
+    /// assert!(a.check_compatible(&b).is_ok());
+    ///
+    /// // Different energy bins can not be combined
+    /// b.e = vec![1.0, 100.0];
+    /// b.ne = 2;
+    /// assert!(a.check_compatible(&b).is_err());
+    /// ```
+    pub fn check_compatible(&self, other: &WeightWindow) -> Result<()> {
+        if self.particle != other.particle {
+            return Err(incompatible(&f!(
+                "particle types {} and {} differ",
+                self.particle,
+                other.particle
+            )));
+        }
+
+        if !is_geometry_match(self, other) {
+            return Err(incompatible("mesh geometries differ"));
+        }
+
+        if self.ne != other.ne || !same_bounds(&self.e, &other.e) {
+            return Err(incompatible("energy bins differ"));
+        }
+
+        if self.nt != other.nt || !same_bounds(&self.t, &other.t) {
+            return Err(incompatible("time bins differ"));
+        }
+
+        if self.weights.len() != other.weights.len() {
+            return Err(incompatible(&f!(
+                "{} weights do not match {} weights",
+                self.weights.len(),
+                other.weights.len()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Combine the weights with those of a previous iteration
+    ///
+    /// When the flux mesh of an iteration has holes, the weights generated
+    /// from it are analogue where the previous iteration may have had
+    /// perfectly good weights. This keeps the previous weights wherever the
+    /// new set is analogue, and combines weights that exist in both sets
+    /// according to the [WeightMerge] method.
+    ///
+    /// Fails if the sets are not compatible, see
+    /// [check_compatible()](WeightWindow::check_compatible), or if any errors
+    /// provided do not match the number of weights.
+    ///
+    /// Returns the number of weights that were changed.
+    ///
+    /// ```rust
+    /// # use ntools_weights::{WeightWindow, WeightMerge};
+    /// let previous = WeightWindow {
+    ///     weights: vec![0.1, 0.2, 0.0, 0.4],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let mut ww = WeightWindow {
+    ///     weights: vec![0.4, 0.0, 0.0, 0.4],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// // Fill the hole, and average where both sets have weights
+    /// let changed = ww.merge(&previous, &WeightMerge::LogAverage).unwrap();
+    ///
+    /// assert_eq!(changed, 2);
+    /// assert_eq!(ww.weights, vec![0.2, 0.2, 0.0, 0.4]);
+    /// ```
+    ///
+    /// Relative errors decide which weight to keep, or how much each
+    /// contributes to the average:
+    ///
+    /// ```rust
+    /// # use ntools_weights::{WeightWindow, WeightMerge};
+    /// let previous = WeightWindow {
+    ///     weights: vec![0.1, 0.2],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let mut ww = WeightWindow {
+    ///     weights: vec![0.4, 0.3],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// // New weights are only kept where their statistics are better
+    /// let method = WeightMerge::BestError {
+    ///     errors: vec![0.05, 0.5],
+    ///     previous_errors: vec![0.1, 0.1],
+    /// };
+    ///
+    /// ww.merge(&previous, &method).unwrap();
+    /// assert_eq!(ww.weights, vec![0.4, 0.2]);
+    /// ```
+    ///
+    /// Errors from the flux meshes must first be put into weight order:
+    ///
+    /// ```rust, no_run
+    /// # use ntools_mesh::read_target;
+    /// # use ntools_weights::{WeightWindow, WeightMerge};
+    /// let mesh = read_target("./iteration_2.msht", 104).unwrap();
+    /// let previous_mesh = read_target("./iteration_1.msht", 104).unwrap();
+    ///
+    /// let mut ww = WeightWindow::from_mesh(&mesh).unwrap();
+    /// let previous = WeightWindow::from_mesh(&previous_mesh).unwrap();
+    ///
+    /// let method = WeightMerge::BestError {
+    ///     errors: ww.mesh_errors(&mesh).unwrap(),
+    ///     previous_errors: previous.mesh_errors(&previous_mesh).unwrap(),
+    /// };
+    /// ww.merge(&previous, &method).unwrap();
+    /// ```
+    pub fn merge(&mut self, previous: &WeightWindow, method: &WeightMerge) -> Result<usize> {
+        self.check_compatible(previous)?;
+        method.check_errors(self.weights.len())?;
+
+        let mut changed = 0;
+        for (idx, (new, old)) in self.weights.iter_mut().zip(&previous.weights).enumerate() {
+            let merged = match (*new > 0.0, *old > 0.0) {
+                (false, true) => *old,
+                (true, true) => method.combine(idx, *new, *old),
+                _ => continue,
+            };
+
+            if merged != *new {
+                *new = merged;
+                changed += 1;
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+impl WeightMerge {
+    /// Combine two non-zero weights at the same index
+    fn combine(&self, idx: usize, new: f64, old: f64) -> f64 {
+        match self {
+            WeightMerge::Fill => new,
+            WeightMerge::BestError {
+                errors,
+                previous_errors,
+            } => match previous_errors[idx] < errors[idx] {
+                true => old,
+                false => new,
+            },
+            WeightMerge::LogAverage => (new * old).sqrt(),
+            WeightMerge::ErrorWeighted {
+                errors,
+                previous_errors,
+            } => {
+                let a = inverse_variance(errors[idx]);
+                let b = inverse_variance(previous_errors[idx]);
+                ((a * new.ln() + b * old.ln()) / (a + b)).exp()
+            }
+        }
+    }
+
+    /// Errors are needed for every weight
+    fn check_errors(&self, n_weights: usize) -> Result<()> {
+        match self {
+            WeightMerge::BestError {
+                errors,
+                previous_errors,
+            }
+            | WeightMerge::ErrorWeighted {
+                errors,
+                previous_errors,
+            } => {
+                if errors.len() != n_weights || previous_errors.len() != n_weights {
+                    return Err(incompatible(&f!(
+                        "expected {n_weights} errors, found {} and {}",
+                        errors.len(),
+                        previous_errors.len()
+                    )));
+                }
+
+                if errors.iter().chain(previous_errors).any(|e| *e < 0.0) {
+                    return Err(incompatible("relative errors must not be negative"));
+                }
+
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Inverse variance, with zero errors treated as extremely well converged
+fn inverse_variance(error: f64) -> f64 {
+    1.0 / error.max(f64::EPSILON).powi(2)
+}
+
+/// Bin bounds agree to output precision
+fn same_bounds(a: &[f64], b: &[f64]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(a, b)| (a - b).abs() <= 1e-6 * a.abs().max(b.abs()))
+}
+
+fn incompatible(reason: &str) -> Error {
+    Error::IncompatibleWeights {
+        reason: reason.to_string(),
+    }
+}
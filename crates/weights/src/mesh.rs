@@ -0,0 +1,402 @@
+// ntools modules
+use ntools_mesh::{Format, Geometry, Mesh, Particle, Voxel};
+use ntools_utils::f;
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::weight_window::WeightWindow;
+
+/// Implementations for conversion to and from [Mesh]
+impl WeightWindow {
+    /// Convert to a [Mesh] with the weights as voxel results
+    ///
+    /// Every mesh analysis tool then works on the weights too, e.g. point
+    /// queries, slices, statistics, and VTK conversion, and weights read from
+    /// file may be compared directly against the flux mesh used to generate
+    /// them.
+    ///
+    /// The mesh bounds are the fine mesh bounds from
+    /// [fine_mesh_bounds()](WeightWindow::fine_mesh_bounds), and all errors are
+    /// set to 0. Energy groups start from 0, and time groups from -1e36.
+    ///
+    /// Meshes with multiple energy or time groups also need the 'Total'
+    /// groups, which take the lowest non-zero weight of every group they
+    /// include. These are ignored when converting back with
+    /// [from_mesh()](WeightWindow::from_mesh).
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// # use ntools_mesh::Geometry;
+    /// let ww = WeightWindow {
+    ///     nfx: 2, nfy: 1, nfz: 1,
+    ///     ncx: 1, ncy: 1, ncz: 1,
+    ///     qps_x: vec![[1.0, 10.0, 2.0]],
+    ///     qps_y: vec![[1.0, 10.0, 1.0]],
+    ///     qps_z: vec![[1.0, 10.0, 1.0]],
+    ///     e: vec![100.0],
+    ///     weights: vec![0.1, 0.2],
+    ///     particle: 1,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let mesh = ww.to_mesh().unwrap();
+    ///
+    /// assert_eq!(mesh.geometry, Geometry::Rectangular);
+    /// assert_eq!(mesh.imesh, vec![0.0, 5.0, 10.0]);
+    /// assert_eq!(mesh.voxels[1].result, 0.2);
+    /// ```
+    pub fn to_mesh(&self) -> Result<Mesh> {
+        self.check_weights()?;
+
+        let geometry = match self.nwg {
+            1 => Geometry::Rectangular,
+            2 => Geometry::Cylindrical,
+            nwg => return Err(invalid(&f!("no mesh geometry for nwg={nwg}"))),
+        };
+
+        if self.e.len() != self.ne || (self.nt > 1 && self.t.len() != self.nt) {
+            return Err(invalid("group bounds do not match ne/nt"));
+        }
+
+        let [imesh, jmesh, kmesh] = self.fine_mesh_bounds();
+        let mut mesh = Mesh {
+            geometry,
+            particle: Particle::from_id(self.particle),
+            iints: imesh.len() - 1,
+            jints: jmesh.len() - 1,
+            kints: kmesh.len() - 1,
+            imesh,
+            jmesh,
+            kmesh,
+            emesh: std::iter::once(0.0).chain(self.e.iter().copied()).collect(),
+            eints: self.ne,
+            tmesh: match self.nt {
+                1 => Vec::new(),
+                _ => std::iter::once(-1e36)
+                    .chain(self.t.iter().copied())
+                    .collect(),
+            },
+            tints: if self.nt > 1 { self.nt } else { 0 },
+            origin: [self.x0, self.y0, self.z0],
+            axs: [self.x1, self.y1, self.z1],
+            vec: [self.x2, self.y2, self.z2],
+            format: Format::NONE,
+            ..Default::default()
+        };
+
+        // (e,t,i,j,k) loops with k fastest, including any total groups
+        let mut voxels = Vec::with_capacity(mesh.n_voxels_expected());
+        for e_idx in 0..mesh.n_ebins() {
+            for t_idx in 0..mesh.n_tbins() {
+                let groups = self.included_groups(e_idx, t_idx);
+                for i in 0..mesh.iints {
+                    for j in 0..mesh.jints {
+                        for k in 0..mesh.kints {
+                            let idx = self.fine_index(i, j, k);
+                            voxels.push(Voxel {
+                                index: voxels.len(),
+                                result: self.lowest_weight(&groups, idx),
+                                error: 0.0,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        mesh.voxels = voxels;
+        Ok(mesh)
+    }
+
+    /// Convert a [Mesh] directly into a [WeightWindow], using the voxel results as weights
+    ///
+    /// This is the reverse of [to_mesh()](WeightWindow::to_mesh), and no
+    /// normalisation is applied. For generating weights from a flux mesh see
+    /// the `ntools_wwgen` crate.
+    ///
+    /// Any 'Total' groups are ignored, and every mesh bin becomes one coarse
+    /// mesh with a single fine mesh. The weights, geometry, groups, and
+    /// particle type survive the round trip unchanged.
+    ///
+    /// A [Mesh] has no equivalent of the coarse mesh structure, the problem
+    /// description, or the `f`/`ni` header values. These are reset to the
+    /// defaults for a single particle file, with `ni` set to the particle
+    /// type. Use [with_mesh_weights()](WeightWindow::with_mesh_weights) to keep
+    /// everything from the original weight window.
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let ww = WeightWindow {
+    ///     nfx: 2, nfy: 1, nfz: 1,
+    ///     ncx: 2, ncy: 1, ncz: 1,
+    ///     qps_x: vec![[1.0, 5.0, 1.0], [1.0, 10.0, 1.0]],
+    ///     qps_y: vec![[1.0, 10.0, 1.0]],
+    ///     qps_z: vec![[1.0, 10.0, 1.0]],
+    ///     ne: 2,
+    ///     e: vec![1.0, 100.0],
+    ///     weights: vec![0.1, 0.2, 0.3, 0.4],
+    ///     particle: 1,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let copy = WeightWindow::from_mesh(&ww.to_mesh().unwrap()).unwrap();
+    /// assert_eq!(copy, ww);
+    /// ```
+    pub fn from_mesh(mesh: &Mesh) -> Result<WeightWindow> {
+        let (energy_groups, time_groups) = mesh_groups(mesh)?;
+
+        let (nr, nwg) = match mesh.geometry {
+            Geometry::Rectangular => (10, 1),
+            Geometry::Cylindrical => (16, 2),
+        };
+
+        let mut ww = WeightWindow {
+            iv: if time_groups > 1 { 2 } else { 1 },
+            ne: energy_groups,
+            nt: time_groups,
+            nr,
+            nwg,
+            nfx: mesh.iints,
+            nfy: mesh.jints,
+            nfz: mesh.kints,
+            ncx: mesh.iints,
+            ncy: mesh.jints,
+            ncz: mesh.kints,
+            x0: mesh.origin[0],
+            y0: mesh.origin[1],
+            z0: mesh.origin[2],
+            x1: mesh.axs[0],
+            y1: mesh.axs[1],
+            z1: mesh.axs[2],
+            x2: mesh.vec[0],
+            y2: mesh.vec[1],
+            z2: mesh.vec[2],
+            e: mesh.emesh[1..].to_vec(),
+            t: match time_groups {
+                1 => Vec::new(),
+                _ => mesh.tmesh[1..].to_vec(),
+            },
+            qps_x: qps_tuples(&mesh.imesh),
+            qps_y: qps_tuples(&mesh.jmesh),
+            qps_z: qps_tuples(&mesh.kmesh),
+            ni: mesh.particle.id().max(1),
+            particle: mesh.particle.id(),
+            ..Default::default()
+        };
+
+        ww.weights = voxel_values(mesh, energy_groups, time_groups, |v| v.result);
+        Ok(ww)
+    }
+
+    /// Copy of the weight window with the weights replaced by [Mesh] results
+    ///
+    /// This is the lossless reverse of [to_mesh()](WeightWindow::to_mesh).
+    /// Everything except the weights is kept from `self`, including the coarse
+    /// mesh structure, problem description, and header values that a [Mesh]
+    /// can not store.
+    ///
+    /// The mesh must have the same geometry, fine mesh bounds, and number of
+    /// energy and time groups as the weight window. Any 'Total' groups are
+    /// ignored.
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let ww = WeightWindow {
+    ///     nfx: 2, nfy: 1, nfz: 1,
+    ///     ncx: 1, ncy: 1, ncz: 1,
+    ///     qps_x: vec![[1.0, 10.0, 2.0]],
+    ///     qps_y: vec![[1.0, 10.0, 1.0]],
+    ///     qps_z: vec![[1.0, 10.0, 1.0]],
+    ///     e: vec![100.0],
+    ///     weights: vec![0.1, 0.2],
+    ///     probid: "example".to_string(),
+    ///     particle: 1,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let mut mesh = ww.to_mesh().unwrap();
+    /// mesh.scale(2.0);
+    ///
+    /// let scaled = ww.with_mesh_weights(&mesh).unwrap();
+    /// assert_eq!(scaled.weights, vec![0.2, 0.4]);
+    /// assert_eq!(scaled.qps_x, ww.qps_x);
+    /// assert_eq!(scaled.probid, ww.probid);
+    /// ```
+    pub fn with_mesh_weights(&self, mesh: &Mesh) -> Result<WeightWindow> {
+        Ok(WeightWindow {
+            weights: self.mesh_results(mesh)?,
+            ..self.clone()
+        })
+    }
+
+    /// Results of [Mesh] voxels, in the same order as the weights
+    ///
+    /// As for [mesh_errors()](WeightWindow::mesh_errors), but for the voxel
+    /// results. Typically used to provide the fluxes for
+    /// [GroupCollapse::FluxWeighted](crate::GroupCollapse::FluxWeighted).
+    pub fn mesh_results(&self, mesh: &Mesh) -> Result<Vec<f64>> {
+        self.mesh_values(mesh, |v| v.result)
+    }
+
+    /// Relative errors of [Mesh] voxels, in the same order as the weights
+    ///
+    /// Mesh voxels are stored with k varying fastest and include any 'Total'
+    /// groups, while weights have i varying fastest and no totals. This puts
+    /// the errors of the flux mesh used to generate the weights into weight
+    /// order, e.g. for [WeightMerge](crate::WeightMerge) methods.
+    ///
+    /// The mesh must match the weight window, as for
+    /// [with_mesh_weights()](WeightWindow::with_mesh_weights).
+    ///
+    /// ```rust
+    /// # use ntools_mesh::{Mesh, Voxel};
+    /// # use ntools_weights::WeightWindow;
+    /// // Mesh with 2x1x2 voxels, k varying fastest
+    /// let mesh = Mesh {
+    ///     imesh: vec![0.0, 1.0, 2.0], iints: 2,
+    ///     jmesh: vec![0.0, 1.0], jints: 1,
+    ///     kmesh: vec![0.0, 1.0, 2.0], kints: 2,
+    ///     emesh: vec![0.0, 100.0], eints: 1,
+    ///     voxels: (0..4)
+    ///         .map(|index| Voxel { index, result: 1.0, error: (index + 1) as f64 / 10.0 })
+    ///         .collect(),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let ww = WeightWindow::from_mesh(&mesh).unwrap();
+    ///
+    /// // Errors with i varying fastest
+    /// assert_eq!(ww.mesh_errors(&mesh).unwrap(), vec![0.1, 0.3, 0.2, 0.4]);
+    /// ```
+    pub fn mesh_errors(&self, mesh: &Mesh) -> Result<Vec<f64>> {
+        self.mesh_values(mesh, |v| v.error)
+    }
+
+    /// Values of every voxel in weight order, for a mesh matching the weights
+    fn mesh_values(&self, mesh: &Mesh, value: impl Fn(&Voxel) -> f64) -> Result<Vec<f64>> {
+        let (energy_groups, time_groups) = mesh_groups(mesh)?;
+
+        let nwg = match mesh.geometry {
+            Geometry::Rectangular => 1,
+            Geometry::Cylindrical => 2,
+        };
+
+        if nwg != self.nwg {
+            return Err(invalid(&f!(
+                "mesh geometry nwg={nwg} does not match nwg={}",
+                self.nwg
+            )));
+        }
+
+        if energy_groups != self.ne || time_groups != self.nt {
+            return Err(invalid(&f!(
+                "mesh has {energy_groups} energy and {time_groups} time groups, expected {} and {}",
+                self.ne,
+                self.nt
+            )));
+        }
+
+        let [imesh, jmesh, kmesh] = self.fine_mesh_bounds();
+        if !same_bounds(&imesh, &mesh.imesh)
+            || !same_bounds(&jmesh, &mesh.jmesh)
+            || !same_bounds(&kmesh, &mesh.kmesh)
+        {
+            return Err(invalid("mesh bounds do not match the fine mesh bounds"));
+        }
+
+        Ok(voxel_values(mesh, energy_groups, time_groups, value))
+    }
+
+    /// Weight groups making up a mesh group, where the last index is the total
+    fn included_groups(&self, e_idx: usize, t_idx: usize) -> Vec<usize> {
+        let energies = match e_idx < self.ne {
+            true => e_idx..e_idx + 1,
+            false => 0..self.ne,
+        };
+        let times = match t_idx < self.nt {
+            true => t_idx..t_idx + 1,
+            false => 0..self.nt,
+        };
+
+        energies
+            .flat_map(|e| times.clone().map(move |t| e * self.nt + t))
+            .collect()
+    }
+
+    /// Lowest non-zero weight of a fine mesh voxel over several groups
+    fn lowest_weight(&self, groups: &[usize], idx: usize) -> f64 {
+        let n_voxels = self.nfx * self.nfy * self.nfz;
+        groups
+            .iter()
+            .map(|group| self.weights[group * n_voxels + idx])
+            .filter(|w| *w > 0.0)
+            .reduce(f64::min)
+            .unwrap_or(0.0)
+    }
+}
+
+/// Number of energy and time groups on a mesh, excluding any totals
+fn mesh_groups(mesh: &Mesh) -> Result<(usize, usize)> {
+    if mesh.voxels.len() != mesh.n_voxels_expected() {
+        return Err(invalid(&f!(
+            "found {} voxels, expected {}",
+            mesh.voxels.len(),
+            mesh.n_voxels_expected()
+        )));
+    }
+
+    if mesh.emesh.len() < 2 {
+        return Err(invalid("no energy bounds on the mesh"));
+    }
+
+    // total groups are only present for multiple bins
+    let energy_groups = if mesh.eints > 1 { mesh.eints } else { 1 };
+    let time_groups = if mesh.tints > 1 { mesh.tints } else { 1 };
+    Ok((energy_groups, time_groups))
+}
+
+/// Voxel values in weight order, i.e. cell order with i fastest per group
+fn voxel_values(
+    mesh: &Mesh,
+    energy_groups: usize,
+    time_groups: usize,
+    value: impl Fn(&Voxel) -> f64,
+) -> Vec<f64> {
+    let mut values = Vec::with_capacity(energy_groups * time_groups * mesh.n_voxels_per_group());
+    for e_idx in 0..energy_groups {
+        for t_idx in 0..time_groups {
+            for k in 0..mesh.kints {
+                for j in 0..mesh.jints {
+                    for i in 0..mesh.iints {
+                        let idx = mesh.voxel_index_from_etijk(e_idx, t_idx, i, j, k);
+                        values.push(value(&mesh.voxels[idx]));
+                    }
+                }
+            }
+        }
+    }
+    values
+}
+
+/// Bounds equal to within floating point precision
+fn same_bounds(a: &[f64], b: &[f64]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(a, b)| (a - b).abs() <= 1e-9 * a.abs().max(1.0))
+}
+
+/// One fine mesh per coarse mesh for each of the bounds
+fn qps_tuples(bounds: &[f64]) -> Vec<[f64; 3]> {
+    bounds
+        .iter()
+        .skip(1)
+        .map(|bound| [1.0, *bound, 1.0])
+        .collect()
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::MeshConversion {
+        reason: reason.to_string(),
+    }
+}
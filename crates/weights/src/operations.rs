@@ -1,13 +1,18 @@
 // standard library
 use std::fs::File;
 use std::io::{BufWriter, Write};
+use std::path::Path;
 
 // internal modules
-use crate::weight_window::WeightWindow;
+use crate::error::{Error, Result};
+use crate::weight_window::{probid_columns, WeightWindow};
 
 // ntools modules
 use ntools_utils::f;
 
+// extrenal crates
+use log::warn;
+
 /// Convenience function for writing a [WeightWindow] into a single wwout file
 ///
 /// A [WeightWindow] instance corresponds to a full set of weight windows for a
@@ -67,44 +72,217 @@ pub fn write_single_particle(weight_window: &WeightWindow, output: &str) {
 /// let weight_window = write_multi_particle(&ww_sets, "wwout_NP", false);
 /// ```
 pub fn write_multi_particle(weight_windows: &[WeightWindow], output: &str, padded: bool) {
-    let ww_list = preprocess_set(weight_windows);
+    let (ww_list, dropped) = preprocess_set(weight_windows, false);
+
+    for reason in dropped {
+        warn!("Warning: {reason}, set not written");
+    }
+
+    write_combined(&ww_list, output, padded).expect("Unable to write file");
+}
+
+/// Fallible version of [write_single_particle()]
+///
+/// See [try_write()](WeightWindow::try_write) for details.
+pub fn try_write_single_particle(
+    weight_window: &WeightWindow,
+    output: impl AsRef<Path>,
+) -> Result<()> {
+    weight_window.try_write(output)
+}
+
+/// Fallible version of [write_multi_particle()]
+///
+/// The same rules apply for combining sets, but nothing is dropped silently.
+/// Every set that could not be written is returned as an [Error] explaining
+/// why, including sets where the number of weights does not match the mesh,
+/// energy, and time bins.
+///
+/// An error is only returned for I/O failures or if no valid sets remain.
+///
+/// ```rust
+/// # use ntools_weights::{Error, WeightWindow, try_write_multi_particle};
+/// let neutron = WeightWindow {
+///     nfx: 2, nfy: 1, nfz: 1,
+///     ncx: 1, ncy: 1, ncz: 1,
+///     qps_x: vec![[1.0, 10.0, 2.0]],
+///     qps_y: vec![[1.0, 10.0, 1.0]],
+///     qps_z: vec![[1.0, 10.0, 1.0]],
+///     e: vec![100.0],
+///     weights: vec![0.5, 0.25],
+///     particle: 1,
+///     ..Default::default()
+/// };
+///
+/// // Photons on the same mesh
+/// let photon = WeightWindow {
+///     particle: 2,
+///     ..neutron.clone()
+/// };
+///
+/// // Electrons with too many weights for the mesh
+/// let electron = WeightWindow {
+///     particle: 3,
+///     weights: vec![0.5, 0.25, 0.1],
+///     ..neutron.clone()
+/// };
+///
+/// // Write a combined NP weight window file, with the electrons dropped
+/// let path = std::env::temp_dir().join("ntools_try_write_multi_particle_example");
+/// let dropped = try_write_multi_particle(&[neutron, photon, electron], path, false).unwrap();
+///
+/// assert_eq!(dropped.len(), 1);
+/// assert!(matches!(
+///     dropped[0],
+///     Error::WeightCountMismatch { particle: 3, expected: 2, found: 3 }
+/// ));
+/// ```
+pub fn try_write_multi_particle(
+    weight_windows: &[WeightWindow],
+    output: impl AsRef<Path>,
+    padded: bool,
+) -> Result<Vec<Error>> {
+    let (ww_list, dropped) = preprocess_set(weight_windows, true);
+
+    if ww_list.is_empty() {
+        return Err(Error::NoWeightWindows);
+    }
+
+    write_combined(&ww_list, output, padded)?;
+    Ok(dropped)
+}
+
+/// Check that every set can be combined into a single wwout file
+///
+/// The same rules apply as for [try_write_multi_particle()], but nothing is
+/// written. The first reason any set would be dropped is returned as an
+/// [Error], so a strict write can be validated before creating the file.
+///
+/// ```rust
+/// # use ntools_weights::{check_multi_particle, Error, WeightWindow};
+/// let neutron = WeightWindow {
+///     nfx: 2, nfy: 1, nfz: 1,
+///     e: vec![100.0],
+///     weights: vec![0.5, 0.25],
+///     particle: 1,
+///     ..Default::default()
+/// };
+///
+/// // Photons on a different mesh
+/// let photon = WeightWindow {
+///     particle: 2,
+///     nfx: 1,
+///     weights: vec![0.5],
+///     ..neutron.clone()
+/// };
+///
+/// assert!(check_multi_particle(&[neutron.clone()]).is_ok());
+/// assert!(matches!(
+///     check_multi_particle(&[neutron, photon]),
+///     Err(Error::InconsistentGeometry { particle: 2, target: 1 })
+/// ));
+/// ```
+pub fn check_multi_particle(weight_windows: &[WeightWindow]) -> Result<()> {
+    let (ww_list, dropped) = preprocess_set(weight_windows, true);
+
+    if let Some(e) = dropped.into_iter().next() {
+        return Err(e);
+    }
 
-    // assume fine >2 meshes for now
-    let f = File::create(output).expect("Unable to create file");
+    match ww_list.is_empty() {
+        true => Err(Error::NoWeightWindows),
+        false => Ok(()),
+    }
+}
+
+/// Write the combined blocks of a multi-particle file
+fn write_combined(ww_list: &[&WeightWindow], output: impl AsRef<Path>, padded: bool) -> Result<()> {
+    let f = File::create(output)?;
     let mut f = BufWriter::new(f);
 
     // block 1
-    f.write_all(combined_header(&ww_list, padded).as_bytes())
-        .unwrap();
-    f.write_all(ww_list[0].block_1().as_bytes()).unwrap();
+    f.write_all(combined_header(ww_list, padded).as_bytes())?;
+    f.write_all(ww_list[0].block_1().as_bytes())?;
 
     // block 2
-    f.write_all(ww_list[0].block_2().as_bytes()).unwrap();
+    f.write_all(ww_list[0].block_2().as_bytes())?;
 
     // block 3
     for ww in ww_list {
-        f.write_all(ww.block_3().as_bytes()).unwrap();
+        f.write_all(ww.block_3().as_bytes())?;
     }
+
+    f.flush()?;
+    Ok(())
 }
 
 /// Sort by particle type, remove duplicates, and ensure geometry match
-fn preprocess_set(weight_windows: &[WeightWindow]) -> Vec<&WeightWindow> {
+///
+/// Returns the sets that can be combined, and the reason for every set that
+/// was dropped. Weight counts are only checked if `check_weights` is set.
+fn preprocess_set(
+    weight_windows: &[WeightWindow],
+    check_weights: bool,
+) -> (Vec<&WeightWindow>, Vec<Error>) {
     let mut ww_list = weight_windows.iter().collect::<Vec<&WeightWindow>>();
+    let mut dropped = Vec::new();
 
-    // Sort by particle type and get rid of any duplicates
+    // Sort by particle type, stable so the first of any duplicates is kept
     ww_list.sort_by_key(|&k| k.particle);
-    ww_list.dedup_by_key(|k| k.particle);
+
+    // Get rid of unknown particle types and any duplicates
+    let mut previous = None;
+    ww_list.retain(|&ww| {
+        if ww.particle == 0 {
+            dropped.push(Error::UnknownParticle {
+                particle: ww.particle,
+            });
+            false
+        } else if previous == Some(ww.particle) {
+            dropped.push(Error::DuplicateParticle {
+                particle: ww.particle,
+            });
+            false
+        } else {
+            previous = Some(ww.particle);
+            true
+        }
+    });
+
+    // Get rid of any with the wrong number of weights
+    if check_weights {
+        ww_list.retain(|&ww| match ww.check_weights() {
+            Ok(()) => true,
+            Err(e) => {
+                dropped.push(e);
+                false
+            }
+        });
+    }
 
     // Get rid of any that do not mach the mesh geometry
-    let target = ww_list[0];
-    ww_list.retain(|&ww| is_geometry_match(ww, target));
-    ww_list.retain(|&ww| ww.particle != 0);
-    ww_list
+    if let Some(&target) = ww_list.first() {
+        ww_list.retain(|&ww| {
+            let is_match = is_geometry_match(ww, target);
+            if !is_match {
+                dropped.push(Error::InconsistentGeometry {
+                    particle: ww.particle,
+                    target: target.particle,
+                });
+            }
+            is_match
+        });
+    }
+
+    (ww_list, dropped)
 }
 
 /// The wwout file forces the same geometry for every weight set
-fn is_geometry_match(a: &WeightWindow, b: &WeightWindow) -> bool {
+///
+/// VEC is only written for cylindrical meshes, so is ignored for rectangular.
+pub(crate) fn is_geometry_match(a: &WeightWindow, b: &WeightWindow) -> bool {
     if a.nr  != b.nr  // words (meshtype)
+        || a.nwg != b.nwg
         || a.nfx != b.nfx
         || a.nfy != b.nfy
         || a.nfz != b.nfz
@@ -114,6 +292,7 @@ fn is_geometry_match(a: &WeightWindow, b: &WeightWindow) -> bool {
         || a.x1  != b.x1
         || a.y1  != b.y1
         || a.z1  != b.z1
+        || (a.nwg != 1 && [a.x2, a.y2, a.z2] != [b.x2, b.y2, b.z2]) // VEC
         || a.qps_x != b.qps_x
         || a.qps_y != b.qps_y
         || a.qps_z != b.qps_z
@@ -152,7 +331,9 @@ fn combined_header(weight_windows: &[&WeightWindow], padded: bool) -> String {
     };
 
     // if iv ni nr probid
-    let mut s = f!("{:>10}{:>10}{:>10}{:>10}\n", base.f, iv, ne.len(), base.nr,);
+    let mut s = f!("{:>10}{:>10}{:>10}{:>10}", base.f, iv, ne.len(), base.nr,);
+    s += &probid_columns(&base.probid);
+    s += "\n";
 
     // nt(1) ... nt(ni) [if iv=2]
     let mut count: u8 = 1;
@@ -0,0 +1,332 @@
+// standard library
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::Path;
+
+// ntools modules
+use ntools_utils::f;
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::weight_window::WeightWindow;
+
+/// Read every weight window set from a WWINP/WWOUT/WWONE file
+///
+/// Parses the full block 1/2/3 format outlined in the appendices of the MCNP
+/// user manuals, returning one [WeightWindow] per particle type in the file.
+///
+/// This includes:
+/// - multi-particle files, with particle types set from their position in the
+///   header and any types with no energy groups skipped
+/// - coarse/fine mesh structures, kept as the `qps` tuples of block 2
+/// - time dependent windows (`iv=2`)
+/// - rectangular, cylindrical, and spherical meshes (`nwg=1,2,3`)
+///
+/// ```rust, no_run
+/// # use ntools_weights::read_wwinp;
+/// // Read all weight window sets from a file
+/// let weight_windows = read_wwinp("path/to/wwout").unwrap();
+///
+/// for ww in &weight_windows {
+///     println!("particle {}: {} weights", ww.particle, ww.weights.len());
+/// }
+/// ```
+///
+/// Writing out what was read reproduces the file. Use
+/// [write()](WeightWindow::write) for single particle files, and
+/// [write_multi_particle()](crate::write_multi_particle) for files with
+/// several particle types.
+///
+/// ```rust
+/// # use ntools_weights::{read_wwinp, WeightWindow};
+/// let ww = WeightWindow {
+///     nfx: 2, nfy: 1, nfz: 1,
+///     ncx: 1, ncy: 1, ncz: 1,
+///     qps_x: vec![[1.0, 10.0, 2.0]],
+///     qps_y: vec![[1.0, 10.0, 1.0]],
+///     qps_z: vec![[1.0, 10.0, 1.0]],
+///     e: vec![100.0],
+///     weights: vec![0.1, 0.2],
+///     particle: 1,
+///     ..Default::default()
+/// };
+///
+/// let path = std::env::temp_dir().join("ntools_read_wwinp_example");
+/// ww.write(path.to_str().unwrap());
+///
+/// let copy = read_wwinp(&path).unwrap();
+/// assert_eq!(copy, vec![ww]);
+/// ```
+pub fn read_wwinp(path: impl AsRef<Path>) -> Result<Vec<WeightWindow>> {
+    let file = File::open(path.as_ref())?;
+    parse(BufReader::new(file))
+}
+
+/// Parse weight window sets from any buffered reader
+fn parse<R: BufRead>(reader: R) -> Result<Vec<WeightWindow>> {
+    let mut values = Values::new(reader);
+
+    // if iv ni nr probid
+    let header = values
+        .next_line()?
+        .ok_or_else(|| invalid("empty file".to_string()))?;
+    let Header {
+        f,
+        iv,
+        ni,
+        nr,
+        probid,
+    } = Header::parse(&header)?;
+
+    // nt(1) ... nt(ni) [if iv=2], ne(1) ... ne(ni)
+    let nt = match iv {
+        2 => values.counts(ni, "number of time bins")?,
+        _ => vec![1; ni],
+    };
+    let ne = values.counts(ni, "number of energy bins")?;
+
+    // nfx nfy nfz x0 y0 z0
+    let [nfx, nfy, nfz] = values.counts_3("fine mesh counts")?;
+    let [x0, y0, z0] = values.floats_3("origin")?;
+
+    // ncx ncy ncz nwg [if nr=10], or ncx ncy ncz x1 y1 z1 x2 y2 z2 nwg [if nr=16]
+    let [ncx, ncy, ncz] = values.counts_3("coarse mesh counts")?;
+    let base = WeightWindow::default();
+    let ([x1, y1, z1], [x2, y2, z2]) = match nr {
+        10 => ([base.x1, base.y1, base.z1], [base.x2, base.y2, base.z2]),
+        16 => (values.floats_3("axs")?, values.floats_3("vec")?),
+        _ => return Err(values.error(&f!("unknown number of mesh words nr={nr}"))),
+    };
+    let nwg = values.count("mesh type")?;
+    if !(1..=3).contains(&nwg) {
+        return Err(values.error(&f!("unknown mesh type nwg={nwg}")));
+    }
+
+    // block 2, the leading origin of each is already known from block 1
+    let qps_x = values.qps(ncx, nfx, "x")?;
+    let qps_y = values.qps(ncy, nfy, "y")?;
+    let qps_z = values.qps(ncz, nfz, "z")?;
+
+    // block 3, for every particle type with any energy bins
+    let mut weight_windows = Vec::new();
+    for (i, (nt, ne)) in nt.into_iter().zip(ne).enumerate() {
+        if ne == 0 {
+            continue;
+        }
+
+        // t(i,1) ... t(i,nt(i)) [if nt(i)>1]
+        let t = match nt > 1 {
+            true => values.floats(nt, "time bounds")?,
+            false => Vec::new(),
+        };
+        let e = values.floats(ne, "energy bounds")?;
+        let weights = values.floats(nfx * nfy * nfz * ne * nt, "weights")?;
+
+        weight_windows.push(WeightWindow {
+            f,
+            iv,
+            ni: ni as u8,
+            ne,
+            nt,
+            nr,
+            nwg: nwg as u8,
+            probid: probid.clone(),
+            nfx,
+            nfy,
+            nfz,
+            ncx,
+            ncy,
+            ncz,
+            x0,
+            y0,
+            z0,
+            x1,
+            y1,
+            z1,
+            x2,
+            y2,
+            z2,
+            e,
+            t,
+            qps_x: qps_x.clone(),
+            qps_y: qps_y.clone(),
+            qps_z: qps_z.clone(),
+            weights,
+            particle: (i + 1) as u8,
+        });
+    }
+
+    if values.next()?.is_some() {
+        return Err(values.error("unexpected values after the last weight window set"));
+    }
+
+    Ok(weight_windows)
+}
+
+/// First line of the file, formatted as `4i10, 20x, a19`
+struct Header {
+    f: u8,
+    iv: u8,
+    ni: usize,
+    nr: u8,
+    probid: String,
+}
+
+impl Header {
+    fn parse(line: &str) -> Result<Self> {
+        // fixed width columns, falling back to whitespace for hand written files
+        let (numbers, probid) = match line.get(..40).map(|s| s.split_whitespace().count()) {
+            Some(4) => (line[..40].to_string(), Self::probid(&line[40..])),
+            _ => {
+                let mut tokens = line.split_whitespace();
+                let numbers = tokens.by_ref().take(4).collect::<Vec<&str>>().join(" ");
+                (numbers, tokens.collect::<Vec<&str>>().join(" "))
+            }
+        };
+
+        let numbers = numbers
+            .split_whitespace()
+            .map(|n| n.parse::<u8>())
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|_| invalid(f!("line 1: unable to parse header \"{line}\"")))?;
+
+        match numbers[..] {
+            [f, iv, ni, nr] => Ok(Self {
+                f,
+                iv,
+                ni: ni as usize,
+                nr,
+                probid,
+            }),
+            _ => Err(invalid(f!(
+                "line 1: expected \"if iv ni nr\", found \"{line}\""
+            ))),
+        }
+    }
+
+    /// Problem description following the 20 blank columns
+    fn probid(text: &str) -> String {
+        let text = text.trim_end();
+        match text.strip_prefix(&" ".repeat(20)) {
+            Some(probid) => probid.to_string(),
+            None => text.trim_start().to_string(),
+        }
+    }
+}
+
+/// Whitespace separated values of a weight window file, read line by line
+struct Values<R: BufRead> {
+    lines: Lines<R>,
+    pending: std::vec::IntoIter<String>,
+    line: usize,
+}
+
+impl<R: BufRead> Values<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            pending: Vec::new().into_iter(),
+            line: 0,
+        }
+    }
+
+    /// Next full line of the file
+    fn next_line(&mut self) -> Result<Option<String>> {
+        let line = self.lines.next().transpose()?;
+        if line.is_some() {
+            self.line += 1;
+        }
+        Ok(line)
+    }
+
+    /// Next value of the file, wherever it is
+    fn next(&mut self) -> Result<Option<String>> {
+        loop {
+            if let Some(value) = self.pending.next() {
+                return Ok(Some(value));
+            }
+
+            match self.next_line()? {
+                Some(line) => {
+                    self.pending = line
+                        .split_whitespace()
+                        .map(String::from)
+                        .collect::<Vec<String>>()
+                        .into_iter()
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn float(&mut self, what: &str) -> Result<f64> {
+        let value = self
+            .next()?
+            .ok_or_else(|| self.error(&f!("unexpected end of file reading {what}")))?;
+
+        parse_float(&value).ok_or_else(|| self.error(&f!("unable to parse {what} \"{value}\"")))
+    }
+
+    fn floats(&mut self, n: usize, what: &str) -> Result<Vec<f64>> {
+        let mut values = Vec::with_capacity(n);
+        for _ in 0..n {
+            values.push(self.float(what)?);
+        }
+        Ok(values)
+    }
+
+    fn floats_3(&mut self, what: &str) -> Result<[f64; 3]> {
+        Ok([self.float(what)?, self.float(what)?, self.float(what)?])
+    }
+
+    /// Counts are integers, but block 1 writes them in the same `g13.5` format
+    fn count(&mut self, what: &str) -> Result<usize> {
+        let value = self.float(what)?;
+        match value >= 0.0 && value.fract() == 0.0 {
+            true => Ok(value as usize),
+            false => Err(self.error(&f!("expected a whole number for {what}, found {value}"))),
+        }
+    }
+
+    fn counts(&mut self, n: usize, what: &str) -> Result<Vec<usize>> {
+        (0..n).map(|_| self.count(what)).collect()
+    }
+
+    fn counts_3(&mut self, what: &str) -> Result<[usize; 3]> {
+        Ok([self.count(what)?, self.count(what)?, self.count(what)?])
+    }
+
+    /// Origin followed by `(q, p, s)` for every coarse mesh
+    fn qps(&mut self, n_coarse: usize, n_fine: usize, axis: &str) -> Result<Vec<[f64; 3]>> {
+        self.float(&f!("{axis} origin"))?;
+
+        let what = f!("{axis} coarse mesh");
+        let qps = (0..n_coarse)
+            .map(|_| self.floats_3(&what))
+            .collect::<Result<Vec<[f64; 3]>>>()?;
+
+        let total = qps.iter().map(|q| q[2]).sum::<f64>();
+        match total == n_fine as f64 {
+            true => Ok(qps),
+            false => Err(self.error(&f!(
+                "{total} fine meshes in {axis} do not match the {n_fine} of block 1"
+            ))),
+        }
+    }
+
+    fn error(&self, reason: &str) -> Error {
+        invalid(f!("line {}: {reason}", self.line))
+    }
+}
+
+/// Parse a float, including fortran exponents that drop the 'E' (1.0-100)
+fn parse_float(value: &str) -> Option<f64> {
+    value.parse::<f64>().ok().or_else(|| {
+        let idx = value.rfind(['+', '-']).filter(|idx| *idx > 0)?;
+        f!("{}E{}", &value[..idx], &value[idx..]).parse().ok()
+    })
+}
+
+fn invalid(reason: String) -> Error {
+    Error::InvalidWwinp { reason }
+}
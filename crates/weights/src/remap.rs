@@ -0,0 +1,401 @@
+// ntools modules
+use ntools_utils::f;
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::weight_window::WeightWindow;
+
+// extrenal crates
+use nalgebra::Vector3;
+
+/// Mesh geometry definition for spatial remapping
+///
+/// Bounds follow the same conventions as FMESH cards and
+/// [fine_mesh_bounds()](WeightWindow::fine_mesh_bounds):
+///
+/// - Rectangular (`nwg=1`) bounds are absolute (x, y, z) coordinates aligned
+///   with the global axes, and `axs`/`vec` are ignored.
+/// - Cylindrical (`nwg=2`) bounds are (r, z, theta) relative to the `origin`
+///   at the bottom centre, all starting at 0 with theta in revolutions. The
+///   `axs` vector is the cylinder axis and `vec` sets where theta is 0.
+///
+/// ```rust
+/// # use ntools_weights::Grid;
+/// // 10x10x10 cm cube split into 2x2x2 voxels
+/// let rectangular = Grid::rectangular(
+///     vec![0.0, 5.0, 10.0],
+///     vec![0.0, 5.0, 10.0],
+///     vec![0.0, 5.0, 10.0],
+/// );
+///
+/// // Cylinder along the z axis with 2 radial, 1 axial, and 4 theta bins
+/// let cylindrical = Grid::cylindrical(
+///     [0.0, 0.0, -10.0],
+///     [0.0, 0.0, 1.0],
+///     [1.0, 0.0, 0.0],
+///     vec![0.0, 5.0, 10.0],
+///     vec![0.0, 20.0],
+///     vec![0.0, 0.25, 0.5, 0.75, 1.0],
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    /// Mesh type 1=rec, 2=cyl
+    pub nwg: u8,
+    /// Corner of (x,y,z) rec, bottom center of (r,z,t) cyl
+    pub origin: [f64; 3],
+    /// Cylinder axis
+    pub axs: [f64; 3],
+    /// Direction of theta=0 for cylinders
+    pub vec: [f64; 3],
+    /// Bounds of the first dimension, x or r
+    pub imesh: Vec<f64>,
+    /// Bounds of the second dimension, y or z
+    pub jmesh: Vec<f64>,
+    /// Bounds of the third dimension, z or theta
+    pub kmesh: Vec<f64>,
+}
+
+impl Grid {
+    /// Rectangular grid from absolute (x, y, z) bounds
+    pub fn rectangular(imesh: Vec<f64>, jmesh: Vec<f64>, kmesh: Vec<f64>) -> Self {
+        Self {
+            nwg: 1,
+            origin: [
+                imesh.first().copied().unwrap_or_default(),
+                jmesh.first().copied().unwrap_or_default(),
+                kmesh.first().copied().unwrap_or_default(),
+            ],
+            axs: [0.0, 0.0, 1.0],
+            vec: [1.0, 0.0, 0.0],
+            imesh,
+            jmesh,
+            kmesh,
+        }
+    }
+
+    /// Cylindrical grid from (r, z, theta) bounds relative to the origin
+    pub fn cylindrical(
+        origin: [f64; 3],
+        axs: [f64; 3],
+        vec: [f64; 3],
+        imesh: Vec<f64>,
+        jmesh: Vec<f64>,
+        kmesh: Vec<f64>,
+    ) -> Self {
+        Self {
+            nwg: 2,
+            origin,
+            axs,
+            vec,
+            imesh,
+            jmesh,
+            kmesh,
+        }
+    }
+
+    /// Make sure the bounds describe a valid mesh
+    fn check(&self) -> Result<()> {
+        for (name, bounds) in [
+            ("imesh", &self.imesh),
+            ("jmesh", &self.jmesh),
+            ("kmesh", &self.kmesh),
+        ] {
+            if bounds.len() < 2 || bounds.windows(2).any(|w| w[1] <= w[0]) {
+                return Err(invalid(&f!("{name} needs at least 2 increasing bounds")));
+            }
+        }
+
+        match self.nwg {
+            1 => Ok(()),
+            2 if self.imesh[0] != 0.0 || self.jmesh[0] != 0.0 => {
+                Err(invalid("cylindrical r and z bounds must start at 0"))
+            }
+            2 if self.kmesh[0] != 0.0 || self.kmesh[self.kmesh.len() - 1] != 1.0 => Err(invalid(
+                "cylindrical theta bounds must cover 0 to 1 revolution",
+            )),
+            2 => Ok(()),
+            nwg => Err(invalid(&f!("unsupported mesh type nwg={nwg}"))),
+        }
+    }
+}
+
+/// How weights are projected onto the voxels of a new mesh
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpatialRemap {
+    /// Weight of the original voxel containing the new voxel centre (default)
+    #[default]
+    Centre,
+    /// Lowest non-zero weight over the volume of the new voxel
+    ///
+    /// The volume is sampled with n points per dimension, placed so that
+    /// every sample represents an equal volume of the voxel.
+    VolumeMinimum(usize),
+}
+
+/// Implementations for spatial remapping onto a new mesh
+impl WeightWindow {
+    /// Project the weights onto a new mesh geometry
+    ///
+    /// Useful for reusing existing weights after small changes to the model.
+    /// Every energy/time group is projected with the chosen [SpatialRemap]
+    /// method, keeping the original group structure, and any part of the new
+    /// mesh outside of the original is set to 0 (analogue).
+    ///
+    /// The original may be rectangular or cylindrical with any coarse/fine
+    /// mesh structure, as given by
+    /// [fine_mesh_bounds()](WeightWindow::fine_mesh_bounds). The new weight
+    /// window has one fine mesh per coarse mesh.
+    ///
+    /// ```rust
+    /// # use ntools_weights::{WeightWindow, Grid, SpatialRemap};
+    /// let ww = WeightWindow {
+    ///     nfx: 2, nfy: 1, nfz: 1,
+    ///     ncx: 1, ncy: 1, ncz: 1,
+    ///     qps_x: vec![[1.0, 10.0, 2.0]],
+    ///     qps_y: vec![[1.0, 10.0, 1.0]],
+    ///     qps_z: vec![[1.0, 10.0, 1.0]],
+    ///     e: vec![100.0],
+    ///     weights: vec![0.1, 0.2],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// // Shifted mesh with 4 voxels in x
+    /// let grid = Grid::rectangular(
+    ///     vec![2.0, 4.0, 6.0, 8.0, 14.0],
+    ///     vec![0.0, 10.0],
+    ///     vec![0.0, 10.0],
+    /// );
+    ///
+    /// let centre = ww.remap(&grid, SpatialRemap::Centre).unwrap();
+    /// assert_eq!(centre.weights, vec![0.1, 0.2, 0.2, 0.0]);
+    ///
+    /// let minimum = ww.remap(&grid, SpatialRemap::VolumeMinimum(4)).unwrap();
+    /// assert_eq!(minimum.weights, vec![0.1, 0.1, 0.2, 0.2]);
+    /// ```
+    pub fn remap(&self, grid: &Grid, method: SpatialRemap) -> Result<WeightWindow> {
+        self.check_weights()?;
+        grid.check()?;
+
+        if !matches!(self.nwg, 1 | 2) {
+            return Err(invalid(&f!("unsupported mesh type nwg={}", self.nwg)));
+        }
+
+        let samples = match method {
+            SpatialRemap::Centre => 1,
+            SpatialRemap::VolumeMinimum(n) => n.max(1),
+        };
+
+        let frame = Frame::new(grid.origin, grid.axs, grid.vec);
+        let source = Frame::new(
+            [self.x0, self.y0, self.z0],
+            [self.x1, self.y1, self.z1],
+            [self.x2, self.y2, self.z2],
+        );
+        let bounds = self.fine_mesh_bounds();
+
+        // original voxel index for every sample point of every new voxel
+        let mut voxels = Vec::new();
+        for k in 0..grid.kmesh.len() - 1 {
+            for j in 0..grid.jmesh.len() - 1 {
+                for i in 0..grid.imesh.len() - 1 {
+                    let points = Self::sample_points(grid, [i, j, k], samples, method);
+                    voxels.push(
+                        points
+                            .iter()
+                            .map(|local| {
+                                let global = frame.to_global(grid.nwg, *local);
+                                self.locate(&bounds, source.to_local(self.nwg, global))
+                            })
+                            .collect::<Vec<Option<usize>>>(),
+                    );
+                }
+            }
+        }
+
+        let n_voxels = self.nfx * self.nfy * self.nfz;
+        let mut weights = Vec::with_capacity(voxels.len() * self.ne * self.nt);
+        for group in self.weights.chunks(n_voxels) {
+            weights.extend(voxels.iter().map(|found| {
+                match method {
+                    SpatialRemap::Centre => found[0].map(|idx| group[idx]).unwrap_or(0.0),
+                    SpatialRemap::VolumeMinimum(_) => found
+                        .iter()
+                        .flatten()
+                        .map(|idx| group[*idx])
+                        .filter(|w| *w > 0.0)
+                        .reduce(f64::min)
+                        .unwrap_or(0.0),
+                }
+            }));
+        }
+
+        let qps = |bounds: &[f64]| -> Vec<[f64; 3]> {
+            bounds[1..].iter().map(|b| [1.0, *b, 1.0]).collect()
+        };
+        let [x0, y0, z0] = grid.origin;
+        let ([x1, y1, z1], [x2, y2, z2]) = match grid.nwg {
+            1 => {
+                let base = WeightWindow::default();
+                ([base.x1, base.y1, base.z1], [base.x2, base.y2, base.z2])
+            }
+            _ => (grid.axs, grid.vec),
+        };
+
+        Ok(WeightWindow {
+            f: self.f,
+            iv: self.iv,
+            ni: self.ni,
+            ne: self.ne,
+            nt: self.nt,
+            nr: if grid.nwg == 1 { 10 } else { 16 },
+            nwg: grid.nwg,
+            probid: self.probid.clone(),
+            nfx: grid.imesh.len() - 1,
+            nfy: grid.jmesh.len() - 1,
+            nfz: grid.kmesh.len() - 1,
+            ncx: grid.imesh.len() - 1,
+            ncy: grid.jmesh.len() - 1,
+            ncz: grid.kmesh.len() - 1,
+            x0,
+            y0,
+            z0,
+            x1,
+            y1,
+            z1,
+            x2,
+            y2,
+            z2,
+            e: self.e.clone(),
+            t: self.t.clone(),
+            qps_x: qps(&grid.imesh),
+            qps_y: qps(&grid.jmesh),
+            qps_z: qps(&grid.kmesh),
+            weights,
+            particle: self.particle,
+        })
+    }
+
+    /// Sample points of a voxel in the local coordinates of the grid
+    fn sample_points(
+        grid: &Grid,
+        [i, j, k]: [usize; 3],
+        n: usize,
+        method: SpatialRemap,
+    ) -> Vec<[f64; 3]> {
+        let fractions = (0..n)
+            .map(|s| (s as f64 + 0.5) / n as f64)
+            .collect::<Vec<f64>>();
+        let between =
+            |bounds: &[f64], idx: usize, f: f64| bounds[idx] + f * (bounds[idx + 1] - bounds[idx]);
+
+        // equal volume radii for cylinders, except the centre which is the
+        // usual mid-point of the voxel
+        let radius = |f: f64| match (grid.nwg, method) {
+            (2, SpatialRemap::VolumeMinimum(_)) => {
+                let (r0, r1) = (grid.imesh[i], grid.imesh[i + 1]);
+                (r0 * r0 + f * (r1 * r1 - r0 * r0)).sqrt()
+            }
+            _ => between(&grid.imesh, i, f),
+        };
+
+        let mut points = Vec::with_capacity(n * n * n);
+        for c in &fractions {
+            for b in &fractions {
+                for a in &fractions {
+                    points.push([
+                        radius(*a),
+                        between(&grid.jmesh, j, *b),
+                        between(&grid.kmesh, k, *c),
+                    ]);
+                }
+            }
+        }
+        points
+    }
+
+    /// Fine mesh index within a group containing a point in local coordinates
+    fn locate(&self, bounds: &[Vec<f64>; 3], local: [f64; 3]) -> Option<usize> {
+        let i = bin(&bounds[0], local[0])?;
+        let j = bin(&bounds[1], local[1])?;
+        let k = bin(&bounds[2], local[2])?;
+        Some(self.fine_index(i, j, k))
+    }
+}
+
+/// Bin containing a value, including the very last bound
+fn bin(bounds: &[f64], value: f64) -> Option<usize> {
+    let last = bounds.len().checked_sub(1)?;
+    if value < bounds[0] || value > bounds[last] {
+        return None;
+    }
+
+    let idx = bounds.partition_point(|b| *b <= value);
+    Some(idx.clamp(1, last) - 1)
+}
+
+/// Orthonormal frame for converting between local and global coordinates
+struct Frame {
+    origin: Vector3<f64>,
+    axis: Vector3<f64>,
+    reference: Vector3<f64>,
+    normal: Vector3<f64>,
+}
+
+impl Frame {
+    fn new(origin: [f64; 3], axs: [f64; 3], vec: [f64; 3]) -> Self {
+        let axis = Vector3::from(axs)
+            .try_normalize(0.0)
+            .unwrap_or(Vector3::z());
+
+        // theta=0 direction, made perpendicular to the axis
+        let vec = Vector3::from(vec);
+        let reference = (vec - axis * axis.dot(&vec))
+            .try_normalize(1e-12)
+            .unwrap_or_else(|| {
+                axis.cross(&Vector3::x())
+                    .try_normalize(1e-12)
+                    .unwrap_or(Vector3::y())
+            });
+
+        Self {
+            origin: Vector3::from(origin),
+            axis,
+            reference,
+            normal: axis.cross(&reference),
+        }
+    }
+
+    /// Global cartesian coordinates of a local point
+    fn to_global(&self, nwg: u8, [a, b, c]: [f64; 3]) -> Vector3<f64> {
+        match nwg {
+            1 => Vector3::new(a, b, c),
+            _ => {
+                let theta = 2.0 * std::f64::consts::PI * c;
+                self.origin
+                    + self.axis * b
+                    + (self.reference * theta.cos() + self.normal * theta.sin()) * a
+            }
+        }
+    }
+
+    /// Local coordinates of a global point, (r, z, theta) for cylinders
+    fn to_local(&self, nwg: u8, point: Vector3<f64>) -> [f64; 3] {
+        match nwg {
+            1 => [point.x, point.y, point.z],
+            _ => {
+                let d = point - self.origin;
+                let z = self.axis.dot(&d);
+                let (x, y) = (self.reference.dot(&d), self.normal.dot(&d));
+                let theta = y.atan2(x) / (2.0 * std::f64::consts::PI);
+                [x.hypot(y), z, theta.rem_euclid(1.0)]
+            }
+        }
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidRemap {
+        reason: reason.to_string(),
+    }
+}
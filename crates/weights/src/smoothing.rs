@@ -0,0 +1,248 @@
+// ntools modules
+use ntools_utils::f;
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::weight_window::WeightWindow;
+
+// extrenal crates
+use log::warn;
+
+/// Implementations for ratio limiting and smoothing of weights
+///
+/// Every operation works on each energy/time group independently, and zero
+/// (analogue) voxels are always left untouched.
+impl WeightWindow {
+    /// Cap the ratio between the weights of face-adjacent voxels
+    ///
+    /// Large jumps in weight between neighbouring voxels lead to excessive
+    /// splitting and long histories. Every group is limited separately with
+    /// [limit_ratio()], raising any non-zero weight more than `max_ratio` times
+    /// lower than a non-zero neighbour.
+    ///
+    /// Theta is periodic for cylindrical meshes, so the first and last theta
+    /// bins are treated as neighbours.
+    ///
+    /// Returns the number of weights that were changed, or an error if
+    /// `max_ratio` is below 1 or the weights do not match the mesh.
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let mut ww = WeightWindow {
+    ///     nfx: 4, nfy: 1, nfz: 1,
+    ///     weights: vec![1.0, 0.001, 0.0001, 0.0],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// // Limit the jump between neighbours to a factor of 10
+    /// let changed = ww.limit_adjacent_ratio(10.0).unwrap();
+    ///
+    /// assert_eq!(changed, 2);
+    /// assert_eq!(ww.weights, vec![1.0, 0.1, 0.01, 0.0]);
+    /// ```
+    pub fn limit_adjacent_ratio(&mut self, max_ratio: f64) -> Result<usize> {
+        check_ratio(max_ratio)?;
+        self.check_weights()?;
+        if self.weights.is_empty() {
+            return Ok(0);
+        }
+
+        // fine voxel indices of every face-adjacent voxel
+        let n_voxels = self.nfx * self.nfy * self.nfz;
+        let mut adjacency = vec![Vec::new(); n_voxels];
+        for [i, j, k] in self.fine_voxels() {
+            adjacency[self.fine_index(i, j, k)] = self
+                .neighbours(i, j, k)
+                .iter()
+                .map(|[a, b, c]| self.fine_index(*a, *b, *c))
+                .collect();
+        }
+
+        let mut changed = 0;
+        for group in self.weights.chunks_mut(n_voxels) {
+            changed += limit_ratio(group, &adjacency, max_ratio)?;
+        }
+        Ok(changed)
+    }
+
+    /// Gaussian smoothing of the weights in log space
+    ///
+    /// Weights often vary over orders of magnitude, so the smoothing is done on
+    /// the logarithm of the weights to avoid large values dominating. The
+    /// standard deviation `sigma` is in units of fine mesh voxels, with the
+    /// kernel truncated at 3 sigma.
+    ///
+    /// Only non-zero weights contribute to the average, so zero (analogue)
+    /// regions keep their shape and do not drag down the weights around them.
+    /// Theta is periodic for cylindrical meshes, so the kernel wraps around
+    /// the first and last theta bins.
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let mut ww = WeightWindow {
+    ///     nfx: 5, nfy: 1, nfz: 1,
+    ///     weights: vec![1.0, 1.0, 100.0, 1.0, 0.0],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// ww.smooth(1.0);
+    ///
+    /// // The peak is spread out, but the analogue voxel is untouched
+    /// assert!(ww.weights[2] < 100.0);
+    /// assert!(ww.weights[1] > 1.0);
+    /// assert_eq!(ww.weights[4], 0.0);
+    /// ```
+    pub fn smooth(&mut self, sigma: f64) {
+        if !(sigma.is_finite() && sigma > 0.0) {
+            warn!("Warning: smoothing sigma must be positive, found {sigma}");
+            return;
+        }
+
+        if !self.has_valid_groups() {
+            return;
+        }
+
+        let kernel = gaussian_kernel(sigma);
+        let n_voxels = self.nfx * self.nfy * self.nfz;
+        let mut weights = std::mem::take(&mut self.weights);
+
+        for group in weights.chunks_mut(n_voxels) {
+            // normalised convolution, where the mask excludes analogue voxels
+            let mut values = group
+                .iter()
+                .map(|w| if *w > 0.0 { w.ln() } else { 0.0 })
+                .collect::<Vec<f64>>();
+            let mut mask = group
+                .iter()
+                .map(|w| if *w > 0.0 { 1.0 } else { 0.0 })
+                .collect::<Vec<f64>>();
+
+            // the kernel is separable, so convolve one axis at a time
+            for axis in 0..3 {
+                values = self.convolve_axis(&values, &kernel, axis);
+                mask = self.convolve_axis(&mask, &kernel, axis);
+            }
+
+            for (idx, w) in group.iter_mut().enumerate() {
+                if *w > 0.0 {
+                    *w = (values[idx] / mask[idx]).exp();
+                }
+            }
+        }
+
+        self.weights = weights;
+    }
+
+    /// Convolve the values of a group with a symmetric kernel along one axis
+    fn convolve_axis(&self, values: &[f64], kernel: &[f64], axis: usize) -> Vec<f64> {
+        let n = [self.nfx, self.nfy, self.nfz][axis] as isize;
+        let periodic = axis == 2 && self.nwg == 2;
+        let radius = (kernel.len() / 2) as isize;
+
+        let mut result = vec![0.0; values.len()];
+        for ijk in self.fine_voxels() {
+            let mut sum = 0.0;
+            for (offset, factor) in kernel.iter().enumerate() {
+                let mut position = ijk[axis] as isize + offset as isize - radius;
+                match periodic {
+                    true => position = position.rem_euclid(n),
+                    false if !(0..n).contains(&position) => continue,
+                    false => (),
+                }
+
+                let mut other = ijk;
+                other[axis] = position as usize;
+                sum += factor * values[self.fine_index(other[0], other[1], other[2])];
+            }
+            result[self.fine_index(ijk[0], ijk[1], ijk[2])] = sum;
+        }
+
+        result
+    }
+}
+
+/// Cap the ratio between adjacent values by raising the lower value
+///
+/// The neighbours of every value are listed by index in `adjacency`. Any
+/// non-zero value more than `max_ratio` times lower than a non-zero neighbour
+/// is raised to the neighbour divided by `max_ratio`. Raising one value can
+/// push it above its other neighbours, so this is repeated until no ratio
+/// exceeds the limit. Zero values are left untouched.
+///
+/// This is the ratio limit used for every group by
+/// [limit_adjacent_ratio()](WeightWindow::limit_adjacent_ratio), for any other
+/// set of values with known neighbours.
+///
+/// Returns the number of values that were changed.
+///
+/// ```rust
+/// # use ntools_weights::limit_ratio;
+/// // Three values in a ring, each adjacent to the other two
+/// let mut values = vec![1.0, 0.001, 0.5];
+/// let adjacency = vec![vec![1, 2], vec![0, 2], vec![0, 1]];
+///
+/// assert_eq!(limit_ratio(&mut values, &adjacency, 10.0).unwrap(), 1);
+/// assert_eq!(values, vec![1.0, 0.1, 0.5]);
+/// ```
+pub fn limit_ratio(values: &mut [f64], adjacency: &[Vec<usize>], max_ratio: f64) -> Result<usize> {
+    check_ratio(max_ratio)?;
+
+    if adjacency.len() != values.len() {
+        return Err(invalid(&f!(
+            "{} adjacency lists provided for {} values",
+            adjacency.len(),
+            values.len()
+        )));
+    }
+
+    let mut modified = vec![false; values.len()];
+    loop {
+        let mut updated = false;
+
+        for (idx, neighbours) in adjacency.iter().enumerate() {
+            if values[idx] == 0.0 {
+                continue;
+            }
+
+            let highest = neighbours.iter().map(|n| values[*n]).fold(0.0, f64::max);
+
+            let floor = highest / max_ratio;
+            if values[idx] < floor {
+                values[idx] = floor;
+                modified[idx] = true;
+                updated = true;
+            }
+        }
+
+        if !updated {
+            break;
+        }
+    }
+
+    Ok(modified.iter().filter(|m| **m).count())
+}
+
+/// Ratio limits must be finite and at least 1
+fn check_ratio(max_ratio: f64) -> Result<()> {
+    match max_ratio.is_finite() && max_ratio >= 1.0 {
+        true => Ok(()),
+        false => Err(invalid(&f!("ratio limit {max_ratio} must be at least 1"))),
+    }
+}
+
+/// Normalised Gaussian kernel truncated at 3 sigma
+fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel = (-radius..=radius)
+        .map(|d| (-((d * d) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<f64>>();
+
+    let total = kernel.iter().sum::<f64>();
+    kernel.into_iter().map(|k| k / total).collect()
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidSmoothing {
+        reason: reason.to_string(),
+    }
+}
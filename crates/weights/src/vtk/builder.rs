@@ -1,8 +1,9 @@
 // internal modules
 use crate::vtk::convert::WeightsToVtk;
-use crate::vtk::ByteOrder;
 
 // extrenal crates
+use log::warn;
+use vtkio::model::ByteOrder;
 use vtkio::xml::Compressor;
 
 /// Builder implementation for WeightsToVtk configuration
@@ -46,6 +47,12 @@ pub struct WeightsToVtkBuilder {
     compressor: Compressor,
     /// Cylindrical mesh resolution
     resolution: u8,
+    /// Cylindrical mesh resolution in the radial direction
+    radial_resolution: u8,
+    /// Cylindrical mesh resolution in the axial direction
+    axial_resolution: u8,
+    /// Name of the file the weights were read from, recorded in the metadata
+    source_file: Option<String>,
 }
 
 impl WeightsToVtkBuilder {
@@ -60,6 +67,9 @@ impl WeightsToVtkBuilder {
             byte_order: self.byte_order,
             compressor: self.compressor,
             resolution: self.resolution,
+            radial_resolution: self.radial_resolution,
+            axial_resolution: self.axial_resolution,
+            source_file: self.source_file,
         }
     }
 
@@ -76,10 +86,48 @@ impl WeightsToVtkBuilder {
     /// `--resolution 3` generates 12 edges instead and looks more rounded in
     /// plots.
     pub fn resolution(mut self, resolution: u8) -> Self {
+        if resolution > 1 {
+            warn!(
+                "Warning: Increasing cylindrical mesh resolution may increase memory usage significantly"
+            );
+        }
         self.resolution = resolution;
         self
     }
 
+    /// Cylindrical mesh resolution in the radial direction
+    ///
+    /// Integer value for subdividing every radial bin of cylindrical meshes.
+    /// Warping or clipping large voxels in ParaView can show faceting and poor
+    /// interpolation, which is improved by splitting them into smaller cells.
+    ///
+    /// This is independent of the theta [resolution](Self::resolution), and
+    /// also multiplies the number of cells and verticies generated.
+    pub fn radial_resolution(mut self, resolution: u8) -> Self {
+        if resolution > 1 {
+            warn!(
+                "Warning: Increasing cylindrical mesh resolution may increase memory usage significantly"
+            );
+        }
+        self.radial_resolution = resolution;
+        self
+    }
+
+    /// Cylindrical mesh resolution in the axial direction
+    ///
+    /// Integer value for subdividing every axial bin of cylindrical meshes,
+    /// independent of the theta and radial resolutions. See
+    /// [radial_resolution()](Self::radial_resolution) for details.
+    pub fn axial_resolution(mut self, resolution: u8) -> Self {
+        if resolution > 1 {
+            warn!(
+                "Warning: Increasing cylindrical mesh resolution may increase memory usage significantly"
+            );
+        }
+        self.axial_resolution = resolution;
+        self
+    }
+
     /// Set the byte ordering
     ///
     /// Note that Visit being Visit only reads big endian, even though most
@@ -101,6 +149,15 @@ impl WeightsToVtkBuilder {
         self.compressor = xml_compressor;
         self
     }
+
+    /// Name of the source file to record in the output metadata
+    ///
+    /// The [WeightWindow](crate::WeightWindow) does not know where it was read
+    /// from, so this is only included in the field data when provided.
+    pub fn source_file(mut self, name: &str) -> Self {
+        self.source_file = Some(name.to_string());
+        self
+    }
 }
 
 impl Default for WeightsToVtkBuilder {
@@ -109,6 +166,9 @@ impl Default for WeightsToVtkBuilder {
             byte_order: ByteOrder::BigEndian,
             compressor: Compressor::LZMA,
             resolution: 1,
+            radial_resolution: 1,
+            axial_resolution: 1,
+            source_file: None,
         }
     }
 }
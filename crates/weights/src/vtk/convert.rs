@@ -2,15 +2,15 @@
 use std::ops::RangeInclusive;
 
 // ntools modules
+use ntools_mesh::vtk::cylinder;
+use ntools_mesh::vtk::field::{field_attribute, number_array, text_array};
 use ntools_utils::f;
 
 // internal modules
 use crate::vtk::builder::WeightsToVtkBuilder;
-use crate::vtk::Vertex;
 use crate::WeightWindow;
 
 // extrenal crates
-use nalgebra::{Rotation, Vector3};
 use vtkio::model::{
     Attribute, Attributes, ByteOrder, CellType, Cells, Coordinates, DataArray, DataSet,
     ElementType, Extent, IOBuffer, RangeExtent, RectilinearGridPiece, UnstructuredGridPiece,
@@ -84,6 +84,18 @@ use vtkio::xml::Compressor;
 /// Setting the `resolution` to 3 will subbdivide the theta bins into 3, thereby
 /// tripling the number of edges plotted from 8 to 24 for a more rounded look.
 ///
+/// The r and z bins may also be subdivided independently, which helps when
+/// warping or clipping large voxels.
+///
+/// ```rust
+/// # use ntools_weights::vtk::WeightsToVtk;
+/// let converter = WeightsToVtk::builder()
+///     .radial_resolution(2)
+///     .axial_resolution(4)
+///     .resolution(3)
+///     .build();
+/// ```
+///
 /// Note that this can increase memory usage and file size significantly but is
 /// a nice feature for generating more accurate cylinders.  
 #[derive(Debug, PartialEq)]
@@ -94,6 +106,12 @@ pub struct WeightsToVtk {
     pub compressor: Compressor,
     /// Cylindrical mesh resolution
     pub resolution: u8,
+    /// Cylindrical mesh resolution in the radial direction
+    pub radial_resolution: u8,
+    /// Cylindrical mesh resolution in the axial direction
+    pub axial_resolution: u8,
+    /// Name of the file the weights were read from, recorded in the metadata
+    pub source_file: Option<String>,
 }
 
 // Public API
@@ -128,6 +146,44 @@ impl Default for WeightsToVtk {
     }
 }
 
+/// Common use implementations
+impl WeightsToVtk {
+    /// Collect weight window metadata into field data
+    ///
+    /// Data arrays are named `group_<n>` in the order of the weight sets, so
+    /// the upper energy and time bounds are included to recover the groups.
+    /// The full coarse/fine mesh structure is also kept for reading back in.
+    fn metadata(&self, ww: &WeightWindow) -> Attribute {
+        let geometry = match ww.nwg {
+            1 => "Rectangular",
+            2 => "Cylindrical",
+            3 => "Spherical",
+            _ => "Unknown",
+        };
+
+        let mut arrays = vec![
+            number_array("particle", 1, vec![ww.particle as f64]),
+            text_array("geometry", geometry),
+            number_array("origin", 3, vec![ww.x0, ww.y0, ww.z0]),
+            number_array("axs", 3, vec![ww.x1, ww.y1, ww.z1]),
+            number_array("vec", 3, vec![ww.x2, ww.y2, ww.z2]),
+            number_array("qps_x", 3, ww.qps_x.concat()),
+            number_array("qps_y", 3, ww.qps_y.concat()),
+            number_array("qps_z", 3, ww.qps_z.concat()),
+            number_array("energy_bounds", 1, ww.e.clone()),
+            number_array("time_bounds", 1, ww.t.clone()),
+            text_array("probid", ww.probid.trim()),
+            text_array("ntools_version", env!("CARGO_PKG_VERSION")),
+        ];
+
+        if let Some(source) = &self.source_file {
+            arrays.push(text_array("source_file", source));
+        }
+
+        field_attribute(arrays)
+    }
+}
+
 /// Implementations for proecessing Rectangular mesh types
 impl WeightsToVtk {
     /// Convert WeightWindow data to vtkio types for writing
@@ -155,24 +211,13 @@ impl WeightsToVtk {
         Extent::Ranges(range_ext)
     }
 
-    /// Defines coordiantes for rectilinear grid from mesh bounds
+    /// Defines coordiantes for rectilinear grid from the fine mesh bounds
     fn coordinates(ww: &WeightWindow) -> Coordinates {
+        let [x, y, z] = ww.fine_mesh_bounds();
         Coordinates {
-            x: IOBuffer::F64(
-                std::iter::once(ww.x0)
-                    .chain(ww.qps_x.iter().map(|x| x[1]))
-                    .collect(),
-            ),
-            y: IOBuffer::F64(
-                std::iter::once(ww.y0)
-                    .chain(ww.qps_y.iter().map(|y| y[1]))
-                    .collect(),
-            ),
-            z: IOBuffer::F64(
-                std::iter::once(ww.z0)
-                    .chain(ww.qps_z.iter().map(|z| z[1]))
-                    .collect(),
-            ),
+            x: IOBuffer::F64(x),
+            y: IOBuffer::F64(y),
+            z: IOBuffer::F64(z),
         }
     }
 
@@ -200,6 +245,7 @@ impl WeightsToVtk {
             attributes.cell.push(Attribute::DataArray(cell_data));
         }
 
+        attributes.cell.push(self.metadata(ww));
         attributes
     }
 }
@@ -233,174 +279,29 @@ impl WeightsToVtk {
 
     /// Cylinders need to be built explicitly from vertex points
     fn cell_verticies(&self, ww: &WeightWindow) -> (Vec<f64>, Vec<u64>, Vec<CellType>) {
-        let mut points: Vec<f64> = Vec::new();
-        let mut offsets: Vec<u64> = Vec::new();
-        let mut cell_types: Vec<CellType> = Vec::new();
-        let rotation_axs = Self::init_rotation(&[ww.x1, ww.y1, ww.z1]);
-        let rotation_vec = ww.y2.atan2(ww.x2);
-
-        // first inner segments always CellType::Wedge
-        for layer in 0..ww.ncy {
-            self.wedge_segments(
-                ww,
-                layer,
-                &mut points,
-                &mut offsets,
-                &mut cell_types,
-                &rotation_axs,
-                rotation_vec,
-            );
-        }
-
-        // any additional ring segments use CellType::Voxel
-        if ww.ncx > 1 {
-            // start from 1, the first ring is already made from CellType::Wedge
-            for ring in 1..ww.ncx {
-                for layer in 0..ww.ncy {
-                    self.voxel_segments(
-                        ww,
-                        ring,
-                        layer,
-                        &mut points,
-                        &mut offsets,
-                        &mut cell_types,
-                        &rotation_axs,
-                        rotation_vec,
-                    );
-                }
-            }
-        }
-
-        (points, offsets, cell_types)
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    /// For the central voxels where r=0
-    fn wedge_segments(
-        &self,
-        ww: &WeightWindow,
-        layer: usize,
-        points: &mut Vec<f64>,
-        offsets: &mut Vec<u64>,
-        cell_types: &mut Vec<CellType>,
-        rotation_axs: &Option<Rotation<f64, 3>>,
-        rotation_vec: f64,
-    ) {
-        let mut step = 2.0 * std::f64::consts::PI / (ww.ncz as f64);
-        step /= self.get_resolution(&ww.ncz) as f64;
-
-        // move this shit out of here
-        let r = ww.qps_x[0][1]; // outer radius, the inner is always 0
-
-        // wedge type has 6 verticies
-        // only need to find three and then repeat for the lower layer
-        for i in 0..(ww.ncz * self.get_resolution(&ww.ncz) as usize) {
-            let t0 = step * (i as f64) + rotation_vec;
-            let t1 = step * (i as f64 + 1.0) + rotation_vec;
-
-            let x0 = r * t0.cos();
-            let y0 = r * t0.sin();
-
-            let x1 = r * t1.cos();
-            let y1 = r * t1.sin();
-
-            for idx in layer..=(layer + 1) {
-                let z = if idx == 0 { 0.0 } else { ww.qps_y[idx - 1][1] };
-
-                points.extend(
-                    Vertex { x: 0.0, y: 0.0, z }
-                        .rotate(rotation_axs)
-                        .translate(&[ww.x0, ww.y0, ww.z0])
-                        .as_array(),
-                );
-                points.extend(
-                    Vertex { x: x0, y: y0, z }
-                        .rotate(rotation_axs)
-                        .translate(&[ww.x0, ww.y0, ww.z0])
-                        .as_array(),
-                );
-                points.extend(
-                    Vertex { x: x1, y: y1, z }
-                        .rotate(rotation_axs)
-                        .translate(&[ww.x0, ww.y0, ww.z0])
-                        .as_array(),
-                );
-            }
-
-            Self::update_offsets(offsets, 6);
-            cell_types.push(CellType::Wedge);
-        }
+        let transform = cylinder::Transform::new(
+            [ww.x0, ww.y0, ww.z0],
+            [ww.x1, ww.y1, ww.z1],
+            [ww.x2, ww.y2, ww.z2],
+        );
+        let revolution = 2.0 * std::f64::consts::PI;
+
+        // fine mesh bounds relative to the origin, with theta in revolutions
+        let [r_bounds, z_bounds, t_bounds] = ww.fine_mesh_bounds();
+
+        cylinder::verticies(Self::voxel_order(ww), self.subdivisions(ww), |[r, z, t]| {
+            transform.point(
+                cylinder::lerp(r, |n| r_bounds[n]),
+                cylinder::lerp(z, |n| z_bounds[n]),
+                revolution * cylinder::lerp(t, |n| t_bounds[n]),
+            )
+        })
     }
 
-    #[allow(clippy::too_many_arguments)]
-    /// For anything beyond the first inside ring
-    fn voxel_segments(
-        &self,
-        ww: &WeightWindow,
-        ring: usize,
-        layer: usize,
-        points: &mut Vec<f64>,
-        offsets: &mut Vec<u64>,
-        cell_types: &mut Vec<CellType>,
-        rotation_axs: &Option<Rotation<f64, 3>>,
-        rotation_vec: f64,
-    ) {
-        let mut step = 2.0 * std::f64::consts::PI / (ww.ncz as f64);
-        step /= self.get_resolution(&ww.ncz) as f64;
-
-        let r0 = ww.qps_x[ring - 1][1]; // inner radius
-        let r1 = ww.qps_x[ring][1]; // outer radius
-
-        // voxel type has 8 verticies
-        // only need to find 4 and then repeat at lower layer
-        for i in 0..(ww.ncz * self.get_resolution(&ww.ncz) as usize) {
-            let t0 = step * (i as f64) + rotation_vec;
-            let t1 = step * (i as f64 + 1.0) + rotation_vec;
-
-            let x00: f64 = r0 * t0.cos();
-            let y00: f64 = r0 * t0.sin();
-
-            let x01: f64 = r0 * t1.cos();
-            let y01: f64 = r0 * t1.sin();
-
-            let x10: f64 = r1 * t0.cos();
-            let y10: f64 = r1 * t0.sin();
-
-            let x11: f64 = r1 * t1.cos();
-            let y11: f64 = r1 * t1.sin();
-
-            for idx in layer..=(layer + 1) {
-                let z = if idx == 0 { 0.0 } else { ww.qps_y[idx - 1][1] };
-
-                points.extend(
-                    Vertex { x: x00, y: y00, z }
-                        .rotate(rotation_axs)
-                        .translate(&[ww.x0, ww.y0, ww.z0])
-                        .as_array(),
-                );
-                points.extend(
-                    Vertex { x: x01, y: y01, z }
-                        .rotate(rotation_axs)
-                        .translate(&[ww.x0, ww.y0, ww.z0])
-                        .as_array(),
-                );
-                points.extend(
-                    Vertex { x: x10, y: y10, z }
-                        .rotate(rotation_axs)
-                        .translate(&[ww.x0, ww.y0, ww.z0])
-                        .as_array(),
-                );
-                points.extend(
-                    Vertex { x: x11, y: y11, z }
-                        .rotate(rotation_axs)
-                        .translate(&[ww.x0, ww.y0, ww.z0])
-                        .as_array(),
-                );
-            }
-
-            Self::update_offsets(offsets, 8);
-            cell_types.push(CellType::Voxel);
-        }
+    /// Every fine mesh voxel in voxel index order, i.e. theta varies fastest
+    fn voxel_order(ww: &WeightWindow) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        (0..ww.nfx)
+            .flat_map(move |i| (0..ww.nfy).flat_map(move |j| (0..ww.nfz).map(move |k| (i, j, k))))
     }
 
     /// Bring all of the cell data together
@@ -413,17 +314,16 @@ impl WeightsToVtk {
         // The vtk voxels have been built to the i-j-k indexing order
         let cell_order = Self::get_order(ww);
 
+        // number of unstructured cells generated for each voxel
+        let n_cells = self.subdivisions(ww).iter().product();
+
         // iterate over each set
         let weight_sets = ww.weights.chunks(ww.nfx * ww.nfy * ww.nfz);
 
         let mut attributes: Attributes = Attributes::new();
         for (i, set) in weight_sets.enumerate() {
             // reorder back into voxel i-j-k indexing rom cell k-j-i indexing
-            let mut results = Self::sort_set(set, &cell_order);
-
-            if self.resolution > 1 {
-                results = Self::repeat_values(results, self.get_resolution(&ww.ncz));
-            }
+            let results = Self::repeat_values(Self::sort_set(set, &cell_order), n_cells);
 
             let cell_data = DataArray {
                 // todo: do something more clever here later
@@ -437,55 +337,28 @@ impl WeightsToVtk {
             attributes.cell.push(Attribute::DataArray(cell_data));
         }
 
+        attributes.cell.push(self.metadata(ww));
         attributes
     }
 
     /// Repeat whatever set of values is in a vector
-    fn repeat_values(values: Vec<f64>, repeat: u8) -> Vec<f64> {
+    fn repeat_values(values: Vec<f64>, repeat: usize) -> Vec<f64> {
         values
             .into_iter()
-            .flat_map(|n| std::iter::repeat(n).take(repeat.into()))
+            .flat_map(|n| std::iter::repeat(n).take(repeat))
             .collect()
     }
 
-    /// Used in calculation of verticies
-    fn update_offsets(offsets: &mut Vec<u64>, size: usize) {
-        let offset = match offsets.is_empty() {
-            true => size.try_into().unwrap(),
-            false => (size + (*offsets.last().unwrap() as usize))
-                .try_into()
-                .unwrap(),
-        };
-        offsets.push(offset);
-    }
-
-    /// Fix the resolution issue in the background for 1-2 theta bins
-    ///
-    /// For performance the converter would have to be mutable, or the user
-    /// would have to know to set the resolution for a couple of special cases.
-    /// This is just easier for everyone.
-    fn get_resolution(&self, n_bins: &usize) -> u8 {
-        match n_bins {
-            // only one theta bin, minimim verticies needed will be 3
-            1 => self.resolution.max(3),
-            // only two theta bins, minimim verticies needed will be 4
-            2 => self.resolution.max(2),
-            // anything else is fine
-            _ => self.resolution,
-        }
-    }
-
-    /// Initialise the rotation matrix from AXS if required
-    fn init_rotation(axis: &[f64]) -> Option<Rotation<f64, 3>> {
-        let axs_default = [0.0, 0.0, 1.0];
-
-        if axs_default == *axis {
-            None
-        } else {
-            let axs_default = Vector3::from(axs_default);
-            let axs_user = Vector3::from([axis[0], axis[1], axis[2]]);
-            Some(Rotation::face_towards(&axs_user, &axs_default))
-        }
+    /// Number of subdivisions of every cylindrical voxel in `[r, z, theta]`
+    fn subdivisions(&self, ww: &WeightWindow) -> [usize; 3] {
+        cylinder::subdivisions(
+            [
+                self.radial_resolution,
+                self.axial_resolution,
+                self.resolution,
+            ],
+            ww.nfz,
+        )
     }
 
     /// Get the correct ordering required for cell index back to voxel index
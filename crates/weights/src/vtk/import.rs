@@ -0,0 +1,314 @@
+// standard library
+use std::collections::HashMap;
+use std::path::Path;
+
+// ntools modules
+use ntools_mesh::vtk::field::{self, FieldData};
+use ntools_utils::f;
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::WeightWindow;
+
+// extrenal crates
+use vtkio::model::{Attribute, DataSet, Piece, Vtk};
+
+/// Read a VTK file written by [write_vtk()](crate::vtk::write_vtk) back into a [WeightWindow]
+///
+/// Any of the formats written by this crate are supported, i.e. `.vtr`/`.vtu`
+/// XML files and `.vtk` legacy files of either byte order.
+///
+/// The [WeightWindow] is rebuilt from the metadata embedded in the file as
+/// field data, including the coarse/fine mesh structure. For generic VTK
+/// files from other codes, see [read_vtk_array()].
+///
+/// ```rust, no_run
+/// # use ntools_weights::vtk::read_vtk;
+/// let weight_window = read_vtk("path/to/weights.vtr").unwrap();
+/// ```
+pub fn read_vtk(path: impl AsRef<Path>) -> Result<WeightWindow> {
+    vtk_to_weights(&field::import(path.as_ref())?)
+}
+
+/// Convert a vtkio::Vtk with ntools metadata back into a [WeightWindow]
+///
+/// The inverse of [weights_to_vtk()](crate::vtk::weights_to_vtk), for a VTK
+/// that is already in memory.
+///
+/// ```rust
+/// # use ntools_weights::vtk::{weights_to_vtk, vtk_to_weights};
+/// # use ntools_weights::WeightWindow;
+/// let ww = WeightWindow {
+///     nfx: 2, nfy: 1, nfz: 1,
+///     ncx: 2, ncy: 1, ncz: 1,
+///     qps_x: vec![[1.0, 5.0, 1.0], [1.0, 10.0, 1.0]],
+///     qps_y: vec![[1.0, 10.0, 1.0]],
+///     qps_z: vec![[1.0, 10.0, 1.0]],
+///     e: vec![100.0],
+///     weights: vec![0.1, 0.2],
+///     ..Default::default()
+/// };
+///
+/// let copy = vtk_to_weights(&weights_to_vtk(&ww)).unwrap();
+/// assert_eq!(copy.weights, ww.weights);
+/// assert_eq!(copy.qps_x, ww.qps_x);
+/// ```
+pub fn vtk_to_weights(vtk: &Vtk) -> Result<WeightWindow> {
+    let metadata = FieldData::from_vtk(vtk);
+    if metadata.is_empty() {
+        return Err(invalid(
+            "no ntools metadata found, use read_vtk_array() for generic VTK files",
+        ));
+    }
+
+    let mut ww = weights_from_metadata(&metadata)?;
+    let arrays = cell_arrays(vtk)?;
+
+    let n_voxels = ww.nfx * ww.nfy * ww.nfz;
+    let n_cells = match ww.nwg {
+        1 => n_voxels,
+        _ => unstructured_cells(vtk)?,
+    };
+
+    if n_voxels == 0 || n_cells % n_voxels != 0 {
+        return Err(invalid(&f!(
+            "{n_cells} cells do not match the {n_voxels} voxels of the metadata"
+        )));
+    }
+    let resolution = n_cells / n_voxels;
+
+    ww.weights = Vec::with_capacity(ww.ne * ww.nt * n_voxels);
+    for n in 0..(ww.ne * ww.nt) {
+        let name = f!("group_{n}");
+        let values = arrays
+            .get(&name)
+            .ok_or_else(|| invalid(&f!("missing \"{name}\" array")))?;
+
+        if values.len() != n_cells {
+            return Err(invalid(&f!(
+                "\"{name}\" has {} values, expected {n_cells}",
+                values.len()
+            )));
+        }
+
+        match ww.nwg {
+            // rectilinear grids are already in the same cell index order
+            1 => ww.weights.extend(values),
+            // unstructured cells are in voxel order, repeated for resolution
+            _ => {
+                let offset = n * n_voxels;
+                for cell_idx in 0..n_voxels {
+                    let voxel_idx = ww.voxel_index_from_cell_index(offset + cell_idx) - offset;
+                    ww.weights.push(values[voxel_idx * resolution]);
+                }
+            }
+        }
+    }
+
+    Ok(ww)
+}
+
+/// Read a named cell data array from any VTK rectilinear grid into a [WeightWindow]
+///
+/// Generic rectilinear grids from other codes have no ntools metadata, so the
+/// cell data array of lower weight bounds is given explicitly.
+///
+/// ```rust, no_run
+/// # use ntools_weights::vtk::read_vtk_array;
+/// let weight_window = read_vtk_array("path/to/weights.vtr", "lower_ww_bounds").unwrap();
+/// ```
+///
+/// The result is a single energy group [WeightWindow] of unknown particle type
+/// with one fine mesh per coarse mesh.
+pub fn read_vtk_array(path: impl AsRef<Path>, array: &str) -> Result<WeightWindow> {
+    vtk_array_to_weights(&field::import(path.as_ref())?, array)
+}
+
+/// Convert a named cell data array of a vtkio::Vtk rectilinear grid into a [WeightWindow]
+///
+/// See [read_vtk_array()] for details.
+pub fn vtk_array_to_weights(vtk: &Vtk, array: &str) -> Result<WeightWindow> {
+    let arrays = cell_arrays(vtk)?;
+    let (x, y, z) = rectilinear_coordinates(vtk)?;
+
+    let weights = arrays
+        .get(array)
+        .ok_or_else(|| invalid(&f!("no cell data array named \"{array}\"")))?
+        .clone();
+
+    let expected = (x.len() - 1) * (y.len() - 1) * (z.len() - 1);
+    if weights.len() != expected {
+        return Err(invalid(&f!(
+            "\"{array}\" has {} values, expected {expected}",
+            weights.len()
+        )));
+    }
+
+    Ok(WeightWindow {
+        nfx: x.len() - 1,
+        nfy: y.len() - 1,
+        nfz: z.len() - 1,
+        ncx: x.len() - 1,
+        ncy: y.len() - 1,
+        ncz: z.len() - 1,
+        x0: x[0],
+        y0: y[0],
+        z0: z[0],
+        qps_x: qps_tuples(&x),
+        qps_y: qps_tuples(&y),
+        qps_z: qps_tuples(&z),
+        e: vec![1e36],
+        weights,
+        ..Default::default()
+    })
+}
+
+/// Initialise everything but the weights from ntools metadata
+fn weights_from_metadata(metadata: &FieldData) -> Result<WeightWindow> {
+    let number = |name: &str| {
+        metadata
+            .number(name)
+            .ok_or_else(|| invalid(&f!("missing \"{name}\" in metadata")))
+    };
+    let vector = |name: &str| -> Result<[f64; 3]> {
+        number(name)?
+            .try_into()
+            .map_err(|_| invalid(&f!("expected 3 values for \"{name}\"")))
+    };
+    let qps = |name: &str| -> Result<Vec<[f64; 3]>> {
+        Ok(number(name)?
+            .chunks_exact(3)
+            .map(|c| [c[0], c[1], c[2]])
+            .collect())
+    };
+
+    let nwg = match metadata.text("geometry").as_deref() {
+        Some("Rectangular") => 1,
+        Some("Cylindrical") => 2,
+        Some("Spherical") => 3,
+        other => return Err(invalid(&f!("unknown geometry {other:?}"))),
+    };
+
+    let [x0, y0, z0] = vector("origin")?;
+    let [x1, y1, z1] = vector("axs")?;
+    let [x2, y2, z2] = vector("vec")?;
+    let (qps_x, qps_y, qps_z) = (qps("qps_x")?, qps("qps_y")?, qps("qps_z")?);
+    let e = number("energy_bounds")?;
+    let t = metadata.number("time_bounds").unwrap_or_default();
+
+    Ok(WeightWindow {
+        iv: if t.is_empty() { 1 } else { 2 },
+        ne: e.len(),
+        nt: t.len().max(1),
+        nr: if nwg == 1 { 10 } else { 16 },
+        nwg,
+        probid: metadata.text("probid").unwrap_or_default(),
+        nfx: fine_count(&qps_x),
+        nfy: fine_count(&qps_y),
+        nfz: fine_count(&qps_z),
+        ncx: qps_x.len(),
+        ncy: qps_y.len(),
+        ncz: qps_z.len(),
+        x0,
+        y0,
+        z0,
+        x1,
+        y1,
+        z1,
+        x2,
+        y2,
+        z2,
+        e,
+        t,
+        qps_x,
+        qps_y,
+        qps_z,
+        particle: number("particle")?.first().copied().unwrap_or_default() as u8,
+        ..Default::default()
+    })
+}
+
+/// Total number of fine meshes over every coarse mesh
+fn fine_count(qps: &[[f64; 3]]) -> usize {
+    qps.iter().map(|q| q[2] as usize).sum()
+}
+
+/// One fine mesh per coarse mesh for each of the bounds
+fn qps_tuples(bounds: &[f64]) -> Vec<[f64; 3]> {
+    bounds[1..].iter().map(|b| [1.0, *b, 1.0]).collect()
+}
+
+/// Collect every cell data array by name, as f64
+///
+/// Names are decoded in case of spaces encoded for legacy formats.
+fn cell_arrays(vtk: &Vtk) -> Result<HashMap<String, Vec<f64>>> {
+    let cell = match &vtk.data {
+        DataSet::RectilinearGrid { pieces, .. } => &single_piece(pieces)?.data.cell,
+        DataSet::UnstructuredGrid { pieces, .. } => &single_piece(pieces)?.data.cell,
+        _ => {
+            return Err(invalid(
+                "only rectilinear and unstructured grids are supported",
+            ))
+        }
+    };
+
+    Ok(cell
+        .iter()
+        .filter_map(|attribute| match attribute {
+            Attribute::DataArray(array) => array
+                .data
+                .cast_into::<f64>()
+                .map(|values| (array.name.replace("%20", " "), values)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Coordinates of a rectilinear grid, as f64
+fn rectilinear_coordinates(vtk: &Vtk) -> Result<(Vec<f64>, Vec<f64>, Vec<f64>)> {
+    let piece = match &vtk.data {
+        DataSet::RectilinearGrid { pieces, .. } => single_piece(pieces)?,
+        _ => return Err(invalid("expected a rectilinear grid")),
+    };
+
+    let cast = |buffer: &vtkio::model::IOBuffer| {
+        buffer
+            .cast_into::<f64>()
+            .filter(|c| c.len() > 1)
+            .ok_or_else(|| invalid("unsupported coordinate data"))
+    };
+
+    Ok((
+        cast(&piece.coords.x)?,
+        cast(&piece.coords.y)?,
+        cast(&piece.coords.z)?,
+    ))
+}
+
+/// Number of cells in an unstructured grid
+fn unstructured_cells(vtk: &Vtk) -> Result<usize> {
+    match &vtk.data {
+        DataSet::UnstructuredGrid { pieces, .. } => Ok(single_piece(pieces)?.cells.types.len()),
+        _ => Err(invalid(
+            "expected an unstructured grid for cylindrical weights",
+        )),
+    }
+}
+
+/// The only piece of a dataset, which must already be loaded
+fn single_piece<P>(pieces: &[Piece<P>]) -> Result<&P> {
+    match pieces {
+        [Piece::Inline(p)] => Ok(p),
+        [_] => Err(invalid("piece data not loaded")),
+        _ => Err(invalid(&f!(
+            "expected a single piece, found {}",
+            pieces.len()
+        ))),
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidVtk {
+        reason: reason.to_string(),
+    }
+}
@@ -72,9 +72,26 @@
 //!
 //! In the background, a call to [WeightsToVtk::new()] simply returns a default
 //! configuration generated by the builder anyway.
+//!
+//! # Metadata
+//!
+//! Every converted VTK carries the weight window metadata (probid, particle, geometry,
+//! origin/axs/vec, energy and time bounds, and the ntools version) as dataset
+//! field data. The source file name is included when set on the builder with
+//! `source_file()`.
+//!
+//! vtkio does not write field data itself, so files should be written with
+//! [write_vtk()] for the metadata to be kept.
+//!
+//! # Reading VTK files
+//!
+//! The metadata also means files written by [write_vtk()] can be read back
+//! into a [WeightWindow] with [read_vtk()]. Generic rectilinear grids from other codes
+//! are read from named cell data arrays with [read_vtk_array()].
 
 mod builder;
 mod convert;
+mod import;
 
 #[doc(inline)]
 pub use builder::WeightsToVtkBuilder;
@@ -82,11 +99,15 @@ pub use builder::WeightsToVtkBuilder;
 #[doc(inline)]
 pub use convert::WeightsToVtk;
 
+#[doc(inline)]
+pub use import::{read_vtk, read_vtk_array, vtk_array_to_weights, vtk_to_weights};
+
+#[doc(inline)]
+pub use ntools_mesh::vtk::{Vertex, VtkFormat};
+
 use crate::error::Result;
 use crate::WeightWindow;
-use nalgebra::{Rotation, Vector3};
 use std::path::Path;
-use vtkio::model::ByteOrder;
 use vtkio::Vtk;
 
 /// Convert a set of weight windows to vtk using default options
@@ -137,70 +158,10 @@ pub fn weights_to_vtk(weight_window: &WeightWindow) -> Vtk {
 /// For XML, the file must end in `.vtr` for rectangular meshes (rectilinear
 /// grid), and `.vtu` for cylindrical meshes (unstructured). For both legacy
 /// formats `.vtk` will work fine.
-pub fn write_vtk(vtk: Vtk, path: impl AsRef<Path>, format: VtkFormat) -> Result<()> {
-    match format {
-        VtkFormat::Xml => Ok(vtk.export(path)?),
-        VtkFormat::LegacyBinary => match vtk.byte_order {
-            ByteOrder::BigEndian => Ok(vtk.export_be(path)?),
-            ByteOrder::LittleEndian => Ok(vtk.export_le(path)?),
-        },
-        VtkFormat::LegacyAscii => Ok(vtk.export_ascii(path)?),
-    }
-}
-
-/// Enum of VTK output formats
 ///
-/// [VtkFormat::Xml] is recommended as the most modern format that also supports
-/// compression to reduce file sizes.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub enum VtkFormat {
-    /// Extensible Markup Language (xml)
-    Xml,
-    /// ASCII text file
-    LegacyAscii,
-    /// ASCII headers with binary data
-    LegacyBinary,
-}
-
-/// Vertex for use in cylindrical and unstructured mesh types
-pub struct Vertex {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
-}
-
-impl Vertex {
-    /// turn vertex into a vector, rounding to 5 decimal places for consistency
-    pub fn as_array(&self) -> [f64; 3] {
-        [Self::rnd(self.x), Self::rnd(self.y), Self::rnd(self.z)]
-    }
-
-    /// Rotate a point about the origin
-    pub fn rotate(mut self, rotation: &Option<Rotation<f64, 3>>) -> Vertex {
-        if let Some(r) = rotation {
-            let a = r.transform_vector(&Vector3::from(self.as_array()));
-            self.x = a[0];
-            self.y = a[1];
-            self.z = a[2];
-        };
-        self
-    }
-
-    /// Pass along simple translations by (x,y,z) cartesian coordinates
-    pub fn translate(mut self, origin: &[f64; 3]) -> Vertex {
-        self.x += origin[0];
-        self.y += origin[1];
-        self.z += origin[2];
-        self
-    }
-
-    /// Force rounding of f64 to 5 decimal places
-    ///
-    /// Makes absolutely sure the vertex points are not slightly off due to f64
-    /// rounding errors. The points in meshtal files are are only to 5 decimal
-    /// places anyway so this should not lose precision.
-    pub fn rnd(value: f64) -> f64 {
-        let factor: f64 = (10_f64).powf(5.0);
-        (factor * value).round() / factor
-    }
+/// This is exactly the same writer as
+/// [ntools_mesh::vtk::write_vtk()](ntools_mesh::vtk::write_vtk), including the
+/// field data metadata.
+pub fn write_vtk(vtk: Vtk, path: impl AsRef<Path>, format: VtkFormat) -> Result<()> {
+    Ok(ntools_mesh::vtk::write_vtk(vtk, path, format)?)
 }
@@ -1,13 +1,18 @@
 // standard library
 use std::fs::File;
 use std::io::{BufWriter, Write};
+use std::path::Path;
 
 // ntools modules
 use ntools_utils::{f, ValueExt};
 
 // internal modules
+use crate::error::{Error, Result};
 use crate::operations::track_newlines;
 
+// extrenal crates
+use log::warn;
+
 /// Mesh-based global weight window data for WWINP/WWOUT/WWONE
 ///
 /// The [WeightWindow] data structure represents a set of weight windows for a
@@ -53,14 +58,14 @@ use crate::operations::track_newlines;
 ///
 /// Formatting is done in blocks for consistency with the specifications
 /// provided in the user manual appendices.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct WeightWindow {
     // Basic header info
     /// File type, manual states unused, so always 1.
     pub f: u8,
     /// Time-dependent windows flag, 1=no 2=yes.
     pub iv: u8,
-    /// Number of particle types
+    /// Number of particle types in the file, the position of the last type
     pub ni: u8,
     /// Number of energy bins for each particle type
     pub ne: usize,
@@ -149,13 +154,35 @@ impl WeightWindow {
     /// Tools to combine weight window sets for multiple particles are provided
     /// see [write_multi_particle()](crate::write_multi_particle).
     pub fn write(&self, path: &str) {
-        // assume fine >2 meshes for now
-        let f = File::create(path).expect("Unable to create file");
-        let mut f = BufWriter::new(f);
-        f.write_all(self.block_1_header().as_bytes()).unwrap();
-        f.write_all(self.block_1().as_bytes()).unwrap();
-        f.write_all(self.block_2().as_bytes()).unwrap();
-        f.write_all(self.block_3().as_bytes()).unwrap();
+        self.write_blocks(path).expect("Unable to write file");
+    }
+
+    /// Fallible version of [write()](WeightWindow::write)
+    ///
+    /// The number of weights is checked against the fine mesh, energy, and
+    /// time bins before anything is written. Any failure is returned as an
+    /// [Error] rather than a panic.
+    ///
+    /// ```rust
+    /// # use ntools_weights::{Error, WeightWindow};
+    /// // Three weights for a 2x1x1 mesh with one energy group
+    /// let ww = WeightWindow {
+    ///     nfx: 2, nfy: 1, nfz: 1,
+    ///     e: vec![100.0],
+    ///     weights: vec![0.2, 0.15, 0.4],
+    ///     particle: 1,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let path = std::env::temp_dir().join("ntools_try_write_example");
+    /// assert!(matches!(
+    ///     ww.try_write(path),
+    ///     Err(Error::WeightCountMismatch { expected: 2, found: 3, .. })
+    /// ));
+    /// ```
+    pub fn try_write(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.check_weights()?;
+        self.write_blocks(path)
     }
 
     /// Multiply all weights by a constant factor
@@ -204,6 +231,157 @@ impl WeightWindow {
         100.0 * (non_zero as f64) / (self.weights.len() as f64)
     }
 
+    /// Number of weights expected from the fine mesh, energy, and time bins
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let ww = WeightWindow {
+    ///     nfx: 10, nfy: 5, nfz: 2,
+    ///     ne: 3,
+    ///     nt: 2,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(ww.n_weights_expected(), 600)
+    /// ```
+    pub fn n_weights_expected(&self) -> usize {
+        self.nfx * self.nfy * self.nfz * self.ne * self.nt
+    }
+
+    /// Fine mesh bounds along each axis, built from the coarse mesh tuples
+    ///
+    /// Rectangular bounds start from the origin. Cylindrical bounds are
+    /// relative to the origin, so (r, z, theta) all start from 0 with theta in
+    /// revolutions.
+    ///
+    /// Each coarse mesh is split into `s` fine meshes. MCNP always writes a
+    /// fine mesh ratio of `q=1` for equal widths, but any other ratio is
+    /// treated as the ratio between the widths of successive fine meshes.
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let ww = WeightWindow {
+    ///     x0: -10.0,
+    ///     qps_x: vec![[1.0, 0.0, 2.0], [3.0, 40.0, 2.0]],
+    ///     qps_y: vec![[1.0, 10.0, 1.0]],
+    ///     qps_z: vec![[1.0, 10.0, 1.0]],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let [x, y, z] = ww.fine_mesh_bounds();
+    /// assert_eq!(x, vec![-10.0, -5.0, 0.0, 10.0, 40.0]);
+    /// assert_eq!(y, vec![0.0, 10.0]);
+    /// ```
+    pub fn fine_mesh_bounds(&self) -> [Vec<f64>; 3] {
+        let [x0, y0, z0] = match self.nwg {
+            1 => [self.x0, self.y0, self.z0],
+            _ => [0.0; 3],
+        };
+
+        [
+            fine_bounds(x0, &self.qps_x),
+            fine_bounds(y0, &self.qps_y),
+            fine_bounds(z0, &self.qps_z),
+        ]
+    }
+
+    /// Set the mesh from coarse mesh bounds and fine mesh counts
+    ///
+    /// Every coarse mesh is split into the given number of equal fine meshes,
+    /// and may be different for every coarse mesh. This sets the `qps` tuples
+    /// along with the coarse and fine mesh counts, which can make for much
+    /// more compact files on large models.
+    ///
+    /// Rectangular bounds are absolute, and set the origin. Cylindrical bounds
+    /// are (r, z, theta) relative to the existing origin, so must start from 0,
+    /// and theta is in revolutions so must end at 1.
+    ///
+    /// The weights are not changed, and should be set for every fine mesh
+    /// voxel afterwards.
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let mut ww = WeightWindow::default();
+    ///
+    /// // 2 coarse meshes in x, split into 1 and 3 fine meshes
+    /// ww.set_coarse_mesh(
+    ///     [&[-10.0, 0.0, 30.0], &[0.0, 10.0], &[0.0, 10.0]],
+    ///     [&[1, 3], &[1], &[2]],
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!((ww.ncx, ww.nfx), (2, 4));
+    /// assert_eq!(ww.qps_x, vec![[1.0, 0.0, 1.0], [1.0, 30.0, 3.0]]);
+    /// assert_eq!(ww.fine_mesh_bounds()[0], vec![-10.0, 0.0, 10.0, 20.0, 30.0]);
+    /// assert_eq!(ww.n_weights_expected(), 8);
+    /// ```
+    ///
+    /// Cylindrical theta bounds must cover a full revolution.
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let mut ww = WeightWindow { nwg: 2, ..Default::default() };
+    /// let fine: [&[usize]; 3] = [&[1], &[1], &[1, 2]];
+    ///
+    /// assert!(ww.set_coarse_mesh([&[0.0, 5.0], &[0.0, 10.0], &[0.0, 0.25, 1.0]], fine).is_ok());
+    /// assert!(ww.set_coarse_mesh([&[0.0, 5.0], &[0.0, 10.0], &[0.0, 0.25, 0.5]], fine).is_err());
+    /// ```
+    pub fn set_coarse_mesh(&mut self, bounds: [&[f64]; 3], fine: [&[usize]; 3]) -> Result<()> {
+        for ((axis, b), n) in ["i", "j", "k"].iter().zip(bounds).zip(fine) {
+            if b.len() < 2 || b.windows(2).any(|w| w[1] <= w[0]) {
+                return Err(invalid_mesh(f!(
+                    "{axis} bounds need at least 2 increasing values"
+                )));
+            }
+
+            if n.len() != b.len() - 1 {
+                return Err(invalid_mesh(f!(
+                    "{} fine mesh counts for {} coarse meshes in {axis}",
+                    n.len(),
+                    b.len() - 1
+                )));
+            }
+
+            if n.contains(&0) {
+                return Err(invalid_mesh(f!(
+                    "every coarse mesh in {axis} needs at least one fine mesh"
+                )));
+            }
+        }
+
+        if self.nwg != 1 && bounds.iter().any(|b| b[0] != 0.0) {
+            return Err(invalid_mesh(
+                "cylindrical bounds are relative to the origin, so must start at 0".to_string(),
+            ));
+        }
+
+        if self.nwg != 1 && (bounds[2][bounds[2].len() - 1] - 1.0).abs() > 1e-6 {
+            return Err(invalid_mesh(
+                "cylindrical theta bounds are in revolutions, so must end at 1".to_string(),
+            ));
+        }
+
+        let qps = |axis: usize| -> Vec<[f64; 3]> {
+            bounds[axis][1..]
+                .iter()
+                .zip(fine[axis])
+                .map(|(p, s)| [1.0, *p, *s as f64])
+                .collect()
+        };
+
+        (self.qps_x, self.qps_y, self.qps_z) = (qps(0), qps(1), qps(2));
+        (self.ncx, self.ncy, self.ncz) = (fine[0].len(), fine[1].len(), fine[2].len());
+        self.nfx = fine[0].iter().sum();
+        self.nfy = fine[1].iter().sum();
+        self.nfz = fine[2].iter().sum();
+
+        if self.nwg == 1 {
+            (self.x0, self.y0, self.z0) = (bounds[0][0], bounds[1][0], bounds[2][0]);
+        }
+
+        Ok(())
+    }
+
     /// Generate file content as a string (not for large files)
     ///
     /// Build a string for the full wwout file. Can be useful for small files
@@ -218,12 +396,16 @@ impl WeightWindow {
     }
 
     /// Find the (e,t,i,j,k) indicies for a given cell index
+    ///
+    /// The cell index is the position in the flattened weights, and (i,j,k)
+    /// are always fine mesh indicies, however the fine meshes are split
+    /// between coarse meshes.
     pub fn etijk_from_cell_index(&self, idx: usize) -> (usize, usize, usize, usize, usize) {
         // convenient values for readability
-        let a: usize = self.nt * self.ncz * self.ncy * self.ncx;
-        let b: usize = self.ncz * self.ncy * self.ncx;
-        let c: usize = self.ncx * self.ncy;
-        let d: usize = self.ncx;
+        let a: usize = self.nt * self.nfz * self.nfy * self.nfx;
+        let b: usize = self.nfz * self.nfy * self.nfx;
+        let c: usize = self.nfx * self.nfy;
+        let d: usize = self.nfx;
 
         // find indicies in reverse (integer division floors in Rust)
         let e: usize = idx / a;
@@ -235,7 +417,41 @@ impl WeightWindow {
         (e, t, i, j, k)
     }
 
+    /// Convert indexed bins to a cell index, i.e. the position in the weights
+    ///
+    /// Weights loop over (i,j,k) fine mesh indicies with i varying fastest.
+    ///
+    /// ```rust
+    /// # use ntools_weights::WeightWindow;
+    /// let ww = WeightWindow {
+    ///     nfx: 3, nfy: 2, nfz: 1,
+    ///     ne: 2,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(ww.cell_index_from_etijk(1, 0, 2, 1, 0), 11);
+    /// assert_eq!(ww.etijk_from_cell_index(11), (1, 0, 2, 1, 0));
+    /// ```
+    pub fn cell_index_from_etijk(
+        &self,
+        e_idx: usize,
+        t_idx: usize,
+        i_idx: usize,
+        j_idx: usize,
+        k_idx: usize,
+    ) -> usize {
+        let mut idx: usize = e_idx * (self.nt * self.nfx * self.nfy * self.nfz);
+        idx += t_idx * (self.nfx * self.nfy * self.nfz);
+        idx += k_idx * (self.nfx * self.nfy);
+        idx += j_idx * self.nfx;
+        idx += i_idx;
+        idx
+    }
+
     /// Convert indexed bins to a voxel index
+    ///
+    /// Voxel indicies loop over (i,j,k) fine mesh indicies with k varying
+    /// fastest, the same as mesh tally output.
     pub fn voxel_index_from_etijk(
         &self,
         e_idx: usize,
@@ -244,10 +460,10 @@ impl WeightWindow {
         j_idx: usize,
         k_idx: usize,
     ) -> usize {
-        let mut idx: usize = e_idx * (self.nt * self.ncx * self.ncy * self.ncz);
-        idx += t_idx * (self.ncx * self.ncy * self.ncz);
-        idx += i_idx * (self.ncy * self.ncz);
-        idx += j_idx * (self.ncz);
+        let mut idx: usize = e_idx * (self.nt * self.nfx * self.nfy * self.nfz);
+        idx += t_idx * (self.nfx * self.nfy * self.nfz);
+        idx += i_idx * (self.nfy * self.nfz);
+        idx += j_idx * (self.nfz);
         idx += k_idx;
         idx
     }
@@ -262,29 +478,131 @@ impl WeightWindow {
 }
 
 impl WeightWindow {
+    /// Make sure the weights are consistent with the mesh and groups
+    pub(crate) fn check_weights(&self) -> Result<()> {
+        let expected = self.n_weights_expected();
+        match self.weights.len() == expected {
+            true => Ok(()),
+            false => Err(Error::WeightCountMismatch {
+                particle: self.particle,
+                expected,
+                found: self.weights.len(),
+            }),
+        }
+    }
+
+    /// Groups can only be separated if the weights match the mesh
+    pub(crate) fn has_valid_groups(&self) -> bool {
+        match self.check_weights() {
+            Ok(_) if !self.weights.is_empty() => true,
+            Ok(_) => false,
+            Err(e) => {
+                warn!("Warning: {e}, weights unchanged");
+                false
+            }
+        }
+    }
+
+    /// Fine mesh voxels sharing a face with (i,j,k)
+    ///
+    /// Theta is periodic for cylindrical meshes, so the first and last theta
+    /// bins are also adjacent.
+    pub(crate) fn neighbours(&self, i: usize, j: usize, k: usize) -> Vec<[usize; 3]> {
+        let mut neighbours = Vec::with_capacity(6);
+
+        if i > 0 {
+            neighbours.push([i - 1, j, k]);
+        }
+        if i + 1 < self.nfx {
+            neighbours.push([i + 1, j, k]);
+        }
+        if j > 0 {
+            neighbours.push([i, j - 1, k]);
+        }
+        if j + 1 < self.nfy {
+            neighbours.push([i, j + 1, k]);
+        }
+        if k > 0 {
+            neighbours.push([i, j, k - 1]);
+        }
+        if k + 1 < self.nfz {
+            neighbours.push([i, j, k + 1]);
+        }
+
+        // periodic theta bins, avoiding duplicates for 1 or 2 bins
+        if self.nwg == 2 && self.nfz > 2 {
+            if k == 0 {
+                neighbours.push([i, j, self.nfz - 1]);
+            } else if k == self.nfz - 1 {
+                neighbours.push([i, j, 0]);
+            }
+        }
+
+        neighbours
+    }
+
+    /// Every (i,j,k) of the fine mesh, with i varying fastest
+    pub(crate) fn fine_voxels(&self) -> impl Iterator<Item = [usize; 3]> {
+        let (nfx, nfy, nfz) = (self.nfx, self.nfy, self.nfz);
+        (0..nfz).flat_map(move |k| (0..nfy).flat_map(move |j| (0..nfx).map(move |i| [i, j, k])))
+    }
+
+    /// Index of a fine mesh voxel within a group, i varies fastest
+    pub(crate) fn fine_index(&self, i: usize, j: usize, k: usize) -> usize {
+        i + j * self.nfx + k * self.nfx * self.nfy
+    }
+
+    /// Write every block of a single particle file
+    fn write_blocks(&self, path: impl AsRef<Path>) -> Result<()> {
+        let f = File::create(path)?;
+        let mut f = BufWriter::new(f);
+        f.write_all(self.block_1_header().as_bytes())?;
+        f.write_all(self.block_1().as_bytes())?;
+        f.write_all(self.block_2().as_bytes())?;
+        f.write_all(self.block_3().as_bytes())?;
+        f.flush()?;
+        Ok(())
+    }
+
     /// Only the formatted header String
+    ///
+    /// The particle type is given by position in the `nt` and `ne` lists, so
+    /// these are padded with zeros for every other particle type up to `ni`.
+    /// This is also extended as needed to include `particle`, so that a set
+    /// for any particle type is written as that particle type.
     pub fn block_1_header(&self) -> String {
+        let ni = (self.ni as usize).max(self.particle as usize).max(1);
+        let idx = (self.particle as usize).max(1) - 1;
+
         // if iv ni nr probid
-        let mut s = f!(
-            "{:>10}{:>10}{:>10}{:>10}",
-            self.f,
-            self.iv,
-            self.ni,
-            self.nr,
-        );
+        let mut s = f!("{:>10}{:>10}{:>10}{:>10}", self.f, self.iv, ni, self.nr);
 
-        // a19 so no longer than 19 characters
-        let mut comment = self.probid.clone();
-        comment.truncate(19);
-        s += &f!("{comment}\n");
+        // 20x a19 so no longer than 19 characters
+        s += &probid_columns(&self.probid);
+        s += "\n";
 
         // nt(1) ... nt(ni) [if iv=2]
         if self.iv == 2 {
-            s += &f!("{:>10}\n", &self.nt);
+            s += &Self::particle_list(ni, idx, self.nt);
         }
 
         // ne(1) ... ne(ni)
-        s += &f!("{:>10}\n", &self.ne);
+        s += &Self::particle_list(ni, idx, self.ne);
+        s
+    }
+
+    /// List of `ni` values as 7i10, zero for all but the particle at `idx`
+    fn particle_list(ni: usize, idx: usize, value: usize) -> String {
+        let mut s = String::new();
+        let mut count: u8 = 1;
+        for i in 0..ni {
+            s += &f!("{:>10}", if i == idx { value } else { 0 });
+            s += track_newlines(&mut count, 7);
+        }
+
+        if !s.ends_with('\n') {
+            s += "\n";
+        }
         s
     }
 
@@ -424,6 +742,47 @@ impl WeightWindow {
     }
 }
 
+/// Split every coarse mesh into its fine meshes, starting from `lower`
+fn fine_bounds(lower: f64, qps: &[[f64; 3]]) -> Vec<f64> {
+    let mut bounds = vec![lower];
+
+    for [q, p, s] in qps {
+        let n = *s as usize;
+        if n == 0 {
+            continue;
+        }
+
+        // widths of successive fine meshes increase by a factor of q
+        let q = if *q > 0.0 { *q } else { 1.0 };
+        let start = *bounds.last().unwrap();
+        let total = (0..n).map(|m| q.powi(m as i32)).sum::<f64>();
+
+        let mut edge = start;
+        for m in 0..n - 1 {
+            edge += (p - start) * q.powi(m as i32) / total;
+            bounds.push(edge);
+        }
+        bounds.push(*p);
+    }
+
+    bounds
+}
+
+fn invalid_mesh(reason: String) -> Error {
+    Error::InvalidMesh { reason }
+}
+
+/// Problem description as `20x, a19`, or nothing at all if blank
+pub(crate) fn probid_columns(probid: &str) -> String {
+    let mut comment = probid.to_string();
+    comment.truncate(19);
+
+    match comment.is_empty() {
+        true => comment,
+        false => f!("{:20}{comment}", ""),
+    }
+}
+
 impl Default for WeightWindow {
     fn default() -> Self {
         Self {
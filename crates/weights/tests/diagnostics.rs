@@ -0,0 +1,208 @@
+//! Integration tests for weight window validation and diagnostics
+
+use ntools_weights::WeightWindow;
+use rstest::rstest;
+
+/// Single 3x3 plane of voxels with a known set of weights for three groups
+///
+/// - The first group peaks in the centre, 50x any neighbour
+/// - The second group is zero except for an isolated centre voxel
+/// - The third group is non-zero except for an isolated centre voxel
+fn plane() -> WeightWindow {
+    #[rustfmt::skip]
+    let weights = vec![
+        1.0, 2.0, 1.0,
+        2.0, 100.0, 2.0,
+        1.0, 2.0, 1.0,
+
+        0.0, 0.0, 0.0,
+        0.0, 0.5, 0.0,
+        0.0, 0.0, 0.0,
+
+        0.2, 0.2, 0.2,
+        0.2, 0.0, 0.2,
+        0.2, 0.2, 0.4,
+    ];
+
+    WeightWindow {
+        nfx: 3,
+        nfy: 3,
+        nfz: 1,
+        ncx: 1,
+        ncy: 1,
+        ncz: 1,
+        qps_x: vec![[1.0, 3.0, 3.0]],
+        qps_y: vec![[1.0, 3.0, 3.0]],
+        qps_z: vec![[1.0, 1.0, 1.0]],
+        ne: 3,
+        e: vec![0.1, 1.0, 100.0],
+        weights,
+        particle: 1,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn known_groups() {
+    let report = plane().diagnostics();
+    assert!(report.is_consistent(), "{report}");
+    assert_eq!(report.n_weights, 27);
+    assert_eq!(report.n_expected, 27);
+    assert_eq!(report.groups.len(), 3);
+
+    let peak = &report.groups[0];
+    assert_eq!((peak.energy_idx, peak.time_idx), (0, 0));
+    assert_eq!((peak.energy, peak.time), (0.1, None));
+    assert_eq!((peak.min, peak.max), (1.0, 100.0));
+    assert_eq!(peak.dynamic_range, 100.0);
+    assert_eq!(peak.max_adjacent_ratio, 50.0);
+    assert_eq!(peak.max_adjacent_voxels, Some([[1, 0, 0], [1, 1, 0]]));
+    assert_eq!((peak.isolated_non_zero, peak.isolated_zero), (0, 0));
+    assert_eq!(peak.non_analogue_percentage, 100.0);
+
+    // zeros are ignored by the ratios
+    let island = &report.groups[1];
+    assert_eq!(island.energy, 1.0);
+    assert_eq!((island.min, island.max), (0.5, 0.5));
+    assert_eq!(island.dynamic_range, 1.0);
+    assert_eq!(island.max_adjacent_ratio, 0.0);
+    assert_eq!(island.max_adjacent_voxels, None);
+    assert_eq!((island.isolated_non_zero, island.isolated_zero), (1, 0));
+    assert_eq!(island.non_analogue_percentage, 100.0 / 9.0);
+
+    let hole = &report.groups[2];
+    assert_eq!(hole.energy, 100.0);
+    assert_eq!((hole.min, hole.max), (0.2, 0.4));
+    assert_eq!(hole.dynamic_range, 2.0);
+    assert_eq!(hole.max_adjacent_ratio, 2.0);
+    assert_eq!(hole.max_adjacent_voxels, Some([[2, 1, 0], [2, 2, 0]]));
+    assert_eq!((hole.isolated_non_zero, hole.isolated_zero), (0, 1));
+    assert_eq!(hole.non_analogue_percentage, 800.0 / 9.0);
+
+    assert_eq!(report.max_dynamic_range(), 100.0);
+    assert_eq!(report.max_adjacent_ratio(), 50.0);
+}
+
+#[test]
+fn time_groups() {
+    // energy groups outer, time groups inner
+    let ww = WeightWindow {
+        iv: 2,
+        nt: 3,
+        ne: 1,
+        t: vec![1.0, 10.0, 100.0],
+        e: vec![100.0],
+        ..plane()
+    };
+
+    let report = ww.diagnostics();
+    assert!(report.is_consistent(), "{report}");
+    let groups = report
+        .groups
+        .iter()
+        .map(|g| (g.energy_idx, g.time_idx, g.energy, g.time))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        groups,
+        vec![
+            (0, 0, 100.0, Some(1.0)),
+            (0, 1, 100.0, Some(10.0)),
+            (0, 2, 100.0, Some(100.0)),
+        ]
+    );
+    assert_eq!(report.groups[1].isolated_non_zero, 1);
+}
+
+#[rstest]
+#[case::missing_weight(WeightWindow { weights: plane().weights[1..].to_vec(), ..plane() }, "26 weights")]
+#[case::extra_weight(WeightWindow { weights: [plane().weights, vec![1.0]].concat(), ..plane() }, "28 weights")]
+#[case::extra_group(WeightWindow { ne: 4, e: vec![0.1, 1.0, 10.0, 100.0], ..plane() }, "27 weights")]
+fn corrupt_weights_length(#[case] ww: WeightWindow, #[case] reason: &str) {
+    let report = ww.diagnostics();
+    assert!(!report.is_consistent());
+    assert!(report.issues[0].contains(reason), "{report}");
+
+    // groups are unsafe to index without the full set of weights
+    assert!(report.groups.is_empty());
+}
+
+#[rstest]
+#[case::ncx(WeightWindow { ncx: 2, ..plane() }, "1 coarse meshes in qps_x but ncx = 2")]
+#[case::ncz(WeightWindow { ncz: 0, ..plane() }, "1 coarse meshes in qps_z but ncz = 0")]
+#[case::fine(WeightWindow { qps_y: vec![[1.0, 3.0, 2.0]], ..plane() }, "2 fine meshes in qps_y but nfy = 3")]
+#[case::bounds(
+    WeightWindow { ncx: 2, qps_x: vec![[1.0, 3.0, 2.0], [1.0, 3.0, 1.0]], ..plane() },
+    "coarse mesh bounds in qps_x are not increasing"
+)]
+fn corrupt_coarse_mesh(#[case] ww: WeightWindow, #[case] reason: &str) {
+    let report = ww.diagnostics();
+    assert_eq!(report.issues, vec![reason.to_string()], "{report}");
+
+    // the weights still match the fine mesh
+    assert_eq!(report.groups.len(), 3);
+}
+
+#[rstest]
+#[case::energy_count(WeightWindow { e: vec![0.1, 100.0], ..plane() }, "2 energy bounds but ne = 3")]
+#[case::energy_order(WeightWindow { e: vec![0.1, 100.0, 1.0], ..plane() }, "energy bounds are not increasing")]
+#[case::time_flag(WeightWindow { iv: 3, ..plane() }, "unknown time dependence flag iv = 3")]
+#[case::mesh_type(WeightWindow { nwg: 2, ..plane() }, "mesh type nwg = 2 inconsistent with nr = 10")]
+#[case::negative(
+    WeightWindow { weights: [vec![-1.0], plane().weights[1..].to_vec()].concat(), ..plane() },
+    "negative or non-finite weights"
+)]
+#[case::nan(
+    WeightWindow { weights: [vec![f64::NAN], plane().weights[1..].to_vec()].concat(), ..plane() },
+    "negative or non-finite weights"
+)]
+fn corrupt_header(#[case] ww: WeightWindow, #[case] reason: &str) {
+    let report = ww.diagnostics();
+    assert_eq!(report.issues, vec![reason.to_string()], "{report}");
+}
+
+#[rstest]
+#[case::non_zero(vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0], 2, 0)]
+#[case::zero(vec![1.0, 1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 0.0], 0, 2)]
+#[case::corners(vec![1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0], 4, 0)]
+#[case::checkerboard(vec![1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0], 5, 4)]
+fn isolated_voxels(
+    #[case] weights: Vec<f64>,
+    #[case] isolated_non_zero: usize,
+    #[case] isolated_zero: usize,
+) {
+    let ww = WeightWindow {
+        ne: 1,
+        e: vec![100.0],
+        weights,
+        ..plane()
+    };
+
+    // isolated voxels are valid, but reported for the group
+    let report = ww.diagnostics();
+    assert!(report.is_consistent(), "{report}");
+    assert_eq!(report.groups[0].isolated_non_zero, isolated_non_zero);
+    assert_eq!(report.groups[0].isolated_zero, isolated_zero);
+}
+
+#[test]
+fn isolated_voxels_with_corrupt_header() {
+    // isolated voxels are still counted alongside any issues with the header
+    let ww = WeightWindow {
+        ncy: 3,
+        e: vec![100.0, 1.0, 0.1],
+        ..plane()
+    };
+
+    let report = ww.diagnostics();
+    assert_eq!(report.issues.len(), 2, "{report}");
+    assert_eq!(report.groups[1].isolated_non_zero, 1);
+    assert_eq!(report.groups[2].isolated_zero, 1);
+
+    let text = report.to_string();
+    assert!(text.contains("consistency: 2 issue(s)"), "{text}");
+    assert!(
+        text.contains("- 1 coarse meshes in qps_y but ncy = 3"),
+        "{text}"
+    );
+}
@@ -0,0 +1,63 @@
+//! Integration tests for remapping energy and time groups
+
+use ntools_mesh::{read_target, Mesh};
+use ntools_weights::{GroupCollapse, WeightWindow};
+
+/// Mesh fixture with a distinct result in every voxel
+fn flux_mesh(id: u32, result: impl Fn(usize) -> f64) -> Mesh {
+    let mut mesh = read_target(format!("../mesh/data/meshes/fmesh_{id}.msht"), id).unwrap();
+    for (i, voxel) in mesh.voxels.iter_mut().enumerate() {
+        voxel.result = result(i);
+    }
+    mesh
+}
+
+#[test]
+fn flux_weighted_from_mesh() {
+    // 2 energy and 3 time groups, plus totals for both
+    let mesh = flux_mesh(114, |i| 1.0 + (i % 11) as f64);
+    let flux = flux_mesh(114, |i| 1.0 + (i % 7) as f64);
+
+    let mut ww = WeightWindow::from_mesh(&mesh).unwrap();
+    let method = GroupCollapse::FluxWeighted(ww.mesh_results(&flux).unwrap());
+
+    // collapse both energy groups, and the first two time groups
+    ww.remap_groups(&[100.0], &[1e15, 1e30], method).unwrap();
+    assert_eq!((ww.ne, ww.nt), (1, 2));
+
+    let collapsed = ww.to_mesh().unwrap();
+    let time_map = [vec![0, 1], vec![2]];
+    for (t_new, t_old) in time_map.iter().enumerate() {
+        for i in 0..mesh.iints {
+            for j in 0..mesh.jints {
+                for k in 0..mesh.kints {
+                    let (mut sum, mut total) = (0.0, 0.0);
+                    for e in 0..2 {
+                        for t in t_old {
+                            let idx = mesh.voxel_index_from_etijk(e, *t, i, j, k);
+                            sum += flux.voxels[idx].result * mesh.voxels[idx].result;
+                            total += flux.voxels[idx].result;
+                        }
+                    }
+
+                    let idx = collapsed.voxel_index_from_etijk(0, t_new, i, j, k);
+                    let result = collapsed.voxels[idx].result;
+                    let expected = sum / total;
+                    assert!(
+                        (result - expected).abs() < 1e-12 * expected,
+                        "{result} != {expected} at ({t_new}, {i}, {j}, {k})"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn flux_mesh_must_match() {
+    let ww = WeightWindow::from_mesh(&flux_mesh(114, |_| 1.0)).unwrap();
+
+    // different groups, then the same voxels on a different geometry
+    assert!(ww.mesh_results(&flux_mesh(104, |_| 1.0)).is_err());
+    assert!(ww.mesh_results(&flux_mesh(134, |_| 1.0)).is_err());
+}
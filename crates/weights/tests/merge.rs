@@ -0,0 +1,204 @@
+//! Integration tests for combining weights between iterations
+
+use ntools_mesh::{read_target, Mesh};
+use ntools_weights::{WeightMerge, WeightWindow};
+use rstest::rstest;
+
+/// Cylindrical 2x1x2 set with a single energy group
+fn cylindrical(weights: Vec<f64>) -> WeightWindow {
+    WeightWindow {
+        nr: 16,
+        nwg: 2,
+        nfx: 2,
+        nfz: 2,
+        ncz: 1,
+        x1: 1.0,
+        y1: 0.0,
+        z1: 0.0,
+        x2: 0.0,
+        y2: 1.0,
+        z2: 0.0,
+        qps_x: vec![[1.0, 10.0, 2.0]],
+        qps_y: vec![[1.0, 10.0, 1.0]],
+        qps_z: vec![[1.0, 1.0, 2.0]],
+        weights,
+        ..Default::default()
+    }
+}
+
+fn best_error(errors: Vec<f64>, previous_errors: Vec<f64>) -> WeightMerge {
+    WeightMerge::BestError {
+        errors,
+        previous_errors,
+    }
+}
+
+fn error_weighted(errors: Vec<f64>, previous_errors: Vec<f64>) -> WeightMerge {
+    WeightMerge::ErrorWeighted {
+        errors,
+        previous_errors,
+    }
+}
+
+#[rstest]
+#[case::nwg(WeightWindow { nwg: 1, ..cylindrical(vec![0.1; 4]) })]
+#[case::vec(WeightWindow { x2: 1.0, y2: 0.0, ..cylindrical(vec![0.1; 4]) })]
+#[case::axs(WeightWindow { x1: 0.0, z1: 1.0, ..cylindrical(vec![0.1; 4]) })]
+#[case::origin(WeightWindow { z0: 5.0, ..cylindrical(vec![0.1; 4]) })]
+#[case::qps(WeightWindow { qps_x: vec![[1.0, 20.0, 2.0]], ..cylindrical(vec![0.1; 4]) })]
+fn incompatible_geometry(#[case] previous: WeightWindow) {
+    let mut ww = cylindrical(vec![0.2; 4]);
+    assert!(ww.check_compatible(&previous).is_err());
+    assert!(ww.merge(&previous, &WeightMerge::Fill).is_err());
+    assert_eq!(ww.weights, vec![0.2; 4]);
+}
+
+#[test]
+fn rectangular_ignores_vec() {
+    // VEC is not part of a rectangular mesh, and never written to file
+    let a = WeightWindow::default();
+    let b = WeightWindow {
+        x2: 0.0,
+        z2: 1.0,
+        ..Default::default()
+    };
+    assert!(a.check_compatible(&b).is_ok());
+}
+
+#[rstest]
+#[case::best_short(best_error(vec![0.1; 3], vec![0.1; 4]))]
+#[case::best_previous_short(best_error(vec![0.1; 4], vec![0.1; 3]))]
+#[case::best_negative(best_error(vec![0.1, -0.1, 0.1, 0.1], vec![0.1; 4]))]
+#[case::weighted_short(error_weighted(vec![0.1; 4], vec![0.1; 5]))]
+#[case::weighted_empty(error_weighted(Vec::new(), Vec::new()))]
+#[case::weighted_negative(error_weighted(vec![0.1; 4], vec![0.1, 0.1, 0.1, -0.1]))]
+fn invalid_errors(#[case] method: WeightMerge) {
+    let previous = cylindrical(vec![0.1, 0.2, 0.0, 0.4]);
+    let mut ww = cylindrical(vec![0.4, 0.0, 0.0, 0.1]);
+
+    assert!(ww.merge(&previous, &method).is_err());
+    assert_eq!(ww.weights, vec![0.4, 0.0, 0.0, 0.1]);
+}
+
+#[test]
+fn best_error_differing_errors() {
+    let previous = cylindrical(vec![0.1, 0.2, 0.3, 0.4]);
+    let mut ww = cylindrical(vec![0.4, 0.3, 0.0, 0.1]);
+
+    // new, previous, filled, and equal errors keep the new weight
+    let method = best_error(vec![0.05, 0.5, 0.1, 0.2], vec![0.1, 0.1, 0.1, 0.2]);
+    let changed = ww.merge(&previous, &method).unwrap();
+
+    assert_eq!(changed, 2);
+    assert_eq!(ww.weights, vec![0.4, 0.2, 0.3, 0.1]);
+}
+
+#[test]
+fn error_weighted_differing_errors() {
+    let previous = cylindrical(vec![0.1, 0.2, 0.3, 0.0]);
+    let mut ww = cylindrical(vec![0.4, 0.2, 0.0, 0.0]);
+
+    let method = error_weighted(vec![0.1, 0.2, 0.1, 0.1], vec![0.2, 0.1, 0.1, 0.1]);
+    let changed = ww.merge(&previous, &method).unwrap();
+    assert_eq!(changed, 2);
+
+    // inverse variance weights of 100 and 25 in log space
+    let expected = ((100.0 * 0.4_f64.ln() + 25.0 * 0.1_f64.ln()) / 125.0).exp();
+    assert!((ww.weights[0] - expected).abs() < 1e-12);
+    assert!(ww.weights[0] > 0.1_f64.sqrt() * 0.4_f64.sqrt());
+
+    // equal weights are unchanged, holes are filled, and analogue stays analogue
+    assert!((ww.weights[1] - 0.2).abs() < 1e-12);
+    assert_eq!(ww.weights[2..], [0.3, 0.0]);
+}
+
+/// Flux mesh with a distinct result and error in every voxel
+fn flux_mesh(id: u32, result: impl Fn(usize) -> f64, error: impl Fn(usize) -> f64) -> Mesh {
+    let mut mesh = read_target(format!("../mesh/data/meshes/fmesh_{id}.msht"), id).unwrap();
+    for (i, voxel) in mesh.voxels.iter_mut().enumerate() {
+        voxel.result = result(i);
+        voxel.error = error(i);
+    }
+    mesh
+}
+
+#[rstest]
+fn best_error_from_meshes(#[values(104, 114, 134)] id: u32) {
+    // errors cross over at different voxels for the new and previous meshes
+    let mesh = flux_mesh(id, |i| 1.0 + i as f64, |i| (i % 7) as f64 / 10.0);
+    let previous_mesh = flux_mesh(id, |i| 1000.0 + i as f64, |i| (i % 5) as f64 / 10.0);
+
+    let mut ww = WeightWindow::from_mesh(&mesh).unwrap();
+    let previous = WeightWindow::from_mesh(&previous_mesh).unwrap();
+
+    let method = WeightMerge::BestError {
+        errors: ww.mesh_errors(&mesh).unwrap(),
+        previous_errors: previous.mesh_errors(&previous_mesh).unwrap(),
+    };
+    ww.merge(&previous, &method).unwrap();
+
+    // every voxel keeps the result with its own lower error
+    let merged = ww.to_mesh().unwrap();
+    for e in 0..ww.ne {
+        for t in 0..ww.nt {
+            for i in 0..mesh.iints {
+                for j in 0..mesh.jints {
+                    for k in 0..mesh.kints {
+                        let idx = mesh.voxel_index_from_etijk(e, t, i, j, k);
+                        let (new, old) = (&mesh.voxels[idx], &previous_mesh.voxels[idx]);
+                        let expected = match old.error < new.error {
+                            true => old.result,
+                            false => new.result,
+                        };
+                        assert_eq!(merged.voxels[idx].result, expected, "voxel {idx}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn error_weighted_from_meshes() {
+    let mesh = flux_mesh(104, |i| 1.0 + i as f64, |i| 0.1 + i as f64 / 100.0);
+    let previous_mesh = flux_mesh(104, |i| 2.0 * (1.0 + i as f64), |_| 0.1);
+
+    let mut ww = WeightWindow::from_mesh(&mesh).unwrap();
+    let previous = WeightWindow::from_mesh(&previous_mesh).unwrap();
+
+    let method = WeightMerge::ErrorWeighted {
+        errors: ww.mesh_errors(&mesh).unwrap(),
+        previous_errors: previous.mesh_errors(&previous_mesh).unwrap(),
+    };
+    ww.merge(&previous, &method).unwrap();
+
+    let merged = ww.to_mesh().unwrap();
+    for (idx, voxel) in merged.voxels.iter().enumerate() {
+        let (new, old) = (&mesh.voxels[idx], &previous_mesh.voxels[idx]);
+        let (a, b) = (new.error.powi(-2), old.error.powi(-2));
+        let expected = ((a * new.result.ln() + b * old.result.ln()) / (a + b)).exp();
+        assert!(
+            (voxel.result - expected).abs() < 1e-12 * expected,
+            "voxel {idx}"
+        );
+    }
+}
+
+#[test]
+fn mesh_errors_must_match() {
+    let mesh = flux_mesh(104, |_| 1.0, |_| 0.1);
+    let ww = WeightWindow::from_mesh(&mesh).unwrap();
+
+    let mut short = mesh.clone();
+    short.voxels.pop();
+    assert!(ww.mesh_errors(&short).is_err());
+
+    // the same number of voxels on a different geometry
+    let cylinder = flux_mesh(124, |_| 1.0, |_| 0.1);
+    assert_eq!(cylinder.voxels.len(), mesh.voxels.len());
+    assert!(ww.mesh_errors(&cylinder).is_err());
+
+    let mut shifted = mesh.clone();
+    shifted.imesh[1] += 1.0;
+    assert!(ww.mesh_errors(&shifted).is_err());
+}
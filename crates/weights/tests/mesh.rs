@@ -0,0 +1,114 @@
+//! Integration tests for conversion between weight windows and meshes
+
+use ntools_mesh::Geometry;
+use ntools_weights::WeightWindow;
+use rstest::rstest;
+
+/// Several coarse meshes with a problem description and two energy groups
+fn multi_coarse() -> WeightWindow {
+    WeightWindow {
+        f: 1,
+        ni: 2,
+        ne: 2,
+        nfx: 5,
+        nfy: 3,
+        nfz: 1,
+        ncx: 2,
+        ncy: 2,
+        ncz: 1,
+        qps_x: vec![[1.0, 10.0, 2.0], [1.0, 25.0, 3.0]],
+        qps_y: vec![[1.0, -5.0, 1.0], [1.0, 5.0, 2.0]],
+        qps_z: vec![[1.0, 10.0, 1.0]],
+        e: vec![1.0, 100.0],
+        weights: (1..=30).map(|w| w as f64 / 100.0).collect(),
+        probid: "multi coarse".to_string(),
+        particle: 2,
+        ..Default::default()
+    }
+}
+
+/// Cylindrical set with three theta bins over two coarse meshes
+fn cylindrical() -> WeightWindow {
+    WeightWindow {
+        nr: 16,
+        nwg: 2,
+        ncz: 2,
+        nfz: 3,
+        x1: 1.0,
+        y1: 0.0,
+        z1: 0.0,
+        x2: 0.0,
+        y2: 1.0,
+        z2: 0.0,
+        qps_z: vec![[1.0, 0.5, 2.0], [1.0, 1.0, 1.0]],
+        weights: (1..=90).map(|w| w as f64 / 10.0).collect(),
+        probid: "cylinder".to_string(),
+        particle: 1,
+        ..multi_coarse()
+    }
+}
+
+#[rstest]
+#[case(multi_coarse())]
+#[case(cylindrical())]
+fn lossless_round_trip(#[case] ww: WeightWindow) {
+    let mesh = ww.to_mesh().unwrap();
+    assert_eq!(ww.with_mesh_weights(&mesh).unwrap(), ww);
+}
+
+#[test]
+fn updated_weights() {
+    let ww = multi_coarse();
+    let mut mesh = ww.to_mesh().unwrap();
+    mesh.scale(2.0);
+
+    let scaled = ww.with_mesh_weights(&mesh).unwrap();
+    let expected: Vec<f64> = ww.weights.iter().map(|w| w * 2.0).collect();
+    assert_eq!(scaled.weights, expected);
+    assert_eq!(scaled.qps_x, ww.qps_x);
+    assert_eq!(scaled.qps_y, ww.qps_y);
+    assert_eq!(scaled.probid, ww.probid);
+}
+
+#[test]
+fn from_mesh_resets_structure() {
+    let ww = multi_coarse();
+    let copy = WeightWindow::from_mesh(&ww.to_mesh().unwrap()).unwrap();
+
+    // the fine mesh and weights survive, but every fine mesh is a coarse mesh
+    assert_eq!(copy.fine_mesh_bounds(), ww.fine_mesh_bounds());
+    assert_eq!(copy.weights, ww.weights);
+    assert_eq!((copy.ncx, copy.ncy, copy.ncz), (5, 3, 1));
+    assert_eq!(copy.probid, "");
+    assert_eq!(copy.f, 1);
+    assert_eq!(copy.ni, 2);
+    assert_eq!(copy.particle, 2);
+}
+
+#[test]
+fn mismatched_bounds() {
+    let ww = multi_coarse();
+    let mut mesh = ww.to_mesh().unwrap();
+    mesh.imesh[1] += 1.0;
+    assert!(ww.with_mesh_weights(&mesh).is_err());
+}
+
+#[test]
+fn mismatched_groups() {
+    let ww = multi_coarse();
+    let single = WeightWindow {
+        ne: 1,
+        e: vec![100.0],
+        weights: ww.weights[..15].to_vec(),
+        ..multi_coarse()
+    };
+    assert!(ww.with_mesh_weights(&single.to_mesh().unwrap()).is_err());
+}
+
+#[test]
+fn mismatched_geometry() {
+    let ww = multi_coarse();
+    let mut mesh = ww.to_mesh().unwrap();
+    mesh.geometry = Geometry::Cylindrical;
+    assert!(ww.with_mesh_weights(&mesh).is_err());
+}
@@ -0,0 +1,195 @@
+//! Integration tests for reading and writing weight window files
+
+use std::path::PathBuf;
+
+use ntools_weights::{read_wwinp, try_write_multi_particle, WeightWindow};
+use rstest::rstest;
+
+/// Rectangular 2x1x1 set with a single energy group
+fn rectangular(particle: u8) -> WeightWindow {
+    WeightWindow {
+        nfx: 2,
+        nfy: 1,
+        nfz: 1,
+        ncx: 1,
+        ncy: 1,
+        ncz: 1,
+        qps_x: vec![[1.0, 10.0, 2.0]],
+        qps_y: vec![[1.0, 10.0, 1.0]],
+        qps_z: vec![[1.0, 10.0, 1.0]],
+        e: vec![100.0],
+        weights: vec![0.5, 0.25],
+        particle,
+        ..Default::default()
+    }
+}
+
+/// Time dependent set with three time and two energy groups
+fn time_dependent() -> WeightWindow {
+    WeightWindow {
+        iv: 2,
+        ne: 2,
+        nt: 3,
+        t: vec![1e5, 1e15, 1e30],
+        e: vec![1.0, 100.0],
+        weights: (1..=12).map(|w| w as f64 / 20.0).collect(),
+        ..rectangular(1)
+    }
+}
+
+/// Cylindrical set with the extra axs/vec words
+fn cylindrical() -> WeightWindow {
+    WeightWindow {
+        nr: 16,
+        nwg: 2,
+        nfx: 2,
+        nfy: 1,
+        nfz: 3,
+        ncz: 1,
+        x0: 1.5,
+        y0: -2.0,
+        z0: 3.0,
+        x1: 1.0,
+        y1: 0.0,
+        z1: 0.0,
+        x2: 0.0,
+        y2: 1.0,
+        z2: 0.0,
+        qps_z: vec![[1.0, 1.0, 3.0]],
+        weights: vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6],
+        ..rectangular(1)
+    }
+}
+
+/// Several coarse meshes with varying numbers of fine meshes
+fn multi_coarse() -> WeightWindow {
+    WeightWindow {
+        nfx: 5,
+        nfy: 3,
+        ncx: 2,
+        ncy: 2,
+        qps_x: vec![[1.0, 10.0, 2.0], [1.0, 25.0, 3.0]],
+        qps_y: vec![[1.0, -5.0, 1.0], [1.0, 5.0, 2.0]],
+        weights: (1..=15).map(|w| w as f64 / 100.0).collect(),
+        ..rectangular(1)
+    }
+}
+
+/// Fresh path for the output of a single test
+fn output_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ntools_weights_{name}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("wwout")
+}
+
+#[rstest]
+#[case("rectangular", rectangular(1))]
+#[case("time_dependent", time_dependent())]
+#[case("cylindrical", cylindrical())]
+#[case("multi_coarse", multi_coarse())]
+fn single_particle_round_trip(#[case] name: &str, #[case] ww: WeightWindow) {
+    let path = output_path(&format!("single_{name}"));
+    ww.try_write(&path).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+
+    let copy = read_wwinp(&path).unwrap();
+    assert_eq!(copy, vec![ww]);
+
+    // writing what was read reproduces the file exactly
+    copy[0].try_write(&path).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+}
+
+#[test]
+fn photon_only_round_trip() {
+    let path = output_path("photon_only");
+    let photon = rectangular(2);
+    photon.try_write(&path).unwrap();
+
+    // no neutron energy groups, so the photons are the second particle type
+    let content = std::fs::read_to_string(&path).unwrap();
+    let mut lines = content.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "         1         1         2        10"
+    );
+    assert_eq!(lines.next().unwrap(), "         0         1");
+
+    let copy = read_wwinp(&path).unwrap();
+    assert_eq!(copy.len(), 1);
+    assert_eq!(copy[0].particle, 2);
+    assert_eq!(copy[0].ni, 2);
+    assert_eq!(copy[0].weights, photon.weights);
+
+    copy[0].try_write(&path).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+}
+
+#[test]
+fn time_dependent_photons() {
+    let path = output_path("time_photon");
+    let photon = WeightWindow {
+        particle: 2,
+        ..time_dependent()
+    };
+    photon.try_write(&path).unwrap();
+
+    // both the time and energy bin lists are padded for the neutrons
+    let content = std::fs::read_to_string(&path).unwrap();
+    let mut lines = content.lines().skip(1);
+    assert_eq!(lines.next().unwrap(), "         0         3");
+    assert_eq!(lines.next().unwrap(), "         0         2");
+
+    let copy = read_wwinp(&path).unwrap();
+    assert_eq!(copy, vec![WeightWindow { ni: 2, ..photon }]);
+}
+
+#[rstest]
+fn multi_particle_round_trip(#[values(false, true)] padded: bool) {
+    let path = output_path(&format!("multi_{padded}"));
+    let neutron = time_dependent();
+    let photon = WeightWindow {
+        particle: 2,
+        ne: 1,
+        e: vec![20.0],
+        weights: (1..=6).map(|w| w as f64 / 10.0).collect(),
+        ..time_dependent()
+    };
+
+    let dropped =
+        try_write_multi_particle(&[photon.clone(), neutron.clone()], &path, padded).unwrap();
+    assert!(dropped.is_empty());
+
+    // every set records the number of particle types in the file
+    let copy = read_wwinp(&path).unwrap();
+    assert_eq!(copy.len(), 2);
+    assert_eq!(copy[0], WeightWindow { ni: 2, ..neutron });
+    assert_eq!(copy[1], WeightWindow { ni: 2, ..photon });
+}
+
+#[test]
+fn padded_missing_particles() {
+    let path = output_path("multi_padded_gap");
+    let neutron = rectangular(1);
+    let electron = rectangular(3);
+
+    try_write_multi_particle(&[neutron.clone(), electron.clone()], &path, true).unwrap();
+
+    // photons are skipped, and the electrons keep their particle type
+    let copy = read_wwinp(&path).unwrap();
+    assert_eq!(
+        copy,
+        vec![
+            WeightWindow { ni: 3, ..neutron },
+            WeightWindow { ni: 3, ..electron },
+        ]
+    );
+
+    // a single set from the file is written back as the same particle type
+    copy[1].try_write(&path).unwrap();
+    let single = std::fs::read_to_string(&path).unwrap();
+    assert!(single
+        .starts_with("         1         1         3        10\n         0         0         1\n"));
+    assert_eq!(read_wwinp(&path).unwrap(), vec![copy[1].clone()]);
+}
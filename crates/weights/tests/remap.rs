@@ -0,0 +1,163 @@
+//! Integration tests for projecting weights onto a new mesh geometry
+
+use ntools_weights::{Grid, SpatialRemap, WeightWindow};
+use rstest::rstest;
+
+/// Cylinder along z with 2 radial and 4 theta bins, all with distinct weights
+fn cylinder() -> WeightWindow {
+    WeightWindow {
+        nr: 16,
+        nwg: 2,
+        nfx: 2,
+        nfy: 1,
+        nfz: 4,
+        ncx: 2,
+        ncy: 1,
+        ncz: 1,
+        x1: 0.0,
+        y1: 0.0,
+        z1: 1.0,
+        x2: 1.0,
+        y2: 0.0,
+        z2: 0.0,
+        qps_x: vec![[1.0, 5.0, 1.0], [1.0, 10.0, 1.0]],
+        qps_y: vec![[1.0, 10.0, 1.0]],
+        qps_z: vec![[1.0, 1.0, 4.0]],
+        e: vec![100.0],
+        weights: (1..=8).map(|w| w as f64 / 10.0).collect(),
+        ..Default::default()
+    }
+}
+
+/// Single row of voxels along x
+fn row(bounds: Vec<f64>, weights: Vec<f64>) -> WeightWindow {
+    let n = weights.len();
+    WeightWindow {
+        nfx: n,
+        nfy: 1,
+        nfz: 1,
+        ncx: n,
+        ncy: 1,
+        ncz: 1,
+        x0: bounds[0],
+        qps_x: bounds[1..].iter().map(|b| [1.0, *b, 1.0]).collect(),
+        qps_y: vec![[1.0, 10.0, 1.0]],
+        qps_z: vec![[1.0, 10.0, 1.0]],
+        e: vec![100.0],
+        weights,
+        ..Default::default()
+    }
+}
+
+/// Weight of the cylinder voxel in radial bin `i` and theta bin `k`
+fn cylinder_weight(i: usize, k: usize) -> f64 {
+    cylinder().weights[i + 2 * k]
+}
+
+#[rstest]
+fn cylinder_rotated(
+    #[values(SpatialRemap::Centre, SpatialRemap::VolumeMinimum(3))] method: SpatialRemap,
+) {
+    // theta=0 moved a quarter turn, so every theta bin shifts by one
+    let grid = Grid::cylindrical(
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0],
+        vec![0.0, 5.0, 10.0],
+        vec![0.0, 10.0],
+        vec![0.0, 0.25, 0.5, 0.75, 1.0],
+    );
+
+    let ww = cylinder().remap(&grid, method).unwrap();
+    assert_eq!((ww.nwg, ww.nfx, ww.nfy, ww.nfz), (2, 2, 1, 4));
+    assert_eq!([ww.x2, ww.y2, ww.z2], [0.0, 1.0, 0.0]);
+
+    for k in 0..4 {
+        for i in 0..2 {
+            assert_eq!(ww.weights[i + 2 * k], cylinder_weight(i, (k + 1) % 4));
+        }
+    }
+}
+
+#[test]
+fn cylinder_to_rectangular() {
+    let grid = Grid::rectangular(
+        vec![-6.0, -5.0, 1.0, 2.0, 12.0, 20.0],
+        vec![2.0, 3.0],
+        vec![4.0, 6.0],
+    );
+
+    let ww = cylinder().remap(&grid, SpatialRemap::Centre).unwrap();
+    assert_eq!(ww.nwg, 1);
+
+    // voxel centres at y=2.5, with the last outside of the cylinder
+    let expected = vec![
+        cylinder_weight(1, 1), // x=-5.5, r=6.0, theta=156 deg
+        cylinder_weight(0, 1), // x=-2.0, r=3.2, theta=129 deg
+        cylinder_weight(0, 0), // x=1.5, r=2.9, theta=59 deg
+        cylinder_weight(1, 0), // x=7.0, r=7.4, theta=20 deg
+        0.0,
+    ];
+    assert_eq!(ww.weights, expected);
+}
+
+#[test]
+fn multiple_fine_meshes() {
+    // fine meshes of unequal width, [0, 5, 10, 20, 50] in x and [0, 4, 6] in z
+    let ww = WeightWindow {
+        nfx: 4,
+        nfz: 2,
+        ncx: 2,
+        qps_x: vec![[1.0, 10.0, 2.0], [3.0, 50.0, 2.0]],
+        qps_z: vec![[0.5, 6.0, 2.0]],
+        weights: (1..=8).map(|w| w as f64 / 10.0).collect(),
+        ..row(vec![0.0, 10.0], vec![0.0])
+    };
+    assert_eq!(ww.fine_mesh_bounds()[0], vec![0.0, 5.0, 10.0, 20.0, 50.0]);
+    assert_eq!(ww.fine_mesh_bounds()[2], vec![0.0, 4.0, 6.0]);
+
+    // the fine mesh bounds themselves map every voxel onto itself
+    let [x, y, z] = ww.fine_mesh_bounds();
+    let same = ww
+        .remap(&Grid::rectangular(x, y, z), SpatialRemap::Centre)
+        .unwrap();
+    assert_eq!(same.weights, ww.weights);
+
+    // x=25 is only in the last fine mesh for unequal widths
+    let grid = Grid::rectangular(vec![2.0, 4.0, 22.0, 28.0], vec![0.0, 10.0], vec![4.5, 5.5]);
+    let ww = ww.remap(&grid, SpatialRemap::Centre).unwrap();
+    assert_eq!(ww.weights, vec![0.5, 0.7, 0.8]);
+}
+
+#[rstest]
+fn volume_minimum_straddling(#[values(3, 5, 9)] n: usize) {
+    // the voxel centre is in the highest weight, and the first bin is analogue
+    let ww = row(vec![0.0, 1.0, 2.0, 3.0, 4.0], vec![0.0, 0.9, 0.2, 0.1]);
+    let grid = Grid::rectangular(vec![0.5, 2.5], vec![0.0, 10.0], vec![0.0, 10.0]);
+
+    let centre = ww.remap(&grid, SpatialRemap::Centre).unwrap();
+    assert_eq!(centre.weights, vec![0.9]);
+
+    let minimum = ww.remap(&grid, SpatialRemap::VolumeMinimum(n)).unwrap();
+    assert_eq!(minimum.weights, vec![0.2]);
+}
+
+#[test]
+fn volume_minimum_every_octant() {
+    // 2x2x2 source with the lowest weight in a single corner
+    let weights = vec![0.5, 0.4, 0.3, 0.2, 0.1, 0.8, 0.7, 0.6];
+    let ww = WeightWindow {
+        nfy: 2,
+        nfz: 2,
+        ncy: 2,
+        ncz: 2,
+        qps_y: vec![[1.0, 5.0, 1.0], [1.0, 10.0, 1.0]],
+        qps_z: vec![[1.0, 5.0, 1.0], [1.0, 10.0, 1.0]],
+        weights,
+        ..row(vec![0.0, 5.0, 10.0], vec![0.0; 2])
+    };
+
+    let grid = Grid::rectangular(vec![1.0, 9.0], vec![1.0, 9.0], vec![1.0, 9.0]);
+    let ww = ww.remap(&grid, SpatialRemap::VolumeMinimum(2)).unwrap();
+    assert_eq!(ww.weights, vec![0.1]);
+}
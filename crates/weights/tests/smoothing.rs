@@ -0,0 +1,83 @@
+//! Integration tests for ratio limiting and smoothing of weights
+
+use ntools_weights::{limit_ratio, WeightWindow};
+use rstest::rstest;
+
+/// Single row of theta bins, analogue except for the first and last
+fn theta_row(nwg: u8) -> WeightWindow {
+    WeightWindow {
+        nwg,
+        nfx: 1,
+        nfy: 1,
+        nfz: 4,
+        weights: vec![1.0, 0.0, 0.0, 0.001],
+        ..Default::default()
+    }
+}
+
+#[rstest]
+#[case::below_one(0.5)]
+#[case::nan(f64::NAN)]
+#[case::infinite(f64::INFINITY)]
+fn invalid_ratio(#[case] max_ratio: f64) {
+    let mut ww = theta_row(1);
+    assert!(ww.limit_adjacent_ratio(max_ratio).is_err());
+    assert_eq!(ww.weights, theta_row(1).weights);
+}
+
+#[test]
+fn mismatched_weights() {
+    let mut ww = theta_row(1);
+    ww.weights.pop();
+    assert!(ww.limit_adjacent_ratio(10.0).is_err());
+}
+
+#[test]
+fn theta_wraps_for_cylinders() {
+    // the first and last theta bins are only adjacent for cylinders
+    let mut ww = theta_row(1);
+    assert_eq!(ww.limit_adjacent_ratio(10.0).unwrap(), 0);
+    assert_eq!(ww.weights, vec![1.0, 0.0, 0.0, 0.001]);
+
+    let mut ww = theta_row(2);
+    assert_eq!(ww.limit_adjacent_ratio(10.0).unwrap(), 1);
+    assert_eq!(ww.weights, vec![1.0, 0.0, 0.0, 0.1]);
+}
+
+#[test]
+fn groups_are_independent() {
+    let mut ww = WeightWindow {
+        nfx: 3,
+        nfy: 1,
+        nfz: 1,
+        ne: 3,
+        e: vec![1.0, 10.0, 100.0],
+        weights: vec![
+            1.0, 0.001, 1e-5, // limited from the left
+            1e-6, 1e-6, 1e-6, // already within the limit
+            1e-5, 0.001, 1.0, // limited from the right
+        ],
+        ..Default::default()
+    };
+
+    assert_eq!(ww.limit_adjacent_ratio(10.0).unwrap(), 4);
+
+    let expected = [1.0, 0.1, 0.01, 1e-6, 1e-6, 1e-6, 0.01, 0.1, 1.0];
+    for (w, e) in ww.weights.iter().zip(expected) {
+        assert!((w - e).abs() < 1e-12 * e, "{w} != {e}");
+    }
+}
+
+#[test]
+fn limit_ratio_checks_inputs() {
+    let mut values = vec![1.0, 0.001];
+    assert!(limit_ratio(&mut values, &[vec![1]], 10.0).is_err());
+    assert!(limit_ratio(&mut values, &[vec![1], vec![0]], 0.5).is_err());
+    assert_eq!(values, vec![1.0, 0.001]);
+
+    // zero values are neither raised nor used to raise others
+    let mut values = vec![1.0, 0.0, 0.001];
+    let adjacency = [vec![1], vec![0, 2], vec![1]];
+    assert_eq!(limit_ratio(&mut values, &adjacency, 10.0).unwrap(), 0);
+    assert_eq!(values, vec![1.0, 0.0, 0.001]);
+}
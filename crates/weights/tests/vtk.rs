@@ -0,0 +1,139 @@
+//! Integration tests for VTK conversion of weight windows
+
+use std::path::PathBuf;
+
+use ntools_mesh::read_target;
+use ntools_weights::vtk::{read_vtk, write_vtk, VtkFormat, WeightsToVtk};
+use ntools_weights::WeightWindow;
+use rstest::rstest;
+use vtkio::model::{ByteOrder, CellType, DataSet, Piece};
+
+/// Weight windows generated from a fixture mesh of the mesh crate
+fn weights(id: u32) -> WeightWindow {
+    let mesh = read_target(format!("../mesh/data/meshes/fmesh_{id}.msht"), id).unwrap();
+    let mut ww = WeightWindow::from_mesh(&mesh).unwrap();
+    ww.probid = "round trip".to_string();
+    ww
+}
+
+/// Fresh directory for the output of a single test
+fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ntools_weights_{name}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[rstest]
+fn file_round_trip(
+    #[values(114, 134)] id: u32,
+    #[values(VtkFormat::Xml, VtkFormat::LegacyAscii, VtkFormat::LegacyBinary)] format: VtkFormat,
+    #[values(ByteOrder::BigEndian, ByteOrder::LittleEndian)] byte_order: ByteOrder,
+) {
+    let ww = weights(id);
+    let dir = output_dir(&format!("round_trip_{id}_{format:?}_{byte_order:?}"));
+    let path = dir.join(match (format, ww.nwg) {
+        (VtkFormat::Xml, 1) => "weights.vtr",
+        (VtkFormat::Xml, _) => "weights.vtu",
+        _ => "weights.vtk",
+    });
+
+    let vtk = WeightsToVtk::builder()
+        .byte_order(byte_order)
+        .build()
+        .convert(&ww);
+    write_vtk(vtk, &path, format).unwrap();
+
+    let copy = read_vtk(&path).unwrap();
+    assert_eq!(copy.nwg, ww.nwg);
+    assert_eq!(copy.probid, ww.probid);
+    assert_eq!(copy.particle, ww.particle);
+    assert_eq!((copy.nfx, copy.nfy, copy.nfz), (ww.nfx, ww.nfy, ww.nfz));
+    assert_eq!([copy.x0, copy.y0, copy.z0], [ww.x0, ww.y0, ww.z0]);
+    assert_eq!(copy.qps_x, ww.qps_x);
+    assert_eq!(copy.qps_y, ww.qps_y);
+    assert_eq!(copy.qps_z, ww.qps_z);
+    assert_eq!(copy.e, ww.e);
+    assert_eq!(copy.t, ww.t);
+    assert_eq!(copy.weights, ww.weights);
+}
+
+#[rstest]
+#[case([1, 1, 1])]
+#[case([2, 1, 1])]
+#[case([1, 3, 1])]
+#[case([1, 1, 2])]
+#[case([2, 2, 3])]
+fn cylindrical_cell_counts(#[case] resolution: [u8; 3]) {
+    let [radial, axial, theta] = resolution;
+    let ww = weights(134);
+    let vtk = WeightsToVtk::builder()
+        .radial_resolution(radial)
+        .axial_resolution(axial)
+        .resolution(theta)
+        .build()
+        .convert(&ww);
+
+    let piece = match &vtk.data {
+        DataSet::UnstructuredGrid { pieces, .. } => match pieces.as_slice() {
+            [Piece::Inline(piece)] => piece,
+            _ => panic!("expected a single inline piece"),
+        },
+        _ => panic!("expected an unstructured grid"),
+    };
+
+    // every voxel is split into r*z*theta cells
+    let per_voxel = (radial * axial * theta) as usize;
+    let n_cells = ww.nfx * ww.nfy * ww.nfz * per_voxel;
+    assert_eq!(piece.cells.types.len(), n_cells);
+
+    // only the innermost radial subdivision touches the axis
+    let n_wedges = ww.nfy * ww.nfz * (axial * theta) as usize;
+    let wedges = piece.cells.types.iter().filter(|t| **t == CellType::Wedge);
+    assert_eq!(wedges.count(), n_wedges);
+
+    // cells do not share verticies
+    let n_points = 6 * n_wedges + 8 * (n_cells - n_wedges);
+    assert_eq!(piece.points.len(), 3 * n_points);
+    assert_eq!(piece.cells.cell_verts.num_cells(), n_cells);
+}
+
+#[test]
+fn cylindrical_theta_bounds() {
+    let mut ww = WeightWindow {
+        nr: 16,
+        nwg: 2,
+        x2: 1.0,
+        y2: 0.0,
+        z2: 0.0,
+        ..Default::default()
+    };
+    ww.set_coarse_mesh(
+        [&[0.0, 5.0], &[0.0, 10.0], &[0.0, 0.25, 1.0]],
+        [&[1], &[1], &[1, 1]],
+    )
+    .unwrap();
+    ww.weights = vec![1.0, 2.0];
+
+    let vtk = WeightsToVtk::builder().resolution(2).build().convert(&ww);
+    let points: Vec<f64> = match &vtk.data {
+        DataSet::UnstructuredGrid { pieces, .. } => match pieces.as_slice() {
+            [Piece::Inline(piece)] => piece.points.cast_into().unwrap(),
+            _ => panic!("expected a single inline piece"),
+        },
+        _ => panic!("expected an unstructured grid"),
+    };
+
+    // angles of every vertex off the axis, in revolutions
+    let mut angles: Vec<f64> = points
+        .chunks(3)
+        .filter(|p| p[0].hypot(p[1]) > 0.0)
+        .map(|p| (p[1].atan2(p[0]) / std::f64::consts::TAU).rem_euclid(1.0))
+        .map(|t| (t * 1e3).round() / 1e3)
+        .collect();
+    angles.sort_by(f64::total_cmp);
+    angles.dedup();
+
+    // theta bins of 0.25 and 0.75 revolutions, each split in 2
+    assert_eq!(angles, vec![0.0, 0.125, 0.25, 0.625]);
+}
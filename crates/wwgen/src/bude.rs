@@ -1,18 +1,53 @@
 //! Module for combining meshes for Build Up Density Extrapolation
 
-// crate modules
-use crate::error::Result;
+// standard library
+use core::iter::zip;
 
 // neutronics toolbox
-use ntools_mesh::Mesh;
+use ntools_mesh::{Mesh, Voxel};
+use ntools_utils::f;
 
-// standard library
-use core::iter::zip;
+// internal modules
+use crate::error::{Error, Result};
 
 // external crates
-use itertools::izip;
+use log::warn;
+
+/// Flux meshes from a single reduced density run
+///
+/// Both meshes are from the same run, with every material in the model at
+/// `fraction` of its full density.
+#[derive(Debug, Clone, Copy)]
+pub struct DensityStep<'a> {
+    /// Density as a fraction of the full density, in the range (0, 1)
+    pub fraction: f64,
+    /// Total flux mesh of the reduced density run
+    pub reduced: &'a Mesh,
+    /// Uncollided flux mesh of the reduced density run
+    pub uncollided: &'a Mesh,
+}
 
 /// Generate new mesh using the BUDE method
+///
+/// Convenience wrapper around [extrapolate_density_steps()] for a single
+/// reduced density run, where `ratio` is the full density divided by the
+/// reduced density.
+///
+/// - `vd` - void flux mesh, run with voided materials
+/// - `rd` - total flux mesh at the reduced density
+/// - `uc` - uncollided flux mesh at the reduced density
+/// - `gamma` - build-up correction factor, 1.0 for none
+///
+/// ```rust, no_run
+/// # use ntools_mesh::read_target;
+/// # use ntools_wwgen::extrapolate_density;
+/// let vd = read_target("./void.msht", 104).unwrap();
+/// let rd = read_target("./reduced.msht", 104).unwrap();
+/// let uc = read_target("./uncollided.msht", 104).unwrap();
+///
+/// // Extrapolate from 10% density to the full density
+/// let forward = extrapolate_density(&vd, &rd, &uc, 1.0, 10.0).unwrap();
+/// ```
 pub fn extrapolate_density(
     vd: &Mesh,
     rd: &Mesh,
@@ -20,45 +55,203 @@ pub fn extrapolate_density(
     gamma: f64,
     ratio: f64,
 ) -> Result<Mesh> {
-    // todo: some sort of common sense checks for compatability
-    // todo: smart way of minimising allocations and clones
+    if !(ratio > 1.0 && ratio.is_finite()) {
+        return Err(invalid(&f!("density ratio {ratio} must be greater than 1")));
+    }
+
+    let step = DensityStep {
+        fraction: 1.0 / ratio,
+        reduced: rd,
+        uncollided: uc,
+    };
+
+    extrapolate_density_steps(vd, &[step], gamma)
+}
+
+/// Generate new mesh using the BUDE method from several reduced densities
+///
+/// The flux at full density is split into the uncollided flux and a build-up
+/// factor, and both are extrapolated separately from the reduced density runs.
+///
+/// For a density fraction `s`, the uncollided flux is attenuated
+/// exponentially, so `ln(uc/vd)` is proportional to `s`. The build-up factor
+/// `B = rd/uc` is modelled the same way, with `ln(B)` proportional to `s`.
+/// The slopes for every voxel are fitted through the origin by least squares
+/// over all steps, and the build-up at full density is `B^gamma`.
+///
+/// With a single step this reduces to the usual BUDE estimate
+///
+/// ```text
+/// forward = vd * (uc/vd)^(1/s) * (rd/uc)^(gamma/s)
+/// ```
+///
+/// Relative errors of every mesh are propagated to first order through the
+/// fit. The void and reduced density runs are independent, but the total and
+/// uncollided meshes of a step come from the same run and are positively
+/// correlated. MCNP does not report this covariance, so it is neglected as an
+/// approximation. The error is then underestimated for `0 < gamma < 1` and
+/// overestimated for any other `gamma`, except for `gamma` of 0 or 1 where
+/// only one of the two meshes contributes.
+///
+/// Voxels are set to zero wherever any of the meshes has a zero, negative, or
+/// non-finite result, since there is nothing to extrapolate from.
+///
+/// Fails if the meshes do not share the same geometry and energy/time groups,
+/// or any of the parameters are invalid.
+///
+/// ```rust
+/// # use ntools_mesh::{Mesh, Voxel};
+/// # use ntools_wwgen::{extrapolate_density_steps, DensityStep};
+/// let mesh = |result: f64| Mesh {
+///     iints: 1, jints: 1, kints: 1,
+///     voxels: vec![Voxel { index: 0, result, error: 0.0 }],
+///     ..Default::default()
+/// };
+///
+/// // Slab with a total optical depth of 2 and ln(B) of 1 at full density
+/// let void = mesh(1.0);
+/// let uc_10 = mesh((-0.2_f64).exp());
+/// let rd_10 = mesh((-0.2_f64 + 0.1).exp());
+/// let uc_20 = mesh((-0.4_f64).exp());
+/// let rd_20 = mesh((-0.4_f64 + 0.2).exp());
+///
+/// let steps = [
+///     DensityStep { fraction: 0.1, reduced: &rd_10, uncollided: &uc_10 },
+///     DensityStep { fraction: 0.2, reduced: &rd_20, uncollided: &uc_20 },
+/// ];
+///
+/// let forward = extrapolate_density_steps(&void, &steps, 1.0).unwrap();
+/// assert!((forward.voxels[0].result - (-1.0_f64).exp()).abs() < 1e-12);
+/// ```
+pub fn extrapolate_density_steps(void: &Mesh, steps: &[DensityStep], gamma: f64) -> Result<Mesh> {
+    check_parameters(steps, gamma)?;
+
+    for (i, step) in steps.iter().enumerate() {
+        check_compatible(void, step.reduced, &f!("reduced density mesh {i}"))?;
+        check_compatible(void, step.uncollided, &f!("uncollided mesh {i}"))?;
+    }
+
+    // least squares slope through the origin is sum(s*y)/sum(s^2)
+    let sum_squares = steps.iter().map(|s| s.fraction.powi(2)).sum::<f64>();
+    let weights = steps
+        .iter()
+        .map(|s| s.fraction / sum_squares)
+        .collect::<Vec<f64>>();
 
-    // get the buildup flux
-    let buildup = buildup_flux(rd, uc, gamma, ratio);
+    let mut forward = void.clone();
+    let mut skipped = 0;
+
+    for (idx, voxel) in forward.voxels.iter_mut().enumerate() {
+        let (result, error) =
+            extrapolate_voxel(idx, void, steps, &weights, gamma).unwrap_or_else(|| {
+                skipped += 1;
+                (0.0, 0.0)
+            });
+
+        voxel.result = result;
+        voxel.error = error;
+    }
+
+    if skipped > 0 {
+        warn!("Warning: Unable to extrapolate {skipped} voxels");
+        warn!("  - Zero, negative, or NaN results set to 0.0");
+    }
 
-    // calculate the forward flux
-    let forward = forward_flux(&buildup, uc, vd, ratio);
     Ok(forward)
 }
 
-/// Calculate the intermediate buildup flux mesh
-fn buildup_flux(reduced_density: &Mesh, uncollided: &Mesh, gamma: f64, density_ratio: f64) -> Mesh {
-    let mut buildup = reduced_density.clone(); // avoid this
-    let factor = gamma * density_ratio;
+/// Extrapolated (result, error) for a single voxel, if possible
+///
+/// In log space the forward flux is a linear combination of the inputs
+///
+/// ```text
+/// ln(fw) = (1-W)ln(vd) + sum_k w_k[(1-gamma)ln(uc_k) + gamma ln(rd_k)]
+/// ```
+///
+/// where `W` is the sum of the fit weights `w_k`. The relative error of each
+/// input is the error on its log, so errors combine in quadrature with the
+/// same coefficients. The covariance of `uc_k` and `rd_k` is neglected.
+fn extrapolate_voxel(
+    idx: usize,
+    void: &Mesh,
+    steps: &[DensityStep],
+    weights: &[f64],
+    gamma: f64,
+) -> Option<(f64, f64)> {
+    let vd = usable(&void.voxels[idx])?;
+    let w_sum = weights.iter().sum::<f64>();
+
+    let mut ln_flux = (1.0 - w_sum) * vd.result.ln();
+    let mut variance = ((1.0 - w_sum) * vd.error).powi(2);
 
-    for (bu, uc) in zip(&mut buildup.voxels, &uncollided.voxels) {
-        // reduced density / uncollided, with errors sorted out automatically
-        *bu /= *uc;
-        // then raise the result to gamma * density_ratio
-        *bu = bu.powf(factor)
+    for (step, w) in zip(steps, weights) {
+        let uc = usable(&step.uncollided.voxels[idx])?;
+        let rd = usable(&step.reduced.voxels[idx])?;
+
+        ln_flux += w * (1.0 - gamma) * uc.result.ln() + w * gamma * rd.result.ln();
+        variance += (w * (1.0 - gamma) * uc.error).powi(2) + (w * gamma * rd.error).powi(2);
     }
 
-    buildup
+    let result = ln_flux.exp();
+    match result > 0.0 && result.is_finite() {
+        true => Some((result, variance.sqrt())),
+        false => None,
+    }
 }
 
-/// Calculate the forward flux mesh
-fn forward_flux(buildup: &Mesh, uncollided: &Mesh, void: &Mesh, density_ratio: f64) -> Mesh {
-    let mut forward_flux = buildup.clone();
-
-    for (fw, bu, uc, vd) in izip!(
-        &mut forward_flux.voxels,
-        &buildup.voxels,
-        &uncollided.voxels,
-        &void.voxels
-    ) {
-        *fw = (*uc / *vd).powf(density_ratio);
-        *fw *= *bu * *vd;
+/// Only positive and finite results can be extrapolated in log space
+fn usable(voxel: &Voxel) -> Option<&Voxel> {
+    let is_usable = voxel.result > 0.0 && voxel.result.is_finite() && voxel.error.is_finite();
+    is_usable.then_some(voxel)
+}
+
+fn check_parameters(steps: &[DensityStep], gamma: f64) -> Result<()> {
+    if steps.is_empty() {
+        return Err(invalid("at least one reduced density step is required"));
+    }
+
+    if !gamma.is_finite() {
+        return Err(invalid(&f!("gamma {gamma} must be finite")));
     }
 
-    forward_flux
+    if let Some(step) = steps
+        .iter()
+        .find(|s| !(s.fraction > 0.0 && s.fraction < 1.0))
+    {
+        return Err(invalid(&f!(
+            "density fraction {} must be within (0, 1)",
+            step.fraction
+        )));
+    }
+
+    Ok(())
+}
+
+/// Meshes must share the same geometry and energy/time groups
+fn check_compatible(void: &Mesh, other: &Mesh, name: &str) -> Result<()> {
+    let differs = if void.geometry != other.geometry {
+        "geometry type"
+    } else if void.imesh != other.imesh || void.jmesh != other.jmesh || void.kmesh != other.kmesh {
+        "mesh bounds"
+    } else if void.origin != other.origin || void.axs != other.axs || void.vec != other.vec {
+        "orientation"
+    } else if void.emesh != other.emesh || void.eints != other.eints {
+        "energy groups"
+    } else if void.tmesh != other.tmesh || void.tints != other.tints {
+        "time groups"
+    } else if void.voxels.len() != other.voxels.len() {
+        "number of voxels"
+    } else {
+        return Ok(());
+    };
+
+    Err(invalid(&f!(
+        "{differs} of {name} does not match the void mesh"
+    )))
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidBude {
+        reason: reason.to_string(),
+    }
 }
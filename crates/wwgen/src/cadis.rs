@@ -0,0 +1,465 @@
+// standard library
+use std::ops::Range;
+use std::path::Path;
+
+// neutronics toolbox
+use ntools_mesh::{BoundaryTreatment, Group, Mesh, Point, Voxel};
+use ntools_utils::{f, ValueExt};
+use ntools_weights::WeightWindow;
+
+// internal modules
+use crate::cells::card;
+use crate::error::{Error, Result};
+use crate::magic::{constrain_weights, initialise_ww_from_mesh, relevant_groups_idx};
+
+/// Forward source description for CADIS
+///
+/// The source is assumed to be separable into a set of discrete positions
+/// with relative strengths, and a single energy spectrum shared by every
+/// position.
+///
+/// - `positions` - Source locations as global (x, y, z) coordinates
+/// - `strengths` - Relative strength of each position
+/// - `energies` - Energy bin bounds of the spectrum, including the lowest
+/// - `spectrum` - Relative probability of each energy bin
+///
+/// ```rust
+/// # use ntools_wwgen::Source;
+/// // Point source at the origin emitting in two energy bins
+/// let source = Source {
+///     positions: vec![[0.0, 0.0, 0.0]],
+///     strengths: vec![1.0],
+///     energies: vec![0.0, 1.0, 14.0],
+///     spectrum: vec![0.2, 0.8],
+/// };
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Source {
+    /// Source locations as global (x, y, z) coordinates
+    pub positions: Vec<[f64; 3]>,
+    /// Relative strength of each position
+    pub strengths: Vec<f64>,
+    /// Energy bin bounds of the spectrum, including the lowest
+    pub energies: Vec<f64>,
+    /// Relative probability of each energy bin
+    pub spectrum: Vec<f64>,
+}
+
+/// Results of the CADIS method
+///
+/// Contains the weight windows and the biased source that go with them. These
+/// are consistent, in that every biased source particle is born with a weight
+/// at the centre of the window it starts in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cadis {
+    /// Weight window lower bounds from the adjoint flux
+    pub weight_window: WeightWindow,
+    /// Estimate of the detector response, sum of source x adjoint flux
+    pub response: f64,
+    /// The unbiased forward source, with probabilities normalised
+    pub source: Source,
+    /// Biased probability of each source position
+    pub position_bias: Vec<f64>,
+    /// Biased energy spectrum of each source position
+    pub energy_bias: Vec<Vec<f64>>,
+}
+
+/// Adjoint source options for FW-CADIS
+///
+/// These choose the forward response that should have a uniform relative
+/// uncertainty everywhere in the mesh.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum FwCadisResponse {
+    /// Flux in every energy group, adjoint source of `1/flux(r, E)` (default)
+    #[default]
+    Flux,
+    /// Total flux, adjoint source of `1/total_flux(r)` in every energy group
+    ///
+    /// The total flux is taken from the 'Total' energy group of the mesh.
+    TotalFlux,
+}
+
+/// Consistent Adjoint Driven Importance Sampling (CADIS)
+///
+/// Given an adjoint flux mesh, i.e. the importance of every voxel to a
+/// detector response, and the forward source, this calculates:
+///
+/// - the estimated response `R`, the sum of source x adjoint flux
+/// - weight window target weights `R / adjoint(r, E)`
+/// - the biased source, `source(r, E) x adjoint(r, E) / R`
+///
+/// Weight windows are set to the lower bounds, calculated from the target
+/// weights as `target * 2 / (1 + ratio)`, where `ratio` is the ratio of the
+/// upper to lower bound. This should match the `wupn` value on the MCNP `WWP`
+/// card, which is 5 by default.
+///
+/// Voxels with no adjoint flux, or errors above `max_error`, are set to 0
+/// (analogue).
+///
+/// ```rust, no_run
+/// # use ntools_mesh::read_target;
+/// # use ntools_wwgen::{cadis, Source};
+/// // Adjoint flux from a run with the detector response as the source
+/// let adjoint = read_target("/path/to/adjoint.msht", 104).unwrap();
+///
+/// // Point source at the origin
+/// let source = Source {
+///     positions: vec![[0.0, 0.0, 0.0]],
+///     strengths: vec![1.0],
+///     energies: vec![0.0, 14.0],
+///     spectrum: vec![1.0],
+/// };
+///
+/// let result = cadis(&adjoint, &source, 5.0, 0.1).unwrap();
+///
+/// // Weight windows and the matching source biasing
+/// result.weight_window.write("wwinp");
+/// result.write_sdef("sdef_cards.i").unwrap();
+/// ```
+pub fn cadis(adjoint: &Mesh, source: &Source, ratio: f64, max_error: f64) -> Result<Cadis> {
+    if !(ratio > 1.0 && ratio.is_finite()) {
+        return Err(invalid(&f!("window ratio {ratio} must be greater than 1")));
+    }
+
+    let source = source.normalised()?;
+
+    // adjoint flux for every energy bin of every source position
+    let importance = source
+        .positions
+        .iter()
+        .map(|position| {
+            source
+                .energies
+                .windows(2)
+                .map(|bin| adjoint_at(adjoint, position, 0.5 * (bin[0] + bin[1])))
+                .collect::<Result<Vec<f64>>>()
+        })
+        .collect::<Result<Vec<Vec<f64>>>>()?;
+
+    // contribution of each position, summed over energy
+    let contributions = importance
+        .iter()
+        .zip(&source.strengths)
+        .map(|(adj, strength)| {
+            strength
+                * adj
+                    .iter()
+                    .zip(&source.spectrum)
+                    .map(|(a, s)| a * s)
+                    .sum::<f64>()
+        })
+        .collect::<Vec<f64>>();
+
+    let response = contributions.iter().sum::<f64>();
+    if response <= 0.0 {
+        return Err(invalid("source has no importance to the response"));
+    }
+
+    let position_bias = contributions.iter().map(|c| c / response).collect();
+    let energy_bias = importance
+        .iter()
+        .map(|adj| biased_spectrum(adj, &source.spectrum))
+        .collect();
+
+    let mut weight_window = initialise_ww_from_mesh(adjoint, false);
+    weight_window.weights = cadis_weights(adjoint, response * 2.0 / (1.0 + ratio), max_error);
+
+    Ok(Cadis {
+        weight_window,
+        response,
+        source,
+        position_bias,
+        energy_bias,
+    })
+}
+
+/// Adjoint source for Forward-Weighted CADIS (FW-CADIS)
+///
+/// FW-CADIS aims for a uniform relative uncertainty across the whole mesh
+/// rather than a single detector. This uses a forward flux mesh to build the
+/// adjoint source, weighting every voxel by the inverse of its forward
+/// response.
+///
+/// The result is a [Mesh] with the same geometry and groups, where every voxel
+/// holds the adjoint source density normalised to a maximum of 1. Voxels with
+/// no forward flux have no adjoint source. Any 'Total' group holds the sum
+/// over energy/time groups.
+///
+/// The adjoint calculation run with this source gives the adjoint flux for
+/// [cadis()], along with the original forward source.
+///
+/// ```rust, no_run
+/// # use ntools_mesh::read_target;
+/// # use ntools_wwgen::{fw_cadis_adjoint_source, FwCadisResponse};
+/// // Forward flux estimate, e.g. from a quick deterministic calculation
+/// let forward = read_target("./data/meshes/fmesh_114.msht", 114).unwrap();
+///
+/// // Adjoint source for a uniform uncertainty in the total flux
+/// let adjoint_source = fw_cadis_adjoint_source(&forward, FwCadisResponse::TotalFlux).unwrap();
+/// ```
+pub fn fw_cadis_adjoint_source(forward: &Mesh, response: FwCadisResponse) -> Result<Mesh> {
+    if forward.voxels.len() != forward.n_voxels_expected() {
+        return Err(invalid("forward mesh is missing voxels"));
+    }
+
+    let (energy_groups, time_groups) = relevant_groups_idx(forward, false);
+    let (n_ebins, n_tbins) = (forward.n_ebins(), forward.n_tbins());
+    let n_voxels = forward.n_voxels_per_group();
+
+    // the total flux is taken from the last energy group
+    if response == FwCadisResponse::TotalFlux
+        && forward.energy_group_from_index(n_ebins - 1)? != Group::Total
+    {
+        return Err(invalid("forward mesh has no total energy group"));
+    }
+
+    // voxels of each group are contiguous, in (e, t) order
+    let block = |e: usize, t: usize| (e * n_tbins + t) * n_voxels..(e * n_tbins + t + 1) * n_voxels;
+
+    let mut mesh = forward.clone();
+    mesh.voxels.iter_mut().for_each(|v| {
+        v.result = 0.0;
+        v.error = 0.0;
+    });
+
+    for e_idx in &energy_groups {
+        for t_idx in &time_groups {
+            let flux = match response {
+                FwCadisResponse::Flux => &forward.voxels[block(*e_idx, *t_idx)],
+                FwCadisResponse::TotalFlux => &forward.voxels[block(n_ebins - 1, *t_idx)],
+            };
+
+            for (voxel, v) in mesh.voxels[block(*e_idx, *t_idx)].iter_mut().zip(flux) {
+                if v.result > 0.0 {
+                    voxel.result = 1.0 / v.result;
+                }
+            }
+        }
+    }
+
+    // fill in the total groups, if there are any
+    if !energy_groups.contains(&(n_ebins - 1)) {
+        for t_idx in &time_groups {
+            sum_blocks(&mut mesh.voxels, block(n_ebins - 1, *t_idx), || {
+                energy_groups.iter().map(|e| block(*e, *t_idx))
+            });
+        }
+    }
+
+    if !time_groups.contains(&(n_tbins - 1)) {
+        for e_idx in 0..n_ebins {
+            sum_blocks(&mut mesh.voxels, block(e_idx, n_tbins - 1), || {
+                time_groups.iter().map(|t| block(e_idx, *t))
+            });
+        }
+    }
+
+    let maximum = mesh
+        .voxels
+        .iter()
+        .map(|v| v.result)
+        .max_by(|a, b| a.total_cmp(b))
+        .unwrap_or(0.0);
+
+    if maximum <= 0.0 {
+        return Err(invalid("forward mesh has no flux"));
+    }
+
+    mesh.voxels.iter_mut().for_each(|v| v.result /= maximum);
+    Ok(mesh)
+}
+
+impl Cadis {
+    /// Format the biased source as MCNP SDEF cards
+    ///
+    /// Positions are sampled from distribution 1, and the energy is dependent
+    /// on the position, with one energy distribution per position.
+    ///
+    /// ```text
+    /// SDEF PAR=1 POS=D1 ERG=FPOS=D2
+    /// SI1 L  x y z  x y z ...
+    /// SP1    strengths...
+    /// SB1    biased strengths...
+    /// DS2 S  3 4 ...
+    /// SI3 H  energies...
+    /// SP3 D  0 spectrum...
+    /// SB3 D  0 biased spectrum...
+    /// ```
+    pub fn sdef(&self) -> String {
+        let n_positions = self.source.positions.len();
+        let mut s = f!(
+            "c CADIS biased source, estimated response {}\n",
+            self.response.sci(5, 2)
+        );
+        s += &f!(
+            "SDEF PAR={} POS=D1 ERG=FPOS=D2\n",
+            self.weight_window.particle
+        );
+
+        let positions = self
+            .source
+            .positions
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<f64>>();
+
+        s += &card("SI1 L", &positions);
+        s += &card("SP1", &self.source.strengths);
+        s += &card("SB1", &self.position_bias);
+
+        let distributions = (0..n_positions)
+            .map(|i| (i + 3) as f64)
+            .collect::<Vec<f64>>();
+        s += &f!(
+            "DS2 S {}\n",
+            distributions
+                .iter()
+                .map(|d| f!("{d}"))
+                .collect::<Vec<String>>()
+                .join(" ")
+        );
+
+        for (i, bias) in self.energy_bias.iter().enumerate() {
+            let d = i + 3;
+            s += &card(&f!("SI{d} H"), &self.source.energies);
+            s += &card(
+                &f!("SP{d} D"),
+                &[&[0.0], self.source.spectrum.as_slice()].concat(),
+            );
+            s += &card(&f!("SB{d} D"), &[&[0.0], bias.as_slice()].concat());
+        }
+
+        s
+    }
+
+    /// Write the biased source SDEF cards to a file
+    ///
+    /// See [sdef()](Cadis::sdef) for details of the cards written.
+    pub fn write_sdef(&self, path: impl AsRef<Path>) -> Result<()> {
+        Ok(std::fs::write(path, self.sdef())?)
+    }
+}
+
+impl Source {
+    /// Check the source is valid and normalise the probabilities
+    fn normalised(&self) -> Result<Source> {
+        if self.positions.is_empty() || self.positions.len() != self.strengths.len() {
+            return Err(invalid("every source position needs one strength"));
+        }
+
+        if self.energies.len() < 2 || self.energies.len() != self.spectrum.len() + 1 {
+            return Err(invalid(
+                "energy bounds must be one longer than the spectrum",
+            ));
+        }
+
+        if !self.energies.windows(2).all(|w| w[1] > w[0]) {
+            return Err(invalid("energy bounds are not increasing"));
+        }
+
+        Ok(Source {
+            positions: self.positions.clone(),
+            strengths: normalise(&self.strengths, "strengths")?,
+            energies: self.energies.clone(),
+            spectrum: normalise(&self.spectrum, "spectrum")?,
+        })
+    }
+}
+
+/// Adjoint flux at a position for the group containing an energy
+fn adjoint_at(adjoint: &Mesh, position: &[f64; 3], energy: f64) -> Result<f64> {
+    let e_idx = adjoint.energy_index_from_value(energy)?;
+    let point = Point {
+        e: adjoint.energy_group_from_index(e_idx)?,
+        t: Group::Total,
+        ..Point::from_xyz(position[0], position[1], position[2])
+    };
+
+    match adjoint.find_point_data(point, BoundaryTreatment::default()) {
+        Some((result, _)) => Ok(result),
+        None => Err(invalid(&f!(
+            "source position ({}, {}, {}) is outside the adjoint mesh",
+            position[0],
+            position[1],
+            position[2]
+        ))),
+    }
+}
+
+/// Biased spectrum for a single position
+///
+/// Positions with no importance keep the unbiased spectrum, since these are
+/// never sampled and MCNP requires a valid distribution anyway.
+fn biased_spectrum(importance: &[f64], spectrum: &[f64]) -> Vec<f64> {
+    let biased = importance
+        .iter()
+        .zip(spectrum)
+        .map(|(a, s)| a * s)
+        .collect::<Vec<f64>>();
+
+    let total = biased.iter().sum::<f64>();
+    match total > 0.0 {
+        true => biased.iter().map(|b| b / total).collect(),
+        false => spectrum.to_vec(),
+    }
+}
+
+/// Weight window lower bounds for every group of the adjoint mesh
+fn cadis_weights(adjoint: &Mesh, scale: f64, max_error: f64) -> Vec<f64> {
+    let (energy_groups, time_groups) = relevant_groups_idx(adjoint, false);
+    let mut weights = Vec::with_capacity(adjoint.n_voxels());
+
+    for e_idx in &energy_groups {
+        for t_idx in &time_groups {
+            let voxels = adjoint.voxels_by_group_index(*e_idx, *t_idx).unwrap();
+            weights.extend(group_weights(adjoint, voxels, scale, max_error));
+        }
+    }
+
+    weights
+}
+
+/// Lower bounds for a single group, sorted into cell order
+fn group_weights(adjoint: &Mesh, voxels: &[Voxel], scale: f64, max_error: f64) -> Vec<f64> {
+    let mut wgt = voxels
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let w = match v.result > 0.0 && v.error <= max_error {
+                true => constrain_weights(scale / v.result),
+                false => 0.0,
+            };
+            (adjoint.cell_index_from_voxel_index(i), w)
+        })
+        .collect::<Vec<(usize, f64)>>();
+
+    wgt.sort_by_key(|a| a.0);
+    wgt.into_iter().map(|r| r.1).collect()
+}
+
+/// Set the results of one block of voxels to the sum of several others
+fn sum_blocks<F, I>(voxels: &mut [Voxel], target: Range<usize>, sources: F)
+where
+    F: Fn() -> I,
+    I: Iterator<Item = Range<usize>>,
+{
+    for (i, idx) in target.enumerate() {
+        voxels[idx].result = sources().map(|block| voxels[block.start + i].result).sum();
+    }
+}
+
+/// Normalise a list of relative probabilities to sum to 1
+fn normalise(values: &[f64], name: &str) -> Result<Vec<f64>> {
+    let total = values.iter().sum::<f64>();
+    match total > 0.0 && values.iter().all(|v| *v >= 0.0) {
+        true => Ok(values.iter().map(|v| v / total).collect()),
+        false => Err(invalid(&f!("source {name} must be positive"))),
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidCadis {
+        reason: reason.to_string(),
+    }
+}
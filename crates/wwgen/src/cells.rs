@@ -0,0 +1,324 @@
+// standard library
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// neutronics toolbox
+use ntools_mctal::{BinData, BinKind, Particle, Tally};
+use ntools_utils::{f, ValueExt};
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::magic::{collect_error_values, collect_power_values, normalised_weights};
+
+/// Cell-based weight windows for a single particle type
+///
+/// These are the `WWE` and `WWNi` input cards of MCNP rather than a mesh, with
+/// one weight for every cell in each energy group.
+///
+/// MCNP applies the `WWNi` entries to cells in the order they appear in the
+/// input deck, so the cells are kept in exactly the order they were listed on
+/// the tally. The tally must therefore list every cell of the problem in input
+/// order for the cards to be valid.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CellWeights {
+    /// Particle type
+    pub particle: Particle,
+    /// Cell numbers, in the order of the weights
+    pub cells: Vec<u32>,
+    /// Energy group upper bounds, empty for a single group
+    pub e: Vec<f64>,
+    /// Weights for every cell, in energy group order
+    pub weights: Vec<f64>,
+}
+
+impl CellWeights {
+    /// Number of energy groups
+    pub fn n_groups(&self) -> usize {
+        self.e.len().max(1)
+    }
+
+    /// Weights of every cell for a single energy group
+    pub fn group(&self, index: usize) -> Option<&[f64]> {
+        self.weights.chunks(self.cells.len().max(1)).nth(index)
+    }
+
+    /// Format the weights as MCNP input cards
+    ///
+    /// Produces an input deck fragment with a `WWE` card for the energy bounds
+    /// (if there is more than one group) and a `WWNi` card for each group.
+    ///
+    /// ```rust
+    /// # use ntools_wwgen::CellWeights;
+    /// # use ntools_mctal::Particle;
+    /// let ww = CellWeights {
+    ///     particle: Particle::Neutron,
+    ///     cells: vec![10, 20],
+    ///     e: vec![1.0, 20.0],
+    ///     weights: vec![0.5, 0.1, 0.5, 0.0],
+    /// };
+    ///
+    /// let cards = ww.cards().unwrap();
+    /// assert!(cards.contains("WWE:n 1.00000e+00 2.00000e+01\n"));
+    /// assert!(cards.contains("WWN1:n 5.00000e-01 1.00000e-01\n"));
+    /// assert!(cards.contains("WWN2:n 5.00000e-01 0.00000e+00\n"));
+    /// ```
+    pub fn cards(&self) -> Result<String> {
+        let Some(symbol) = self.particle.designator() else {
+            return Err(invalid("no designator for an unknown particle"));
+        };
+
+        let expected = self.n_groups() * self.cells.len();
+        if self.weights.len() != expected {
+            return Err(invalid(&f!(
+                "found {} weights, expected {expected}",
+                self.weights.len()
+            )));
+        }
+
+        let mut s = f!(
+            "c Cell-based weight windows for {} cells, in order:\n",
+            self.cells.len()
+        );
+        s += &cell_list(&self.cells);
+
+        if !self.e.is_empty() {
+            s += &card(&f!("WWE:{symbol}"), &self.e);
+        }
+
+        for (i, weights) in self.weights.chunks(self.cells.len()).enumerate() {
+            s += &card(&f!("WWN{}:{symbol}", i + 1), weights);
+        }
+
+        Ok(s)
+    }
+
+    /// Write the input cards to a file
+    ///
+    /// See [cards()](CellWeights::cards) for details of the input deck
+    /// fragment written.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let cards = self.cards()?;
+        let mut f = BufWriter::new(File::create(path)?);
+        f.write_all(cards.as_bytes())?;
+        f.flush()?;
+        Ok(())
+    }
+}
+
+/// Cell flux tally to cell-based weight windows with simple parameters
+///
+/// Uses the same MAGIC normalisation as [mesh_to_ww](crate::mesh_to_ww), where
+/// every energy group is normalised to the cell with the highest flux.
+///
+/// - `power` - Softening factor used as ww=>ww^power
+/// - `max_error` - Errors above this are set to 0/analogue
+/// - `total_only` - Only generate weights from the total energy bin
+///
+/// The tally should be an F4 cell flux tally, with the region bins being the
+/// cell numbers. Any time bins are collapsed onto the total time bin.
+///
+/// ```rust, no_run
+/// # use ntools_mctal::Mctal;
+/// # use ntools_wwgen::tally_to_cell_ww;
+/// // Read tally 14 from a mctal file
+/// let mctal = Mctal::from_file("/path/to/file.m").unwrap();
+/// let tally = mctal.get_tally(14).unwrap();
+///
+/// // Convert the tally into cell-based weight windows
+/// let weights = tally_to_cell_ww(tally, 0.7, 0.10, false).unwrap();
+///
+/// // Write WWE/WWN cards to be included in the input deck
+/// weights.write("wwn_cards.i").unwrap();
+/// ```
+pub fn tally_to_cell_ww(
+    tally: &Tally,
+    power: f64,
+    max_error: f64,
+    total_only: bool,
+) -> Result<CellWeights> {
+    compute_cell_weights(tally, &[power], &[max_error], total_only)
+}
+
+/// Cell flux tally to cell-based weight windows with fine de-tuning and errors
+///
+/// Same as [tally_to_cell_ww] but allows for individual de-tuning factors and
+/// error tolerances for each energy group. If `powers` or `max_errors` have a
+/// single entry this will be applied to all groups.
+///
+/// ```rust, no_run
+/// # use ntools_mctal::Mctal;
+/// # use ntools_wwgen::tally_to_cell_ww_advanced;
+/// let mctal = Mctal::from_file("/path/to/file.m").unwrap();
+/// let tally = mctal.get_tally(14).unwrap();
+///
+/// // Separate parameters for a tally with 3 energy bins
+/// let weights = tally_to_cell_ww_advanced(tally,
+///                                         &[0.7, 0.5, 0.85],
+///                                         &[0.1, 0.1, 0.15]).unwrap();
+/// ```
+pub fn tally_to_cell_ww_advanced(
+    tally: &Tally,
+    powers: &[f64],
+    max_errors: &[f64],
+) -> Result<CellWeights> {
+    compute_cell_weights(tally, powers, max_errors, false)
+}
+
+/// Core function for turning a cell flux tally into weights
+fn compute_cell_weights(
+    tally: &Tally,
+    powers: &[f64],
+    max_errors: &[f64],
+    total_only: bool,
+) -> Result<CellWeights> {
+    let particle = tally_particle(tally, invalid)?;
+    let cells = tally_cells(tally, invalid)?;
+    check_bins(tally, invalid)?;
+
+    let n_times = tally.time_bins.number.max(1);
+    let time_idx = total_bin(&tally.time_bins, "time", invalid)?;
+
+    let (energy_groups, e) = match total_only {
+        true => (
+            vec![total_bin(&tally.energy_bins, "energy", invalid)?],
+            Vec::new(),
+        ),
+        false => {
+            let n = match tally.energy_bins.kind {
+                BinKind::Total => tally.energy_bins.number - 1,
+                _ => tally.energy_bins.number.max(1),
+            };
+            let e = match n {
+                1 => Vec::new(),
+                _ => tally.energy_bins.values[..n].to_vec(),
+            };
+            ((0..n).collect(), e)
+        }
+    };
+
+    let powers = collect_power_values(powers, energy_groups.len());
+    let errors = collect_error_values(max_errors, energy_groups.len());
+
+    let mut weights = Vec::with_capacity(energy_groups.len() * cells.len());
+    for (i, e_idx) in energy_groups.iter().enumerate() {
+        let results = tally
+            .iter()
+            .map(|region| &region[e_idx * n_times + time_idx])
+            .map(|r| (r.value, r.error))
+            .collect::<Vec<(f64, f64)>>();
+
+        weights.extend(normalised_weights(&results, powers[i], errors[i]));
+    }
+
+    Ok(CellWeights {
+        particle,
+        cells,
+        e,
+        weights,
+    })
+}
+
+/// Only energy and time bins can be interpreted, with every result present
+///
+/// The tally helpers are shared with the importances, so errors are made with
+/// `invalid` to report the kind of the caller.
+pub(crate) fn check_bins(tally: &Tally, invalid: fn(&str) -> Error) -> Result<()> {
+    if tally.results.len() != tally.n_expected_results() {
+        return Err(invalid(&f!(
+            "found {} results, expected {}",
+            tally.results.len(),
+            tally.n_expected_results()
+        )));
+    }
+
+    let others = [
+        &tally.flagged_bins,
+        &tally.user_bins,
+        &tally.segment_bins,
+        &tally.multiplier_bins,
+        &tally.cosine_bins,
+    ];
+    if others.iter().any(|bins| bins.number > 1) {
+        return Err(invalid(
+            "only energy and time bins are supported on the tally",
+        ));
+    }
+
+    Ok(())
+}
+
+/// The single particle type of the tally
+pub(crate) fn tally_particle(tally: &Tally, invalid: fn(&str) -> Error) -> Result<Particle> {
+    match tally.particles.as_slice() {
+        [particle] if *particle != Particle::Unknown => Ok(*particle),
+        [_] => Err(invalid("unknown tally particle type")),
+        _ => Err(invalid(&f!(
+            "expected one particle type, found {}",
+            tally.particles.len()
+        ))),
+    }
+}
+
+/// Cell numbers from the region bins of a cell flux tally
+pub(crate) fn tally_cells(tally: &Tally, invalid: fn(&str) -> Error) -> Result<Vec<u32>> {
+    if tally.id % 10 != 4 {
+        return Err(invalid(&f!("tally {} is not an F4 cell tally", tally.id)));
+    }
+
+    let regions = &tally.region_bins.values;
+    if regions.is_empty() || regions.len() != tally.region_bins.number {
+        return Err(invalid("tally has no cell numbers"));
+    }
+
+    // unions and totals have no single cell number
+    regions
+        .iter()
+        .map(|cell| match *cell >= 1.0 && cell.fract() == 0.0 {
+            true => Ok(*cell as u32),
+            false => Err(invalid(&f!("region bin {cell} is not a single cell"))),
+        })
+        .collect()
+}
+
+/// Index of the bin covering everything, either the total or the only bin
+pub(crate) fn total_bin(bins: &BinData, kind: &str, invalid: fn(&str) -> Error) -> Result<usize> {
+    match (bins.kind, bins.number) {
+        (BinKind::Total, n) => Ok(n - 1),
+        (_, 0 | 1) => Ok(0),
+        _ => Err(invalid(&f!("multiple {kind} bins but no total bin"))),
+    }
+}
+
+/// Comment lines listing cell numbers, 10 per line
+pub(crate) fn cell_list(cells: &[u32]) -> String {
+    cells
+        .chunks(10)
+        .map(|chunk| {
+            let line = chunk.iter().map(|c| f!("{c:<7}")).collect::<String>();
+            f!("c   {}\n", line.trim_end())
+        })
+        .collect()
+}
+
+/// Format a card with several values per line, using continuation lines
+pub(crate) fn card(name: &str, values: &[f64]) -> String {
+    let lines = values
+        .chunks(6)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|v| v.sci(5, 2))
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .collect::<Vec<String>>();
+
+    f!("{name} {}\n", lines.join("\n      "))
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidTally {
+        reason: reason.to_string(),
+    }
+}
@@ -8,4 +8,46 @@ pub(crate) type Result<T> = core::result::Result<T, Error>;
 pub enum Error {
     #[error("failed input/output stream")]
     IOError(#[from] std::io::Error),
+
+    #[error("mesh error")]
+    MeshError(#[from] ntools_mesh::Error),
+
+    #[error("weight window error")]
+    WeightsError(#[from] ntools_weights::Error),
+
+    #[error("invalid normalisation: {reason}")]
+    InvalidNormalisation { reason: String },
+
+    #[error("unable to extrapolate density: {reason}")]
+    InvalidBude { reason: String },
+
+    #[error("unable to apply CADIS: {reason}")]
+    InvalidCadis { reason: String },
+
+    #[error("unable to generate importances: {reason}")]
+    InvalidImportance { reason: String },
+
+    #[error("invalid generator settings: {reason}")]
+    InvalidGenerator { reason: String },
+
+    #[error("unable to combine particle types: {reason}")]
+    InvalidMultiParticle { reason: String },
+
+    #[error("invalid workflow: {reason}")]
+    InvalidWorkflow { reason: String },
+
+    #[error("unknown file format: {reason}")]
+    UnknownFormat { reason: String },
+
+    #[error("failed serde JSON operation")]
+    JSONError(#[from] serde_json::Error),
+
+    #[error("failed to read TOML")]
+    TomlDeError(#[from] toml::de::Error),
+
+    #[error("failed to write TOML")]
+    TomlSerError(#[from] toml::ser::Error),
+
+    #[error("unable to generate weights from tally: {reason}")]
+    InvalidTally { reason: String },
 }
@@ -0,0 +1,429 @@
+// standard library
+use std::path::Path;
+
+// neutronics toolbox
+use ntools_mesh::{Group, Mesh};
+use ntools_utils::f;
+use ntools_weights::WeightWindow;
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::magic::{compute_weights, initialise_ww_from_mesh, relevant_groups_idx};
+use crate::normalise::Normalisation;
+use crate::time::TimeSettings;
+
+// external crates
+use serde::{Deserialize, Serialize};
+
+/// Weight window generation recipe
+///
+/// Collects every parameter used to turn a flux mesh into weights, so that a
+/// generation recipe can be written to TOML or JSON, version-controlled, and
+/// rerun exactly.
+///
+/// Unlike [mesh_to_ww_advanced](crate::mesh_to_ww_advanced), settings for
+/// individual groups are keyed explicitly by their energy and time
+/// [Group](ntools_mesh::Group) rather than by position. Groups without explicit
+/// settings use the default `power` and `max_error`. Settings that do not match
+/// a group of the mesh are an error rather than a warning.
+///
+/// ```rust, no_run
+/// # use ntools_mesh::{read_target, Group};
+/// # use ntools_wwgen::WwGenerator;
+/// let mesh = read_target("./data/meshes/fmesh_114.msht", 114).unwrap();
+///
+/// let generator = WwGenerator::builder()
+///     .power(0.7)
+///     .max_error(0.1)
+///     .group(Group::Value(1.0), Group::Value(1e16), 0.5, 0.2)
+///     .build();
+///
+/// // Generate weights and save the recipe for later
+/// let ww = generator.generate(&mesh).unwrap();
+/// generator.write("recipe.toml").unwrap();
+/// ```
+///
+/// The same recipe is easily written by hand, for example in TOML:
+///
+/// ```rust
+/// # use ntools_mesh::Group;
+/// # use ntools_wwgen::{Reference, WwGenerator};
+/// let generator = WwGenerator::from_toml(
+///     r#"
+///     power = 0.7
+///     max_error = 0.1
+///
+///     [normalisation]
+///     target = 0.5
+///     reference = { Percentile = 90.0 }
+///
+///     [[groups]]
+///     energy = { Value = 1.0 }
+///     power = 0.5
+///     "#,
+/// )
+/// .unwrap();
+///
+/// assert_eq!(generator.normalisation.reference, Reference::Percentile(90.0));
+/// assert_eq!(generator.groups[0].energy, Group::Value(1.0));
+/// assert_eq!(generator.groups[0].time, Group::Total);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WwGenerator {
+    /// Default softening factor used as ww=>ww^power
+    pub power: f64,
+    /// Default error tolerance, above which weights are set to 0/analogue
+    pub max_error: f64,
+    /// Only generate weights from [Group::Total](ntools_mesh::Group)
+    pub total_only: bool,
+    /// Reference flux and target weight used to normalise every group
+    pub normalisation: Normalisation,
+    /// Settings for individual energy/time groups
+    pub groups: Vec<GroupSettings>,
+    /// Treatment of time groups for time-dependent weights
+    pub time: TimeSettings,
+    /// Fill analogue holes up to this many voxels from a non-zero weight, 0 to
+    /// leave them as analogue
+    pub fill_distance: usize,
+}
+
+/// Settings for a single energy/time group
+///
+/// The `energy` and `time` groups must exactly match a group of the mesh, and
+/// default to [Group::Total](ntools_mesh::Group). Any parameter not set uses
+/// the default of the [WwGenerator].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupSettings {
+    /// Energy group
+    #[serde(default = "total")]
+    pub energy: Group,
+    /// Time group
+    #[serde(default = "total")]
+    pub time: Group,
+    /// Softening factor used as ww=>ww^power
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power: Option<f64>,
+    /// Errors above this are set to 0/analogue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_error: Option<f64>,
+}
+
+// Public API
+impl WwGenerator {
+    /// Start with the default configuration
+    pub fn new() -> WwGenerator {
+        Default::default()
+    }
+
+    /// Get an instance of the [WwGeneratorBuilder]
+    pub fn builder() -> WwGeneratorBuilder {
+        WwGeneratorBuilder::default()
+    }
+
+    /// Generate weight windows from a flux mesh
+    ///
+    /// Fails if any parameters are invalid, or if any group settings do not
+    /// match exactly one energy/time group of the mesh.
+    pub fn generate(&self, mesh: &Mesh) -> Result<WeightWindow> {
+        self.time.validate()?;
+        let (powers, max_errors) = self.group_parameters(mesh)?;
+        let mut ww = initialise_ww_from_mesh(mesh, self.total_only);
+        ww.weights = compute_weights(
+            mesh,
+            &powers,
+            &max_errors,
+            self.total_only,
+            &self.normalisation,
+            self.time.normalise_all_times,
+        )?;
+        ww.fill_holes(self.fill_distance);
+        self.time.apply(mesh, &mut ww);
+        Ok(ww)
+    }
+
+    /// Read a recipe from a TOML string
+    pub fn from_toml(s: &str) -> Result<WwGenerator> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Write the recipe to a TOML string
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Read a recipe from a JSON string
+    pub fn from_json(s: &str) -> Result<WwGenerator> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    /// Write the recipe to a JSON string
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Read a recipe from a `.toml` or `.json` file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<WwGenerator> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path)?;
+        match FileFormat::from_path(path)? {
+            FileFormat::Toml => Self::from_toml(&s),
+            FileFormat::Json => Self::from_json(&s),
+        }
+    }
+
+    /// Write the recipe to a `.toml` or `.json` file
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let s = match FileFormat::from_path(path)? {
+            FileFormat::Toml => self.to_toml()?,
+            FileFormat::Json => self.to_json()?,
+        };
+        Ok(std::fs::write(path, s)?)
+    }
+}
+
+// Private implementations
+impl WwGenerator {
+    /// Power and error tolerance for every group, in generation order
+    fn group_parameters(&self, mesh: &Mesh) -> Result<(Vec<f64>, Vec<f64>)> {
+        check_power(self.power)?;
+        check_error(self.max_error)?;
+
+        let (energy_groups, time_groups) = relevant_groups_idx(mesh, self.total_only);
+        let mut matched = vec![false; self.groups.len()];
+        let mut powers = Vec::new();
+        let mut max_errors = Vec::new();
+
+        for e_idx in &energy_groups {
+            for t_idx in &time_groups {
+                let energy = mesh.energy_group_from_index(*e_idx)?;
+                let time = mesh.time_group_from_index(*t_idx)?;
+
+                let mut settings = self
+                    .groups
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| same_group(s.energy, energy) && same_group(s.time, time));
+
+                let (power, max_error) = match (settings.next(), settings.next()) {
+                    (Some(_), Some(_)) => {
+                        return Err(invalid(&f!(
+                            "multiple settings for energy {energy}, time {time}"
+                        )))
+                    }
+                    (Some((idx, s)), None) => {
+                        matched[idx] = true;
+                        (
+                            s.power.unwrap_or(self.power),
+                            s.max_error.unwrap_or(self.max_error),
+                        )
+                    }
+                    _ => (self.power, self.max_error),
+                };
+
+                powers.push(check_power(power)?);
+                max_errors.push(check_error(max_error)?);
+            }
+        }
+
+        // anything left over does not correspond to a group of the mesh
+        if let Some(idx) = matched.iter().position(|m| !m) {
+            let s = &self.groups[idx];
+            return Err(invalid(&f!(
+                "no mesh group for energy {}, time {}",
+                s.energy,
+                s.time
+            )));
+        }
+
+        Ok((powers, max_errors))
+    }
+}
+
+impl Default for WwGenerator {
+    fn default() -> Self {
+        WwGeneratorBuilder::default().build()
+    }
+}
+
+/// Builder implementation for WwGenerator configuration
+///
+/// The fields of [WwGenerator] are left public for direct use but the module
+/// also implements a builder.
+///
+/// Any number of parameters can be set this way (including none), and the
+/// final [WwGenerator] is returned by [build()](WwGeneratorBuilder::build).
+///
+/// ```rust
+/// # use ntools_mesh::Group;
+/// # use ntools_wwgen::{Normalisation, Reference, WwGenerator};
+/// let generator = WwGenerator::builder()
+///     .power(0.7)
+///     .max_error(0.1)
+///     .normalisation(Normalisation {
+///         reference: Reference::Percentile(95.0),
+///         target: None,
+///     })
+///     .group(Group::Value(20.0), Group::Total, 0.5, 0.15)
+///     .build();
+///
+/// assert_eq!(generator.groups.len(), 1);
+/// ```
+pub struct WwGeneratorBuilder {
+    /// Default softening factor
+    power: f64,
+    /// Default error tolerance
+    max_error: f64,
+    /// Only generate weights from the total group
+    total_only: bool,
+    /// Reference flux and target weight
+    normalisation: Normalisation,
+    /// Settings for individual groups
+    groups: Vec<GroupSettings>,
+    /// Treatment of time groups
+    time: TimeSettings,
+    /// Maximum distance to fill analogue holes
+    fill_distance: usize,
+}
+
+impl WwGeneratorBuilder {
+    /// Create a new instance of the builder with default parameters
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the [WwGenerator] type
+    pub fn build(self) -> WwGenerator {
+        WwGenerator {
+            power: self.power,
+            max_error: self.max_error,
+            total_only: self.total_only,
+            normalisation: self.normalisation,
+            groups: self.groups,
+            time: self.time,
+            fill_distance: self.fill_distance,
+        }
+    }
+
+    /// Default softening factor for groups without explicit settings
+    pub fn power(mut self, power: f64) -> Self {
+        self.power = power;
+        self
+    }
+
+    /// Default error tolerance for groups without explicit settings
+    pub fn max_error(mut self, max_error: f64) -> Self {
+        self.max_error = max_error;
+        self
+    }
+
+    /// Only generate weights from [Group::Total](ntools_mesh::Group)
+    pub fn total_only(mut self, total_only: bool) -> Self {
+        self.total_only = total_only;
+        self
+    }
+
+    /// Reference flux and target weight used to normalise every group
+    pub fn normalisation(mut self, normalisation: Normalisation) -> Self {
+        self.normalisation = normalisation;
+        self
+    }
+
+    /// Treatment of time groups for time-dependent weights
+    pub fn time(mut self, time: TimeSettings) -> Self {
+        self.time = time;
+        self
+    }
+
+    /// Fill analogue holes up to `fill_distance` voxels from a non-zero weight
+    ///
+    /// See [WeightWindow::fill_holes()] for details of the extrapolation.
+    pub fn fill_distance(mut self, fill_distance: usize) -> Self {
+        self.fill_distance = fill_distance;
+        self
+    }
+
+    /// Explicit power and error tolerance for a single energy/time group
+    pub fn group(mut self, energy: Group, time: Group, power: f64, max_error: f64) -> Self {
+        self.groups.push(GroupSettings {
+            energy,
+            time,
+            power: Some(power),
+            max_error: Some(max_error),
+        });
+        self
+    }
+}
+
+impl Default for WwGeneratorBuilder {
+    fn default() -> Self {
+        Self {
+            power: 0.7,
+            max_error: 1.0,
+            total_only: false,
+            normalisation: Normalisation::default(),
+            groups: Vec::new(),
+            time: TimeSettings::default(),
+            fill_distance: 0,
+        }
+    }
+}
+
+/// Default group for settings
+fn total() -> Group {
+    Group::Total
+}
+
+/// Groups match if both are total, or values agree to output precision
+fn same_group(a: Group, b: Group) -> bool {
+    match (a, b) {
+        (Group::Total, Group::Total) => true,
+        (Group::Value(a), Group::Value(b)) => (a - b).abs() <= 1e-6 * a.abs().max(b.abs()),
+        _ => false,
+    }
+}
+
+fn check_power(power: f64) -> Result<f64> {
+    match power > 0.0 && power.is_finite() {
+        true => Ok(power),
+        false => Err(invalid(&f!("power {power} must be positive"))),
+    }
+}
+
+fn check_error(max_error: f64) -> Result<f64> {
+    match max_error >= 0.0 {
+        true => Ok(max_error),
+        false => Err(invalid(&f!("max error {max_error} must not be negative"))),
+    }
+}
+
+/// Supported file formats for recipes and workflows
+pub(crate) enum FileFormat {
+    Toml,
+    Json,
+}
+
+impl FileFormat {
+    /// Format from the file extension, which must be either toml or json
+    pub(crate) fn from_path(path: &Path) -> Result<FileFormat> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "toml" => Ok(FileFormat::Toml),
+            "json" => Ok(FileFormat::Json),
+            _ => Err(Error::UnknownFormat {
+                reason: f!("'{}', expected .toml or .json", path.display()),
+            }),
+        }
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidGenerator {
+        reason: reason.to_string(),
+    }
+}
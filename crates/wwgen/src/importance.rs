@@ -0,0 +1,497 @@
+// standard library
+use std::collections::{BTreeMap, BTreeSet};
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// neutronics toolbox
+use ntools_mctal::{Particle, Tally};
+use ntools_mesh::{Geometry, Mesh};
+use ntools_utils::f;
+use ntools_weights::limit_ratio;
+
+// internal modules
+use crate::cells::{card, cell_list, check_bins, tally_cells, tally_particle, total_bin};
+use crate::error::{Error, Result};
+
+// external crates
+use log::warn;
+
+/// Cell importances for a single particle type
+///
+/// These are the `IMP` input cards of MCNP for geometric splitting, with one
+/// importance for every cell and no energy dependence. Importances are the
+/// inverse of the MAGIC weights, so the cell with the highest flux has an
+/// importance of 1 and importances increase as the flux falls off.
+///
+/// As with [CellWeights](crate::CellWeights), MCNP applies the entries of the
+/// `IMP` card to cells in the order they appear in the input deck. Cells
+/// outside the problem, e.g. the graveyard, still need an importance of 0.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CellImportances {
+    /// Particle type
+    pub particle: Particle,
+    /// Cell numbers, in the order of the importances
+    pub cells: Vec<u32>,
+    /// Importance of every cell
+    pub importances: Vec<f64>,
+}
+
+impl CellImportances {
+    /// Importance of a cell, if included
+    pub fn get(&self, cell: u32) -> Option<f64> {
+        self.cells
+            .iter()
+            .position(|c| *c == cell)
+            .and_then(|i| self.importances.get(i).copied())
+    }
+
+    /// Format the importances as an MCNP input card
+    ///
+    /// Produces an input deck fragment with a single `IMP` card, preceded by
+    /// comments listing the cells in order.
+    ///
+    /// ```rust
+    /// # use ntools_wwgen::CellImportances;
+    /// # use ntools_mctal::Particle;
+    /// let imp = CellImportances {
+    ///     particle: Particle::Neutron,
+    ///     cells: vec![10, 20, 30],
+    ///     importances: vec![1.0, 2.0, 4.0],
+    /// };
+    ///
+    /// let cards = imp.cards().unwrap();
+    /// assert!(cards.contains("IMP:n 1.00000e+00 2.00000e+00 4.00000e+00\n"));
+    /// ```
+    pub fn cards(&self) -> Result<String> {
+        let Some(symbol) = self.particle.designator() else {
+            return Err(invalid("no designator for an unknown particle"));
+        };
+
+        if self.importances.len() != self.cells.len() {
+            return Err(invalid(&f!(
+                "found {} importances, expected {}",
+                self.importances.len(),
+                self.cells.len()
+            )));
+        }
+
+        let mut s = f!(
+            "c Cell importances for {} cells, in order:\n",
+            self.cells.len()
+        );
+        s += &cell_list(&self.cells);
+        s += &card(&f!("IMP:{symbol}"), &self.importances);
+
+        Ok(s)
+    }
+
+    /// Write the input card to a file
+    ///
+    /// See [cards()](CellImportances::cards) for details of the input deck
+    /// fragment written.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let cards = self.cards()?;
+        let mut f = BufWriter::new(File::create(path)?);
+        f.write_all(cards.as_bytes())?;
+        f.flush()?;
+        Ok(())
+    }
+}
+
+/// Flux mesh to cell importances using a map of the cell in each voxel
+///
+/// The `cell_map` has one cell number for every voxel, in the same order as
+/// the voxels of a single energy/time group, e.g. the dominant cell of each
+/// voxel from a CuV posvol file. Voxels mapped to cell 0 are ignored.
+///
+/// MCNP applies the `IMP` entries in input deck order, which a cell map can
+/// not provide. The `cells` must therefore list every cell of the input deck
+/// in order, including void cells and the graveyard. Fails if the cell map
+/// has any cell that is not listed.
+///
+/// - `power` - Softening factor used as imp=>imp^power
+/// - `max_error` - Cells with flux errors above this are unconverged
+/// - `max_ratio` - Largest ratio allowed between adjacent cells
+///
+/// The flux of each cell is the volume-weighted average over its voxels for
+/// the total energy and time group. Cells are adjacent if any of their voxels
+/// share a face. Cells are kept in the order listed.
+///
+/// Unconverged cells take the lowest importance of their neighbours, or 1.0
+/// if none of their neighbours are converged either. Any importance more than
+/// `max_ratio` times higher than an adjacent cell is then lowered to the
+/// neighbouring importance multiplied by `max_ratio`.
+///
+/// Listed cells without any voxels have no flux or neighbours, so are given
+/// an importance of 1.0 with a warning. Cells outside the problem, e.g. the
+/// graveyard, must be set to 0 before writing the cards.
+///
+/// ```rust
+/// # use ntools_mesh::{Mesh, Voxel, Particle};
+/// # use ntools_wwgen::mesh_to_imp;
+/// // Four voxels in a row, the flux dropping by 100 in every voxel
+/// let mesh = Mesh {
+///     particle: Particle::Neutron,
+///     imesh: vec![0.0, 1.0, 2.0, 3.0, 4.0], iints: 4,
+///     jmesh: vec![0.0, 1.0], jints: 1,
+///     kmesh: vec![0.0, 1.0], kints: 1,
+///     voxels: (0..4)
+///         .map(|i| Voxel { index: i, result: 100_f64.powi(-(i as i32)), error: 0.01 })
+///         .collect(),
+///     ..Default::default()
+/// };
+///
+/// // Two cells per voxel pair, and graveyard cell 99 listed first in the deck
+/// let mut imp = mesh_to_imp(&mesh, &[2, 2, 1, 1], &[99, 2, 1], 0.5, 0.1, 5.0).unwrap();
+///
+/// // At most a factor of 5 between the cells
+/// assert_eq!(imp.cells, vec![99, 2, 1]);
+/// assert_eq!(imp.importances[1], 1.0);
+/// assert!((imp.importances[2] - 5.0).abs() < 1e-12);
+///
+/// // The graveyard must have no importance
+/// imp.importances[0] = 0.0;
+/// ```
+pub fn mesh_to_imp(
+    mesh: &Mesh,
+    cell_map: &[u32],
+    cells: &[u32],
+    power: f64,
+    max_error: f64,
+    max_ratio: f64,
+) -> Result<CellImportances> {
+    let n_voxels = mesh.n_voxels_per_group();
+    if cell_map.len() != n_voxels {
+        return Err(invalid(&f!(
+            "cell map has {} entries, expected {n_voxels}",
+            cell_map.len()
+        )));
+    }
+
+    // importances have no energy or time dependence
+    let voxels = mesh.voxels_by_group_index(mesh.n_ebins() - 1, mesh.n_tbins() - 1)?;
+    if voxels.len() != n_voxels {
+        return Err(invalid(&f!(
+            "found {} voxels, expected {n_voxels}",
+            voxels.len()
+        )));
+    }
+
+    check_cells(cells)?;
+    let lookup = cell_lookup(cells);
+
+    if let Some(cell) = cell_map
+        .iter()
+        .find(|c| **c != 0 && !lookup.contains_key(c))
+    {
+        return Err(invalid(&f!("cell {cell} of the cell map is not listed")));
+    }
+
+    let mapped = cell_map.iter().collect::<BTreeSet<&u32>>();
+    let n_unmapped = cells.iter().filter(|c| !mapped.contains(c)).count();
+    if n_unmapped > 0 {
+        warn!("Warning: {n_unmapped} cells are not in any voxel");
+        warn!("  - Importances set to 1.0, the graveyard needs 0.0");
+    }
+
+    // volume-weighted sums of (volume, flux, variance) for every cell
+    let mut sums = vec![(0.0, 0.0, 0.0); cells.len()];
+    for (idx, (voxel, cell)) in voxels.iter().zip(cell_map).enumerate() {
+        let Some(c) = lookup.get(cell) else {
+            continue;
+        };
+
+        let volume = voxel_volume(mesh, idx);
+        let flux = volume * voxel.result;
+        sums[*c].0 += volume;
+        sums[*c].1 += flux;
+        sums[*c].2 += (flux * voxel.error).powi(2);
+    }
+
+    let results = sums
+        .iter()
+        .map(|(volume, flux, variance)| match *flux > 0.0 {
+            true => (flux / volume, variance.sqrt() / flux),
+            false => (0.0, 0.0),
+        })
+        .collect::<Vec<(f64, f64)>>();
+
+    let adjacency = mesh_adjacency(mesh, cell_map, &lookup);
+    let importances = compute_importances(&results, &adjacency, power, max_error, max_ratio)?;
+
+    Ok(CellImportances {
+        particle: Particle::from_id(mesh.particle.id()),
+        cells: cells.to_vec(),
+        importances,
+    })
+}
+
+/// Cell flux tally to cell importances
+///
+/// Uses the same approach as [mesh_to_imp()], taking the flux of each cell
+/// directly from the total energy and time bins of an F4 cell flux tally. The
+/// region bins must be single cell numbers.
+///
+/// A tally has no knowledge of the geometry, so `adjacent` lists the pairs of
+/// neighbouring cells to apply the `max_ratio` limit to. Cells in an empty list
+/// are not limited at all.
+///
+/// Cells are kept in the order listed on the tally, which must match the
+/// order of every cell in the input deck.
+///
+/// ```rust, no_run
+/// # use ntools_mctal::Mctal;
+/// # use ntools_wwgen::tally_to_imp;
+/// let mctal = Mctal::from_file("/path/to/file.m").unwrap();
+/// let tally = mctal.get_tally(14).unwrap();
+///
+/// // Cells 10, 20, and 30 in a row, at most a factor of 4 between them
+/// let importances = tally_to_imp(tally, &[(10, 20), (20, 30)], 0.7, 0.1, 4.0).unwrap();
+/// importances.write("imp_cards.i").unwrap();
+/// ```
+pub fn tally_to_imp(
+    tally: &Tally,
+    adjacent: &[(u32, u32)],
+    power: f64,
+    max_error: f64,
+    max_ratio: f64,
+) -> Result<CellImportances> {
+    let particle = tally_particle(tally, invalid)?;
+    let cells = tally_cells(tally, invalid)?;
+    check_cells(&cells)?;
+    check_bins(tally, invalid)?;
+
+    let n_times = tally.time_bins.number.max(1);
+    let e_idx = total_bin(&tally.energy_bins, "energy", invalid)?;
+    let t_idx = total_bin(&tally.time_bins, "time", invalid)?;
+
+    let results = tally
+        .iter()
+        .map(|region| &region[e_idx * n_times + t_idx])
+        .map(|r| (r.value, r.error))
+        .collect::<Vec<(f64, f64)>>();
+
+    let lookup = cell_lookup(&cells);
+    let mut adjacency = vec![BTreeSet::new(); cells.len()];
+    for (a, b) in adjacent {
+        match (lookup.get(a), lookup.get(b)) {
+            (Some(i), Some(j)) => connect(&mut adjacency, *i, *j),
+            _ => {
+                return Err(invalid(&f!(
+                    "adjacent cells {a} and {b} are not both on tally {}",
+                    tally.id
+                )))
+            }
+        }
+    }
+
+    let importances = compute_importances(&results, &adjacency, power, max_error, max_ratio)?;
+
+    Ok(CellImportances {
+        particle,
+        cells,
+        importances,
+    })
+}
+
+/// Core function for turning cell (result, error) pairs into importances
+fn compute_importances(
+    results: &[(f64, f64)],
+    adjacency: &[BTreeSet<usize>],
+    power: f64,
+    max_error: f64,
+    max_ratio: f64,
+) -> Result<Vec<f64>> {
+    check_parameters(power, max_ratio)?;
+
+    let is_converged =
+        |(result, error): &(f64, f64)| *result > 0.0 && result.is_finite() && *error <= max_error;
+
+    let flux_ref = results
+        .iter()
+        .filter(|r| is_converged(r))
+        .map(|(result, _)| *result)
+        .fold(0.0, f64::max);
+
+    if flux_ref == 0.0 {
+        return Err(invalid("no cells with a converged flux"));
+    }
+
+    let importances = results
+        .iter()
+        .map(|r| is_converged(r).then(|| (flux_ref / r.0).powf(power)))
+        .collect::<Vec<Option<f64>>>();
+
+    let n_unconverged = importances.iter().filter(|i| i.is_none()).count();
+    if n_unconverged > 0 {
+        warn!("Warning: {n_unconverged} cells have no converged flux");
+        warn!("  - Using the lowest neighbouring importance, or 1.0");
+    }
+
+    let mut importances = fill_unconverged(importances, adjacency);
+    limit_adjacent_ratio(&mut importances, adjacency, max_ratio)?;
+
+    Ok(importances)
+}
+
+/// Unconverged cells take the lowest importance of their neighbours
+///
+/// Filled in layers so that the result does not depend on the cell order,
+/// with anything left over set to 1.0.
+fn fill_unconverged(mut importances: Vec<Option<f64>>, adjacency: &[BTreeSet<usize>]) -> Vec<f64> {
+    loop {
+        let filled = importances
+            .iter()
+            .enumerate()
+            .filter(|(_, imp)| imp.is_none())
+            .filter_map(|(i, _)| {
+                adjacency[i]
+                    .iter()
+                    .filter_map(|n| importances[*n])
+                    .min_by(|a, b| a.total_cmp(b))
+                    .map(|imp| (i, imp))
+            })
+            .collect::<Vec<(usize, f64)>>();
+
+        if filled.is_empty() {
+            break;
+        }
+
+        for (i, imp) in filled {
+            importances[i] = Some(imp);
+        }
+    }
+
+    importances.into_iter().map(|i| i.unwrap_or(1.0)).collect()
+}
+
+/// Cap the ratio between the importances of adjacent cells
+///
+/// Importances are the inverse of weights, so any importance more than
+/// `max_ratio` times higher than a neighbour is lowered by limiting the ratio
+/// of the equivalent weights with [limit_ratio()]. Returns the number of
+/// importances that were changed.
+fn limit_adjacent_ratio(
+    importances: &mut [f64],
+    adjacency: &[BTreeSet<usize>],
+    max_ratio: f64,
+) -> Result<usize> {
+    let adjacency = adjacency
+        .iter()
+        .map(|neighbours| neighbours.iter().copied().collect())
+        .collect::<Vec<Vec<usize>>>();
+
+    let mut weights = importances.iter().map(|i| 1.0 / i).collect::<Vec<f64>>();
+    let changed =
+        limit_ratio(&mut weights, &adjacency, max_ratio).map_err(|e| invalid(&e.to_string()))?;
+
+    // only the limited importances are updated, leaving the rest exact
+    for (importance, weight) in importances.iter_mut().zip(weights) {
+        if weight != 1.0 / *importance {
+            *importance = 1.0 / weight;
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Cells are adjacent wherever voxels of different cells share a face
+fn mesh_adjacency(
+    mesh: &Mesh,
+    cell_map: &[u32],
+    lookup: &BTreeMap<u32, usize>,
+) -> Vec<BTreeSet<usize>> {
+    let mut adjacency = vec![BTreeSet::new(); lookup.len()];
+    let index = |i: usize, j: usize, k: usize| (i * mesh.jints + j) * mesh.kints + k;
+
+    // theta is periodic for cylinders, avoiding duplicates for 1 or 2 bins
+    let is_periodic = mesh.geometry == Geometry::Cylindrical && mesh.kints > 2;
+
+    for i in 0..mesh.iints {
+        for j in 0..mesh.jints {
+            for k in 0..mesh.kints {
+                let mut neighbours = Vec::with_capacity(3);
+                if i + 1 < mesh.iints {
+                    neighbours.push(index(i + 1, j, k));
+                }
+                if j + 1 < mesh.jints {
+                    neighbours.push(index(i, j + 1, k));
+                }
+                if k + 1 < mesh.kints {
+                    neighbours.push(index(i, j, k + 1));
+                } else if is_periodic {
+                    neighbours.push(index(i, j, 0));
+                }
+
+                let a = lookup.get(&cell_map[index(i, j, k)]);
+                for n in neighbours {
+                    if let (Some(a), Some(b)) = (a, lookup.get(&cell_map[n])) {
+                        connect(&mut adjacency, *a, *b);
+                    }
+                }
+            }
+        }
+    }
+
+    adjacency
+}
+
+/// Volume of a voxel within a single energy/time group
+fn voxel_volume(mesh: &Mesh, idx: usize) -> f64 {
+    let (_, _, i, j, k) = mesh.etijk_from_voxel_index(idx);
+
+    let di = mesh.imesh[i + 1] - mesh.imesh[i];
+    let dj = mesh.jmesh[j + 1] - mesh.jmesh[j];
+    let dk = mesh.kmesh[k + 1] - mesh.kmesh[k];
+
+    match mesh.geometry {
+        Geometry::Rectangular => di * dj * dk,
+        // (r, z, theta) with theta in revolutions
+        Geometry::Cylindrical => PI * (mesh.imesh[i + 1].powi(2) - mesh.imesh[i].powi(2)) * dj * dk,
+    }
+}
+
+fn connect(adjacency: &mut [BTreeSet<usize>], a: usize, b: usize) {
+    if a != b {
+        adjacency[a].insert(b);
+        adjacency[b].insert(a);
+    }
+}
+
+/// Listed cells must be unique cell numbers
+fn check_cells(cells: &[u32]) -> Result<()> {
+    if cells.is_empty() {
+        return Err(invalid("no cells listed"));
+    }
+
+    let mut unique = BTreeSet::new();
+    match cells.iter().find(|c| **c == 0 || !unique.insert(**c)) {
+        Some(0) => Err(invalid("cell 0 is not a valid cell number")),
+        Some(cell) => Err(invalid(&f!("cell {cell} is listed more than once"))),
+        None => Ok(()),
+    }
+}
+
+fn cell_lookup(cells: &[u32]) -> BTreeMap<u32, usize> {
+    cells.iter().enumerate().map(|(i, c)| (*c, i)).collect()
+}
+
+fn check_parameters(power: f64, max_ratio: f64) -> Result<()> {
+    if !(power.is_finite() && power >= 0.0) {
+        return Err(invalid(&f!("power {power} must be finite and positive")));
+    }
+
+    if !(max_ratio.is_finite() && max_ratio >= 1.0) {
+        return Err(invalid(&f!("ratio limit {max_ratio} must be at least 1")));
+    }
+
+    Ok(())
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidImportance {
+        reason: reason.to_string(),
+    }
+}
@@ -5,7 +5,7 @@
 //!
 //! There are many ways to do this. Currently the MAGIC method is implemented,
 //! with the option for first generating meshes from the Build-Up Density
-//! Exptrapolation (BUDE) method.
+//! Extrapolation (BUDE) method.
 //!
 //! # Mesh to Weight window
 //!
@@ -87,31 +87,306 @@
 //! 5 -> Energy(200.0)  Time(1E+99)     powers[5]   max_errors[5]
 //! ```
 //!
-//! ## Density extrapolation
+//! ## Multiple particle types
 //!
-//! **Warning: Extremely WIP for testing**
+//! Coupled problems need weights for several particle types in one wwout file.
+//! [meshes_to_ww()] takes a flux mesh and [WwGenerator] for each [Particle](ntools_mesh::Particle),
+//! checks the meshes share the same geometry, and returns a validated set
+//! that is written in one step.
 //!
-//! A very quick and naive implementation for the work in progress implemntation
-//! of the Build-Up Density Exptrapolation (BUDE) method.
+//! ```rust, no_run
+//! # use std::collections::BTreeMap;
+//! # use ntools_mesh::{read_target, Particle};
+//! # use ntools_wwgen::{meshes_to_ww, WwGenerator};
+//! let neutron = read_target("/path/to/meshtal.msht", 104).unwrap();
+//! let photon = read_target("/path/to/meshtal.msht", 204).unwrap();
+//! let generator = WwGenerator::new();
+//!
+//! let meshes = BTreeMap::from([
+//!     (Particle::Neutron, (&neutron, &generator)),
+//!     (Particle::Photon, (&photon, &generator)),
+//! ]);
+//!
+//! meshes_to_ww(&meshes).unwrap().write("wwout_NP", false).unwrap();
+//! ```
+//!
+//! ## Generation recipes
+//!
+//! Positional lists are easy to get wrong, so a [WwGenerator] keys settings
+//! for individual groups explicitly by their energy and time
+//! [Group](ntools_mesh::Group). Any settings that do not match a group of the
+//! mesh are an error.
+//!
+//! ```rust, no_run
+//! # use ntools_mesh::{read_target, Group};
+//! # use ntools_wwgen::WwGenerator;
+//! let mesh = read_target("./data/meshes/fmesh_104.msht", 104).unwrap();
+//!
+//! // Defaults for every group, with different settings for one energy group
+//! let generator = WwGenerator::builder()
+//!     .power(0.7)
+//!     .max_error(0.1)
+//!     .group(Group::Value(20.0), Group::Total, 0.5, 0.15)
+//!     .build();
+//!
+//! let ww = generator.generate(&mesh).unwrap();
+//! ```
+//!
+//! Generators are serialisable, so recipes may be written to TOML or JSON
+//! with [write()](WwGenerator::write), version-controlled, and rerun with
+//! [from_file()](WwGenerator::from_file).
+//!
+//! ## Time-dependent weights
+//!
+//! Meshes with several time groups give time-dependent weights, but every
+//! group is normalised independently so weights can jump between consecutive
+//! times. For pulsed sources and shutdown problems, [TimeSettings] on a
+//! [WwGenerator] normalise all time groups together, limit the ratio between
+//! consecutive time groups, and drop empty late-time groups.
+//!
+//! ```rust, no_run
+//! # use ntools_mesh::read_target;
+//! # use ntools_wwgen::{TimeSettings, WwGenerator};
+//! let mesh = read_target("./data/meshes/fmesh_114.msht", 114).unwrap();
+//!
+//! let generator = WwGenerator::builder()
+//!     .time(TimeSettings {
+//!         normalise_all_times: true,
+//!         max_ratio: Some(10.0),
+//!         drop_empty: true,
+//!     })
+//!     .build();
+//!
+//! let ww = generator.generate(&mesh).unwrap();
+//! ```
+//!
+//! ## Iterating to convergence
+//!
+//! MAGIC is iterative, with the flux mesh of each run generating the weights
+//! for the next. A [MagicWorkflow] records the coverage (non-analogue
+//! percentage) of every iteration by group, updates the power and error
+//! tolerance for the next run according to a [Schedule], and is saved to disk
+//! between MCNP runs.
+//!
+//! ```rust, no_run
+//! # use ntools_mesh::read_target;
+//! # use ntools_wwgen::MagicWorkflow;
+//! let mut workflow = MagicWorkflow::load("workflow.toml").unwrap();
+//!
+//! // Weights for the next run from the latest flux mesh
+//! let mesh = read_target("./data/meshes/fmesh_104.msht", 104).unwrap();
+//! workflow.iterate(&mesh).unwrap().write("wwinp");
+//! workflow.save("workflow.toml").unwrap();
+//! ```
+//!
+//! ## Normalisation
+//!
+//! Each group is normalised to its maximum flux by default. When the source is
+//! not where the maximum flux sits, [mesh_to_ww_normalised()] takes a
+//! [Normalisation] to use a different [Reference] flux instead:
+//!
+//! - [Reference::Maximum] - Maximum flux in the group (default)
+//! - [Reference::Percentile] - Percentile of the non-zero fluxes in the group
+//! - [Reference::Point] - Flux at a [Point](ntools_mesh::Point) or voxel
+//! - [Reference::Constant] - User-defined reference flux
+//!
+//! An optional target weight sets the weight at the reference exactly, so the
+//! source particle weight is consistent with the windows produced.
+//!
+//! ```rust, no_run
+//! # use ntools_mesh::{read_target, Point};
+//! # use ntools_wwgen::{mesh_to_ww_normalised, Normalisation, Reference};
+//! let mesh = read_target("./data/meshes/fmesh_104.msht", 104).unwrap();
+//!
+//! // Weight of 0.5 at the source voxel
+//! let normalisation = Normalisation {
+//!     reference: Reference::Point(Point::from_ijk(0, 2, 4)),
+//!     target: Some(0.5),
+//! };
+//!
+//! let ww = mesh_to_ww_normalised(&mesh, &[0.7], &[0.1], false, &normalisation).unwrap();
+//! ```
+//!
+//! ## Filling analogue holes
+//!
+//! Voxels with errors above the tolerance are set to analogue, which can leave
+//! holes in the weights. A [WwGenerator] fills these by extrapolating from
+//! converged neighbours in log space, up to `fill_distance` voxels away.
+//!
+//! ```rust, no_run
+//! # use ntools_mesh::read_target;
+//! # use ntools_wwgen::WwGenerator;
+//! let mesh = read_target("./data/meshes/fmesh_104.msht", 104).unwrap();
+//!
+//! // Fill holes up to 3 voxels from any converged weight
+//! let generator = WwGenerator::builder()
+//!     .power(0.7)
+//!     .max_error(0.1)
+//!     .fill_distance(3)
+//!     .build();
+//!
+//! let weight_window = generator.generate(&mesh).unwrap();
+//! ```
+//!
+//! Existing weights are filled directly with
+//! [WeightWindow::fill_holes()](ntools_weights::WeightWindow::fill_holes).
+//!
+//! # Adjoint methods
 //!
-//! This takes several meshes as arguments:
+//! Consistent Adjoint Driven Importance Sampling (CADIS) uses an adjoint flux
+//! mesh, i.e. the importance of each voxel to a detector response, to produce
+//! weight windows together with a consistent biased source. See [cadis()] for
+//! details.
+//!
+//! ```rust, no_run
+//! # use ntools_mesh::read_target;
+//! # use ntools_wwgen::{cadis, Source};
+//! let adjoint = read_target("/path/to/adjoint.msht", 104).unwrap();
+//!
+//! // Point source at the origin with a 14 MeV line
+//! let source = Source {
+//!     positions: vec![[0.0, 0.0, 0.0]],
+//!     strengths: vec![1.0],
+//!     energies: vec![13.9, 14.1],
+//!     spectrum: vec![1.0],
+//! };
+//!
+//! // Weight windows for a wupn of 5, and the SDEF/SB biasing cards
+//! let result = cadis(&adjoint, &source, 5.0, 0.1).unwrap();
+//! result.weight_window.write("wwinp");
+//! result.write_sdef("sdef_cards.i").unwrap();
+//! ```
+//!
+//! For a uniform uncertainty everywhere rather than a single detector,
+//! Forward-Weighted CADIS (FW-CADIS) first builds an adjoint source from a
+//! forward flux estimate with [fw_cadis_adjoint_source()]. The adjoint flux
+//! from this source is then used with [cadis()] as usual.
+//!
+//! # Cell tally to weight window
+//!
+//! Not every problem suits a mesh. Cell-based `WWE`/`WWNi` input cards are
+//! generated from an F4 cell flux tally with [tally_to_cell_ww()], using the
+//! same MAGIC normalisation and `power`/`max_error` controls as the mesh
+//! methods. Per-group parameters are available through
+//! [tally_to_cell_ww_advanced()].
+//!
+//! ```rust, no_run
+//! # use ntools_mctal::Mctal;
+//! # use ntools_wwgen::tally_to_cell_ww;
+//! let mctal = Mctal::from_file("/path/to/file.m").unwrap();
+//! let tally = mctal.get_tally(14).unwrap();
+//!
+//! // Write the WWE/WWN cards as an input deck fragment
+//! let weights = tally_to_cell_ww(tally, 0.7, 0.10, false).unwrap();
+//! weights.write("wwn_cards.i").unwrap();
+//! ```
+//!
+//! The weights are written in the order cells appear on the tally, which must
+//! match the order of every cell in the input deck.
+//!
+//! ## Cell importances
+//!
+//! Some models use geometric splitting rather than weight windows. Importances
+//! for the `IMP` card are generated with [mesh_to_imp()] from a flux mesh and
+//! the cell found in each voxel, or with [tally_to_imp()] from an F4 cell flux
+//! tally. Either way, the importance ratio between adjacent cells is kept
+//! within a limit to avoid excessive splitting.
+//!
+//! Importances are in the order of the cells on the tally, or the order of the
+//! cells listed with a mesh. Both must include every cell of the input deck.
+//!
+//! ```rust, no_run
+//! # use ntools_mesh::read_target;
+//! # use ntools_wwgen::mesh_to_imp;
+//! let mesh = read_target("./data/meshes/fmesh_104.msht", 104).unwrap();
+//!
+//! // Dominant cell of every voxel, e.g. from a CuV posvol file
+//! let cell_map = vec![10; mesh.n_voxels_per_group()];
+//!
+//! // Every cell in input deck order, with the graveyard last
+//! let cells = [10, 20, 999];
+//!
+//! // At most a factor of 4 between adjacent cells
+//! let mut importances = mesh_to_imp(&mesh, &cell_map, &cells, 0.7, 0.1, 4.0).unwrap();
+//! importances.importances[2] = 0.0;
+//! importances.write("imp_cards.i").unwrap();
+//! ```
+//!
+//! # Density extrapolation
+//!
+//! Deep penetration problems may be too difficult to get any meaningful flux
+//! from the full model. The Build-Up Density Extrapolation (BUDE) method
+//! estimates the full density flux from much easier runs:
 //!
 //! - void mesh (vd), run with voided materials
-//! - uncollided (uc) flux mesh
 //! - reduced density (rd) flux, with materials set to a fraction of their density
+//! - uncollided (uc) flux mesh from the same reduced density run
+//!
+//! The uncollided flux and build-up factor are extrapolated separately to the
+//! full density, with errors propagated from every mesh. Several reduced
+//! density runs may be fitted together with [extrapolate_density_steps()].
+//!
+//! ```rust, no_run
+//! # use ntools_mesh::read_target;
+//! # use ntools_wwgen::{extrapolate_density_steps, mesh_to_ww, DensityStep};
+//! let vd = read_target("./void.msht", 104).unwrap();
+//! let rd_10 = read_target("./reduced_10.msht", 104).unwrap();
+//! let uc_10 = read_target("./uncollided_10.msht", 104).unwrap();
+//! let rd_20 = read_target("./reduced_20.msht", 104).unwrap();
+//! let uc_20 = read_target("./uncollided_20.msht", 104).unwrap();
 //!
-//! These are combined to build up an estimate of the flux that can be expected
-//! of a complete run with all materials in the geometry at their full density.
+//! // Runs at 10% and 20% of the full density
+//! let steps = [
+//!     DensityStep { fraction: 0.1, reduced: &rd_10, uncollided: &uc_10 },
+//!     DensityStep { fraction: 0.2, reduced: &rd_20, uncollided: &uc_20 },
+//! ];
+//!
+//! // Estimate of the full density flux, used as any other flux mesh
+//! let forward = extrapolate_density_steps(&vd, &steps, 1.0).unwrap();
+//! let ww = mesh_to_ww(&forward, 0.7, 0.1, false);
+//! ```
 
 mod bude;
+mod cadis;
+mod cells;
 mod error;
+mod generator;
+mod importance;
 mod magic;
+mod multi;
+mod normalise;
+mod time;
+mod workflow;
+
+#[doc(inline)]
+pub use magic::{mesh_to_ww, mesh_to_ww_advanced, mesh_to_ww_normalised};
+
+#[doc(inline)]
+pub use multi::{meshes_to_ww, MultiParticleWeights};
+
+#[doc(inline)]
+pub use normalise::{Normalisation, Reference};
+
+#[doc(inline)]
+pub use generator::{GroupSettings, WwGenerator, WwGeneratorBuilder};
+
+#[doc(inline)]
+pub use time::TimeSettings;
+
+#[doc(inline)]
+pub use workflow::{GroupCoverage, Iteration, MagicWorkflow, MeshSummary, Schedule};
+
+#[doc(inline)]
+pub use cells::{tally_to_cell_ww, tally_to_cell_ww_advanced, CellWeights};
+
+#[doc(inline)]
+pub use importance::{mesh_to_imp, tally_to_imp, CellImportances};
 
 #[doc(inline)]
-pub use magic::{mesh_to_ww, mesh_to_ww_advanced};
+pub use cadis::{cadis, fw_cadis_adjoint_source, Cadis, FwCadisResponse, Source};
 
 #[doc(inline)]
-pub use bude::extrapolate_density;
+pub use bude::{extrapolate_density, extrapolate_density_steps, DensityStep};
 
 #[doc(inline)]
 pub use error::Error;
@@ -2,6 +2,10 @@
 use ntools_mesh::{Geometry, Mesh, Voxel};
 use ntools_weights::WeightWindow;
 
+// internal modules
+use crate::error::Result;
+use crate::normalise::{maximum, Normalisation};
+
 use log::warn;
 
 /// Mesh tally to global weight windows with simple parameters
@@ -31,7 +35,7 @@ use log::warn;
 /// [Group::Total](ntools_mesh::Group), set the `total_only` boolean to `true`.
 pub fn mesh_to_ww(mesh: &Mesh, power: f64, max_error: f64, total_only: bool) -> WeightWindow {
     let mut ww: WeightWindow = initialise_ww_from_mesh(mesh, total_only);
-    ww.weights = compute_weights(mesh, &[power], &[max_error], total_only);
+    ww.weights = maximum_weights(mesh, &[power], &[max_error], total_only);
     ww
 }
 
@@ -86,10 +90,48 @@ pub fn mesh_to_ww(mesh: &Mesh, power: f64, max_error: f64, total_only: bool) ->
 /// ```
 pub fn mesh_to_ww_advanced(mesh: &Mesh, powers: &[f64], max_errors: &[f64]) -> WeightWindow {
     let mut ww: WeightWindow = initialise_ww_from_mesh(mesh, false);
-    ww.weights = compute_weights(mesh, powers, max_errors, false);
+    ww.weights = maximum_weights(mesh, powers, max_errors, false);
     ww
 }
 
+/// Mesh tally to global weight windows with a choice of normalisation
+///
+/// Same as [mesh_to_ww_advanced] but every group is normalised using the
+/// provided [Normalisation] rather than the maximum flux of the group. Setting
+/// `total_only` only generates weights from the
+/// [Group::Total](ntools_mesh::Group).
+///
+/// For example, to make the weights at a point source location consistent
+/// with a source particle weight of 1:
+///
+/// ```rust, no_run
+/// # use ntools_mesh::{read_target, Point};
+/// # use ntools_wwgen::{mesh_to_ww_normalised, Normalisation, Reference};
+/// let mesh = read_target("./data/meshes/fmesh_104.msht", 104).unwrap();
+///
+/// // Lower weight bound of 0.5 at the source location in every group
+/// let normalisation = Normalisation {
+///     reference: Reference::Point(Point::from_xyz(0.0, 0.0, 10.0)),
+///     target: Some(0.5),
+/// };
+///
+/// let ww = mesh_to_ww_normalised(&mesh, &[0.7], &[0.1], false, &normalisation).unwrap();
+/// ```
+///
+/// Fails if the normalisation parameters are invalid, or if a reference point
+/// is outside of the mesh.
+pub fn mesh_to_ww_normalised(
+    mesh: &Mesh,
+    powers: &[f64],
+    max_errors: &[f64],
+    total_only: bool,
+    normalisation: &Normalisation,
+) -> Result<WeightWindow> {
+    let mut ww: WeightWindow = initialise_ww_from_mesh(mesh, total_only);
+    ww.weights = compute_weights(mesh, powers, max_errors, total_only, normalisation, false)?;
+    Ok(ww)
+}
+
 /// Core function for setting up the weight mesh geometry
 ///
 /// This initialises everything but the weights themselves, setting up all
@@ -99,7 +141,7 @@ pub fn mesh_to_ww_advanced(mesh: &Mesh, powers: &[f64], max_errors: &[f64]) -> W
 /// This is decoupled from the weights as it can be useful to just be able to
 /// do the setup and weight calculations separately. However, the public API
 /// brings these together to ensure they are used correctly.
-fn initialise_ww_from_mesh(mesh: &Mesh, total_only: bool) -> WeightWindow {
+pub(crate) fn initialise_ww_from_mesh(mesh: &Mesh, total_only: bool) -> WeightWindow {
     // for what this shit means look up appendix B of the mcnp6 manual
     let mut ww = WeightWindow {
         nr: match mesh.geometry {
@@ -141,8 +183,8 @@ fn initialise_ww_from_mesh(mesh: &Mesh, total_only: bool) -> WeightWindow {
     // only bother including time info if relevant
     if mesh.n_tbins() > 1 && !total_only {
         ww.iv = 2;
-        ww.nt = mesh.n_tbins();
         ww.t = mesh.tmesh[1..].to_vec();
+        ww.nt = ww.t.len();
     }
 
     ww
@@ -157,7 +199,19 @@ fn initialise_ww_from_mesh(mesh: &Mesh, total_only: bool) -> WeightWindow {
 ///
 /// For the typical functionality the `powers` and `max_errors` list may be just
 /// one value long, which will be applied to every group.
-fn compute_weights(mesh: &Mesh, powers: &[f64], max_errors: &[f64], total_only: bool) -> Vec<f64> {
+///
+/// With `all_times` set, every time group of an energy group shares the
+/// largest reference flux of its time groups, so weights stay continuous
+/// across time.
+pub(crate) fn compute_weights(
+    mesh: &Mesh,
+    powers: &[f64],
+    max_errors: &[f64],
+    total_only: bool,
+    normalisation: &Normalisation,
+    all_times: bool,
+) -> Result<Vec<f64>> {
+    normalisation.validate()?;
     let (energy_groups, time_groups) = relevant_groups_idx(mesh, total_only);
 
     // set up the weights vector
@@ -175,21 +229,78 @@ fn compute_weights(mesh: &Mesh, powers: &[f64], max_errors: &[f64], total_only:
 
     // Loop over all the requested groups and apply the appropriate factors
     for e_idx in &energy_groups {
+        // one reference for every time group if normalising across time
+        let shared_ref = match all_times {
+            true => Some(time_reference(mesh, *e_idx, &time_groups, normalisation)?),
+            false => None,
+        };
+
         for t_idx in &time_groups {
             // really want slice by idx
             let voxels = mesh.voxels_by_group_index(*e_idx, *t_idx).unwrap();
+            let flux_ref = match shared_ref {
+                Some(flux_ref) => flux_ref,
+                None => normalisation.flux(mesh, *e_idx, *t_idx, voxels)?,
+            };
             weights.extend(weight_from_voxels(
                 mesh,
                 voxels,
+                flux_ref,
+                normalisation,
                 *powers_iter.next().unwrap(),
                 *errors_iter.next().unwrap(),
             ));
         }
     }
 
+    Ok(weights)
+}
+
+/// Weights with every group normalised to its own maximum flux
+///
+/// Same as [compute_weights] with the default [Normalisation], which has no
+/// parameters to validate or points to find, so can not fail.
+fn maximum_weights(mesh: &Mesh, powers: &[f64], max_errors: &[f64], total_only: bool) -> Vec<f64> {
+    let (energy_groups, time_groups) = relevant_groups_idx(mesh, total_only);
+    let n_groups = energy_groups.len() * time_groups.len();
+    let powers = collect_power_values(powers, n_groups);
+    let errors = collect_error_values(max_errors, n_groups);
+    let mut parameters = powers.iter().zip(errors.iter());
+
+    let mut weights: Vec<f64> = Vec::with_capacity(n_groups * mesh.n_voxels_per_group());
+    for e_idx in &energy_groups {
+        for t_idx in &time_groups {
+            let voxels = mesh.voxels_by_group_index(*e_idx, *t_idx).unwrap();
+            let (power, max_error) = parameters.next().unwrap();
+            weights.extend(weight_from_voxels(
+                mesh,
+                voxels,
+                maximum(voxels),
+                &Normalisation::default(),
+                *power,
+                *max_error,
+            ));
+        }
+    }
+
     weights
 }
 
+/// Largest reference flux over every time group of an energy group
+fn time_reference(
+    mesh: &Mesh,
+    e_idx: usize,
+    time_groups: &[usize],
+    normalisation: &Normalisation,
+) -> Result<f64> {
+    let mut flux_ref: f64 = 0.0;
+    for t_idx in time_groups {
+        let voxels = mesh.voxels_by_group_index(e_idx, *t_idx)?;
+        flux_ref = flux_ref.max(normalisation.flux(mesh, e_idx, *t_idx, voxels)?);
+    }
+    Ok(flux_ref)
+}
+
 /// Calculates the weights for a set of voxels
 ///
 /// This is done in groups so that each energy/time group can be normalised
@@ -197,35 +308,82 @@ fn compute_weights(mesh: &Mesh, powers: &[f64], max_errors: &[f64], total_only:
 /// power factors and error tolerances for each group.
 ///
 /// Weights are calculated as `(0.5 * (v.result / flux_ref)).powf(power)`
-fn weight_from_voxels(mesh: &Mesh, voxels: &[Voxel], power: f64, max_error: f64) -> Vec<f64> {
+/// unless a target weight is set on the [Normalisation].
+fn weight_from_voxels(
+    mesh: &Mesh,
+    voxels: &[Voxel],
+    flux_ref: f64,
+    normalisation: &Normalisation,
+    power: f64,
+    max_error: f64,
+) -> Vec<f64> {
+    let results = voxels
+        .iter()
+        .map(|v| (v.result, v.error))
+        .collect::<Vec<(f64, f64)>>();
+
+    // weights are in voxel order, but need to be in cell order
+    let mut wgt: Vec<(usize, f64)> =
+        magic_weights(&results, flux_ref, normalisation, power, max_error)
+            .into_iter()
+            .enumerate()
+            .map(|(i, w)| (mesh.cell_index_from_voxel_index(i), w))
+            .collect();
+
+    wgt.sort_by(|a, b| a.0.cmp(&b.0));
+    wgt.into_iter().map(|r| r.1).collect()
+}
+
+/// MAGIC weights for a set of `(result, error)` pairs in a single group
+///
+/// Every result is normalised to the maximum of the group, and any result with
+/// an error above `max_error` is set to 0/analogue.
+///
+/// Weights are calculated as `(0.5 * (result / flux_ref)).powf(power)`
+pub(crate) fn normalised_weights(results: &[(f64, f64)], power: f64, max_error: f64) -> Vec<f64> {
     // find maximum of the energy/time group set
-    let flux_ref = voxels
+    let flux_ref = results
         .iter()
-        .map(|v| v.result)
+        .map(|(result, _)| *result)
         .max_by(|a, b| a.total_cmp(b))
-        .unwrap();
+        .unwrap_or(0.0);
+
+    magic_weights(
+        results,
+        flux_ref,
+        &Normalisation::default(),
+        power,
+        max_error,
+    )
+}
 
+/// MAGIC weights for a set of `(result, error)` pairs relative to a reference
+fn magic_weights(
+    results: &[(f64, f64)],
+    flux_ref: f64,
+    normalisation: &Normalisation,
+    power: f64,
+    max_error: f64,
+) -> Vec<f64> {
     // guard against groups with no results
     if flux_ref == 0.0 {
-        return vec![0.0; voxels.len()];
+        return vec![0.0; results.len()];
     }
 
     // Main calculation, very simple
-    let mut wgt: Vec<(usize, f64)> = Vec::with_capacity(voxels.len());
-    for (i, v) in voxels.iter().enumerate() {
-        let mut w = if v.error <= max_error {
-            (0.5 * (v.result / flux_ref)).powf(power)
-        } else {
-            0.0
-        };
-
-        // ensure the value is reasonable (looking at you CuV)
-        w = constrain_weights(w);
-        wgt.push((mesh.cell_index_from_voxel_index(i), w));
-    }
+    results
+        .iter()
+        .map(|(result, error)| {
+            let w = if *error <= max_error {
+                normalisation.weight(*result, flux_ref, power)
+            } else {
+                0.0
+            };
 
-    wgt.sort_by(|a, b| a.0.cmp(&b.0));
-    wgt.into_iter().map(|r| r.1).collect()
+            // ensure the value is reasonable (looking at you CuV)
+            constrain_weights(w)
+        })
+        .collect()
 }
 
 /// Generate a list of error cuts for every group in the weight window mesh
@@ -247,7 +405,7 @@ fn weight_from_voxels(mesh: &Mesh, voxels: &[Voxel], power: f64, max_error: f64)
 ///
 /// If the length of the multiple powers list is not valid, the first factor is
 /// applied to all groups and a warning raised.
-fn collect_power_values(powers: &[f64], n_groups: usize) -> Vec<f64> {
+pub(crate) fn collect_power_values(powers: &[f64], n_groups: usize) -> Vec<f64> {
     let n_powers = powers.len();
 
     match n_powers {
@@ -288,7 +446,7 @@ fn collect_power_values(powers: &[f64], n_groups: usize) -> Vec<f64> {
 ///
 /// If the length of the multiple errors list is not valid, the first tolerance
 /// is applied to all groups and a warning raised.
-fn collect_error_values(errors: &[f64], n_groups: usize) -> Vec<f64> {
+pub(crate) fn collect_error_values(errors: &[f64], n_groups: usize) -> Vec<f64> {
     let n_errors = errors.len();
 
     match n_errors {
@@ -327,7 +485,7 @@ fn qps_tuples(mesh_bounds: &[f64]) -> Vec<[f64; 3]> {
 ///
 /// Either just returns the `Total` for total-only selections, or will every
 /// valued group if there are multiple.
-fn relevant_groups_idx(mesh: &Mesh, total_only: bool) -> (Vec<usize>, Vec<usize>) {
+pub(crate) fn relevant_groups_idx(mesh: &Mesh, total_only: bool) -> (Vec<usize>, Vec<usize>) {
     let ebins = mesh.n_ebins();
     let tbins = mesh.n_tbins();
 
@@ -354,7 +512,7 @@ fn relevant_groups_idx(mesh: &Mesh, total_only: bool) -> (Vec<usize>, Vec<usize>
 }
 
 /// Fix ridiculous values that may happen for CuV
-fn constrain_weights(weight: f64) -> f64 {
+pub(crate) fn constrain_weights(weight: f64) -> f64 {
     if weight < 1.0e-99 {
         0.0
     } else if weight >= 1.0e+100 {
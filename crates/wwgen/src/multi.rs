@@ -0,0 +1,159 @@
+// standard library
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// neutronics toolbox
+use ntools_mesh::{Mesh, Particle};
+use ntools_utils::f;
+use ntools_weights::{check_multi_particle, try_write_multi_particle, WeightWindow};
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::generator::WwGenerator;
+
+/// Validated set of weight windows for several particle types
+///
+/// Every set shares the same mesh geometry, and weight counts are consistent
+/// with the mesh and groups, so all of them are written to a single wwout
+/// file. Sets are sorted by particle type.
+///
+/// Returned by [meshes_to_ww()].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiParticleWeights {
+    /// Weight windows for each particle type, sorted by particle
+    pub weight_windows: Vec<WeightWindow>,
+}
+
+impl MultiParticleWeights {
+    /// Particle types included in the set
+    pub fn particles(&self) -> Vec<Particle> {
+        self.weight_windows
+            .iter()
+            .map(|ww| Particle::from_id(ww.particle))
+            .collect()
+    }
+
+    /// Weight windows for a particle type, if included
+    pub fn get(&self, particle: Particle) -> Option<&WeightWindow> {
+        self.weight_windows
+            .iter()
+            .find(|ww| ww.particle == particle.id())
+    }
+
+    /// Write every particle type to a single wwout file
+    ///
+    /// See [write_multi_particle()](ntools_weights::write_multi_particle) for
+    /// the meaning of `padded`. Unlike writing the sets directly, this fails
+    /// rather than dropping any set that can not be combined. Every set is
+    /// checked before the file is created, so nothing is written on failure.
+    pub fn write(&self, path: impl AsRef<Path>, padded: bool) -> Result<()> {
+        check_multi_particle(&self.weight_windows)?;
+        try_write_multi_particle(&self.weight_windows, path, padded)?;
+        Ok(())
+    }
+}
+
+/// Generate weight windows for several particle types in one call
+///
+/// Coupled problems need weights for every particle type in a single wwout
+/// file. Each flux mesh is paired with the [WwGenerator] settings for its
+/// particle, keyed by [Particle].
+///
+/// Meshes must share the same geometry, i.e. the same bounds, origin, and
+/// orientation, since a wwout file can only have one mesh. Energy and time
+/// groups may differ between particle types.
+///
+/// The particle type of each set comes from the key. Meshes with a known
+/// particle type must match their key, while unknown types (e.g. from a
+/// TMESH without particle information) take the key.
+///
+/// ```rust, no_run
+/// # use std::collections::BTreeMap;
+/// # use ntools_mesh::{read_target, Particle};
+/// # use ntools_wwgen::{meshes_to_ww, WwGenerator};
+/// let neutron = read_target("/path/to/meshtal.msht", 104).unwrap();
+/// let photon = read_target("/path/to/meshtal.msht", 204).unwrap();
+///
+/// // Softer windows for the photons
+/// let n_settings = WwGenerator::builder().power(0.7).max_error(0.1).build();
+/// let p_settings = WwGenerator::builder().power(0.5).max_error(0.2).build();
+///
+/// let meshes = BTreeMap::from([
+///     (Particle::Neutron, (&neutron, &n_settings)),
+///     (Particle::Photon, (&photon, &p_settings)),
+/// ]);
+///
+/// // Generate and write a combined NP wwout file
+/// let weights = meshes_to_ww(&meshes).unwrap();
+/// weights.write("wwout_NP", false).unwrap();
+/// ```
+pub fn meshes_to_ww(
+    meshes: &BTreeMap<Particle, (&Mesh, &WwGenerator)>,
+) -> Result<MultiParticleWeights> {
+    let Some((_, (reference, _))) = meshes.first_key_value() else {
+        return Err(invalid("no meshes provided"));
+    };
+
+    let mut weight_windows = Vec::with_capacity(meshes.len());
+
+    for (particle, (mesh, generator)) in meshes {
+        check_particle(*particle, mesh)?;
+        check_geometry(reference, mesh, *particle)?;
+
+        let mut ww = generator.generate(mesh)?;
+        ww.particle = particle.id();
+
+        // should never happen, but never write an invalid file
+        if let Some(issue) = ww.diagnostics().issues.first() {
+            return Err(invalid(&f!("{particle:?} weights are invalid, {issue}")));
+        }
+
+        weight_windows.push(ww);
+    }
+
+    Ok(MultiParticleWeights { weight_windows })
+}
+
+/// The key and mesh particle types must agree
+fn check_particle(particle: Particle, mesh: &Mesh) -> Result<()> {
+    if particle == Particle::Unknown {
+        return Err(invalid(&f!("unknown particle type for mesh {}", mesh.id)));
+    }
+
+    match mesh.particle == particle || mesh.particle == Particle::Unknown {
+        true => Ok(()),
+        false => Err(invalid(&f!(
+            "mesh {} is for {:?}, not {:?}",
+            mesh.id,
+            mesh.particle,
+            particle
+        ))),
+    }
+}
+
+/// A wwout file only has one mesh geometry for every particle
+fn check_geometry(reference: &Mesh, mesh: &Mesh, particle: Particle) -> Result<()> {
+    let is_match = reference.geometry == mesh.geometry
+        && reference.imesh == mesh.imesh
+        && reference.jmesh == mesh.jmesh
+        && reference.kmesh == mesh.kmesh
+        && reference.origin == mesh.origin
+        && reference.axs == mesh.axs
+        && reference.vec == mesh.vec;
+
+    match is_match {
+        true => Ok(()),
+        false => Err(invalid(&f!(
+            "geometry of the {:?} mesh {} does not match mesh {}",
+            particle,
+            mesh.id,
+            reference.id
+        ))),
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidMultiParticle {
+        reason: reason.to_string(),
+    }
+}
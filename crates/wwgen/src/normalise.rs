@@ -0,0 +1,178 @@
+// neutronics toolbox
+use ntools_mesh::{BoundaryTreatment, Mesh, Point, Voxel};
+use ntools_utils::f;
+
+// internal modules
+use crate::error::{Error, Result};
+
+// external crates
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Reference flux each energy/time group is normalised to
+///
+/// MAGIC weights are proportional to the flux relative to some reference flux,
+/// softened by the power factor. By default the reference is the maximum flux
+/// of each group, which is not necessarily where the source particles start.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Reference {
+    /// Maximum flux in the group (default)
+    #[default]
+    Maximum,
+    /// Percentile of the non-zero fluxes in the group, from 0-100
+    ///
+    /// Less sensitive than the maximum to a few voxels with very high flux,
+    /// such as those right next to a point source.
+    Percentile(f64),
+    /// Flux at a point in the mesh, typically the source location
+    ///
+    /// The energy and time groups of the [Point] are ignored, and the flux in
+    /// each group at the location is used instead. Use
+    /// [Point::from_ijk](ntools_mesh::Point::from_ijk) to reference a voxel
+    /// directly.
+    Point(Point),
+    /// A constant user-defined reference flux for every group
+    Constant(f64),
+}
+
+/// Normalisation used to turn fluxes into MAGIC weights
+///
+/// The `reference` sets the flux of each group that weights are relative to.
+///
+/// Without a `target`, weights are calculated in the usual way as
+/// `(0.5 * (flux / flux_ref)).powf(power)`. Providing a target weight instead
+/// calculates weights as `target * (flux / flux_ref).powf(power)`, so that the
+/// weight at the reference is exactly the target.
+///
+/// Note that the power is only applied to the flux ratio with a target. The
+/// usual calculation also softens the factor of 0.5, so the weight at the
+/// reference is `0.5.powf(power)` rather than 0.5. A target of
+/// `0.5.powf(power)` therefore gives exactly the same weights as no target.
+///
+/// For example, to ensure the lower weight bound at a source location is
+/// consistent with a source particle weight of 1:
+///
+/// ```rust
+/// # use ntools_mesh::Point;
+/// # use ntools_wwgen::{Normalisation, Reference};
+/// let normalisation = Normalisation {
+///     reference: Reference::Point(Point::from_xyz(0.0, 0.0, 10.0)),
+///     target: Some(0.5),
+/// };
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Normalisation {
+    /// Weight at the reference flux, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<f64>,
+    /// Reference flux of each group
+    pub reference: Reference,
+}
+
+impl Normalisation {
+    /// Check the normalisation parameters make sense before use
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(target) = self.target {
+            if !(target > 0.0 && target.is_finite()) {
+                return Err(invalid(&f!("target weight {target} must be positive")));
+            }
+        }
+
+        match &self.reference {
+            Reference::Percentile(p) if !(*p > 0.0 && *p <= 100.0) => {
+                Err(invalid(&f!("percentile {p} must be within (0, 100]")))
+            }
+            Reference::Constant(c) if !(*c > 0.0 && c.is_finite()) => {
+                Err(invalid(&f!("reference flux {c} must be positive")))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reference flux for the voxels of an energy/time group
+    ///
+    /// Falls back to the group maximum if the reference flux is zero, for
+    /// example when no particles in the group reach the source point.
+    pub(crate) fn flux(
+        &self,
+        mesh: &Mesh,
+        e_idx: usize,
+        t_idx: usize,
+        voxels: &[Voxel],
+    ) -> Result<f64> {
+        let maximum = maximum(voxels);
+        let flux = match &self.reference {
+            Reference::Maximum => maximum,
+            Reference::Percentile(p) => percentile(voxels, *p),
+            Reference::Constant(c) => *c,
+            Reference::Point(point) => {
+                let point = Point {
+                    e: mesh.energy_group_from_index(e_idx)?,
+                    t: mesh.time_group_from_index(t_idx)?,
+                    ..point.clone()
+                };
+
+                match mesh.find_point_data(point.clone(), BoundaryTreatment::default()) {
+                    Some((result, _)) => result,
+                    None => {
+                        return Err(invalid(&f!(
+                            "reference point ({}, {}, {}) is outside the mesh",
+                            point.i,
+                            point.j,
+                            point.k
+                        )))
+                    }
+                }
+            }
+        };
+
+        if flux <= 0.0 && maximum > 0.0 {
+            warn!("Warning: Zero reference flux for group (e={e_idx}, t={t_idx})");
+            warn!("  - Normalising to the group maximum instead");
+            return Ok(maximum);
+        }
+
+        Ok(flux)
+    }
+
+    /// Weight for a flux relative to the reference flux
+    pub(crate) fn weight(&self, flux: f64, flux_ref: f64, power: f64) -> f64 {
+        match self.target {
+            Some(target) => target * (flux / flux_ref).powf(power),
+            None => (0.5 * (flux / flux_ref)).powf(power),
+        }
+    }
+}
+
+/// Maximum result of a group, or 0 for an empty group
+pub(crate) fn maximum(voxels: &[Voxel]) -> f64 {
+    voxels
+        .iter()
+        .map(|v| v.result)
+        .max_by(|a, b| a.total_cmp(b))
+        .unwrap_or(0.0)
+}
+
+/// Nearest-rank percentile of the non-zero results
+fn percentile(voxels: &[Voxel], p: f64) -> f64 {
+    let mut results = voxels
+        .iter()
+        .map(|v| v.result)
+        .filter(|r| *r > 0.0)
+        .collect::<Vec<f64>>();
+
+    if results.is_empty() {
+        return 0.0;
+    }
+
+    results.sort_by(|a, b| a.total_cmp(b));
+    let rank = (p / 100.0 * results.len() as f64).ceil() as usize;
+    results[rank.clamp(1, results.len()) - 1]
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidNormalisation {
+        reason: reason.to_string(),
+    }
+}
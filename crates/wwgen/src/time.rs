@@ -0,0 +1,161 @@
+// neutronics toolbox
+use ntools_mesh::Mesh;
+use ntools_utils::f;
+use ntools_weights::WeightWindow;
+
+// internal modules
+use crate::error::{Error, Result};
+
+// external crates
+use serde::{Deserialize, Serialize};
+
+/// Treatment of time groups for time-dependent weight windows
+///
+/// By default every energy/time group is normalised independently, which is
+/// fine for steady state problems. For pulsed sources and shutdown problems the
+/// weights of a voxel should change smoothly from one time group to the next.
+///
+/// - `normalise_all_times` - every time group of an energy group shares one
+///   reference flux, the largest of the individual time groups
+/// - `max_ratio` - limits the ratio between the weights of a voxel in
+///   consecutive time groups, raising the lower weight
+/// - `drop_empty` - removes trailing time groups without any flux, extending
+///   the last remaining group to the final time bound
+///
+/// Only relevant for meshes with multiple time groups, and ignored if weights
+/// are only generated from the totals.
+///
+/// ```rust
+/// # use ntools_wwgen::{TimeSettings, WwGenerator};
+/// let generator = WwGenerator::builder()
+///     .time(TimeSettings {
+///         normalise_all_times: true,
+///         max_ratio: Some(10.0),
+///         drop_empty: true,
+///     })
+///     .build();
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeSettings {
+    /// Normalise every time group to one reference flux per energy group
+    pub normalise_all_times: bool,
+    /// Maximum ratio between weights of consecutive time groups
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_ratio: Option<f64>,
+    /// Remove trailing time groups without any flux
+    pub drop_empty: bool,
+}
+
+impl TimeSettings {
+    /// Check the parameters make sense before use
+    pub(crate) fn validate(&self) -> Result<()> {
+        match self.max_ratio {
+            Some(ratio) if !(ratio >= 1.0 && ratio.is_finite()) => {
+                Err(invalid(&f!("time ratio limit {ratio} must be at least 1")))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply the ratio limit and drop empty groups after generation
+    pub(crate) fn apply(&self, mesh: &Mesh, ww: &mut WeightWindow) {
+        if let Some(ratio) = self.max_ratio {
+            limit_time_ratio(ww, ratio);
+        }
+
+        if self.drop_empty {
+            drop_empty_times(mesh, ww);
+        }
+    }
+}
+
+/// Cap the ratio between weights of a voxel in consecutive time groups
+///
+/// Lower weights are raised to the neighbouring weight divided by `max_ratio`.
+/// A forward then backward pass over time is enough for every voxel, since
+/// each only has two neighbours in time. Analogue weights are untouched.
+///
+/// Returns the number of weights that were changed.
+fn limit_time_ratio(ww: &mut WeightWindow, max_ratio: f64) -> usize {
+    let n_voxels = ww.nfx * ww.nfy * ww.nfz;
+    if ww.nt < 2 || ww.weights.len() != ww.ne * ww.nt * n_voxels {
+        return 0;
+    }
+
+    let mut changed = 0;
+    let mut limit = |weights: &mut [f64], from: usize, to: usize| {
+        let (a, b) = (weights[from], weights[to]);
+        if a > 0.0 && b > 0.0 && b < a / max_ratio {
+            weights[to] = a / max_ratio;
+            changed += 1;
+        }
+    };
+
+    // every energy group is a block of nt groups in time
+    for block in ww.weights.chunks_mut(ww.nt * n_voxels) {
+        for v in 0..n_voxels {
+            for t in 1..ww.nt {
+                limit(block, (t - 1) * n_voxels + v, t * n_voxels + v);
+            }
+            for t in (1..ww.nt).rev() {
+                limit(block, t * n_voxels + v, (t - 1) * n_voxels + v);
+            }
+        }
+    }
+
+    changed
+}
+
+/// Remove trailing time groups where the mesh has no flux at all
+///
+/// The last remaining group takes the final time bound, so particles at late
+/// times still have weights. Returns the number of groups removed.
+fn drop_empty_times(mesh: &Mesh, ww: &mut WeightWindow) -> usize {
+    let n_voxels = ww.nfx * ww.nfy * ww.nfz;
+    if ww.nt < 2 || ww.t.len() != ww.nt || ww.weights.len() != ww.ne * ww.nt * n_voxels {
+        return 0;
+    }
+
+    // the total energy group has flux if any energy group does
+    let e_total = mesh.n_ebins() - 1;
+    let is_empty = |t_idx: usize| {
+        mesh.voxels_by_group_index(e_total, t_idx)
+            .is_ok_and(|voxels| voxels.iter().all(|v| v.result == 0.0))
+    };
+
+    let mut n_keep = ww.nt;
+    while n_keep > 1 && is_empty(n_keep - 1) {
+        n_keep -= 1;
+    }
+
+    let n_dropped = ww.nt - n_keep;
+    if n_dropped == 0 {
+        return 0;
+    }
+
+    ww.weights = ww
+        .weights
+        .chunks(ww.nt * n_voxels)
+        .flat_map(|block| block[..n_keep * n_voxels].iter().copied())
+        .collect();
+
+    let last = ww.t[ww.nt - 1];
+    ww.t.truncate(n_keep);
+    ww.t[n_keep - 1] = last;
+    ww.nt = n_keep;
+
+    // a single time group is the same as no time dependence
+    if ww.nt == 1 {
+        ww.iv = 1;
+        ww.t.clear();
+    }
+
+    n_dropped
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidGenerator {
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,355 @@
+// standard library
+use std::path::Path;
+
+// neutronics toolbox
+use ntools_mesh::Mesh;
+use ntools_utils::{f, ValueExt};
+use ntools_weights::WeightWindow;
+
+// internal modules
+use crate::error::{Error, Result};
+use crate::generator::{FileFormat, WwGenerator};
+
+// external crates
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Iterative MAGIC workflow with a persistent history
+///
+/// The MAGIC method is iterative. Each MCNP run produces a flux mesh used to
+/// generate weight windows for the next run, and the fraction of non-analogue
+/// weights should grow with every iteration until the whole problem is
+/// covered.
+///
+/// A [MagicWorkflow] keeps a history of every [Iteration], decides the
+/// [WwGenerator] settings for the next run from the [Schedule], and is saved to
+/// a TOML or JSON file between runs.
+///
+/// ```rust, no_run
+/// # use ntools_mesh::read_target;
+/// # use ntools_wwgen::{MagicWorkflow, WwGenerator};
+/// // First iteration, starting from a soft set of windows
+/// let generator = WwGenerator::builder().power(0.5).max_error(0.1).build();
+/// let mut workflow = MagicWorkflow::new(generator);
+///
+/// let mesh = read_target("./run_0/meshtal.msht", 104).unwrap();
+/// workflow.iterate(&mesh).unwrap().write("./run_1/wwinp");
+/// workflow.save("workflow.toml").unwrap();
+///
+/// // ...after the next MCNP run completes
+/// let mut workflow = MagicWorkflow::load("workflow.toml").unwrap();
+/// let mesh = read_target("./run_1/meshtal.msht", 104).unwrap();
+/// workflow.iterate(&mesh).unwrap().write("./run_2/wwinp");
+/// workflow.save("workflow.toml").unwrap();
+///
+/// if workflow.is_converged() {
+///     println!("Coverage target reached");
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MagicWorkflow {
+    /// Generator settings for the next iteration
+    pub generator: WwGenerator,
+    /// Rules for updating the generator between iterations
+    pub schedule: Schedule,
+    /// Record of every iteration so far
+    pub history: Vec<Iteration>,
+}
+
+/// Rules for updating generator settings between iterations
+///
+/// Progress is measured by the gain in coverage, i.e. the percentage of
+/// non-analogue weights, over the previous iteration.
+///
+/// - Coverage at or above `target_coverage` keeps the current settings
+/// - A gain of at least `min_gain` raises the power by `power_step`, up to
+///   `max_power`, as the windows are reliable enough to be applied more
+///   strongly
+/// - A smaller gain is a stall, and relaxes the error tolerance by a factor
+///   of `error_step`, up to `error_limit`, to include more of the mesh
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Schedule {
+    /// Percentage of non-analogue weights considered converged
+    pub target_coverage: f64,
+    /// Minimum coverage gain (percentage points) counted as progress
+    pub min_gain: f64,
+    /// Increase in the power after progress is made
+    pub power_step: f64,
+    /// Upper limit on the power
+    pub max_power: f64,
+    /// Multiplier applied to the error tolerance after a stall
+    pub error_step: f64,
+    /// Upper limit on the error tolerance
+    pub error_limit: f64,
+}
+
+/// Record of a single iteration of the workflow
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Iteration {
+    /// Iteration number, starting from 0
+    pub index: usize,
+    /// Default power used to generate the weights
+    pub power: f64,
+    /// Default error tolerance used to generate the weights
+    pub max_error: f64,
+    /// Summary of the flux mesh the weights were generated from
+    pub mesh: MeshSummary,
+    /// Percentage of non-analogue weights across all groups
+    pub coverage: f64,
+    /// Coverage and diagnostics for each energy/time group
+    pub groups: Vec<GroupCoverage>,
+}
+
+/// Summary of the flux mesh used for an iteration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeshSummary {
+    /// Tally number
+    pub id: u32,
+    /// Particle type id
+    pub particle: u8,
+    /// Number of voxels in the mesh
+    pub n_voxels: usize,
+    /// Maximum result in the mesh
+    pub maximum: f64,
+    /// Average result in the mesh
+    pub average: f64,
+    /// Percentage of voxels with a non-zero result
+    pub non_zero_percentage: f64,
+}
+
+/// Coverage and diagnostics of a single energy/time group
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupCoverage {
+    /// Energy group index
+    pub energy_idx: usize,
+    /// Time group index
+    pub time_idx: usize,
+    /// Upper energy bound of the group
+    pub energy: f64,
+    /// Upper time bound of the group, if time dependent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<f64>,
+    /// Percentage of non-analogue weights in the group
+    pub non_analogue_percentage: f64,
+    /// Ratio of the largest to smallest non-zero weight
+    pub dynamic_range: f64,
+    /// Largest ratio between the weights of adjacent voxels
+    pub max_adjacent_ratio: f64,
+}
+
+// Public API
+impl MagicWorkflow {
+    /// Start a new workflow from initial generator settings
+    pub fn new(generator: WwGenerator) -> MagicWorkflow {
+        MagicWorkflow {
+            generator,
+            ..Default::default()
+        }
+    }
+
+    /// Generate weights from the latest flux mesh and record the iteration
+    ///
+    /// Weights are generated with the current settings, the iteration is
+    /// added to the history, and the generator is updated for the next
+    /// iteration according to the [Schedule].
+    pub fn iterate(&mut self, mesh: &Mesh) -> Result<WeightWindow> {
+        self.schedule.validate()?;
+        let ww = self.generator.generate(mesh)?;
+
+        let iteration = Iteration {
+            index: self.history.len(),
+            power: self.generator.power,
+            max_error: self.generator.max_error,
+            mesh: MeshSummary::from_mesh(mesh),
+            coverage: ww.non_analogue_percentage(),
+            groups: group_coverage(&ww),
+        };
+
+        info!(
+            "Iteration {}: {}% non-analogue",
+            iteration.index,
+            iteration.coverage.sci(5, 2)
+        );
+
+        self.history.push(iteration);
+        self.update_generator();
+        Ok(ww)
+    }
+
+    /// The most recent iteration, if any
+    pub fn latest(&self) -> Option<&Iteration> {
+        self.history.last()
+    }
+
+    /// Coverage gain of the most recent iteration over the one before it
+    pub fn gain(&self) -> Option<f64> {
+        match self.history.as_slice() {
+            [.., previous, latest] => Some(latest.coverage - previous.coverage),
+            _ => None,
+        }
+    }
+
+    /// Coverage of the most recent iteration has reached the target
+    pub fn is_converged(&self) -> bool {
+        self.latest()
+            .is_some_and(|i| i.coverage >= self.schedule.target_coverage)
+    }
+
+    /// Read a workflow from a TOML string
+    pub fn from_toml(s: &str) -> Result<MagicWorkflow> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Write the workflow to a TOML string
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Read a workflow from a JSON string
+    pub fn from_json(s: &str) -> Result<MagicWorkflow> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    /// Write the workflow to a JSON string
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Load the workflow state from a `.toml` or `.json` file
+    pub fn load(path: impl AsRef<Path>) -> Result<MagicWorkflow> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path)?;
+        match FileFormat::from_path(path)? {
+            FileFormat::Toml => Self::from_toml(&s),
+            FileFormat::Json => Self::from_json(&s),
+        }
+    }
+
+    /// Save the workflow state to a `.toml` or `.json` file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let s = match FileFormat::from_path(path)? {
+            FileFormat::Toml => self.to_toml()?,
+            FileFormat::Json => self.to_json()?,
+        };
+        Ok(std::fs::write(path, s)?)
+    }
+}
+
+// Private implementations
+impl MagicWorkflow {
+    /// Decide the default power and error tolerance for the next iteration
+    fn update_generator(&mut self) {
+        if self.is_converged() {
+            return;
+        }
+
+        // nothing to compare against on the first iteration
+        let Some(gain) = self.gain() else {
+            return;
+        };
+
+        let schedule = &self.schedule;
+        let generator = &mut self.generator;
+
+        if gain >= schedule.min_gain {
+            generator.power = (generator.power + schedule.power_step).min(schedule.max_power);
+        } else if generator.max_error < schedule.error_limit {
+            generator.max_error =
+                (generator.max_error * schedule.error_step).min(schedule.error_limit);
+        } else {
+            warn!("Warning: Coverage has stalled at the error limit");
+            warn!(
+                "  - Gain of {} with max error {}",
+                gain.sci(5, 2),
+                generator.max_error.sci(5, 2)
+            );
+        }
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            target_coverage: 95.0,
+            min_gain: 1.0,
+            power_step: 0.1,
+            max_power: 1.0,
+            error_step: 1.5,
+            error_limit: 1.0,
+        }
+    }
+}
+
+impl Schedule {
+    /// Check the schedule parameters make sense before use
+    fn validate(&self) -> Result<()> {
+        if !(0.0..=100.0).contains(&self.target_coverage) {
+            return Err(invalid(&f!(
+                "target coverage {} must be within [0, 100]",
+                self.target_coverage
+            )));
+        }
+
+        if !(self.error_step >= 1.0 && self.error_step.is_finite()) {
+            return Err(invalid(&f!(
+                "error step {} must be at least 1",
+                self.error_step
+            )));
+        }
+
+        if !(self.power_step >= 0.0 && self.max_power > 0.0) {
+            return Err(invalid(&f!(
+                "power step {} and max power {} must not be negative",
+                self.power_step,
+                self.max_power
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl MeshSummary {
+    fn from_mesh(mesh: &Mesh) -> MeshSummary {
+        let n_voxels = mesh.voxels.len();
+        let non_zero = mesh.voxels.iter().filter(|v| v.result > 0.0).count();
+
+        MeshSummary {
+            id: mesh.id,
+            particle: mesh.particle as u8,
+            n_voxels,
+            maximum: mesh.try_maximum().map(|(v, _)| v).unwrap_or(0.0),
+            average: mesh.try_average().map(|(v, _)| v).unwrap_or(0.0),
+            non_zero_percentage: match n_voxels {
+                0 => 0.0,
+                n => 100.0 * non_zero as f64 / n as f64,
+            },
+        }
+    }
+}
+
+/// Collect the coverage of every group from the weight window diagnostics
+fn group_coverage(ww: &WeightWindow) -> Vec<GroupCoverage> {
+    ww.diagnostics()
+        .groups
+        .into_iter()
+        .map(|g| GroupCoverage {
+            energy_idx: g.energy_idx,
+            time_idx: g.time_idx,
+            energy: g.energy,
+            time: g.time,
+            non_analogue_percentage: g.non_analogue_percentage,
+            dynamic_range: g.dynamic_range,
+            max_adjacent_ratio: g.max_adjacent_ratio,
+        })
+        .collect()
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidWorkflow {
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,210 @@
+//! Integration tests for Build-Up Density Extrapolation on analytic slabs
+
+mod common;
+
+use common::slab;
+use ntools_mesh::Mesh;
+use ntools_wwgen::{extrapolate_density, extrapolate_density_steps, DensityStep};
+use rstest::rstest;
+
+/// Total macroscopic cross section of the slab at full density [1/cm]
+const SIGMA: f64 = 0.5;
+/// Log build-up per cm at full density
+const BUILDUP: f64 = 0.2;
+
+/// Slab of 1 cm voxels from 0 cm with a result for each voxel
+fn slab_cm(results: &[f64], error: f64) -> Mesh {
+    let bounds: Vec<f64> = (0..=results.len()).map(|i| i as f64).collect();
+    slab(&bounds, results, error)
+}
+
+/// Voxel centres through the slab
+fn depths() -> Vec<f64> {
+    (0..20).map(|i| i as f64 + 0.5).collect()
+}
+
+/// Void flux falling off with distance from a source at -1 cm
+fn void_flux() -> Vec<f64> {
+    depths().iter().map(|x| 1.0 / (1.0 + x).powi(2)).collect()
+}
+
+/// Uncollided flux at a fraction of the full density
+fn uncollided_flux(fraction: f64) -> Vec<f64> {
+    zip_depths(|x, vd| vd * (-fraction * SIGMA * x).exp())
+}
+
+/// Total flux at a fraction of the full density, ln(B) linear in density
+fn reduced_flux(fraction: f64) -> Vec<f64> {
+    zip_depths(|x, vd| vd * (-fraction * SIGMA * x).exp() * (fraction * BUILDUP * x).exp())
+}
+
+/// Analytic flux at full density with the build-up raised to gamma
+fn expected_flux(gamma: f64) -> Vec<f64> {
+    zip_depths(|x, vd| vd * (-SIGMA * x).exp() * (gamma * BUILDUP * x).exp())
+}
+
+fn zip_depths(f: impl Fn(f64, f64) -> f64) -> Vec<f64> {
+    depths()
+        .into_iter()
+        .zip(void_flux())
+        .map(|(x, vd)| f(x, vd))
+        .collect()
+}
+
+/// Voxel results agree with the analytic flux to a relative tolerance
+fn assert_flux(mesh: &Mesh, expected: &[f64]) {
+    for (voxel, expected) in mesh.voxels.iter().zip(expected) {
+        let difference = (voxel.result - expected).abs() / expected;
+        assert!(
+            difference < 1e-10,
+            "voxel {}: {} != {}",
+            voxel.index,
+            voxel.result,
+            expected
+        );
+    }
+}
+
+#[rstest]
+#[case(0.1)]
+#[case(0.25)]
+#[case(0.5)]
+fn single_step_slab(#[case] fraction: f64) {
+    let vd = slab_cm(&void_flux(), 0.0);
+    let rd = slab_cm(&reduced_flux(fraction), 0.0);
+    let uc = slab_cm(&uncollided_flux(fraction), 0.0);
+
+    let forward = extrapolate_density(&vd, &rd, &uc, 1.0, 1.0 / fraction).unwrap();
+    assert_flux(&forward, &expected_flux(1.0));
+}
+
+#[rstest]
+#[case(0.5)]
+#[case(1.0)]
+#[case(1.5)]
+fn multiple_step_slab(#[case] gamma: f64) {
+    let vd = slab_cm(&void_flux(), 0.0);
+    let fractions = [0.1, 0.2, 0.4];
+    let reduced = fractions.map(|s| slab_cm(&reduced_flux(s), 0.0));
+    let uncollided = fractions.map(|s| slab_cm(&uncollided_flux(s), 0.0));
+
+    let steps = (0..fractions.len())
+        .map(|i| DensityStep {
+            fraction: fractions[i],
+            reduced: &reduced[i],
+            uncollided: &uncollided[i],
+        })
+        .collect::<Vec<DensityStep>>();
+
+    let forward = extrapolate_density_steps(&vd, &steps, gamma).unwrap();
+    assert_flux(&forward, &expected_flux(gamma));
+}
+
+#[rstest]
+#[case(1.0)]
+#[case(0.7)]
+fn single_step_error_propagation(#[case] gamma: f64) {
+    let fraction: f64 = 0.2;
+    let vd = slab_cm(&void_flux(), 0.01);
+    let rd = slab_cm(&reduced_flux(fraction), 0.05);
+    let uc = slab_cm(&uncollided_flux(fraction), 0.02);
+
+    let forward = extrapolate_density(&vd, &rd, &uc, gamma, 1.0 / fraction).unwrap();
+
+    // forward = vd^(1-r) * uc^(r(1-gamma)) * rd^(r*gamma) with r = 1/s
+    let r = 1.0 / fraction;
+    let expected = (((1.0 - r) * 0.01_f64).powi(2)
+        + (r * (1.0 - gamma) * 0.02_f64).powi(2)
+        + (r * gamma * 0.05_f64).powi(2))
+    .sqrt();
+
+    for voxel in &forward.voxels {
+        assert!((voxel.error - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn multiple_steps_reduce_error() {
+    let vd = slab_cm(&void_flux(), 0.0);
+    let rd_10 = slab_cm(&reduced_flux(0.1), 0.05);
+    let uc_10 = slab_cm(&uncollided_flux(0.1), 0.05);
+    let rd_20 = slab_cm(&reduced_flux(0.2), 0.05);
+    let uc_20 = slab_cm(&uncollided_flux(0.2), 0.05);
+
+    let single = extrapolate_density(&vd, &rd_10, &uc_10, 1.0, 10.0).unwrap();
+    let multiple = extrapolate_density_steps(
+        &vd,
+        &[
+            DensityStep {
+                fraction: 0.1,
+                reduced: &rd_10,
+                uncollided: &uc_10,
+            },
+            DensityStep {
+                fraction: 0.2,
+                reduced: &rd_20,
+                uncollided: &uc_20,
+            },
+        ],
+        1.0,
+    )
+    .unwrap();
+
+    assert!(multiple.voxels[0].error < single.voxels[0].error);
+}
+
+#[test]
+fn zero_and_nan_voxels() {
+    let mut void = void_flux();
+    void[3] = 0.0;
+    let mut reduced = reduced_flux(0.1);
+    reduced[5] = f64::NAN;
+    let mut uncollided = uncollided_flux(0.1);
+    uncollided[7] = -1.0;
+
+    let vd = slab_cm(&void, 0.0);
+    let rd = slab_cm(&reduced, 0.0);
+    let uc = slab_cm(&uncollided, 0.0);
+
+    let forward = extrapolate_density(&vd, &rd, &uc, 1.0, 10.0).unwrap();
+    let expected = expected_flux(1.0);
+
+    for voxel in &forward.voxels {
+        match voxel.index {
+            3 | 5 | 7 => assert_eq!((voxel.result, voxel.error), (0.0, 0.0)),
+            i => assert!((voxel.result - expected[i]).abs() / expected[i] < 1e-10),
+        }
+    }
+}
+
+#[test]
+fn incompatible_meshes() {
+    let vd = slab_cm(&void_flux(), 0.0);
+    let rd = slab_cm(&reduced_flux(0.1), 0.0);
+    let uc = slab_cm(&uncollided_flux(0.1), 0.0);
+
+    // different energy groups
+    let mut other = uc.clone();
+    other.emesh = vec![0.0, 20.0];
+    assert!(extrapolate_density(&vd, &rd, &other, 1.0, 10.0).is_err());
+
+    // different geometry
+    let mut other = rd.clone();
+    other.imesh[1] = 1.5;
+    assert!(extrapolate_density(&vd, &other, &uc, 1.0, 10.0).is_err());
+
+    // different number of voxels
+    let other = slab_cm(&reduced_flux(0.1)[1..], 0.0);
+    assert!(extrapolate_density(&vd, &other, &uc, 1.0, 10.0).is_err());
+}
+
+#[test]
+fn invalid_parameters() {
+    let vd = slab_cm(&void_flux(), 0.0);
+    let rd = slab_cm(&reduced_flux(0.1), 0.0);
+    let uc = slab_cm(&uncollided_flux(0.1), 0.0);
+
+    assert!(extrapolate_density(&vd, &rd, &uc, 1.0, 0.5).is_err());
+    assert!(extrapolate_density(&vd, &rd, &uc, f64::NAN, 10.0).is_err());
+    assert!(extrapolate_density_steps(&vd, &[], 1.0).is_err());
+}
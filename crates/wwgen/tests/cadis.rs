@@ -0,0 +1,209 @@
+//! Integration tests for CADIS and FW-CADIS
+
+mod common;
+
+use common::{assert_close, read_mesh};
+use ntools_mesh::Mesh;
+use ntools_weights::WeightWindow;
+use ntools_wwgen::{cadis, fw_cadis_adjoint_source, Cadis, FwCadisResponse, Source};
+use rstest::rstest;
+
+const RATIO: f64 = 5.0;
+
+/// Adjoint mesh with a constant importance in each energy group, no errors
+fn adjoint(importance: [f64; 3]) -> Mesh {
+    let mut mesh = read_mesh(114);
+    let per_energy = mesh.n_tbins() * mesh.n_voxels_per_group();
+    for (i, voxel) in mesh.voxels.iter_mut().enumerate() {
+        voxel.result = importance[i / per_energy];
+        voxel.error = 0.0;
+    }
+    mesh
+}
+
+/// Two point sources emitting into both energy groups of the fixture mesh
+fn source() -> Source {
+    Source {
+        positions: vec![[2.0, 1.0, 2.0], [10.0, 5.0, 12.0]],
+        strengths: vec![1.0, 3.0],
+        energies: vec![0.0, 1.0, 100.0],
+        spectrum: vec![1.0, 1.0],
+    }
+}
+
+/// Group of voxels in an (e, t) block of a mesh
+fn block(mesh: &Mesh, e: usize, t: usize) -> Vec<f64> {
+    let n = mesh.n_voxels_per_group();
+    let start = (e * mesh.n_tbins() + t) * n;
+    mesh.voxels[start..start + n]
+        .iter()
+        .map(|v| v.result)
+        .collect()
+}
+
+#[test]
+fn uniform_adjoint() {
+    let result = cadis(&adjoint([2.0, 2.0, 4.0]), &source(), RATIO, 0.1).unwrap();
+
+    // every source particle has the same importance
+    assert!((result.response - 2.0).abs() < 1e-12);
+    assert_close(&result.position_bias, &[0.25, 0.75]);
+    assert_close(&result.energy_bias[0], &[0.5, 0.5]);
+    assert_close(&result.energy_bias[1], &[0.5, 0.5]);
+
+    // the unbiased source is normalised
+    assert_close(&result.source.strengths, &[0.25, 0.75]);
+    assert_close(&result.source.spectrum, &[0.5, 0.5]);
+
+    // lower bounds of R / adjoint for a window centred on the target weight
+    let lower = 2.0 / 2.0 * 2.0 / (1.0 + RATIO);
+    let ww = &result.weight_window;
+    assert_eq!((ww.ne, ww.nt), (2, 3));
+    assert_eq!(ww.weights.len(), ww.n_weights_expected());
+    assert!(ww.weights.iter().all(|w| (w - lower).abs() < 1e-12));
+}
+
+#[test]
+fn energy_dependent_adjoint() {
+    let result = cadis(&adjoint([1.0, 3.0, 4.0]), &source(), RATIO, 0.1).unwrap();
+
+    // half the source in each group, with importances 1 and 3
+    assert!((result.response - 2.0).abs() < 1e-12);
+    assert_close(&result.position_bias, &[0.25, 0.75]);
+    assert_close(&result.energy_bias[0], &[0.25, 0.75]);
+    assert_close(&result.energy_bias[1], &[0.25, 0.75]);
+
+    // lower weight bounds are inversely proportional to the importance
+    let ww = &result.weight_window;
+    let n = ww.nfx * ww.nfy * ww.nfz * ww.nt;
+    let target = 2.0 * 2.0 / (1.0 + RATIO);
+    assert!(ww.weights[..n].iter().all(|w| (w - target).abs() < 1e-12));
+    assert!(ww.weights[n..]
+        .iter()
+        .all(|w| (w - target / 3.0).abs() < 1e-12));
+}
+
+#[test]
+fn unimportant_errors() {
+    // the adjoint errors are above the tolerance
+    let mut mesh = adjoint([1.0, 3.0, 4.0]);
+    mesh.voxels.iter_mut().for_each(|v| v.error = 0.5);
+    let result = cadis(&mesh, &source(), RATIO, 0.1).unwrap();
+    assert!(result.weight_window.weights.iter().all(|w| *w == 0.0));
+
+    // nothing is important to the response
+    assert!(cadis(&adjoint([0.0; 3]), &source(), RATIO, 0.1).is_err());
+
+    // the source is outside of the mesh
+    let source = Source {
+        positions: vec![[-100.0, 0.0, 0.0]],
+        strengths: vec![1.0],
+        ..source()
+    };
+    assert!(cadis(&adjoint([1.0; 3]), &source, RATIO, 0.1).is_err());
+}
+
+#[test]
+fn sdef_cards() {
+    let result = Cadis {
+        weight_window: WeightWindow {
+            particle: 2,
+            ..Default::default()
+        },
+        response: 2.5,
+        source: Source {
+            positions: vec![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]],
+            strengths: vec![0.25, 0.25, 0.5],
+            energies: vec![0.0, 1.0, 14.0],
+            spectrum: vec![0.2, 0.8],
+        },
+        position_bias: vec![0.5, 0.25, 0.25],
+        energy_bias: vec![vec![0.5, 0.5], vec![0.1, 0.9], vec![0.2, 0.8]],
+    };
+
+    let expected = "\
+c CADIS biased source, estimated response 2.50000e+00
+SDEF PAR=2 POS=D1 ERG=FPOS=D2
+SI1 L 1.00000e+00 2.00000e+00 3.00000e+00 4.00000e+00 5.00000e+00 6.00000e+00
+      7.00000e+00 8.00000e+00 9.00000e+00
+SP1 2.50000e-01 2.50000e-01 5.00000e-01
+SB1 5.00000e-01 2.50000e-01 2.50000e-01
+DS2 S 3 4 5
+SI3 H 0.00000e+00 1.00000e+00 1.40000e+01
+SP3 D 0.00000e+00 2.00000e-01 8.00000e-01
+SB3 D 0.00000e+00 5.00000e-01 5.00000e-01
+SI4 H 0.00000e+00 1.00000e+00 1.40000e+01
+SP4 D 0.00000e+00 2.00000e-01 8.00000e-01
+SB4 D 0.00000e+00 1.00000e-01 9.00000e-01
+SI5 H 0.00000e+00 1.00000e+00 1.40000e+01
+SP5 D 0.00000e+00 2.00000e-01 8.00000e-01
+SB5 D 0.00000e+00 2.00000e-01 8.00000e-01
+";
+
+    assert_eq!(result.sdef(), expected);
+}
+
+#[rstest]
+fn adjoint_source(
+    #[values(FwCadisResponse::Flux, FwCadisResponse::TotalFlux)] response: FwCadisResponse,
+) {
+    let forward = read_mesh(114);
+    let source = fw_cadis_adjoint_source(&forward, response).unwrap();
+    let (n_ebins, n_tbins) = (forward.n_ebins(), forward.n_tbins());
+
+    // normalised to a maximum of 1
+    let maximum = source.voxels.iter().map(|v| v.result).fold(0.0, f64::max);
+    assert_eq!(maximum, 1.0);
+
+    // every energy/time group is proportional to the inverse of its response
+    let mut scale = None;
+    for e in 0..n_ebins - 1 {
+        for t in 0..n_tbins - 1 {
+            let flux = match response {
+                FwCadisResponse::Flux => block(&forward, e, t),
+                FwCadisResponse::TotalFlux => block(&forward, n_ebins - 1, t),
+            };
+
+            for (s, f) in block(&source, e, t).iter().zip(flux) {
+                match f > 0.0 {
+                    true => {
+                        let c = *scale.get_or_insert(s * f);
+                        assert!((s * f - c).abs() < 1e-12 * c);
+                    }
+                    false => assert_eq!(*s, 0.0),
+                }
+            }
+        }
+    }
+
+    // total groups are the sum of the groups they include
+    for t in 0..n_tbins {
+        let sum = (0..n_ebins - 1).fold(vec![0.0; forward.n_voxels_per_group()], |sum, e| {
+            sum.iter()
+                .zip(block(&source, e, t))
+                .map(|(a, b)| a + b)
+                .collect()
+        });
+        assert_close(&block(&source, n_ebins - 1, t), &sum);
+    }
+
+    for e in 0..n_ebins {
+        let sum = (0..n_tbins - 1).fold(vec![0.0; forward.n_voxels_per_group()], |sum, t| {
+            sum.iter()
+                .zip(block(&source, e, t))
+                .map(|(a, b)| a + b)
+                .collect()
+        });
+        assert_close(&block(&source, e, n_tbins - 1), &sum);
+    }
+}
+
+#[test]
+fn total_flux_shared_by_energy_groups() {
+    let forward = read_mesh(114);
+    let source = fw_cadis_adjoint_source(&forward, FwCadisResponse::TotalFlux).unwrap();
+
+    for t in 0..forward.n_tbins() - 1 {
+        assert_eq!(block(&source, 0, t), block(&source, 1, t));
+    }
+}
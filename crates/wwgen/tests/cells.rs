@@ -0,0 +1,173 @@
+//! Integration tests for cell-based weight windows from cell flux tallies
+
+mod common;
+
+use common::{assert_close, slab};
+use ntools_mctal::{BinData, BinKind, Particle, Tally, TallyResult};
+use ntools_wwgen::{mesh_to_ww, tally_to_cell_ww, tally_to_cell_ww_advanced, Error};
+use rstest::rstest;
+
+/// Flux and error of three cells, one poorly converged, for two energy groups
+const GROUPS: [[(f64, f64); 3]; 2] = [
+    [(4.0, 0.01), (1.0, 0.02), (0.5, 0.5)],
+    [(2.0, 0.05), (8.0, 0.01), (0.1, 0.04)],
+];
+
+/// Energy or time bins with upper bounds, and a total bin if requested
+fn bins(bounds: &[f64], with_total: bool) -> BinData {
+    BinData {
+        number: bounds.len() + with_total as usize,
+        kind: match with_total {
+            true => BinKind::Total,
+            false => BinKind::None,
+        },
+        values: bounds.to_vec(),
+        ..Default::default()
+    }
+}
+
+/// F4 tally of cells 10, 20, and 30 with results ordered by cell, energy, time
+fn cell_tally(energy_bins: BinData, time_bins: BinData, results: Vec<(f64, f64)>) -> Tally {
+    Tally {
+        id: 14,
+        particles: vec![Particle::Neutron],
+        region_bins: BinData {
+            number: 3,
+            values: vec![10.0, 20.0, 30.0],
+            ..Default::default()
+        },
+        energy_bins,
+        time_bins,
+        results: results
+            .into_iter()
+            .map(|(value, error)| TallyResult { value, error })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Results of every cell for the energy groups, with an optional total
+fn energy_results(with_total: bool) -> Vec<(f64, f64)> {
+    (0..3)
+        .flat_map(|cell| {
+            let groups = GROUPS.iter().map(move |group| group[cell]);
+            let total = (GROUPS[0][cell].0 + GROUPS[1][cell].0, 0.01);
+            groups.chain(with_total.then_some(total))
+        })
+        .collect()
+}
+
+/// Weights from a mesh with one voxel per cell, for the same results
+fn mesh_weights(results: &[(f64, f64)], power: f64, max_error: f64) -> Vec<f64> {
+    let mut mesh = slab(&[0.0, 1.0, 2.0, 3.0], &[0.0; 3], 0.0);
+    for (voxel, (result, error)) in mesh.voxels.iter_mut().zip(results) {
+        voxel.result = *result;
+        voxel.error = *error;
+    }
+    mesh_to_ww(&mesh, power, max_error, false).weights
+}
+
+#[rstest]
+fn energy_groups(#[values(false, true)] with_total: bool) {
+    let tally = cell_tally(
+        bins(&[1.0, 20.0], with_total),
+        BinData::default(),
+        energy_results(with_total),
+    );
+
+    let ww = tally_to_cell_ww(&tally, 0.7, 0.1, false).unwrap();
+    assert_eq!(ww.cells, vec![10, 20, 30]);
+    assert_eq!(ww.e, vec![1.0, 20.0]);
+    assert_eq!(ww.n_groups(), 2);
+
+    // every group is normalised on its own, exactly as for a mesh
+    for (g, group) in GROUPS.iter().enumerate() {
+        let expected = mesh_weights(group, 0.7, 0.1);
+        assert_close(ww.group(g).unwrap(), &expected);
+    }
+    assert_eq!(ww.group(0).unwrap()[2], 0.0);
+}
+
+#[test]
+fn energy_group_parameters() {
+    let tally = cell_tally(
+        bins(&[1.0, 20.0], true),
+        BinData::default(),
+        energy_results(true),
+    );
+
+    let ww = tally_to_cell_ww_advanced(&tally, &[0.5, 1.0], &[1.0, 0.02]).unwrap();
+    assert_close(ww.group(0).unwrap(), &mesh_weights(&GROUPS[0], 0.5, 1.0));
+    assert_close(ww.group(1).unwrap(), &mesh_weights(&GROUPS[1], 1.0, 0.02));
+}
+
+#[test]
+fn total_only() {
+    let results = energy_results(true);
+    let tally = cell_tally(
+        bins(&[1.0, 20.0], true),
+        BinData::default(),
+        results.clone(),
+    );
+
+    let ww = tally_to_cell_ww(&tally, 0.7, 0.1, true).unwrap();
+    assert!(ww.e.is_empty());
+
+    let totals = results
+        .iter()
+        .skip(2)
+        .step_by(3)
+        .copied()
+        .collect::<Vec<_>>();
+    assert_close(&ww.weights, &mesh_weights(&totals, 0.7, 0.1));
+
+    // several energy bins with nothing to take a total from
+    let tally = cell_tally(
+        bins(&[1.0, 20.0], false),
+        BinData::default(),
+        energy_results(false),
+    );
+    assert!(tally_to_cell_ww(&tally, 0.7, 0.1, true).is_err());
+}
+
+#[test]
+fn time_bins_collapsed() {
+    // results for two time bins that should be ignored, then the time total
+    let ignored = [(1e10, 0.0), (1e-10, 0.9)];
+    let results = energy_results(true)
+        .into_iter()
+        .flat_map(|total| ignored.into_iter().chain([total]))
+        .collect::<Vec<(f64, f64)>>();
+
+    let tally = cell_tally(bins(&[1.0, 20.0], true), bins(&[1e5, 1e10], true), results);
+    let ww = tally_to_cell_ww(&tally, 0.7, 0.1, false).unwrap();
+    for (g, group) in GROUPS.iter().enumerate() {
+        assert_close(ww.group(g).unwrap(), &mesh_weights(group, 0.7, 0.1));
+    }
+
+    // several time bins with no total to collapse onto
+    let tally = Tally {
+        time_bins: bins(&[1e5, 1e10, 1e15], false),
+        ..tally
+    };
+    assert!(tally_to_cell_ww(&tally, 0.7, 0.1, false).is_err());
+}
+
+#[rstest]
+#[case::surface_tally(12, vec![10.0, 20.0, 30.0])]
+#[case::point_detector(15, vec![10.0, 20.0, 30.0])]
+#[case::union(14, vec![10.0, 0.0, 30.0])]
+#[case::negative(14, vec![10.0, -20.0, 30.0])]
+#[case::fractional(14, vec![10.0, 20.5, 30.0])]
+fn rejected_regions(#[case] id: u32, #[case] cells: Vec<f64>) {
+    let mut tally = cell_tally(
+        bins(&[1.0, 20.0], true),
+        BinData::default(),
+        energy_results(true),
+    );
+    tally.id = id;
+    tally.region_bins.values = cells;
+
+    let error = tally_to_cell_ww(&tally, 0.7, 0.1, false).unwrap_err();
+    assert!(matches!(error, Error::InvalidTally { .. }), "{error}");
+}
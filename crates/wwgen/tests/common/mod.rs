@@ -0,0 +1,59 @@
+//! Shared helpers for the integration tests
+
+// not every test uses every helper
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use ntools_mesh::{read_target, Mesh, Particle, Voxel};
+
+/// Read a mesh tally from the fixtures of the mesh crate
+pub fn read_mesh(id: u32) -> Mesh {
+    read_target(format!("../mesh/data/meshes/fmesh_{id}.msht"), id).unwrap()
+}
+
+/// Fresh directory for the output of a single test
+pub fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ntools_wwgen_{name}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Values agree to a relative tolerance
+pub fn assert_close(a: &[f64], b: &[f64]) {
+    assert_eq!(a.len(), b.len());
+    for (a, b) in a.iter().zip(b) {
+        assert!((a - b).abs() <= 1e-12 * b.abs(), "{a} != {b}");
+    }
+}
+
+/// Largest of the values, or 0.0 if there are none
+pub fn maximum(values: &[f64]) -> f64 {
+    values.iter().copied().fold(0.0, f64::max)
+}
+
+/// 1D neutron slab mesh with the given voxel bounds and results
+pub fn slab(bounds: &[f64], results: &[f64], error: f64) -> Mesh {
+    Mesh {
+        particle: Particle::Neutron,
+        imesh: bounds.to_vec(),
+        iints: results.len(),
+        jmesh: vec![0.0, 1.0],
+        jints: 1,
+        kmesh: vec![0.0, 1.0],
+        kints: 1,
+        emesh: vec![0.0, 1e36],
+        eints: 1,
+        voxels: results
+            .iter()
+            .enumerate()
+            .map(|(index, result)| Voxel {
+                index,
+                result: *result,
+                error,
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
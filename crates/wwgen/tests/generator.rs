@@ -0,0 +1,135 @@
+//! Integration tests for weight window generation recipes
+
+mod common;
+
+use common::{output_dir, read_mesh};
+use ntools_mesh::{Group, Point};
+use ntools_wwgen::{
+    mesh_to_ww_advanced, GroupSettings, Normalisation, Reference, TimeSettings, WwGenerator,
+};
+use rstest::rstest;
+
+/// Settings for a single group, with only the power set
+fn settings(energy: Group, time: Group, power: f64) -> GroupSettings {
+    GroupSettings {
+        energy,
+        time,
+        power: Some(power),
+        max_error: None,
+    }
+}
+
+/// Recipe using every kind of setting
+fn recipe() -> WwGenerator {
+    WwGenerator::builder()
+        .power(0.6)
+        .max_error(0.2)
+        .normalisation(Normalisation {
+            reference: Reference::Point(Point::from_xyz(1.0, 2.0, 3.0)),
+            target: Some(0.25),
+        })
+        .group(Group::Value(1.0), Group::Value(1e5), 0.5, 0.1)
+        .group(Group::Total, Group::Value(1e30), 0.9, 0.3)
+        .time(TimeSettings {
+            normalise_all_times: true,
+            ..Default::default()
+        })
+        .fill_distance(2)
+        .build()
+}
+
+#[test]
+fn group_settings_applied() {
+    let mesh = read_mesh(114);
+    let generator = WwGenerator::builder()
+        .power(0.7)
+        .group(Group::Value(100.0), Group::Value(1e15), 0.5, 1.0)
+        .build();
+
+    // energy 100.0 is the second energy group, time 1e15 the second time group
+    let expected = mesh_to_ww_advanced(&mesh, &[0.7, 0.7, 0.7, 0.7, 0.5, 0.7], &[1.0]);
+    assert_eq!(generator.generate(&mesh).unwrap(), expected);
+}
+
+#[rstest]
+#[case(Group::Value(1.0), Group::Value(1e5), false)]
+#[case(Group::Total, Group::Total, true)]
+fn duplicate_group_settings(#[case] energy: Group, #[case] time: Group, #[case] total_only: bool) {
+    let mesh = read_mesh(114);
+    let mut generator = WwGenerator::new();
+    generator.groups = vec![settings(energy, time, 0.5), settings(energy, time, 0.6)];
+    generator.total_only = total_only;
+
+    let error = generator.generate(&mesh).unwrap_err().to_string();
+    assert!(error.contains("multiple settings"), "{error}");
+}
+
+#[rstest]
+#[case(Group::Value(5.0), Group::Value(1e5), false)]
+#[case(Group::Value(1.0), Group::Value(1e10), false)]
+#[case(Group::Total, Group::Total, false)]
+#[case(Group::Value(1.0), Group::Value(1e5), true)]
+fn unmatched_group_settings(#[case] energy: Group, #[case] time: Group, #[case] total_only: bool) {
+    let mesh = read_mesh(114);
+    let mut generator = WwGenerator::new();
+    generator.groups = vec![settings(energy, time, 0.5)];
+    generator.total_only = total_only;
+
+    let error = generator.generate(&mesh).unwrap_err().to_string();
+    assert!(error.contains("no mesh group"), "{error}");
+}
+
+#[test]
+fn total_only_settings() {
+    let mesh = read_mesh(114);
+    let mut generator = WwGenerator::new();
+    generator.groups = vec![settings(Group::Total, Group::Total, 0.5)];
+    generator.total_only = true;
+    assert!(generator.generate(&mesh).is_ok());
+}
+
+#[rstest]
+fn holes_filled(#[values(104, 114, 124)] id: u32) {
+    let mesh = read_mesh(id);
+    let unfilled = WwGenerator::builder().max_error(0.1).build();
+    let filled = WwGenerator {
+        fill_distance: 2,
+        ..unfilled.clone()
+    };
+
+    // filled after generation, exactly as for existing weights
+    let mut expected = unfilled.generate(&mesh).unwrap();
+    let n_filled = expected.fill_holes(2);
+    assert!(n_filled > 0);
+    assert_eq!(filled.generate(&mesh).unwrap(), expected);
+}
+
+#[test]
+fn holes_left_by_default() {
+    let generator = WwGenerator::from_toml("max_error = 0.1").unwrap();
+    assert_eq!(generator.fill_distance, 0);
+
+    let ww = generator.generate(&read_mesh(104)).unwrap();
+    assert!(ww.weights.contains(&0.0));
+    assert!(ww.weights.iter().any(|w| *w > 0.0));
+}
+
+#[test]
+fn string_round_trip() {
+    let generator = recipe();
+
+    let toml = generator.to_toml().unwrap();
+    assert_eq!(WwGenerator::from_toml(&toml).unwrap(), generator);
+
+    let json = generator.to_json().unwrap();
+    assert_eq!(WwGenerator::from_json(&json).unwrap(), generator);
+}
+
+#[rstest]
+fn file_round_trip(#[values("recipe.toml", "recipe.json", "RECIPE.TOML")] name: &str) {
+    let generator = recipe();
+    let path = output_dir(&format!("generator_{name}")).join(name);
+
+    generator.write(&path).unwrap();
+    assert_eq!(WwGenerator::from_file(&path).unwrap(), generator);
+}
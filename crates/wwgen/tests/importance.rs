@@ -0,0 +1,264 @@
+//! Integration tests for cell importance generation
+
+mod common;
+
+use common::{read_mesh, slab};
+use ntools_mctal::{BinData, BinKind, Particle, Tally, TallyResult};
+use ntools_mesh::{Mesh, Voxel};
+use ntools_wwgen::{mesh_to_imp, tally_to_imp, Error};
+use rstest::rstest;
+
+/// F4 cell tally with a single energy and time bin
+fn cell_tally(cells: &[u32], results: &[(f64, f64)]) -> Tally {
+    Tally {
+        id: 14,
+        particles: vec![Particle::Neutron],
+        region_bins: BinData {
+            number: cells.len(),
+            values: cells.iter().map(|c| *c as f64).collect(),
+            ..Default::default()
+        },
+        results: results
+            .iter()
+            .map(|(value, error)| TallyResult {
+                value: *value,
+                error: *error,
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn volume_weighted_cell_flux() {
+    // cell 1 is a thin and a thick voxel, cell 2 a single voxel
+    let mesh = slab(&[0.0, 1.0, 4.0, 5.0], &[8.0, 4.0, 0.5], 0.01);
+    let imp = mesh_to_imp(&mesh, &[1, 1, 2], &[1, 2], 1.0, 0.1, 100.0).unwrap();
+
+    // (1*8 + 3*4)/4 = 5 in cell 1, 0.5 in cell 2
+    assert_eq!(imp.cells, vec![1, 2]);
+    assert_eq!(imp.importances[0], 1.0);
+    assert!((imp.importances[1] - 10.0).abs() < 1e-12);
+}
+
+#[test]
+fn deck_cell_order() {
+    // cells are numbered out of order in the deck, with a void and a graveyard
+    let mesh = slab(&[0.0, 1.0, 2.0, 3.0], &[1.0, 0.1, 0.01], 0.01);
+    let cells = [30, 10, 5, 20, 999];
+    let imp = mesh_to_imp(&mesh, &[20, 10, 30], &cells, 1.0, 0.1, 1e6).unwrap();
+
+    // listed cells without voxels have nothing to go on
+    assert_eq!(imp.cells, cells);
+    assert_eq!(imp.importances, vec![100.0, 10.0, 1.0, 1.0, 1.0]);
+
+    let cards = imp.cards().unwrap();
+    assert!(cards.contains("c   30     10     5      20     999\n"));
+}
+
+#[rstest]
+#[case::unlisted(&[1, 2], &[1], "not listed")]
+#[case::duplicate(&[1, 2], &[1, 2, 1], "more than once")]
+#[case::zero(&[1, 2], &[0, 1, 2], "cell 0")]
+#[case::empty(&[0, 0], &[], "no cells")]
+fn invalid_cells(#[case] cell_map: &[u32], #[case] cells: &[u32], #[case] reason: &str) {
+    let mesh = slab(&[0.0, 1.0, 2.0], &[1.0, 0.1], 0.01);
+
+    let error = mesh_to_imp(&mesh, cell_map, cells, 0.7, 0.1, 4.0).unwrap_err();
+    assert!(matches!(error, Error::InvalidImportance { .. }), "{error}");
+    assert!(error.to_string().contains(reason), "{error}");
+}
+
+#[rstest]
+#[case(0.5)]
+#[case(0.7)]
+#[case(1.0)]
+fn importance_follows_flux(#[case] power: f64) {
+    let flux = [1.0, 0.1, 0.01, 0.001];
+    let mesh = slab(&[0.0, 1.0, 2.0, 3.0, 4.0], &flux, 0.01);
+    let imp = mesh_to_imp(&mesh, &[5, 6, 7, 8], &[5, 6, 7, 8], power, 0.1, 1e6).unwrap();
+
+    for (i, f) in flux.iter().enumerate() {
+        let expected = (1.0 / f).powf(power);
+        assert!((imp.importances[i] - expected).abs() < 1e-12 * expected);
+    }
+}
+
+#[rstest]
+#[case(2.0)]
+#[case(4.0)]
+#[case(10.0)]
+fn limit_adjacent_ratio(#[case] max_ratio: f64) {
+    let flux = [1.0, 1e-2, 1e-4, 1e-6, 1e-8];
+    let mesh = slab(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0], &flux, 0.01);
+    let imp = mesh_to_imp(
+        &mesh,
+        &[1, 2, 3, 4, 5],
+        &[1, 2, 3, 4, 5],
+        1.0,
+        0.1,
+        max_ratio,
+    )
+    .unwrap();
+
+    // a factor of 100 every cell is limited from the highest flux outwards
+    for (i, importance) in imp.importances.iter().enumerate() {
+        let expected = max_ratio.powi(i as i32);
+        assert!((importance - expected).abs() < 1e-12 * expected);
+    }
+}
+
+#[rstest]
+#[case(134)]
+#[case(234)]
+#[case(334)]
+fn cylindrical_rings(#[case] id: u32) {
+    let mesh = read_mesh(id);
+
+    // one cell for every radial ring, plus a separate cell for a theta wedge
+    let cell_map = (0..mesh.n_voxels_per_group())
+        .map(|idx| {
+            let (_, _, i, _, k) = mesh.etijk_from_voxel_index(idx);
+            match (i, k) {
+                (3, 2) => 50,
+                _ => 10 * (i as u32 + 1),
+            }
+        })
+        .collect::<Vec<u32>>();
+
+    let imp = mesh_to_imp(&mesh, &cell_map, &[10, 20, 30, 40, 50], 1.0, 1.0, 1.01).unwrap();
+    assert_eq!(imp.cells, vec![10, 20, 30, 40, 50]);
+
+    // rings are adjacent in order, and the wedge only touches rings 3 and 4
+    let adjacent = [(10, 20), (20, 30), (30, 40), (30, 50), (40, 50)];
+    for (a, b) in adjacent {
+        let (a, b) = (imp.get(a).unwrap(), imp.get(b).unwrap());
+        assert!(a.max(b) / a.min(b) <= 1.01 * (1.0 + 1e-12));
+    }
+
+    assert!(imp.importances.iter().all(|i| *i >= 1.0));
+}
+
+#[test]
+fn unconverged_cells() {
+    let mesh = Mesh {
+        voxels: vec![
+            Voxel {
+                index: 0,
+                result: 1.0,
+                error: 0.01,
+            },
+            Voxel {
+                index: 1,
+                result: 0.5,
+                error: 0.9,
+            },
+            Voxel {
+                index: 2,
+                result: 0.0,
+                error: 0.0,
+            },
+            Voxel {
+                index: 3,
+                result: 0.01,
+                error: 0.01,
+            },
+        ],
+        ..slab(&[0.0, 1.0, 2.0, 3.0, 4.0], &[0.0; 4], 0.0)
+    };
+
+    // unconverged cells take the lowest converged neighbour
+    let imp = mesh_to_imp(&mesh, &[1, 2, 3, 4], &[1, 2, 3, 4], 1.0, 0.1, 1e6).unwrap();
+    assert_eq!(imp.importances, vec![1.0, 1.0, 100.0, 100.0]);
+
+    // cells without any converged neighbours default to 1.0
+    let imp = mesh_to_imp(&mesh, &[0, 2, 0, 4], &[2, 4], 1.0, 0.1, 1e6).unwrap();
+    assert_eq!(imp.get(2), Some(1.0));
+    assert_eq!(imp.get(4), Some(1.0));
+    assert_eq!(imp.get(1), None);
+}
+
+#[test]
+fn cell_tally_importances() {
+    let tally = cell_tally(
+        &[10, 20, 30, 40],
+        &[(1.0, 0.01), (0.01, 0.02), (1e-4, 0.05), (1e-4, 0.5)],
+    );
+
+    // no adjacency means no limits
+    let imp = tally_to_imp(&tally, &[], 0.5, 0.1, 2.0).unwrap();
+    assert_eq!(imp.cells, vec![10, 20, 30, 40]);
+    assert_eq!(imp.importances, vec![1.0, 10.0, 100.0, 1.0]);
+
+    // cell 40 is unconverged, taking the importance of cell 30 before limits
+    let adjacent = [(10, 20), (20, 30), (30, 40)];
+    let imp = tally_to_imp(&tally, &adjacent, 0.5, 0.1, 4.0).unwrap();
+    assert_eq!(imp.importances, vec![1.0, 4.0, 16.0, 64.0]);
+
+    let cards = imp.cards().unwrap();
+    assert!(cards.contains("c   10     20     30     40\n"));
+    assert!(cards.contains("IMP:n 1.00000e+00 4.00000e+00 1.60000e+01 6.40000e+01\n"));
+}
+
+#[test]
+fn cell_tally_with_total_bins() {
+    let mut tally = cell_tally(
+        &[1, 2],
+        &[
+            (0.2, 0.01),
+            (0.8, 0.01),
+            (1.0, 0.01),
+            (0.01, 0.01),
+            (0.03, 0.01),
+            (0.04, 0.01),
+        ],
+    );
+    tally.energy_bins = BinData {
+        number: 3,
+        kind: BinKind::Total,
+        values: vec![1.0, 20.0],
+        ..Default::default()
+    };
+
+    // only the total energy bin is used
+    let imp = tally_to_imp(&tally, &[(1, 2)], 1.0, 0.1, 100.0).unwrap();
+    assert_eq!(imp.importances, vec![1.0, 25.0]);
+}
+
+#[test]
+fn invalid_inputs() {
+    let mesh = slab(&[0.0, 1.0, 2.0], &[1.0, 0.1], 0.01);
+
+    // cell map does not match the mesh
+    assert!(mesh_to_imp(&mesh, &[1, 2, 3], &[1, 2, 3], 0.7, 0.1, 4.0).is_err());
+    assert!(mesh_to_imp(&mesh, &[0, 0], &[1, 2], 0.7, 0.1, 4.0).is_err());
+
+    // invalid parameters
+    assert!(mesh_to_imp(&mesh, &[1, 2], &[1, 2], 0.7, 0.1, 0.5).is_err());
+    assert!(mesh_to_imp(&mesh, &[1, 2], &[1, 2], f64::NAN, 0.1, 4.0).is_err());
+
+    // nothing converged
+    assert!(mesh_to_imp(&mesh, &[1, 2], &[1, 2], 0.7, 0.001, 4.0).is_err());
+
+    // adjacent cells must be on the tally
+    let tally = cell_tally(&[10, 20], &[(1.0, 0.01), (0.1, 0.01)]);
+    assert!(tally_to_imp(&tally, &[(10, 30)], 0.7, 0.1, 4.0).is_err());
+
+    // cells must be unique
+    let duplicate = cell_tally(&[10, 10], &[(1.0, 0.01), (0.1, 0.01)]);
+    assert!(tally_to_imp(&duplicate, &[], 0.7, 0.1, 4.0).is_err());
+}
+
+#[rstest]
+#[case::not_cell_tally(Tally { id: 12, ..cell_tally(&[10], &[(1.0, 0.01)]) })]
+#[case::particles(Tally { particles: vec![Particle::Neutron, Particle::Photon], ..cell_tally(&[10], &[(1.0, 0.01)]) })]
+#[case::results(cell_tally(&[10, 20], &[(1.0, 0.01)]))]
+#[case::no_total(Tally {
+    time_bins: BinData { number: 2, values: vec![1.0, 2.0], ..Default::default() },
+    ..cell_tally(&[10], &[(1.0, 0.01), (1.0, 0.01)])
+})]
+fn invalid_tally(#[case] tally: Tally) {
+    // the same error kind as every other importance failure
+    let error = tally_to_imp(&tally, &[], 0.7, 0.1, 4.0).unwrap_err();
+    assert!(matches!(error, Error::InvalidImportance { .. }), "{error}");
+}
@@ -0,0 +1,148 @@
+//! Integration tests for multi-particle weight window generation
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{output_dir, read_mesh};
+use ntools_mesh::{Geometry, Mesh, Particle};
+use ntools_weights::{read_wwinp, WeightWindow};
+use ntools_wwgen::{meshes_to_ww, MultiParticleWeights, WwGenerator};
+use rstest::rstest;
+
+/// Photon mesh on the same geometry as the neutron fixture
+fn photon_mesh() -> Mesh {
+    Mesh {
+        id: 124,
+        particle: Particle::Photon,
+        ..read_mesh(114)
+    }
+}
+
+/// Neutron weights for every group, photon weights for the total only
+fn settings() -> (WwGenerator, WwGenerator) {
+    let neutron = WwGenerator::builder().power(0.7).max_error(1.0).build();
+    let photon = WwGenerator::builder()
+        .power(0.5)
+        .max_error(1.0)
+        .total_only(true)
+        .build();
+    (neutron, photon)
+}
+
+#[test]
+fn neutron_photon_round_trip() {
+    let neutron = read_mesh(114);
+    let photon = photon_mesh();
+    let (n_settings, p_settings) = settings();
+
+    let meshes = BTreeMap::from([
+        (Particle::Photon, (&photon, &p_settings)),
+        (Particle::Neutron, (&neutron, &n_settings)),
+    ]);
+    let weights = meshes_to_ww(&meshes).unwrap();
+    assert_eq!(weights.particles(), [Particle::Neutron, Particle::Photon]);
+    assert_eq!(weights.get(Particle::Neutron).unwrap().ne, 2);
+    assert_eq!(weights.get(Particle::Photon).unwrap().ne, 1);
+    assert!(weights.get(Particle::Electron).is_none());
+
+    let path = output_dir("multi_round_trip").join("wwout_NP");
+    weights.write(&path, false).unwrap();
+    let copy = read_wwinp(&path).unwrap();
+
+    // weights are written to 6 significant figures
+    assert_eq!(copy.len(), 2);
+    for (read, written) in copy.iter().zip(&weights.weight_windows) {
+        let expected = WeightWindow {
+            ni: 2,
+            iv: read.iv,
+            weights: read.weights.clone(),
+            ..written.clone()
+        };
+        assert_eq!(*read, expected);
+
+        assert_eq!(read.weights.len(), written.weights.len());
+        for (a, b) in read.weights.iter().zip(&written.weights) {
+            assert!((a - b).abs() <= 1e-5 * b, "{a} != {b}");
+        }
+    }
+}
+
+#[rstest]
+#[case::bounds(Mesh { imesh: vec![0.0, 5.0, 10.0, 15.0, 20.0], ..photon_mesh() })]
+#[case::origin(Mesh { origin: [1.0, 0.0, 0.0], ..photon_mesh() })]
+#[case::geometry(Mesh { geometry: Geometry::Cylindrical, ..photon_mesh() })]
+fn geometry_mismatch(#[case] photon: Mesh) {
+    let neutron = read_mesh(114);
+    let (n_settings, p_settings) = settings();
+
+    let meshes = BTreeMap::from([
+        (Particle::Neutron, (&neutron, &n_settings)),
+        (Particle::Photon, (&photon, &p_settings)),
+    ]);
+
+    let error = meshes_to_ww(&meshes).unwrap_err().to_string();
+    assert!(error.contains("does not match"), "{error}");
+}
+
+#[rstest]
+#[case::wrong_key(Particle::Photon, Particle::Neutron)]
+#[case::unknown_key(Particle::Unknown, Particle::Neutron)]
+#[case::unknown_both(Particle::Unknown, Particle::Unknown)]
+fn particle_mismatch(#[case] key: Particle, #[case] particle: Particle) {
+    let mesh = Mesh {
+        particle,
+        ..read_mesh(114)
+    };
+    let (settings, _) = settings();
+
+    let meshes = BTreeMap::from([(key, (&mesh, &settings))]);
+    assert!(meshes_to_ww(&meshes).is_err());
+}
+
+#[test]
+fn unknown_mesh_takes_key() {
+    let mesh = Mesh {
+        particle: Particle::Unknown,
+        ..photon_mesh()
+    };
+    let (_, settings) = settings();
+
+    let meshes = BTreeMap::from([(Particle::Photon, (&mesh, &settings))]);
+    let weights = meshes_to_ww(&meshes).unwrap();
+    assert_eq!(weights.particles(), [Particle::Photon]);
+}
+
+#[test]
+fn no_meshes() {
+    assert!(meshes_to_ww(&BTreeMap::new()).is_err());
+}
+
+#[rstest]
+#[case::duplicate(1, 1, 0.0)]
+#[case::unknown(1, 0, 0.0)]
+#[case::geometry(1, 2, 5.0)]
+fn failed_write_creates_nothing(#[case] first: u8, #[case] second: u8, #[case] x0: f64) {
+    let neutron = read_mesh(114);
+    let (settings, _) = settings();
+    let ww = settings.generate(&neutron).unwrap();
+
+    // sets that would be dropped when writing
+    let weights = MultiParticleWeights {
+        weight_windows: vec![
+            WeightWindow {
+                particle: first,
+                ..ww.clone()
+            },
+            WeightWindow {
+                particle: second,
+                x0,
+                ..ww
+            },
+        ],
+    };
+
+    let path = output_dir(&format!("multi_failed_{first}_{second}")).join("wwout");
+    assert!(weights.write(&path, false).is_err());
+    assert!(!path.exists());
+}
@@ -0,0 +1,149 @@
+//! Integration tests for the normalisation of MAGIC weights
+
+mod common;
+
+use common::{assert_close, maximum, read_mesh};
+use ntools_mesh::{Mesh, Point};
+use ntools_wwgen::{mesh_to_ww, mesh_to_ww_normalised, Normalisation, Reference};
+use rstest::rstest;
+
+const POWER: f64 = 0.7;
+
+/// Fluxes of the total group, in the same order as the weights on a mesh
+fn total_flux(mesh: &Mesh) -> Vec<f64> {
+    mesh.voxels_by_group_index(mesh.n_ebins() - 1, mesh.n_tbins() - 1)
+        .unwrap()
+        .iter()
+        .map(|v| v.result)
+        .collect()
+}
+
+/// Total only weights with every error accepted, in voxel order
+fn weights(mesh: &Mesh, normalisation: Normalisation) -> Vec<f64> {
+    mesh_to_ww_normalised(mesh, &[POWER], &[1.0], true, &normalisation)
+        .unwrap()
+        .to_mesh()
+        .unwrap()
+        .voxels
+        .iter()
+        .map(|v| v.result)
+        .collect()
+}
+
+/// Weights expected for a reference flux without a target
+fn expected(fluxes: &[f64], flux_ref: f64) -> Vec<f64> {
+    fluxes
+        .iter()
+        .map(|f| (0.5 * f / flux_ref).powf(POWER))
+        .collect()
+}
+
+fn reference(reference: Reference) -> Normalisation {
+    Normalisation {
+        reference,
+        target: None,
+    }
+}
+
+#[rstest]
+#[case(114)]
+#[case(134)]
+fn reference_maximum(#[case] id: u32) {
+    let mesh = read_mesh(id);
+    let fluxes = total_flux(&mesh);
+    let ww = weights(&mesh, reference(Reference::Maximum));
+    assert_close(&ww, &expected(&fluxes, maximum(&fluxes)));
+
+    // the default is the same as the simple interface
+    let simple = mesh_to_ww(&mesh, POWER, 1.0, true).to_mesh().unwrap();
+    let simple: Vec<f64> = simple.voxels.iter().map(|v| v.result).collect();
+    assert_eq!(ww, simple);
+}
+
+#[rstest]
+#[case(114)]
+#[case(134)]
+fn reference_percentile(#[case] id: u32) {
+    let mesh = read_mesh(id);
+    let fluxes = total_flux(&mesh);
+
+    // nearest rank of the sorted non-zero fluxes
+    let mut sorted: Vec<f64> = fluxes.iter().copied().filter(|f| *f > 0.0).collect();
+    sorted.sort_by(f64::total_cmp);
+    let rank = (0.5 * sorted.len() as f64).ceil() as usize;
+
+    let ww = weights(&mesh, reference(Reference::Percentile(50.0)));
+    assert_close(&ww, &expected(&fluxes, sorted[rank - 1]));
+
+    // the 100th percentile is the maximum
+    let ww = weights(&mesh, reference(Reference::Percentile(100.0)));
+    assert_close(&ww, &expected(&fluxes, maximum(&fluxes)));
+}
+
+#[rstest]
+#[case(114)]
+#[case(134)]
+fn reference_point(#[case] id: u32) {
+    let mesh = read_mesh(id);
+    let fluxes = total_flux(&mesh);
+
+    // voxel (1, 1, 2) is index 1*jints*kints + 1*kints + 2
+    let idx = mesh.jints * mesh.kints + mesh.kints + 2;
+    let point = Point::from_ijk(1, 1, 2);
+    let ww = weights(&mesh, reference(Reference::Point(point)));
+    assert_close(&ww, &expected(&fluxes, fluxes[idx]));
+    assert!((ww[idx] - 0.5_f64.powf(POWER)).abs() < 1e-12);
+}
+
+#[rstest]
+#[case(114)]
+#[case(134)]
+fn reference_constant(#[case] id: u32) {
+    let mesh = read_mesh(id);
+    let fluxes = total_flux(&mesh);
+    let constant = 3.0 * maximum(&fluxes);
+
+    let ww = weights(&mesh, reference(Reference::Constant(constant)));
+    assert_close(&ww, &expected(&fluxes, constant));
+}
+
+#[test]
+fn target_weight() {
+    let mesh = read_mesh(114);
+    let fluxes = total_flux(&mesh);
+    let flux_ref = maximum(&fluxes);
+
+    let normalisation = Normalisation {
+        reference: Reference::Maximum,
+        target: Some(0.25),
+    };
+    let ww = weights(&mesh, normalisation);
+    let target: Vec<f64> = fluxes
+        .iter()
+        .map(|f| 0.25 * (f / flux_ref).powf(POWER))
+        .collect();
+    assert_close(&ww, &target);
+    assert!((maximum(&ww) - 0.25).abs() < 1e-12);
+
+    // the usual weights also soften the factor of 0.5
+    let normalisation = Normalisation {
+        reference: Reference::Maximum,
+        target: Some(0.5_f64.powf(POWER)),
+    };
+    assert_close(
+        &weights(&mesh, normalisation),
+        &weights(&mesh, Normalisation::default()),
+    );
+}
+
+#[rstest]
+#[case(reference(Reference::Percentile(0.0)))]
+#[case(reference(Reference::Percentile(101.0)))]
+#[case(reference(Reference::Constant(0.0)))]
+#[case(reference(Reference::Constant(f64::INFINITY)))]
+#[case(reference(Reference::Point(Point::from_xyz(1e6, 0.0, 0.0))))]
+#[case(Normalisation { reference: Reference::Maximum, target: Some(-1.0) })]
+fn invalid_normalisation(#[case] normalisation: Normalisation) {
+    let mesh = read_mesh(114);
+    assert!(mesh_to_ww_normalised(&mesh, &[POWER], &[1.0], true, &normalisation).is_err());
+}
@@ -0,0 +1,249 @@
+//! Integration tests for time-dependent weight windows on time-binned meshes
+
+mod common;
+
+use common::{maximum, read_mesh};
+use ntools_mesh::Mesh;
+use ntools_weights::WeightWindow;
+use ntools_wwgen::{TimeSettings, WwGenerator};
+use rstest::rstest;
+
+const POWER: f64 = 0.7;
+
+fn generate(mesh: &Mesh, time: TimeSettings) -> WeightWindow {
+    WwGenerator::builder()
+        .power(POWER)
+        .time(time)
+        .build()
+        .generate(mesh)
+        .unwrap()
+}
+
+/// Weights of a single energy/time group
+fn group(ww: &WeightWindow, e: usize, t: usize) -> &[f64] {
+    let n = ww.nfx * ww.nfy * ww.nfz;
+    let start = (e * ww.nt + t) * n;
+    &ww.weights[start..start + n]
+}
+
+#[rstest]
+#[case(114)]
+#[case(214)]
+#[case(314)]
+#[case(414)]
+#[case(514)]
+#[case(134)]
+#[case(234)]
+#[case(334)]
+#[case(434)]
+#[case(534)]
+fn independent_time_groups(#[case] id: u32) {
+    let mesh = read_mesh(id);
+    let ww = generate(&mesh, TimeSettings::default());
+
+    assert_eq!(ww.iv, 2);
+    assert_eq!(ww.nt, 3);
+    assert_eq!(ww.t, vec![1e5, 1e15, 1e30]);
+
+    // every group is normalised to itself
+    for e in 0..ww.ne {
+        for t in 0..ww.nt {
+            let max = maximum(group(&ww, e, t));
+            assert!((max - 0.5_f64.powf(POWER)).abs() < 1e-12);
+        }
+    }
+}
+
+#[rstest]
+#[case(114)]
+#[case(214)]
+#[case(314)]
+#[case(414)]
+#[case(514)]
+#[case(134)]
+#[case(234)]
+#[case(334)]
+#[case(434)]
+#[case(534)]
+fn normalise_all_times(#[case] id: u32) {
+    let mesh = read_mesh(id);
+    let settings = TimeSettings {
+        normalise_all_times: true,
+        ..Default::default()
+    };
+    let ww = generate(&mesh, settings);
+
+    for e in 0..ww.ne {
+        // one reference flux for all times of the energy group
+        let flux_ref = (0..ww.nt)
+            .map(|t| mesh.voxels_by_group_index(e, t).unwrap())
+            .flat_map(|voxels| voxels.iter().map(|v| v.result))
+            .fold(0.0, f64::max);
+
+        for t in 0..ww.nt {
+            let voxels = mesh.voxels_by_group_index(e, t).unwrap();
+            let weights = group(&ww, e, t);
+
+            for (i, voxel) in voxels.iter().enumerate() {
+                let expected = (0.5 * voxel.result / flux_ref).powf(POWER);
+                let weight = weights[mesh.cell_index_from_voxel_index(i)];
+                assert!((weight - expected).abs() < 1e-12 * expected);
+            }
+        }
+
+        // only the time group with the maximum flux reaches the usual maximum
+        let max = maximum(group(&ww, e, ww.nt - 1));
+        assert!((max - 0.5_f64.powf(POWER)).abs() < 1e-12);
+        assert!(maximum(group(&ww, e, 0)) < max);
+    }
+}
+
+#[rstest]
+#[case(114, 2.0)]
+#[case(134, 2.0)]
+#[case(214, 1.5)]
+#[case(234, 1.5)]
+fn limit_time_ratio(#[case] id: u32, #[case] max_ratio: f64) {
+    let mesh = read_mesh(id);
+    let original = generate(&mesh, TimeSettings::default());
+    let settings = TimeSettings {
+        max_ratio: Some(max_ratio),
+        ..Default::default()
+    };
+    let ww = generate(&mesh, settings);
+
+    // weights are only ever raised
+    assert!(ww
+        .weights
+        .iter()
+        .zip(&original.weights)
+        .all(|(a, b)| a >= b));
+    assert_ne!(ww.weights, original.weights);
+
+    for e in 0..ww.ne {
+        for t in 1..ww.nt {
+            for (a, b) in group(&ww, e, t - 1).iter().zip(group(&ww, e, t)) {
+                let ratio = a.max(*b) / a.min(*b);
+                assert!(ratio <= max_ratio * (1.0 + 1e-12));
+            }
+        }
+    }
+}
+
+#[rstest]
+#[case(114, 1)]
+#[case(114, 2)]
+#[case(134, 1)]
+#[case(134, 2)]
+fn drop_empty_times(#[case] id: u32, #[case] n_empty: usize) {
+    let mut mesh = read_mesh(id);
+
+    // no flux at late times in any energy group, including the total
+    let n_tbins = mesh.n_tbins();
+    for e in 0..mesh.n_ebins() {
+        for t in (n_tbins - 1 - n_empty)..(n_tbins - 1) {
+            for i in 0..mesh.iints {
+                for j in 0..mesh.jints {
+                    for k in 0..mesh.kints {
+                        let idx = mesh.voxel_index_from_etijk(e, t, i, j, k);
+                        mesh.voxels[idx].result = 0.0;
+                    }
+                }
+            }
+        }
+    }
+
+    let original = generate(&mesh, TimeSettings::default());
+    let settings = TimeSettings {
+        drop_empty: true,
+        ..Default::default()
+    };
+    let ww = generate(&mesh, settings);
+
+    let n_keep = 3 - n_empty;
+    assert_eq!(ww.nt, n_keep);
+    assert_eq!(ww.weights.len(), ww.n_weights_expected());
+
+    match n_keep {
+        1 => {
+            assert_eq!(ww.iv, 1);
+            assert!(ww.t.is_empty());
+        }
+        _ => {
+            assert_eq!(ww.iv, 2);
+            assert_eq!(ww.t, vec![1e5, 1e30]);
+        }
+    }
+
+    // remaining groups are unchanged
+    for e in 0..ww.ne {
+        for t in 0..n_keep {
+            assert_eq!(group(&ww, e, t), group(&original, e, t));
+        }
+    }
+}
+
+#[test]
+fn flux_everywhere_keeps_all_times() {
+    let mesh = read_mesh(114);
+    let settings = TimeSettings {
+        drop_empty: true,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        generate(&mesh, settings),
+        generate(&mesh, TimeSettings::default())
+    );
+}
+
+#[test]
+fn total_only_ignores_time_settings() {
+    let mesh = read_mesh(114);
+    let generator = WwGenerator::builder()
+        .total_only(true)
+        .time(TimeSettings {
+            normalise_all_times: true,
+            max_ratio: Some(2.0),
+            drop_empty: true,
+        })
+        .build();
+
+    let ww = generator.generate(&mesh).unwrap();
+    assert_eq!(ww.iv, 1);
+    assert_eq!(ww.nt, 1);
+}
+
+#[test]
+fn invalid_time_ratio() {
+    let mesh = read_mesh(114);
+    let generator = WwGenerator::builder()
+        .time(TimeSettings {
+            max_ratio: Some(0.5),
+            ..Default::default()
+        })
+        .build();
+
+    assert!(generator.generate(&mesh).is_err());
+}
+
+#[test]
+fn time_settings_in_recipe() {
+    let generator = WwGenerator::from_toml(
+        r#"
+        power = 0.7
+
+        [time]
+        normalise_all_times = true
+        max_ratio = 10.0
+        "#,
+    )
+    .unwrap();
+
+    assert!(generator.time.normalise_all_times);
+    assert_eq!(generator.time.max_ratio, Some(10.0));
+    assert!(!generator.time.drop_empty);
+
+    let copy = WwGenerator::from_toml(&generator.to_toml().unwrap()).unwrap();
+    assert_eq!(copy, generator);
+}
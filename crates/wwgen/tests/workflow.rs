@@ -0,0 +1,116 @@
+//! Integration tests for the iterative MAGIC workflow
+
+mod common;
+
+use common::{output_dir, read_mesh};
+use ntools_mesh::Mesh;
+use ntools_wwgen::{MagicWorkflow, WwGenerator};
+use rstest::rstest;
+
+/// Flat flux mesh where a percentage of the voxels in every group have flux
+fn mesh(coverage: usize) -> Mesh {
+    let mut mesh = read_mesh(114);
+    let n = mesh.n_voxels_per_group();
+    for (i, voxel) in mesh.voxels.iter_mut().enumerate() {
+        voxel.result = match 100 * (i % n) < coverage * n {
+            true => 1.0,
+            false => 0.0,
+        };
+        voxel.error = 0.0;
+    }
+    mesh
+}
+
+fn workflow(power: f64, max_error: f64) -> MagicWorkflow {
+    MagicWorkflow::new(
+        WwGenerator::builder()
+            .power(power)
+            .max_error(max_error)
+            .build(),
+    )
+}
+
+fn assert_settings(workflow: &MagicWorkflow, power: f64, max_error: f64) {
+    let generator = &workflow.generator;
+    assert!(
+        (generator.power - power).abs() < 1e-12,
+        "{}",
+        generator.power
+    );
+    assert!(
+        (generator.max_error - max_error).abs() < 1e-12,
+        "{}",
+        generator.max_error
+    );
+}
+
+#[test]
+fn first_iteration() {
+    let mut workflow = workflow(0.5, 0.1);
+    workflow.iterate(&mesh(25)).unwrap();
+
+    // nothing to compare against yet
+    assert_eq!(workflow.history.len(), 1);
+    assert_eq!(workflow.gain(), None);
+    assert!((workflow.latest().unwrap().coverage - 25.0).abs() < 1e-12);
+    assert!(!workflow.is_converged());
+    assert_settings(&workflow, 0.5, 0.1);
+}
+
+#[rstest]
+#[case::progress(0.5, 0.1, 50, 0.6, 0.1)]
+#[case::power_limit(0.95, 0.1, 50, 1.0, 0.1)]
+#[case::stall(0.5, 0.1, 25, 0.5, 0.15)]
+#[case::error_limit(0.5, 0.8, 25, 0.5, 1.0)]
+#[case::stalled_at_limit(0.5, 1.0, 25, 0.5, 1.0)]
+#[case::converged(0.5, 0.1, 100, 0.5, 0.1)]
+fn schedule(
+    #[case] power: f64,
+    #[case] max_error: f64,
+    #[case] coverage: usize,
+    #[case] next_power: f64,
+    #[case] next_error: f64,
+) {
+    let mut workflow = workflow(power, max_error);
+    workflow.iterate(&mesh(25)).unwrap();
+    workflow.iterate(&mesh(coverage)).unwrap();
+
+    assert!((workflow.gain().unwrap() - (coverage as f64 - 25.0)).abs() < 1e-12);
+    assert_eq!(workflow.is_converged(), coverage == 100);
+    assert_settings(&workflow, next_power, next_error);
+
+    // the history records the settings each iteration was generated with
+    let iteration = workflow.latest().unwrap();
+    assert_eq!(iteration.index, 1);
+    assert_eq!((iteration.power, iteration.max_error), (power, max_error));
+}
+
+#[rstest]
+fn file_round_trip(#[values("workflow.toml", "workflow.json")] name: &str) {
+    let mut workflow = workflow(0.5, 0.1);
+    workflow.iterate(&mesh(25)).unwrap();
+    workflow.iterate(&mesh(50)).unwrap();
+
+    let path = output_dir(&format!("workflow_{name}")).join(name);
+    workflow.save(&path).unwrap();
+    assert_eq!(MagicWorkflow::load(&path).unwrap(), workflow);
+}
+
+#[test]
+fn unknown_extension() {
+    let dir = output_dir("workflow_unknown");
+    let workflow = workflow(0.5, 0.1);
+
+    for name in ["workflow.yaml", "workflow"] {
+        let path = dir.join(name);
+        assert!(workflow.save(&path).is_err());
+        assert!(!path.exists());
+    }
+
+    // even if the content is valid
+    let path = dir.join("workflow.txt");
+    std::fs::write(&path, workflow.to_json().unwrap()).unwrap();
+    assert!(MagicWorkflow::load(&path).is_err());
+    assert!(WwGenerator::from_file(&path).is_err());
+    assert!(workflow.generator.write(dir.join("recipe.yml")).is_err());
+}